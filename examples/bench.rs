@@ -0,0 +1,8 @@
+use procspawn::bench::{measure_spawn_overhead, BenchConfig};
+
+fn main() {
+    procspawn::init();
+
+    let overhead = measure_spawn_overhead(BenchConfig::new().iterations(50)).unwrap();
+    println!("{}", overhead);
+}
@@ -3,6 +3,6 @@ use procspawn::{self, spawn};
 #[allow(clippy::empty_loop)]
 fn main() {
     procspawn::init();
-    let mut handle = spawn((), |()| loop {});
+    let mut handle = spawn::<_, ()>((), |()| loop {});
     handle.kill().unwrap();
 }
@@ -0,0 +1,155 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use ipc_channel::ipc::{IpcOneShotServer, IpcReceiver, IpcSender};
+use serde::{Deserialize, Serialize};
+
+use crate::core::MarshalledCall;
+use crate::endpoints::EndpointGuard;
+
+/// Sent by a new parent to an orphaned worker.
+#[derive(Serialize, Deserialize)]
+enum AdoptionMessage {
+    Adopt(IpcReceiver<MarshalledCall>),
+    Retire,
+}
+
+/// Configuration for workers that can be adopted by a new parent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Adoption {
+    pub state_file: PathBuf,
+    pub timeout: Duration,
+}
+
+impl Adoption {
+    /// Called in the worker once the parent went away.
+    ///
+    /// The worker registers itself in the state file and waits for a new
+    /// parent to adopt it.  Returns the new call receiver or `None` if the
+    /// worker should shut down.
+    pub fn wait_for_parent(&self) -> Option<IpcReceiver<MarshalledCall>> {
+        let (server, token) = IpcOneShotServer::<AdoptionMessage>::new().ok()?;
+        let _endpoint = EndpointGuard::register(&token);
+        let line = format!(
+            "{}\t{}\t{}\n",
+            process::id(),
+            executable_identity().ok()?,
+            token
+        );
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.state_file)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .ok()?;
+
+        // if nobody adopts us in time we just go away.
+        let adopted = Arc::new(AtomicBool::new(false));
+        {
+            let adopted = adopted.clone();
+            let timeout = self.timeout;
+            thread::Builder::new()
+                .name("procspawn-adoption".into())
+                .spawn(move || {
+                    thread::sleep(timeout);
+                    if !adopted.load(Ordering::SeqCst) {
+                        process::exit(0);
+                    }
+                })
+                .ok()?;
+        }
+
+        match server.accept() {
+            Ok((_, AdoptionMessage::Adopt(call_rx))) => {
+                adopted.store(true, Ordering::SeqCst);
+                Some(call_rx)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A worker that was handed over from a previous parent.
+pub struct AdoptedWorker {
+    pub pid: u32,
+    pub call_tx: IpcSender<MarshalledCall>,
+}
+
+/// Adopts up to `max` orphaned workers registered in the state file.
+///
+/// Workers that cannot be adopted because they run a different executable
+/// or because enough workers were already adopted are asked to shut down.
+pub fn adopt_workers(state_file: &Path, max: usize) -> Vec<AdoptedWorker> {
+    let contents = match fs::read_to_string(state_file) {
+        Ok(contents) => contents,
+        Err(_) => return Vec::new(),
+    };
+    fs::remove_file(state_file).ok();
+
+    let identity = executable_identity().ok();
+    let mut rv = Vec::new();
+    for line in contents.lines() {
+        let mut pieces = line.splitn(3, '\t');
+        let (pid, worker_identity, token) = match (pieces.next(), pieces.next(), pieces.next()) {
+            (Some(pid), Some(worker_identity), Some(token)) => match pid.parse() {
+                Ok(pid) => (pid, worker_identity, token),
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+        let sender = match IpcSender::<AdoptionMessage>::connect(token.to_string()) {
+            Ok(sender) => sender,
+            Err(_) => continue,
+        };
+
+        // functions are marshalled as offsets into the executable so we
+        // can only hand calls to workers that run the very same binary.
+        if rv.len() >= max || identity.as_deref() != Some(worker_identity) {
+            sender.send(AdoptionMessage::Retire).ok();
+            continue;
+        }
+
+        let (call_tx, call_rx) = match ipc_channel::ipc::channel() {
+            Ok(channel) => channel,
+            Err(_) => continue,
+        };
+        if sender.send(AdoptionMessage::Adopt(call_rx)).is_ok() {
+            rv.push(AdoptedWorker { pid, call_tx });
+        }
+    }
+    rv
+}
+
+/// Identifies the executable of the current process.
+fn executable_identity() -> io::Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let path = if cfg!(target_os = "linux") {
+            PathBuf::from("/proc/self/exe")
+        } else {
+            std::env::current_exe()?
+        };
+        let md = fs::metadata(path)?;
+        Ok(format!(
+            "{}:{}:{}:{}",
+            md.dev(),
+            md.ino(),
+            md.size(),
+            md.mtime()
+        ))
+    }
+    #[cfg(not(unix))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "worker adoption is not supported on this platform",
+        ))
+    }
+}
@@ -0,0 +1,294 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{ENV_NAME, MAX_NESTING_DEPTH_ENV_NAME, NESTING_DEPTH_ENV_NAME};
+use crate::error::SpawnError;
+#[cfg(feature = "metrics")]
+use crate::telemetry;
+
+pub type AuditSink = dyn Fn(&AuditRecord) + Send + Sync;
+
+static AUDIT_SINK: Mutex<Option<Arc<AuditSink>>> = Mutex::new(None);
+
+pub fn set_audit_sink(sink: Arc<AuditSink>) {
+    *AUDIT_SINK.lock().unwrap() = Some(sink);
+}
+
+fn audit_sink() -> Option<Arc<AuditSink>> {
+    AUDIT_SINK.lock().unwrap().clone()
+}
+
+/// Describes a single spawn for auditing purposes.
+///
+/// A record is passed to the sink configured with
+/// [`ProcConfig::audit_log`](struct.ProcConfig.html#method.audit_log) for
+/// every spawn, whether it succeeded or not.  The `Display` implementation
+/// renders the record as a single line, alternatively the record can be
+/// serialized with serde.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuditRecord {
+    sequence: u64,
+    forked: bool,
+    executable: Option<String>,
+    args: Vec<String>,
+    env_set: Vec<String>,
+    env_removed: Vec<String>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    run_as: Option<String>,
+    pre_exec: bool,
+    nesting_depth: usize,
+    max_nesting_depth: Option<usize>,
+    bootstrap_timeout: Option<Duration>,
+    pid: Option<u32>,
+    error: Option<String>,
+}
+
+impl AuditRecord {
+    /// The spawn sequence number of the process.
+    ///
+    /// See [`JoinHandle::sequence`](struct.JoinHandle.html#method.sequence).
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// True if the process was forked instead of executed.
+    ///
+    /// Forked processes run the executable of their parent and inherit its
+    /// environment and credentials.
+    pub fn forked(&self) -> bool {
+        self.forked
+    }
+
+    /// The executable that was started.
+    ///
+    /// This is `None` if the spawn failed before the executable was known.
+    pub fn executable(&self) -> Option<&str> {
+        self.executable.as_deref()
+    }
+
+    /// The arguments passed to the executable.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Names of environment variables that were set or changed compared to
+    /// the parent.
+    ///
+    /// Only the names are recorded as the values might be secrets.
+    /// Variables used internally by procspawn are not included.
+    pub fn env_set(&self) -> &[String] {
+        &self.env_set
+    }
+
+    /// Environment variables of the parent that were removed.
+    pub fn env_removed(&self) -> &[String] {
+        &self.env_removed
+    }
+
+    /// The user ID the process was started with, if changed.
+    pub fn uid(&self) -> Option<u32> {
+        self.uid
+    }
+
+    /// The group ID the process was started with, if changed.
+    pub fn gid(&self) -> Option<u32> {
+        self.gid
+    }
+
+    /// The user the process was started as with `run_as`.
+    pub fn run_as(&self) -> Option<&str> {
+        self.run_as.as_deref()
+    }
+
+    /// True if a `pre_exec` hook was installed.
+    pub fn pre_exec(&self) -> bool {
+        self.pre_exec
+    }
+
+    /// The nesting depth of the spawned process.
+    pub fn nesting_depth(&self) -> usize {
+        self.nesting_depth
+    }
+
+    /// The nesting depth limit in effect.
+    pub fn max_nesting_depth(&self) -> Option<usize> {
+        self.max_nesting_depth
+    }
+
+    /// The bootstrap timeout in effect.
+    pub fn bootstrap_timeout(&self) -> Option<Duration> {
+        self.bootstrap_timeout
+    }
+
+    /// The process ID if the spawn succeeded.
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// The error message if the spawn failed.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Records the prepared command.
+    pub(crate) fn describe_command(&mut self, cmd: &process::Command) {
+        self.executable = Some(cmd.get_program().to_string_lossy().into_owned());
+        self.args = cmd
+            .get_args()
+            .map(|x| x.to_string_lossy().into_owned())
+            .collect();
+        for (key, value) in cmd.get_envs() {
+            if is_internal_var(key) {
+                continue;
+            }
+            let parent_value = env::var_os(key);
+            match value {
+                Some(value) if parent_value.as_deref() != Some(value) => {
+                    self.env_set.push(key.to_string_lossy().into_owned());
+                }
+                None if parent_value.is_some() => {
+                    self.env_removed.push(key.to_string_lossy().into_owned());
+                }
+                _ => {}
+            }
+        }
+        self.env_set.sort();
+        self.env_removed.sort();
+    }
+}
+
+fn is_internal_var(key: &OsStr) -> bool {
+    key == ENV_NAME
+        || key == NESTING_DEPTH_ENV_NAME
+        || key == MAX_NESTING_DEPTH_ENV_NAME
+        || key.to_str().is_some_and(|x| x.starts_with("__PROCSPAWN_"))
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "spawn seq={}", self.sequence)?;
+        if self.forked {
+            write!(f, " forked")?;
+        }
+        if let Some(ref executable) = self.executable {
+            write!(f, " executable={:?}", executable)?;
+        }
+        write!(f, " args={:?}", self.args)?;
+        if !self.env_set.is_empty() {
+            write!(f, " env_set={:?}", self.env_set)?;
+        }
+        if !self.env_removed.is_empty() {
+            write!(f, " env_removed={:?}", self.env_removed)?;
+        }
+        if let Some(uid) = self.uid {
+            write!(f, " uid={}", uid)?;
+        }
+        if let Some(gid) = self.gid {
+            write!(f, " gid={}", gid)?;
+        }
+        if let Some(ref run_as) = self.run_as {
+            write!(f, " run_as={:?}", run_as)?;
+        }
+        if self.pre_exec {
+            write!(f, " pre_exec")?;
+        }
+        write!(f, " nesting_depth={}", self.nesting_depth)?;
+        if let Some(max_depth) = self.max_nesting_depth {
+            write!(f, " max_nesting_depth={}", max_depth)?;
+        }
+        if let Some(timeout) = self.bootstrap_timeout {
+            write!(f, " bootstrap_timeout={:?}", timeout)?;
+        }
+        match (self.pid, &self.error) {
+            (_, Some(err)) => write!(f, " error={:?}", err),
+            (Some(pid), None) => write!(f, " pid={}", pid),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Collects the audit record of a spawn if auditing is enabled.
+pub struct Audit {
+    sink: Option<Arc<AuditSink>>,
+    pub record: AuditRecord,
+    #[cfg(feature = "metrics")]
+    started: Instant,
+}
+
+impl Audit {
+    pub fn start(forked: bool, sequence: u64) -> Audit {
+        #[cfg(feature = "metrics")]
+        telemetry::spawn_started(forked);
+        let sink = audit_sink();
+        let executable = if forked && sink.is_some() {
+            env::current_exe()
+                .ok()
+                .map(|x| x.to_string_lossy().into_owned())
+        } else {
+            None
+        };
+        Audit {
+            sink,
+            record: AuditRecord {
+                sequence,
+                forked,
+                executable,
+                ..AuditRecord::default()
+            },
+            #[cfg(feature = "metrics")]
+            started: Instant::now(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Reports the outcome of the spawn to the sink.
+    pub fn finish<T>(mut self, rv: &Result<T, SpawnError>, pid: impl FnOnce(&T) -> Option<u32>) {
+        #[cfg(feature = "metrics")]
+        telemetry::spawn_finished(self.record.forked, self.started.elapsed(), rv.is_err());
+        let sink = match self.sink.take() {
+            Some(sink) => sink,
+            None => return,
+        };
+        match rv {
+            Ok(value) => self.record.pid = pid(value),
+            Err(err) => self.record.error = Some(err.to_string()),
+        }
+        sink(&self.record);
+    }
+
+    pub fn set_credentials(
+        &mut self,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        run_as: Option<&str>,
+        pre_exec: bool,
+    ) {
+        self.record.uid = uid;
+        self.record.gid = gid;
+        self.record.run_as = run_as.map(|x| x.to_string());
+        self.record.pre_exec = pre_exec;
+    }
+
+    pub fn set_limits(
+        &mut self,
+        nesting_depth: usize,
+        max_nesting_depth: Option<usize>,
+        bootstrap_timeout: Option<Duration>,
+    ) {
+        self.record.nesting_depth = nesting_depth;
+        self.record.max_nesting_depth = max_nesting_depth;
+        self.record.bootstrap_timeout = bootstrap_timeout;
+    }
+}
@@ -0,0 +1,83 @@
+use std::io;
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use crate::error::SpawnError;
+
+static SPAWN_BACKEND: Mutex<Option<Arc<dyn SpawnBackend>>> = Mutex::new(None);
+
+/// Launches the processes that spawned functions are executed in.
+///
+/// procspawn prepares a [`Command`](std::process::Command) which re-executes
+/// the current executable with all arguments, environment variables and
+/// stdio configured.  The backend is responsible for turning this command
+/// into a running process.  The default [`ExecBackend`](struct.ExecBackend.html)
+/// just spawns the command but custom backends can wrap or rewrite it, for
+/// instance to launch the process in a container or on another host.
+///
+/// Whatever the backend does, the launched process must run the same
+/// executable, see the environment variables of the command and be able to
+/// connect to the bootstrap socket of the parent.
+///
+/// Backends are configured with
+/// [`ProcConfig::spawn_backend`](struct.ProcConfig.html#method.spawn_backend).
+pub trait SpawnBackend: Send + Sync + 'static {
+    /// Launches a process for the prepared command.
+    fn launch(&self, cmd: &mut process::Command) -> io::Result<process::Child>;
+}
+
+/// The default backend which spawns the command as a regular child process.
+///
+/// The process is created with [`Command::spawn`](std::process::Command::spawn)
+/// which already uses `posix_spawn` when the platform and the configuration
+/// of the command allow it and falls back to `fork` and `exec` otherwise.
+/// Which options rule out `posix_spawn` is up to the standard library.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExecBackend;
+
+impl SpawnBackend for ExecBackend {
+    fn launch(&self, cmd: &mut process::Command) -> io::Result<process::Child> {
+        cmd.spawn()
+    }
+}
+
+pub fn set_spawn_backend(backend: Arc<dyn SpawnBackend>) {
+    *SPAWN_BACKEND.lock().unwrap() = Some(backend);
+}
+
+/// Launches the command with the configured backend.
+///
+/// Errors of custom backends are passed on as they are, only the default
+/// backend reports [exec failures](struct.SpawnError.html#method.is_exec_failure).
+pub fn launch(cmd: &mut process::Command) -> Result<process::Child, SpawnError> {
+    let backend = SPAWN_BACKEND.lock().unwrap().clone();
+    match backend {
+        Some(backend) => Ok(backend.launch(cmd)?),
+        None => ExecBackend.launch(cmd).map_err(|err| {
+            if is_exec_stage_error(&err) {
+                SpawnError::new_exec_failure(err)
+            } else {
+                err.into()
+            }
+        }),
+    }
+}
+
+/// Tells errors of the exec stage apart from failures to create the process.
+///
+/// The standard library reports both as plain OS errors.  The errors that
+/// `fork`, `posix_spawn` and creating pipes fail with when resources run
+/// out are not counted even though `exec` can fail with some of them too.
+#[cfg(unix)]
+fn is_exec_stage_error(err: &io::Error) -> bool {
+    match err.raw_os_error() {
+        Some(libc::EAGAIN | libc::ENOMEM | libc::EMFILE | libc::ENFILE | libc::ENOSYS) => false,
+        Some(_) => true,
+        None => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_exec_stage_error(_err: &io::Error) -> bool {
+    false
+}
@@ -0,0 +1,270 @@
+//! Utilities to measure the overhead of spawning.
+//!
+//! The cost of a spawn depends a lot on the platform, the size of the
+//! executable and the configuration (for instance the [codec](../codec/index.html)
+//! or dropping privileges with `run_as`).  [`measure_spawn_overhead`](fn.measure_spawn_overhead.html)
+//! measures the individual phases of spawns with a given configuration on
+//! the current machine so that settings can be compared:
+//!
+//! ```rust,no_run
+//! use procspawn::bench::{measure_spawn_overhead, BenchConfig};
+//!
+//! procspawn::init();
+//! let overhead = measure_spawn_overhead(BenchConfig::new().iterations(50)).unwrap();
+//! println!("{}", overhead);
+//! ```
+use std::cell::RefCell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::error::SpawnError;
+use crate::pool::{Pool, PoolBuilder};
+use crate::proc::Builder;
+
+type ConfigureFunc<T> = Box<dyn Fn(&mut T) + Send + Sync>;
+
+/// The phases of a spawn that are timed while measuring.
+#[derive(Debug, Default)]
+struct SpawnTimings {
+    exec: Vec<Duration>,
+    handshake: Vec<Duration>,
+}
+
+thread_local! {
+    static SPAWN_TIMINGS: RefCell<Option<SpawnTimings>> = const { RefCell::new(None) };
+}
+
+/// Records how long it took to launch the process.
+pub(crate) fn record_exec(duration: Duration) {
+    SPAWN_TIMINGS.with(|timings| {
+        if let Some(ref mut timings) = *timings.borrow_mut() {
+            timings.exec.push(duration);
+        }
+    });
+}
+
+/// Records how long it took until the launched process connected back.
+pub(crate) fn record_handshake(duration: Duration) {
+    SPAWN_TIMINGS.with(|timings| {
+        if let Some(ref mut timings) = *timings.borrow_mut() {
+            timings.handshake.push(duration);
+        }
+    });
+}
+
+/// Configures what [`measure_spawn_overhead`](fn.measure_spawn_overhead.html)
+/// measures.
+pub struct BenchConfig {
+    iterations: usize,
+    builder: Option<ConfigureFunc<Builder>>,
+    pool_builder: Option<ConfigureFunc<PoolBuilder>>,
+}
+
+impl fmt::Debug for BenchConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BenchConfig")
+            .field("iterations", &self.iterations)
+            .finish()
+    }
+}
+
+impl Default for BenchConfig {
+    fn default() -> BenchConfig {
+        BenchConfig::new()
+    }
+}
+
+impl BenchConfig {
+    /// Creates the default configuration which runs 20 iterations.
+    pub fn new() -> BenchConfig {
+        BenchConfig {
+            iterations: 20,
+            builder: None,
+            pool_builder: None,
+        }
+    }
+
+    /// Sets how many spawns and pooled calls are measured.
+    pub fn iterations(&mut self, iterations: usize) -> &mut Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// Configures the builder of every measured spawn.
+    pub fn builder<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&mut Builder) + Send + Sync + 'static,
+    {
+        self.builder = Some(Box::new(f));
+        self
+    }
+
+    /// Configures the builder of the pool used to measure pooled calls.
+    pub fn pool_builder<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&mut PoolBuilder) + Send + Sync + 'static,
+    {
+        self.pool_builder = Some(Box::new(f));
+        self
+    }
+}
+
+/// Statistics over the samples of a measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    samples: usize,
+    min: Duration,
+    max: Duration,
+    mean: Duration,
+    median: Duration,
+}
+
+impl Stats {
+    fn from_samples(mut samples: Vec<Duration>) -> Stats {
+        samples.sort();
+        let total: Duration = samples.iter().sum();
+        Stats {
+            samples: samples.len(),
+            min: samples.first().copied().unwrap_or_default(),
+            max: samples.last().copied().unwrap_or_default(),
+            mean: total / samples.len().max(1) as u32,
+            median: samples.get(samples.len() / 2).copied().unwrap_or_default(),
+        }
+    }
+
+    /// The number of samples.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// The fastest sample.
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    /// The slowest sample.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// The average of all samples.
+    pub fn mean(&self) -> Duration {
+        self.mean
+    }
+
+    /// The median of all samples.
+    pub fn median(&self) -> Duration {
+        self.median
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "median {:?}, mean {:?}, min {:?}, max {:?}",
+            self.median, self.mean, self.min, self.max
+        )
+    }
+}
+
+/// The overhead of spawning measured by
+/// [`measure_spawn_overhead`](fn.measure_spawn_overhead.html).
+#[derive(Debug, Clone, Copy)]
+pub struct SpawnOverhead {
+    exec: Stats,
+    handshake: Stats,
+    round_trip: Stats,
+    pool_dispatch: Stats,
+}
+
+impl SpawnOverhead {
+    /// The time it takes to launch the process.
+    pub fn exec(&self) -> Stats {
+        self.exec
+    }
+
+    /// The time from the launch until the process connected back.
+    ///
+    /// This includes everything the process runs before it reaches
+    /// [`init`](../fn.init.html).
+    pub fn handshake(&self) -> Stats {
+        self.handshake
+    }
+
+    /// The time of a complete spawn of a function that receives and returns
+    /// a single byte, until the result was joined.
+    pub fn round_trip(&self) -> Stats {
+        self.round_trip
+    }
+
+    /// The time of a call with a single byte payload in a warm pool.
+    pub fn pool_dispatch(&self) -> Stats {
+        self.pool_dispatch
+    }
+}
+
+impl fmt::Display for SpawnOverhead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "exec:          {}", self.exec)?;
+        writeln!(f, "handshake:     {}", self.handshake)?;
+        writeln!(f, "round trip:    {}", self.round_trip)?;
+        write!(f, "pool dispatch: {}", self.pool_dispatch)
+    }
+}
+
+/// Resets the recorded timings when measuring ends.
+struct TimingsGuard;
+
+impl Drop for TimingsGuard {
+    fn drop(&mut self) {
+        SPAWN_TIMINGS.with(|timings| timings.borrow_mut().take());
+    }
+}
+
+/// Measures the overhead of spawns and pooled calls.
+///
+/// This runs the configured number of spawns one after another on the
+/// current thread, followed by the same number of calls in a pool with a
+/// single worker.  Spawns are measured as configured with
+/// [`BenchConfig::builder`](struct.BenchConfig.html#method.builder) so the
+/// numbers reflect the real cost of the configuration.
+pub fn measure_spawn_overhead(config: &BenchConfig) -> Result<SpawnOverhead, SpawnError> {
+    let _guard = TimingsGuard;
+    SPAWN_TIMINGS.with(|timings| *timings.borrow_mut() = Some(SpawnTimings::default()));
+
+    let mut round_trip = Vec::with_capacity(config.iterations);
+    for _ in 0..config.iterations {
+        let mut builder = Builder::new();
+        if let Some(ref configure) = config.builder {
+            configure(&mut builder);
+        }
+        let started = Instant::now();
+        builder.spawn(1u8, |x: u8| x).join()?;
+        round_trip.push(started.elapsed());
+    }
+    let timings = SPAWN_TIMINGS
+        .with(|timings| timings.borrow_mut().take())
+        .unwrap_or_default();
+
+    let mut builder = Pool::builder(1);
+    if let Some(ref configure) = config.pool_builder {
+        configure(&mut builder);
+    }
+    let pool = builder.build()?;
+    // the first call also waits for the worker to start up
+    pool.spawn(1u8, |x: u8| x).join()?;
+    let mut pool_dispatch = Vec::with_capacity(config.iterations);
+    for _ in 0..config.iterations {
+        let started = Instant::now();
+        pool.spawn(1u8, |x: u8| x).join()?;
+        pool_dispatch.push(started.elapsed());
+    }
+
+    Ok(SpawnOverhead {
+        exec: Stats::from_samples(timings.exec),
+        handshake: Stats::from_samples(timings.handshake),
+        round_trip: Stats::from_samples(round_trip),
+        pool_dispatch: Stats::from_samples(pool_dispatch),
+    })
+}
@@ -0,0 +1,201 @@
+//! Low-level access to marshalled function calls.
+//!
+//! [`spawn`](../fn.spawn.html) and [`Pool`](../struct.Pool.html) are built on
+//! a core that turns a function pointer into a [`Call`](struct.Call.html)
+//! which can be executed by another process running the same executable.
+//! This module exposes that core so that frameworks can bring their own
+//! process management, scheduling or transport and still use procspawn to
+//! marshal functions, arguments and return values.
+//!
+//! A call is created together with an [`ArgsSender`](struct.ArgsSender.html)
+//! which sends the arguments once and turns into a
+//! [`ResultReceiver`](struct.ResultReceiver.html) for the return value.
+//! The call itself has to be delivered to the executing process over an
+//! [`ipc-channel`](https://crates.io/crates/ipc-channel) channel as it
+//! carries the channels for the arguments and the return value:
+//!
+//! ```rust,no_run
+//! use procspawn::call::Call;
+//!
+//! let (call, args) = Call::new(|(a, b): (u32, u32)| a + b);
+//! // deliver the call, here by spawning a process which executes it.
+//! procspawn::spawn(call, |call| unsafe { call.execute() }.unwrap());
+//! let result = args.send((1, 2)).unwrap().recv().unwrap();
+//! assert_eq!(result, 3);
+//! ```
+//!
+//! The serialized form of a call carries the [`VERSION`](constant.VERSION.html)
+//! of this API and calls from an incompatible version are refused.
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use ipc_channel::ipc::{self, IpcReceiver, IpcSender};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::chunked::{self, Frame};
+use crate::codec::{create_codec, default_codec, with_codec, Codec, Coded};
+use crate::core::{assert_spawn_okay, max_message_size, MarshalledCall, ReturnMessage};
+use crate::error::SpawnError;
+use crate::proc::{send_args, unpack_result};
+use crate::serde::with_ipc_mode;
+
+/// The version of the serialized form of a [`Call`](struct.Call.html).
+///
+/// This is bumped whenever calls created by one version of procspawn can no
+/// longer be executed by another.
+pub const VERSION: u32 = 1;
+
+/// A function call that can be executed by another process.
+///
+/// Calls are created with [`new`](#method.new) and executed with
+/// [`execute`](#method.execute).  They can only be serialized into
+/// [`ipc-channel`](https://crates.io/crates/ipc-channel) messages.
+#[derive(Serialize, Deserialize)]
+pub struct Call {
+    version: u32,
+    inner: MarshalledCall,
+}
+
+impl fmt::Debug for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Call")
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+impl Call {
+    /// Marshals a call to the given function.
+    ///
+    /// The call uses the codec configured with
+    /// [`ProcConfig::codec`](../struct.ProcConfig.html#method.codec) and
+    /// the limit configured with
+    /// [`ProcConfig::max_message_size`](../struct.ProcConfig.html#method.max_message_size).
+    ///
+    /// # Panics
+    ///
+    /// This panics if procspawn was not initialized or if the IPC channels
+    /// for the call cannot be created.
+    pub fn new<A, R>(func: fn(A) -> R) -> (Call, ArgsSender<A, R>)
+    where
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned,
+    {
+        assert_spawn_okay();
+        let (args_tx, args_rx) = ipc::channel().unwrap();
+        let (return_tx, return_rx) = ipc::channel().unwrap();
+        let codec_factory = default_codec();
+        let max_message_size = max_message_size();
+        let call = Call {
+            version: VERSION,
+            inner: MarshalledCall::marshal::<A, R>(
+                func,
+                args_rx,
+                return_tx,
+                codec_factory,
+                None,
+                max_message_size,
+                None,
+            ),
+        };
+        let args = ArgsSender {
+            args_tx,
+            return_rx,
+            codec: create_codec(codec_factory, None, max_message_size),
+            _marker: PhantomData,
+        };
+        (call, args)
+    }
+
+    /// Returns the version of the API that created the call.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Executes the call in this process.
+    ///
+    /// This blocks until the arguments arrived and the return value was
+    /// sent back.  Panics of the function are caught and reported to the
+    /// [`ResultReceiver`](struct.ResultReceiver.html).  An error is only
+    /// returned if the call was created by an incompatible version.
+    ///
+    /// # Safety
+    ///
+    /// The function is located by its offset in the executable so the call
+    /// must be executed by a process running the very same build of the
+    /// executable that created it, and that process must have called
+    /// [`init`](../fn.init.html).  Unless the `safe-shared-libraries` feature
+    /// is enabled functions from shared libraries must be loaded at the same
+    /// place as in the creating process.
+    pub unsafe fn execute(self) -> Result<(), SpawnError> {
+        if self.version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "call has version {} but version {} is supported",
+                    self.version, VERSION
+                ),
+            )
+            .into());
+        }
+        self.inner.call(true);
+        Ok(())
+    }
+}
+
+/// Sends the arguments of a [`Call`](struct.Call.html).
+pub struct ArgsSender<A, R> {
+    args_tx: IpcSender<Frame<Coded<A>>>,
+    return_rx: IpcReceiver<Frame<ReturnMessage<R>>>,
+    codec: Option<Arc<dyn Codec>>,
+    _marker: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R> fmt::Debug for ArgsSender<A, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArgsSender").finish()
+    }
+}
+
+impl<A: Serialize, R> ArgsSender<A, R> {
+    /// Sends the arguments and returns the receiver for the return value.
+    ///
+    /// Large arguments are only sent as fast as the executing process reads
+    /// them, so this can block until the call is executed.
+    pub fn send(self, args: A) -> Result<ResultReceiver<R>, SpawnError> {
+        with_codec(self.codec.as_ref(), || {
+            with_ipc_mode(|| send_args(&self.args_tx, args))
+        })?;
+        Ok(ResultReceiver {
+            return_rx: self.return_rx,
+            codec: self.codec,
+        })
+    }
+}
+
+/// Receives the return value of a [`Call`](struct.Call.html).
+pub struct ResultReceiver<R> {
+    return_rx: IpcReceiver<Frame<ReturnMessage<R>>>,
+    codec: Option<Arc<dyn Codec>>,
+}
+
+impl<R> fmt::Debug for ResultReceiver<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResultReceiver").finish()
+    }
+}
+
+impl<R: Serialize + DeserializeOwned> ResultReceiver<R> {
+    /// Waits for the return value of the call.
+    ///
+    /// If the executing process goes away before it sent the return value
+    /// this fails with an error.
+    pub fn recv(self) -> Result<R, SpawnError> {
+        let msg = with_codec(self.codec.as_ref(), || {
+            with_ipc_mode(|| chunked::recv(&self.return_rx))
+        })?;
+        unpack_result(msg, None, self.codec.as_ref(), None)
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::Mutex;
+
+use ipc_channel::ipc::IpcSender;
+use serde::{Deserialize, Serialize};
+
+/// The capture of the call that is running in this process.
+static CURRENT_CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+/// The output a pooled call wrote to stdout and stderr.
+///
+/// Output is only captured for pools built with
+/// [`PoolBuilder::capture_output`](struct.PoolBuilder.html#method.capture_output)
+/// and returned by
+/// [`JoinHandle::join_with_output`](struct.JoinHandle.html#method.join_with_output).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedOutput {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl CapturedOutput {
+    /// The bytes written to stdout.
+    pub fn stdout(&self) -> &[u8] {
+        &self.stdout
+    }
+
+    /// The bytes written to stderr.
+    pub fn stderr(&self) -> &[u8] {
+        &self.stderr
+    }
+}
+
+#[cfg(unix)]
+mod redirect {
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::os::unix::io::{FromRawFd, RawFd};
+    use std::thread::{self, JoinHandle};
+
+    /// Redirects a standard stream into a pipe that is read on a thread.
+    pub struct Redirect {
+        fd: RawFd,
+        saved: RawFd,
+        reader: JoinHandle<Vec<u8>>,
+    }
+
+    impl Redirect {
+        pub fn start(fd: RawFd) -> io::Result<Redirect> {
+            let mut fds = [0; 2];
+            unsafe {
+                if libc::pipe(fds.as_mut_ptr()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let saved = libc::dup(fd);
+                if saved < 0 || libc::dup2(fds[1], fd) < 0 {
+                    let err = io::Error::last_os_error();
+                    libc::close(fds[0]);
+                    libc::close(fds[1]);
+                    if saved >= 0 {
+                        libc::close(saved);
+                    }
+                    return Err(err);
+                }
+                libc::close(fds[1]);
+                let mut pipe = File::from_raw_fd(fds[0]);
+                let reader = thread::Builder::new()
+                    .name("procspawn-capture".into())
+                    .spawn(move || {
+                        let mut buf = Vec::new();
+                        pipe.read_to_end(&mut buf).ok();
+                        buf
+                    })?;
+                Ok(Redirect { fd, saved, reader })
+            }
+        }
+
+        /// Restores the stream and returns what was written to it.
+        pub fn finish(self) -> Vec<u8> {
+            io::stdout().flush().ok();
+            io::stderr().flush().ok();
+            unsafe {
+                // this closes the last write end of the pipe so the reader
+                // sees the end of it.
+                libc::dup2(self.saved, self.fd);
+                libc::close(self.saved);
+            }
+            self.reader.join().unwrap_or_default()
+        }
+    }
+}
+
+/// A running capture of stdout and stderr.
+struct Capture {
+    sender: IpcSender<CapturedOutput>,
+    #[cfg(unix)]
+    redirects: Option<(redirect::Redirect, redirect::Redirect)>,
+}
+
+impl Capture {
+    fn start(sender: IpcSender<CapturedOutput>) -> Capture {
+        #[cfg(unix)]
+        {
+            use std::io::Write;
+            std::io::stdout().flush().ok();
+            std::io::stderr().flush().ok();
+            let stdout = redirect::Redirect::start(libc::STDOUT_FILENO);
+            let stderr = redirect::Redirect::start(libc::STDERR_FILENO);
+            let redirects = match (stdout, stderr) {
+                (Ok(stdout), Ok(stderr)) => Some((stdout, stderr)),
+                (Ok(stdout), Err(_)) => {
+                    stdout.finish();
+                    None
+                }
+                (Err(_), Ok(stderr)) => {
+                    stderr.finish();
+                    None
+                }
+                (Err(_), Err(_)) => None,
+            };
+            Capture { sender, redirects }
+        }
+        #[cfg(not(unix))]
+        {
+            Capture { sender }
+        }
+    }
+
+    fn finish(self) {
+        #[cfg(unix)]
+        let output = match self.redirects {
+            Some((stdout, stderr)) => CapturedOutput {
+                stdout: stdout.finish(),
+                stderr: stderr.finish(),
+            },
+            None => CapturedOutput::default(),
+        };
+        #[cfg(not(unix))]
+        let output = CapturedOutput::default();
+        // the parent might have stopped waiting for the call.
+        self.sender.send(output).ok();
+    }
+}
+
+/// Ends the capture of the running call and sends the output to the
+/// parent.
+///
+/// This has to happen before the result is sent because the parent reads
+/// the output first.
+pub fn finish_capture() {
+    let capture = CURRENT_CAPTURE.lock().ok().and_then(|mut x| x.take());
+    if let Some(capture) = capture {
+        capture.finish();
+    }
+}
+
+/// Captures the output of a call until it's finished or the guard is
+/// dropped.
+pub struct CaptureGuard(());
+
+impl CaptureGuard {
+    pub fn install(sender: Option<IpcSender<CapturedOutput>>) -> CaptureGuard {
+        *CURRENT_CAPTURE.lock().unwrap() = sender.map(Capture::start);
+        CaptureGuard(())
+    }
+}
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        finish_capture();
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use ipc_channel::ipc::{
+    self, IpcReceiver, IpcSender, OpaqueIpcReceiver, OpaqueIpcSender, TryRecvError,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::error::SpawnError;
+use crate::serde::with_ipc_mode;
+
+/// The channel to the parent of the call that is running in this process.
+static PARENT_SENDER: Mutex<Option<OpaqueIpcSender>> = Mutex::new(None);
+
+/// Creates a channel that can be passed to spawned processes.
+///
+/// Both ends can be sent as (part of) the arguments of a spawned function,
+/// the other end stays in the current process.  For communication in both
+/// directions create two channels:
+///
+/// ```rust,no_run
+/// let (requests_tx, requests_rx) = procspawn::channel::<u32>().unwrap();
+/// let (responses_tx, responses_rx) = procspawn::channel::<u32>().unwrap();
+/// let handle = procspawn::spawn((requests_rx, responses_tx), |(rx, tx)| {
+///     while let Ok(x) = rx.recv() {
+///         tx.send(x * 2).unwrap();
+///     }
+/// });
+///
+/// requests_tx.send(21).unwrap();
+/// assert_eq!(responses_rx.recv().unwrap(), 42);
+/// drop(requests_tx);
+/// handle.join().unwrap();
+/// ```
+///
+/// This is a thin wrapper around the channels of
+/// [`ipc-channel`](https://crates.io/crates/ipc-channel).  Unlike with raw
+/// IPC channels, values are sent the same way as arguments so that types
+/// from the [`serde`](serde/index.html) module such as file descriptors
+/// work, and errors are reported as [`SpawnError`](struct.SpawnError.html).
+pub fn channel<T: Serialize + DeserializeOwned>() -> Result<(Sender<T>, Receiver<T>), SpawnError> {
+    let (tx, rx) = ipc::channel()?;
+    Ok((Sender { inner: tx }, Receiver { inner: rx }))
+}
+
+/// Returns a sender to the parent of the running spawned function.
+///
+/// This lets a spawned function send messages such as events or log
+/// records to its parent while it's running.  The final value is still
+/// returned through the join handle.  The channel has to be enabled with
+/// [`Builder::with_parent_channel`](struct.Builder.html#method.with_parent_channel)
+/// or [`PoolBuilder::with_parent_channel`](struct.PoolBuilder.html#method.with_parent_channel)
+/// and the parent receives the messages through
+/// [`JoinHandle::child_receiver`](struct.JoinHandle.html#method.child_receiver):
+///
+/// ```rust,no_run
+/// use procspawn::Builder;
+///
+/// let mut handle = Builder::new().with_parent_channel().spawn((), |()| {
+///     let parent = procspawn::parent::<String>().unwrap();
+///     parent.send("starting".into()).unwrap();
+///     42
+/// });
+/// let messages = handle.child_receiver::<String>().unwrap();
+/// assert_eq!(messages.recv().unwrap(), "starting");
+/// assert_eq!(handle.join().unwrap(), 42);
+/// ```
+///
+/// This is `None` outside of spawned functions and if the channel was not
+/// enabled.  The type of the messages has to match the type the parent
+/// receives.
+pub fn parent<T: Serialize + DeserializeOwned>() -> Option<Sender<T>> {
+    let sender = PARENT_SENDER.lock().unwrap().clone()?;
+    Some(Sender { inner: sender.to() })
+}
+
+/// Creates the channel to the parent of a call if it's enabled.
+pub(crate) fn parent_channel(
+    enabled: bool,
+) -> Result<(Option<OpaqueIpcSender>, Option<OpaqueIpcReceiver>), SpawnError> {
+    if !enabled {
+        return Ok((None, None));
+    }
+    let (sender, receiver) = ipc::channel::<()>()?;
+    Ok((Some(sender.to_opaque()), Some(receiver.to_opaque())))
+}
+
+/// Turns the receiving end of a channel to a parent into a typed receiver.
+pub(crate) fn typed_receiver<T: Serialize + DeserializeOwned>(
+    receiver: OpaqueIpcReceiver,
+) -> Receiver<T> {
+    Receiver {
+        inner: receiver.to(),
+    }
+}
+
+/// Makes a channel to the parent the current one until the guard is
+/// dropped.
+pub(crate) struct ParentGuard(());
+
+impl ParentGuard {
+    pub fn install(sender: Option<OpaqueIpcSender>) -> ParentGuard {
+        *PARENT_SENDER.lock().unwrap() = sender;
+        ParentGuard(())
+    }
+}
+
+impl Drop for ParentGuard {
+    fn drop(&mut self) {
+        if let Ok(mut sender) = PARENT_SENDER.lock() {
+            *sender = None;
+        }
+    }
+}
+
+/// The sending end of a [`channel`](fn.channel.html).
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "T: Serialize + for<'x> Deserialize<'x>")]
+pub struct Sender<T> {
+    inner: IpcSender<T>,
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sender").finish()
+    }
+}
+
+impl<T: Serialize> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Serialize> Sender<T> {
+    /// Sends a value to the receiving end.
+    pub fn send(&self, value: T) -> Result<(), SpawnError> {
+        with_ipc_mode(|| self.inner.send(value)).map_err(Into::into)
+    }
+}
+
+/// The receiving end of a [`channel`](fn.channel.html).
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "T: Serialize + for<'x> Deserialize<'x>")]
+pub struct Receiver<T> {
+    inner: IpcReceiver<T>,
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Receiver").finish()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Receiver<T> {
+    /// Blocks until a value is received.
+    ///
+    /// Once all senders are gone this fails with an error for which
+    /// [`is_remote_close`](struct.SpawnError.html#method.is_remote_close)
+    /// returns `true`.
+    pub fn recv(&self) -> Result<T, SpawnError> {
+        with_ipc_mode(|| self.inner.recv()).map_err(Into::into)
+    }
+
+    /// Returns a value if one is ready, otherwise `None`.
+    pub fn try_recv(&self) -> Result<Option<T>, SpawnError> {
+        match with_ipc_mode(|| self.inner.try_recv()) {
+            Ok(value) => Ok(Some(value)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Like `recv` but fails with a timeout error if no value arrived in
+    /// time.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, SpawnError> {
+        match with_ipc_mode(|| self.inner.try_recv_timeout(timeout)) {
+            Ok(value) => Ok(value),
+            Err(TryRecvError::Empty) => Err(SpawnError::new_timeout()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! Transfers large messages in chunks.
+//!
+//! Arguments and return values are normally sent as a single IPC message
+//! which means the sender has to hold the entire serialized message in
+//! memory and the receiver has to read it in one go.  Messages that grow
+//! beyond a threshold are instead sent as a sequence of chunks while they
+//! are being serialized.  The receiver deserializes directly from the
+//! chunks as they come in and the sender blocks while the channel is full,
+//! so neither process needs more than a bounded buffer.
+use std::any::type_name;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use ipc_channel::ipc::{IpcError, IpcReceiver, IpcSender, TryRecvError};
+use ipc_channel::ErrorKind as BincodeErrorKind;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::codec::BytesVisitor;
+use crate::core::log_stderr;
+
+/// Messages above this size are sent in chunks by default.
+pub const DEFAULT_CHUNK_THRESHOLD: usize = 16 * 1024 * 1024;
+
+/// The size of the individual chunks.
+///
+/// This is kept below the size of a single datagram so that a chunk does not
+/// need to be fragmented by ipc-channel.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+static CHUNK_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_CHUNK_THRESHOLD);
+
+/// Enables IPC debugging in all processes if set to anything but `0`.
+pub const DEBUG_IPC_ENV_NAME: &str = "PROCSPAWN_DEBUG_IPC";
+
+static DEBUG_IPC: AtomicBool = AtomicBool::new(false);
+
+pub fn set_chunk_threshold(threshold: usize) {
+    CHUNK_THRESHOLD.store(threshold, Ordering::SeqCst);
+}
+
+fn chunk_threshold() -> usize {
+    CHUNK_THRESHOLD.load(Ordering::SeqCst)
+}
+
+pub fn set_debug_ipc(enabled: bool) {
+    DEBUG_IPC.store(enabled, Ordering::SeqCst);
+}
+
+fn debug_ipc() -> bool {
+    DEBUG_IPC.load(Ordering::Relaxed)
+}
+
+/// Logs a message that crossed the process boundary to stderr.
+fn log_message<M>(
+    direction: &str,
+    size: Option<usize>,
+    started: Instant,
+    error: Option<&dyn fmt::Debug>,
+) {
+    let size = match size {
+        Some(size) => format!("{} bytes", size),
+        None => "unknown size".into(),
+    };
+    let outcome = match error {
+        Some(err) => format!(" failed: {:?}", err),
+        None => String::new(),
+    };
+    log_stderr(format_args!(
+        "procspawn ipc: pid={} {} {} ({}) in {:?}{}",
+        process::id(),
+        direction,
+        type_name::<M>(),
+        size,
+        started.elapsed(),
+        outcome
+    ));
+}
+
+type ChunkSink = Box<dyn FnMut(&[u8]) -> Result<(), ipc_channel::Error>>;
+
+thread_local! {
+    static CHUNK_SINK: RefCell<Option<ChunkSink>> = const { RefCell::new(None) };
+    static SENT_SIZE: Cell<usize> = const { Cell::new(0) };
+    static RECEIVED_SIZE: Cell<Option<usize>> = const { Cell::new(None) };
+    static LAST_RECEIVED_SIZE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// A message on a channel that supports chunked transfers.
+///
+/// On the wire a message is either sent as is, as an inline byte buffer or
+/// as a sequence of chunks followed by an end marker.  The end marker
+/// carries the IPC handles of the message.
+pub enum Frame<M> {
+    Message(M),
+    Chunk(Vec<u8>),
+    End,
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ByteBuf, D::Error> {
+        deserializer.deserialize_byte_buf(BytesVisitor).map(ByteBuf)
+    }
+}
+
+impl<M: Serialize> Serialize for Frame<M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Frame::Message(ref msg) => {
+                let sink = match CHUNK_SINK.with(|x| x.borrow_mut().take()) {
+                    Some(sink) => sink,
+                    None => {
+                        return serializer.serialize_newtype_variant("Frame", 0, "Message", msg)
+                    }
+                };
+                let mut writer = ChunkWriter {
+                    sink,
+                    threshold: chunk_threshold(),
+                    buf: Vec::new(),
+                    spilled: false,
+                    written: 0,
+                };
+                bincode::serialize_into(&mut writer, msg).map_err(ser::Error::custom)?;
+                SENT_SIZE.with(|x| x.set(writer.written));
+                match writer.finish().map_err(ser::Error::custom)? {
+                    Some(buf) => {
+                        serializer.serialize_newtype_variant("Frame", 1, "Inline", &Bytes(&buf))
+                    }
+                    None => serializer.serialize_unit_variant("Frame", 3, "End"),
+                }
+            }
+            Frame::Chunk(ref bytes) => {
+                serializer.serialize_newtype_variant("Frame", 2, "Chunk", &Bytes(bytes))
+            }
+            Frame::End => serializer.serialize_unit_variant("Frame", 3, "End"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Frame")]
+enum WireFrame<M> {
+    Message(M),
+    Inline(ByteBuf),
+    Chunk(ByteBuf),
+    End,
+}
+
+impl<'de, M: de::DeserializeOwned> Deserialize<'de> for Frame<M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Frame<M>, D::Error> {
+        Ok(match WireFrame::<M>::deserialize(deserializer)? {
+            WireFrame::Message(msg) => Frame::Message(msg),
+            // deserialized right away so that the IPC handles of the
+            // message are still available.
+            WireFrame::Inline(ByteBuf(buf)) => {
+                RECEIVED_SIZE.with(|x| x.set(Some(buf.len())));
+                Frame::Message(bincode::deserialize(&buf).map_err(de::Error::custom)?)
+            }
+            WireFrame::Chunk(ByteBuf(buf)) => Frame::Chunk(buf),
+            WireFrame::End => Frame::End,
+        })
+    }
+}
+
+/// Buffers a serialized message and starts sending it in chunks once it
+/// grows beyond the threshold.
+struct ChunkWriter {
+    sink: ChunkSink,
+    threshold: usize,
+    buf: Vec<u8>,
+    spilled: bool,
+    written: usize,
+}
+
+impl ChunkWriter {
+    fn send_chunks(&mut self) -> io::Result<()> {
+        let full = self.buf.len() / CHUNK_SIZE * CHUNK_SIZE;
+        for chunk in self.buf[..full].chunks(CHUNK_SIZE) {
+            (self.sink)(chunk).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+        self.buf.drain(..full);
+        Ok(())
+    }
+
+    /// Returns the buffered message unless it was sent in chunks.
+    fn finish(mut self) -> io::Result<Option<Vec<u8>>> {
+        if !self.spilled {
+            return Ok(Some(self.buf));
+        }
+        self.send_chunks()?;
+        if !self.buf.is_empty() {
+            (self.sink)(&self.buf).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+        Ok(None)
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        self.written += data.len();
+        if !self.spilled && self.buf.len() > self.threshold {
+            self.spilled = true;
+        }
+        if self.spilled && self.buf.len() >= CHUNK_SIZE {
+            self.send_chunks()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends a message, in chunks if it is large.
+///
+/// Returns the size of the serialized message.  Messages that are sent in
+/// chunks cannot carry IPC handles as the receiver deserializes them before
+/// the handles arrive.
+pub fn send<M: Serialize>(
+    sender: &IpcSender<Frame<M>>,
+    msg: M,
+) -> Result<usize, ipc_channel::Error> {
+    let chunk_sender = sender.clone().to_opaque().to::<Frame<()>>();
+    let sink: ChunkSink = Box::new(move |chunk| chunk_sender.send(Frame::Chunk(chunk.to_vec())));
+    CHUNK_SINK.with(|x| *x.borrow_mut() = Some(sink));
+    SENT_SIZE.with(|x| x.set(0));
+    let started = Instant::now();
+    let rv = sender.send(Frame::Message(msg));
+    CHUNK_SINK.with(|x| x.borrow_mut().take());
+    let size = SENT_SIZE.with(Cell::get);
+    if debug_ipc() {
+        match rv {
+            Ok(()) => log_message::<M>("sent", Some(size), started, None),
+            Err(ref err) => log_message::<M>("sending", None, started, Some(err)),
+        }
+    }
+    rv.map(|()| size)
+}
+
+/// Reads the chunks of a message.
+struct ChunkReader<'a, M> {
+    receiver: &'a IpcReceiver<Frame<M>>,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+    received: usize,
+}
+
+impl<M: Serialize + de::DeserializeOwned> ChunkReader<'_, M> {
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        if self.done {
+            return Ok(false);
+        }
+        match self.receiver.recv() {
+            Ok(Frame::Chunk(buf)) => {
+                self.received += buf.len();
+                self.buf = buf;
+                self.pos = 0;
+                Ok(true)
+            }
+            Ok(Frame::End) => {
+                self.done = true;
+                Ok(false)
+            }
+            Ok(Frame::Message(_)) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected message in chunked transfer",
+            )),
+            Err(IpcError::Io(err)) => Err(err),
+            Err(IpcError::Bincode(err)) => Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(IpcError::Disconnected) => Err(io::ErrorKind::ConnectionReset.into()),
+        }
+    }
+
+    /// Skips over whatever is left of the message.
+    ///
+    /// Returns the size of the message.
+    fn finish(mut self) -> io::Result<usize> {
+        while self.next_chunk()? {}
+        Ok(self.received)
+    }
+}
+
+impl<M: Serialize + de::DeserializeOwned> Read for ChunkReader<'_, M> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.buf.len() {
+            if !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Returns the message and its size if it was sent in chunks.
+fn assemble<M: Serialize + de::DeserializeOwned>(
+    frame: Frame<M>,
+    receiver: &IpcReceiver<Frame<M>>,
+) -> Result<(M, Option<usize>), IpcError> {
+    match frame {
+        Frame::Message(msg) => Ok((msg, None)),
+        Frame::Chunk(buf) => {
+            let mut reader = ChunkReader {
+                receiver,
+                received: buf.len(),
+                buf,
+                pos: 0,
+                done: false,
+            };
+            let msg = bincode::deserialize_from(&mut reader).map_err(IpcError::Bincode)?;
+            let received = reader.finish().map_err(IpcError::Io)?;
+            Ok((msg, Some(received)))
+        }
+        Frame::End => Err(IpcError::Bincode(Box::new(BincodeErrorKind::Custom(
+            "unexpected end of chunked transfer".into(),
+        )))),
+    }
+}
+
+fn finish_receive<M, E: fmt::Debug>(rv: &Result<(M, Option<usize>), E>, started: Instant) {
+    // inline messages record their size while they are deserialized.
+    let inline_size = RECEIVED_SIZE.with(|x| x.take());
+    let size = match rv {
+        Ok((_, size)) => size.or(inline_size),
+        Err(_) => None,
+    };
+    LAST_RECEIVED_SIZE.with(|x| x.set(size));
+    if !debug_ipc() {
+        return;
+    }
+    match rv {
+        Ok(_) => log_message::<M>("received", size, started, None),
+        Err(err) => log_message::<M>("receiving", None, started, Some(err)),
+    }
+}
+
+/// Returns the size of the last message this thread received.
+pub fn received_size() -> Option<usize> {
+    LAST_RECEIVED_SIZE.with(|x| x.take())
+}
+
+/// Receives a message, reassembling it if it was sent in chunks.
+pub fn recv<M: Serialize + de::DeserializeOwned>(
+    receiver: &IpcReceiver<Frame<M>>,
+) -> Result<M, IpcError> {
+    let started = Instant::now();
+    let rv = receiver.recv().and_then(|frame| assemble(frame, receiver));
+    finish_receive(&rv, started);
+    rv.map(|(msg, _)| msg)
+}
+
+/// Like [`recv`] but does not block until the message starts to arrive.
+pub fn try_recv<M: Serialize + de::DeserializeOwned>(
+    receiver: &IpcReceiver<Frame<M>>,
+) -> Result<M, TryRecvError> {
+    let started = Instant::now();
+    let rv = receiver
+        .try_recv()
+        .and_then(|frame| assemble(frame, receiver).map_err(TryRecvError::IpcError));
+    if !matches!(rv, Err(TryRecvError::Empty)) {
+        finish_receive(&rv, started);
+    }
+    rv.map(|(msg, _)| msg)
+}
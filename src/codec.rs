@@ -0,0 +1,491 @@
+//! Pluggable serialization formats.
+//!
+//! By default arguments and return values are serialized with
+//! [`bincode`](https://github.com/servo/bincode) as part of the IPC messages.
+//! This is fast but some types cannot be represented in bincode.  Instead of
+//! wrapping every such type in [`Json`](../serde/struct.Json.html) a codec
+//! can be selected per spawn with `Builder::codec` or for all spawns with
+//! [`ProcConfig::codec`](../struct.ProcConfig.html#method.codec):
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "json")] {
+//! use procspawn::codec::Json;
+//!
+//! let handle = procspawn::Builder::new()
+//!     .codec::<Json>()
+//!     .spawn((1, 2), |(a, b)| a + b);
+//! # }
+//! ```
+//!
+//! The following codecs are available:
+//!
+//! * [`Bincode`](struct.Bincode.html): always available
+//! * [`Json`](struct.Json.html): requires the `json` feature
+//! * [`MessagePack`](struct.MessagePack.html): requires the `msgpack` feature
+//! * [`Cbor`](struct.Cbor.html): requires the `cbor` feature
+//! * [`Postcard`](struct.Postcard.html): requires the `postcard` feature
+//!
+//! Independently of the codec, payloads can be compressed with
+//! [`Compression`](enum.Compression.html) and limited in size with
+//! [`ProcConfig::max_message_size`](../struct.ProcConfig.html#method.max_message_size).
+//!
+//! Values serialized with a codec are serialized outside of IPC mode (see
+//! [`in_ipc_mode`](../serde/fn.in_ipc_mode.html)) as they can not carry IPC
+//! handles.  For instance a [`Shmem`](../serde/struct.Shmem.html) is sent as
+//! its raw bytes.
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::serde::without_ipc_mode;
+
+/// The error type of codecs.
+pub type CodecError = Box<dyn Error + Send + Sync>;
+
+/// A serialization format for arguments and return values.
+///
+/// Codecs are type erased so that they can be selected at runtime.  The
+/// process running the function creates its own instance of the codec
+/// through `Default`, so codecs cannot carry configuration.
+pub trait Codec: Send + Sync + 'static {
+    /// Serializes a value into bytes.
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError>;
+
+    /// Deserializes a value from bytes.
+    ///
+    /// The codec creates a deserializer for the bytes and passes it to the
+    /// given function which performs the actual deserialization.
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError>;
+}
+
+/// Encodes values with bincode.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        // `bincode::serialize` serializes twice to size the buffer which
+        // would hand out IPC channels twice.
+        let mut rv = Vec::new();
+        bincode::serialize_into(&mut rv, value)?;
+        Ok(rv)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError> {
+        use bincode::Options;
+        let mut de = bincode::Deserializer::from_slice(
+            bytes,
+            bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .allow_trailing_bytes(),
+        );
+        Ok(visit(&mut <dyn erased_serde::Deserializer>::erase(
+            &mut de,
+        ))?)
+    }
+}
+
+/// Encodes values as JSON.
+///
+/// This requires the `json` feature.
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError> {
+        let mut de = serde_json::Deserializer::from_slice(bytes);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))?;
+        Ok(de.end()?)
+    }
+}
+
+/// Encodes values as MessagePack.
+///
+/// This requires the `msgpack` feature.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError> {
+        let mut de = rmp_serde::Deserializer::from_read_ref(bytes);
+        Ok(visit(&mut <dyn erased_serde::Deserializer>::erase(
+            &mut de,
+        ))?)
+    }
+}
+
+/// Encodes values as CBOR.
+///
+/// This requires the `cbor` feature.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        Ok(serde_cbor::to_vec(&value)?)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError> {
+        let mut de = serde_cbor::Deserializer::from_slice(bytes);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))?;
+        Ok(de.end()?)
+    }
+}
+
+/// Encodes values with postcard.
+///
+/// Postcard encodes integers with a variable length which makes messages
+/// smaller than bincode's for most data.  This makes it a good alternative
+/// default for spawns that pass around a lot of data:
+///
+/// ```rust,no_run
+/// procspawn::ProcConfig::new()
+///     .codec::<procspawn::codec::Postcard>()
+///     .init();
+/// ```
+///
+/// This requires the `postcard` feature.
+#[cfg(feature = "postcard")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Postcard;
+
+#[cfg(feature = "postcard")]
+impl Codec for Postcard {
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        Ok(postcard::to_allocvec(value)?)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError> {
+        let mut de = postcard::Deserializer::from_bytes(bytes);
+        Ok(visit(&mut <dyn erased_serde::Deserializer>::erase(
+            &mut de,
+        ))?)
+    }
+}
+
+/// A compression algorithm for arguments and return values.
+///
+/// Compression is enabled per spawn with `Builder::compress` or per pool
+/// with `PoolBuilder::compress`.  Only payloads above a size threshold are
+/// compressed, smaller ones are sent as is.  The parent passes the settings
+/// to the child together with the call so both sides always agree.
+///
+/// Compressed payloads are encoded like values of a [codec](index.html), so
+/// the same restrictions apply.  If no codec is selected
+/// [`Bincode`](struct.Bincode.html) is used.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Compresses with LZ4.  This is very fast but compresses less.
+    ///
+    /// This requires the `lz4` feature.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Compresses with zstd at the given level.
+    ///
+    /// This requires the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    #[allow(unused_variables)]
+    fn compress(self, bytes: &[u8]) -> Result<Vec<u8>, CodecError> {
+        match self {
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(bytes)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => Ok(zstd::bulk::compress(bytes, level)?),
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>, CodecError> {
+        match self {
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(lz4_flex::decompress_size_prepended(bytes)?),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => Ok(zstd::stream::decode_all(bytes)?),
+        }
+    }
+}
+
+/// Compression settings that are passed to the child.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub(crate) struct CompressionConfig {
+    pub compression: Compression,
+    pub threshold: usize,
+}
+
+/// Wraps a codec and compresses payloads above the threshold.
+///
+/// The payload is prefixed with a byte that tells if it's compressed.
+struct Compressed {
+    inner: Arc<dyn Codec>,
+    config: CompressionConfig,
+}
+
+impl Codec for Compressed {
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        let bytes = self.inner.encode(value)?;
+        if bytes.len() <= self.config.threshold {
+            let mut rv = Vec::with_capacity(bytes.len() + 1);
+            rv.push(0);
+            rv.extend_from_slice(&bytes);
+            return Ok(rv);
+        }
+        let mut rv = vec![1];
+        rv.extend_from_slice(&self.config.compression.compress(&bytes)?);
+        Ok(rv)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError> {
+        match bytes.split_first() {
+            Some((0, rest)) => self.inner.decode(rest, visit),
+            Some((1, rest)) => {
+                let bytes = self.config.compression.decompress(rest)?;
+                self.inner.decode(&bytes, visit)
+            }
+            _ => Err("invalid compressed payload".into()),
+        }
+    }
+}
+
+/// Wraps a codec and rejects payloads above the maximum message size.
+///
+/// Rejected payloads are recorded so that the failure can be reported as
+/// [`SpawnError::is_message_too_large`](../struct.SpawnError.html#method.is_message_too_large)
+/// once the serialization error makes it out of serde.
+struct Limited {
+    inner: Arc<dyn Codec>,
+    limit: usize,
+}
+
+impl Limited {
+    fn check(&self, size: usize) -> Result<(), CodecError> {
+        if size <= self.limit {
+            return Ok(());
+        }
+        OVERSIZED_MESSAGE.with(|x| x.set(Some((size, self.limit))));
+        Err(format!(
+            "message of {} bytes exceeds the limit of {} bytes",
+            size, self.limit
+        )
+        .into())
+    }
+}
+
+impl Codec for Limited {
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        let bytes = self.inner.encode(value)?;
+        self.check(bytes.len())?;
+        Ok(bytes)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError> {
+        self.check(bytes.len())?;
+        self.inner.decode(bytes, visit)
+    }
+}
+
+/// Creates codecs.  This is marshalled to the child so it uses the same codec.
+pub(crate) type CodecFactory = fn(()) -> Arc<dyn Codec>;
+
+/// Creates the codec for a call, if any.
+pub(crate) fn create_codec(
+    factory: Option<CodecFactory>,
+    compression: Option<CompressionConfig>,
+    max_message_size: Option<usize>,
+) -> Option<Arc<dyn Codec>> {
+    let mut codec = factory.map(|f| f(()));
+    if let Some(config) = compression {
+        codec = Some(Arc::new(Compressed {
+            inner: codec.unwrap_or_else(|| Arc::new(Bincode)),
+            config,
+        }));
+    }
+    if let Some(limit) = max_message_size {
+        codec = Some(Arc::new(Limited {
+            inner: codec.unwrap_or_else(|| Arc::new(Bincode)),
+            limit,
+        }));
+    }
+    codec
+}
+
+static DEFAULT_CODEC: Mutex<Option<CodecFactory>> = Mutex::new(None);
+
+fn make_codec<C: Codec + Default>(_: ()) -> Arc<dyn Codec> {
+    Arc::new(C::default())
+}
+
+pub(crate) fn codec_factory<C: Codec + Default>() -> CodecFactory {
+    make_codec::<C>
+}
+
+pub(crate) fn set_default_codec(factory: CodecFactory) {
+    *DEFAULT_CODEC.lock().unwrap() = Some(factory);
+}
+
+pub(crate) fn default_codec() -> Option<CodecFactory> {
+    *DEFAULT_CODEC.lock().unwrap()
+}
+
+thread_local! {
+    static CURRENT_CODEC: RefCell<Option<Arc<dyn Codec>>> = const { RefCell::new(None) };
+    static OVERSIZED_MESSAGE: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+struct ResetCodec(Option<Arc<dyn Codec>>);
+
+impl Drop for ResetCodec {
+    fn drop(&mut self) {
+        CURRENT_CODEC.with(|codec| *codec.borrow_mut() = self.0.take());
+    }
+}
+
+/// Invokes the function with the codec used for [`Coded`] values.
+pub(crate) fn with_codec<F: FnOnce() -> R, R>(codec: Option<&Arc<dyn Codec>>, f: F) -> R {
+    let old = CURRENT_CODEC.with(|current| current.replace(codec.cloned()));
+    OVERSIZED_MESSAGE.with(|x| x.set(None));
+    let _reset = ResetCodec(old);
+    f()
+}
+
+/// Returns the size and limit of the last message that was rejected for
+/// being too large within the current [`with_codec`] call.
+pub(crate) fn take_oversized_message() -> Option<(usize, usize)> {
+    OVERSIZED_MESSAGE.with(|x| x.take())
+}
+
+fn current_codec() -> Option<Arc<dyn Codec>> {
+    CURRENT_CODEC.with(|codec| codec.borrow().clone())
+}
+
+/// Wraps arguments and return values on the wire.
+///
+/// Without a codec the value is serialized as is, otherwise it's encoded
+/// with the codec into a byte buffer.
+pub(crate) struct Coded<T>(pub T);
+
+impl<T: Serialize> Serialize for Coded<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match current_codec() {
+            Some(codec) => {
+                let bytes = without_ipc_mode(|| codec.encode(&self.0))
+                    .map_err(|e| ser::Error::custom(e.to_string()))?;
+                serializer.serialize_bytes(&bytes)
+            }
+            None => self.0.serialize(serializer),
+        }
+    }
+}
+
+/// Deserializes an owned byte buffer.
+pub(crate) struct BytesVisitor;
+
+impl<'de> de::Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("encoded bytes")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(v)
+    }
+}
+
+impl<'de, T: de::DeserializeOwned> Deserialize<'de> for Coded<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Coded<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let codec = match current_codec() {
+            Some(codec) => codec,
+            None => return T::deserialize(deserializer).map(Coded),
+        };
+        let bytes = deserializer.deserialize_byte_buf(BytesVisitor)?;
+        let mut rv = None;
+        without_ipc_mode(|| {
+            codec.decode(&bytes, &mut |de| {
+                rv = Some(erased_serde::deserialize(de)?);
+                Ok(())
+            })
+        })
+        .map_err(|e| de::Error::custom(e.to_string()))?;
+        rv.map(Coded)
+            .ok_or_else(|| de::Error::custom("codec did not produce a value"))
+    }
+}
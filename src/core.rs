@@ -1,25 +1,70 @@
+#[cfg(feature = "safe-shared-libraries")]
+use std::collections::HashMap;
 use std::env;
 use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::io;
+use std::marker::PhantomData;
 use std::mem;
+#[cfg(feature = "safe-shared-libraries")]
+use std::ops::Range;
 use std::panic;
 use std::process;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+#[cfg(unix)]
+use std::time::Duration;
 
 #[cfg(feature = "safe-shared-libraries")]
-use findshlibs::{Avma, IterationControl, Segment, SharedLibrary};
+use findshlibs::{Segment, SharedLibrary};
 
-use ipc_channel::ipc::{self, IpcReceiver, IpcSender, OpaqueIpcReceiver, OpaqueIpcSender};
+use ipc_channel::ipc::{
+    self, IpcBytesSender, IpcReceiver, IpcSender, OpaqueIpcReceiver, OpaqueIpcSender,
+};
 use ipc_channel::ErrorKind as IpcErrorKind;
 use serde::{Deserialize, Serialize};
 
+use crate::audit::{set_audit_sink, AuditRecord, AuditSink};
+use crate::backend::{set_spawn_backend, SpawnBackend};
+use crate::capture::{finish_capture, CaptureGuard, CapturedOutput};
+use crate::channel::ParentGuard;
+use crate::chunked::{self, set_chunk_threshold, set_debug_ipc, Frame, DEBUG_IPC_ENV_NAME};
+use crate::codec::{
+    codec_factory, create_codec, set_default_codec, take_oversized_message, with_codec, Codec,
+    CodecFactory, Coded, CompressionConfig,
+};
+use crate::coverage::{coverage_enabled, set_coverage};
+use crate::env_policy::{set_default_env_policy, EnvPolicy};
 use crate::error::PanicInfo;
+use crate::leak::set_leak_detection;
+use crate::lifecycle::{set_exit_hook, set_spawn_hook, ExitHook, ExitInfo, SpawnHook, SpawnInfo};
+#[cfg(feature = "tracing")]
+use crate::logs::{log_forwarder, set_log_forwarding, LogForwardGuard, LogRecord};
+use crate::messages::{set_message_hook, MessageHook, MessageInfo};
+#[cfg(unix)]
+use crate::panic::{enable_forked_panic_hook, init_forked_panic_hook};
 use crate::panic::{init_panic_hook, reset_panic_info, take_panic, BacktraceCapture};
-use crate::serde::with_ipc_mode;
+use crate::proc::{set_builder_defaults, Builder, BuilderDefaults};
+use crate::progress::{CurrentSenderGuard, Progress};
+#[cfg(unix)]
+use crate::reattach::{reattached_sender, Reattach, ReattachGuard};
+use crate::serde::{with_ipc_mode, without_ipc_mode};
+#[cfg(unix)]
+use crate::signals::{forward_signals, Signal};
+#[cfg(feature = "tracing")]
+use crate::span::SpanContext;
 
 pub const ENV_NAME: &str = "__PROCSPAWN_CONTENT_PROCESS_ID";
+pub const NESTING_DEPTH_ENV_NAME: &str = "__PROCSPAWN_NESTING_DEPTH";
+pub const MAX_NESTING_DEPTH_ENV_NAME: &str = "__PROCSPAWN_MAX_NESTING_DEPTH";
+pub const IN_PROCESS_ENV_NAME: &str = "PROCSPAWN_IN_PROCESS";
 static INITIALIZED: AtomicBool = AtomicBool::new(false);
 static PASS_ARGS: AtomicBool = AtomicBool::new(false);
+static PANIC_HANDLING: AtomicBool = AtomicBool::new(true);
+static RUN_IN_PROCESS: AtomicBool = AtomicBool::new(false);
+static BACKTRACE_CAPTURE: Mutex<Option<BacktraceCapture>> = Mutex::new(None);
+static MAX_NESTING_DEPTH: AtomicUsize = AtomicUsize::new(usize::MAX);
+static MAX_MESSAGE_SIZE: AtomicUsize = AtomicUsize::new(usize::MAX);
 
 #[cfg(not(feature = "safe-shared-libraries"))]
 static ALLOW_UNSAFE_SPAWN: AtomicBool = AtomicBool::new(false);
@@ -49,10 +94,33 @@ pub struct ProcConfig {
     callback: Option<Box<dyn FnOnce()>>,
     panic_handling: bool,
     pass_args: bool,
+    max_nesting_depth: Option<usize>,
+    max_message_size: Option<usize>,
+    chunk_threshold: Option<usize>,
+    debug_ipc: bool,
+    run_in_process: bool,
+    coverage: bool,
+    env_policy: Option<EnvPolicy>,
+    spawn_backend: Option<Arc<dyn SpawnBackend>>,
+    codec: Option<CodecFactory>,
+    audit_sink: Option<Arc<AuditSink>>,
+    spawn_hook: Option<Arc<SpawnHook>>,
+    exit_hook: Option<Arc<ExitHook>>,
+    message_hook: Option<Arc<MessageHook>>,
+    builder_defaults: Option<Arc<BuilderDefaults>>,
+    leak_detection: bool,
+    #[cfg(feature = "tracing")]
+    forward_logs: bool,
+    #[cfg(unix)]
+    forward_signals: Vec<Signal>,
+    #[cfg(unix)]
+    signal_escalation: Option<Duration>,
     #[cfg(feature = "backtrace")]
     capture_backtraces: bool,
     #[cfg(feature = "backtrace")]
     resolve_backtraces: bool,
+    #[cfg(feature = "backtrace")]
+    trim_backtraces: bool,
 }
 
 impl Default for ProcConfig {
@@ -61,10 +129,33 @@ impl Default for ProcConfig {
             callback: None,
             panic_handling: true,
             pass_args: true,
+            max_nesting_depth: None,
+            max_message_size: None,
+            chunk_threshold: None,
+            debug_ipc: false,
+            run_in_process: false,
+            coverage: false,
+            env_policy: None,
+            spawn_backend: None,
+            codec: None,
+            audit_sink: None,
+            spawn_hook: None,
+            exit_hook: None,
+            message_hook: None,
+            builder_defaults: None,
+            leak_detection: false,
+            #[cfg(feature = "tracing")]
+            forward_logs: false,
+            #[cfg(unix)]
+            forward_signals: Vec::new(),
+            #[cfg(unix)]
+            signal_escalation: None,
             #[cfg(feature = "backtrace")]
             capture_backtraces: true,
             #[cfg(feature = "backtrace")]
             resolve_backtraces: true,
+            #[cfg(feature = "backtrace")]
+            trim_backtraces: false,
         }
     }
 }
@@ -77,57 +168,163 @@ pub fn should_pass_args() -> bool {
     PASS_ARGS.load(Ordering::SeqCst)
 }
 
+/// Returns how many spawns deep the current process is nested.
+pub fn nesting_depth() -> usize {
+    env::var(NESTING_DEPTH_ENV_NAME)
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Returns the nesting depth limit.
+///
+/// A limit configured in this process wins over one inherited from the
+/// parent process.
+pub fn max_nesting_depth() -> Option<usize> {
+    match MAX_NESTING_DEPTH.load(Ordering::SeqCst) {
+        usize::MAX => env::var(MAX_NESTING_DEPTH_ENV_NAME)
+            .ok()
+            .and_then(|x| x.parse().ok()),
+        x => Some(x),
+    }
+}
+
+/// Returns the maximum size of messages.
+pub fn max_message_size() -> Option<usize> {
+    match MAX_MESSAGE_SIZE.load(Ordering::SeqCst) {
+        usize::MAX => None,
+        x => Some(x),
+    }
+}
+
 fn find_shared_library_offset_by_name(name: &OsStr) -> isize {
-    #[cfg(feature = "safe-shared-libraries")]
-    {
-        let mut result = None;
-        findshlibs::TargetSharedLibrary::each(|shlib| {
-            if shlib.name() == name {
-                result = Some(
-                    shlib
+    match shared_library_offset_by_name(name) {
+        Some(rv) => rv,
+        None => panic!("Unable to locate shared library {:?} in subprocess", name),
+    }
+}
+
+/// A shared library loaded into this process.
+#[cfg(feature = "safe-shared-libraries")]
+struct LoadedLibrary {
+    name: OsString,
+    start: isize,
+    segments: Vec<Range<usize>>,
+}
+
+/// The loaded shared libraries and the lookups done in them.
+#[cfg(feature = "safe-shared-libraries")]
+#[derive(Default)]
+struct LibraryCache {
+    libraries: Option<Vec<LoadedLibrary>>,
+    lookups: HashMap<usize, (OsString, isize)>,
+}
+
+#[cfg(feature = "safe-shared-libraries")]
+impl LibraryCache {
+    /// Returns the loaded libraries, reading them on first use.
+    fn libraries(&mut self) -> &[LoadedLibrary] {
+        self.libraries.get_or_insert_with(|| {
+            let mut libraries = Vec::new();
+            findshlibs::TargetSharedLibrary::each(|shlib| {
+                libraries.push(LoadedLibrary {
+                    name: shlib.name().to_owned(),
+                    start: shlib
                         .segments()
                         .next()
                         .map_or(0, |x| x.actual_virtual_memory_address(shlib).0 as isize),
-                );
-                return IterationControl::Break;
-            }
-            IterationControl::Continue
-        });
-        match result {
-            Some(rv) => rv,
-            None => panic!("Unable to locate shared library {:?} in subprocess", name),
+                    segments: shlib
+                        .segments()
+                        .map(|seg| {
+                            let start = seg.actual_virtual_memory_address(shlib).0;
+                            start..start + seg.len()
+                        })
+                        .collect(),
+                });
+            });
+            libraries
+        })
+    }
+
+    /// Finds a library, reading the libraries again if it is not known.
+    ///
+    /// Libraries loaded with `dlopen` after the libraries were read are
+    /// picked up this way.
+    fn find<T, F: Fn(&LoadedLibrary) -> Option<T>>(&mut self, f: F) -> Option<T> {
+        if let Some(rv) = self.libraries().iter().find_map(&f) {
+            return Some(rv);
         }
+        self.libraries = None;
+        self.libraries().iter().find_map(&f)
+    }
+}
+
+#[cfg(feature = "safe-shared-libraries")]
+static LIBRARY_CACHE: Mutex<Option<LibraryCache>> = Mutex::new(None);
+
+#[cfg(feature = "safe-shared-libraries")]
+fn with_library_cache<R, F: FnOnce(&mut LibraryCache) -> R>(f: F) -> R {
+    // a panic while the lock is held cannot leave the cache inconsistent.
+    let mut cache = LIBRARY_CACHE.lock().unwrap_or_else(|err| err.into_inner());
+    f(cache.get_or_insert_with(LibraryCache::default))
+}
+
+/// Forgets the shared libraries that were looked up.
+///
+/// Marshalling a function requires finding the shared library it is
+/// defined in.  The loaded libraries are read once and the library of every
+/// function is remembered.  Libraries loaded later are picked up on their
+/// own, but if a library was unloaded and something else was loaded at its
+/// address this must be called before spawning functions from it.
+pub fn refresh_shared_libraries() {
+    #[cfg(feature = "safe-shared-libraries")]
+    {
+        *LIBRARY_CACHE.lock().unwrap_or_else(|err| err.into_inner()) = None;
+    }
+}
+
+/// Returns the address a shared library is loaded at.
+pub(crate) fn shared_library_offset_by_name(name: &OsStr) -> Option<isize> {
+    #[cfg(feature = "safe-shared-libraries")]
+    {
+        with_library_cache(|cache| cache.find(|lib| Some(lib.start).filter(|_| lib.name == name)))
     }
     #[cfg(not(feature = "safe-shared-libraries"))]
     {
         let _ = name;
-        init as *const () as isize
+        Some(init as *const () as isize)
     }
 }
 
 fn find_library_name_and_offset(f: *const u8) -> (OsString, isize) {
+    library_name_and_offset(f).expect("Unable to locate function pointer in loaded image")
+}
+
+/// Returns the name and the load address of the shared library that
+/// contains an address.
+pub(crate) fn library_name_and_offset(f: *const u8) -> Option<(OsString, isize)> {
     #[cfg(feature = "safe-shared-libraries")]
     {
-        let mut result = None;
-        findshlibs::TargetSharedLibrary::each(|shlib| {
-            let start = shlib
-                .segments()
-                .next()
-                .map_or(0, |x| x.actual_virtual_memory_address(shlib).0 as isize);
-            for seg in shlib.segments() {
-                if seg.contains_avma(shlib, Avma(f as usize)) {
-                    result = Some((shlib.name().to_owned(), start));
-                    return IterationControl::Break;
-                }
+        with_library_cache(|cache| {
+            let addr = f as usize;
+            if let Some(rv) = cache.lookups.get(&addr) {
+                return Some(rv.clone());
             }
-            IterationControl::Continue
-        });
-        result.expect("Unable to locate function pointer in loaded image")
+            let rv = cache.find(|lib| {
+                if lib.segments.iter().any(|seg| seg.contains(&addr)) {
+                    Some((lib.name.clone(), lib.start))
+                } else {
+                    None
+                }
+            })?;
+            cache.lookups.insert(addr, rv.clone());
+            Some(rv)
+        })
     }
     #[cfg(not(feature = "safe-shared-libraries"))]
     {
         let _ = f;
-        (OsString::new(), init as *const () as isize)
+        Some((OsString::new(), init as *const () as isize))
     }
 }
 
@@ -151,6 +348,346 @@ impl ProcConfig {
         self
     }
 
+    /// Limits how deeply spawned processes can be nested.
+    ///
+    /// Every process spawned by procspawn is one level deeper than the
+    /// process that spawned it.  With a limit of `n` processes at depth `n`
+    /// can no longer spawn and the spawn fails with an error for which
+    /// [`SpawnError::is_nesting_limit_exceeded`](struct.SpawnError.html#method.is_nesting_limit_exceeded)
+    /// returns `true`.  This protects against functions that accidentally
+    /// spawn themselves recursively.
+    ///
+    /// The limit is inherited by spawned processes.  By default there is
+    /// no limit.
+    pub fn max_nesting_depth(&mut self, depth: usize) -> &mut Self {
+        self.max_nesting_depth = Some(depth);
+        self
+    }
+
+    /// Limits the size of the messages carrying arguments and return values.
+    ///
+    /// Arguments and return values that encode to more than the given
+    /// number of bytes are rejected on both ends of the channel.  The spawn
+    /// or join fails with an error for which
+    /// [`SpawnError::is_message_too_large`](struct.SpawnError.html#method.is_message_too_large)
+    /// returns `true` and which carries the offending size.  This protects
+    /// against accidentally shipping huge values between processes.
+    ///
+    /// The size is measured after the [codec](codec/index.html) and
+    /// compression are applied.  If no codec is selected values are encoded
+    /// with [`Bincode`](codec/struct.Bincode.html), so the same restrictions
+    /// as for codecs apply.  By default there is no limit.
+    pub fn max_message_size(&mut self, bytes: usize) -> &mut Self {
+        self.max_message_size = Some(bytes);
+        self
+    }
+
+    /// Sets the size above which messages are transferred in chunks.
+    ///
+    /// Arguments and return values that serialize to more than this number
+    /// of bytes are not sent as a single message.  Instead they are sent in
+    /// small chunks while they are being serialized and deserialized as the
+    /// chunks come in, which keeps the memory usage of both processes flat.
+    /// The sender waits if the receiver falls behind.  The default is 16MB.
+    ///
+    /// IPC channels and shared memory handles can not be passed in values
+    /// that are transferred in chunks.
+    pub fn chunk_threshold(&mut self, bytes: usize) -> &mut Self {
+        self.chunk_threshold = Some(bytes);
+        self
+    }
+
+    /// Logs the arguments and return values that cross process boundaries.
+    ///
+    /// Every value that is sent or received is logged to stderr with the
+    /// process ID, the direction, the type, the serialized size and the
+    /// time the transfer took.  Failed transfers are logged with their
+    /// error, which helps to find out which side failed to serialize a
+    /// value when a call fails with a remote close error:
+    ///
+    /// ```text
+    /// procspawn ipc: pid=4242 sent procspawn::codec::Coded<alloc::vec::Vec<u8>> (1032 bytes) in 41.2µs
+    /// ```
+    ///
+    /// Setting the `PROCSPAWN_DEBUG_IPC` environment variable to anything
+    /// but `0` has the same effect and also applies to spawned processes
+    /// as they inherit the variable.  The default is to not log.
+    pub fn debug_ipc(&mut self, enabled: bool) -> &mut Self {
+        self.debug_ipc = enabled;
+        self
+    }
+
+    /// Runs spawned functions on a thread of this process.
+    ///
+    /// This is meant for debugging: with the process boundary gone,
+    /// debuggers, sanitizers and code coverage see the spawned code.
+    /// Arguments and return values still go through serialization and the
+    /// returned [`JoinHandle`](struct.JoinHandle.html) behaves as usual,
+    /// but there is no process ID, killing has no effect, and the
+    /// environment, stdio and credentials configured on the builder are
+    /// ignored.  Pools, forked and detached spawns still create processes.
+    ///
+    /// Setting the `PROCSPAWN_IN_PROCESS` environment variable to anything
+    /// but `0` has the same effect.  The default is to spawn processes.
+    pub fn run_in_process(&mut self, enabled: bool) -> &mut Self {
+        self.run_in_process = enabled;
+        self
+    }
+
+    /// Sets up spawned processes for code coverage.
+    ///
+    /// Coverage data of instrumented builds, for instance with
+    /// `cargo llvm-cov`, is written by every process when it exits.  When
+    /// enabled, spawned processes get a `LLVM_PROFILE_FILE` which contains
+    /// `%p` so that they do not overwrite each other's data, processes
+    /// that are killed get a grace period to exit through their exit
+    /// handlers, and forked processes run the exit handlers.  Forked
+    /// processes only write separate files if the pattern of the parent
+    /// already contains `%p`.
+    ///
+    /// Setting the `PROCSPAWN_COVERAGE` environment variable to anything
+    /// but `0` has the same effect and also applies to spawned processes
+    /// as they inherit the variable.  The default is to not adjust
+    /// anything.
+    pub fn coverage(&mut self, enabled: bool) -> &mut Self {
+        self.coverage = enabled;
+        self
+    }
+
+    /// Sets which environment variables spawned processes inherit.
+    ///
+    /// By default spawned processes inherit the entire environment, which
+    /// includes secrets such as credentials.  This is the default for all
+    /// builders and pools, which can override it with
+    /// [`Builder::env_policy`](struct.Builder.html#method.env_policy):
+    ///
+    /// ```rust,no_run
+    /// use procspawn::EnvPolicy;
+    ///
+    /// procspawn::ProcConfig::new()
+    ///     .env_policy(EnvPolicy::Denylist(vec!["AWS_*".into(), "GITHUB_TOKEN".into()]))
+    ///     .init();
+    /// ```
+    pub fn env_policy(&mut self, policy: EnvPolicy) -> &mut Self {
+        self.env_policy = Some(policy);
+        self
+    }
+
+    /// Sets the backend that launches spawned processes.
+    ///
+    /// By default processes are launched with the
+    /// [`ExecBackend`](struct.ExecBackend.html).  See
+    /// [`SpawnBackend`](trait.SpawnBackend.html) for more information.
+    pub fn spawn_backend<B: SpawnBackend>(&mut self, backend: B) -> &mut Self {
+        self.spawn_backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// Sets the codec used for arguments and return values of all spawns.
+    ///
+    /// By default values are serialized with bincode.  Builders can still
+    /// pick a different codec for individual spawns.  See the
+    /// [`codec`](codec/index.html) module for more information.
+    pub fn codec<C: Codec + Default>(&mut self) -> &mut Self {
+        self.codec = Some(codec_factory::<C>());
+        self
+    }
+
+    /// Records every spawn in an audit log.
+    ///
+    /// The callback is invoked with an [`AuditRecord`](struct.AuditRecord.html)
+    /// for every process this process spawns, including failed attempts.
+    /// The record describes the executable, arguments, names of changed
+    /// environment variables, credentials and limits of the process and the
+    /// outcome of the spawn.
+    ///
+    /// ```rust,no_run
+    /// use std::fs::File;
+    /// use std::io::Write;
+    /// use std::sync::Mutex;
+    ///
+    /// let log = Mutex::new(File::create("spawns.log").unwrap());
+    /// procspawn::ProcConfig::new()
+    ///     .audit_log(move |record| {
+    ///         writeln!(log.lock().unwrap(), "{}", record).ok();
+    ///     })
+    ///     .init();
+    /// ```
+    ///
+    /// Processes spawned by pools are recorded when the pool starts or
+    /// restarts a worker, not for every call.
+    pub fn audit_log<F: Fn(&AuditRecord) + Send + Sync + 'static>(&mut self, f: F) -> &mut Self {
+        self.audit_sink = Some(Arc::new(f));
+        self
+    }
+
+    /// Invokes a callback for every process this process spawns.
+    ///
+    /// The callback gets a [`SpawnInfo`](struct.SpawnInfo.html) with the
+    /// process ID and, with the `backtrace` feature, the name of the spawned
+    /// function.  Together with [`on_exit`](#method.on_exit) this allows
+    /// emitting metrics for all spawned processes in one place:
+    ///
+    /// ```rust,no_run
+    /// procspawn::ProcConfig::new()
+    ///     .on_spawn(|info| eprintln!("spawned {} ({:?})", info.pid(), info.function()))
+    ///     .on_exit(|info| eprintln!("{} ended: {:?} after {:?}", info.pid(), info.outcome(), info.duration()))
+    ///     .init();
+    /// ```
+    ///
+    /// Like the [`audit_log`](#method.audit_log) pools only report the
+    /// processes of their workers, not individual calls.
+    pub fn on_spawn<F: Fn(&SpawnInfo) + Send + Sync + 'static>(&mut self, f: F) -> &mut Self {
+        self.spawn_hook = Some(Arc::new(f));
+        self
+    }
+
+    /// Invokes a callback when a spawned process ended.
+    ///
+    /// The callback gets an [`ExitInfo`](struct.ExitInfo.html) with the
+    /// outcome and the duration of the process.  The end is noticed when the
+    /// process is joined or killed, processes that are detached or whose
+    /// handles are dropped are not reported.
+    pub fn on_exit<F: Fn(&ExitInfo) + Send + Sync + 'static>(&mut self, f: F) -> &mut Self {
+        self.exit_hook = Some(Arc::new(f));
+        self
+    }
+
+    /// Invokes a callback with the size of every argument and return value.
+    ///
+    /// The callback gets a [`MessageInfo`](struct.MessageInfo.html) with the
+    /// serialized size and the type of the arguments this process sends to
+    /// spawned functions and pooled calls and of the return values it
+    /// receives from them.  This helps to find the calls that ship
+    /// unexpectedly large payloads:
+    ///
+    /// ```rust,no_run
+    /// procspawn::ProcConfig::new()
+    ///     .on_message(|info| {
+    ///         if info.size() > 10 * 1024 * 1024 {
+    ///             eprintln!("large {:?}: {} ({} bytes)", info.kind(), info.type_name(), info.size());
+    ///         }
+    ///     })
+    ///     .init();
+    /// ```
+    ///
+    /// The arguments of forked processes are not serialized and not
+    /// reported.
+    pub fn on_message<F: Fn(&MessageInfo) + Send + Sync + 'static>(&mut self, f: F) -> &mut Self {
+        self.message_hook = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets defaults for every [`Builder`](struct.Builder.html).
+    ///
+    /// The callback is invoked with every new builder, including the ones
+    /// used by [`spawn`](fn.spawn.html) and by pools, before the options of
+    /// the individual spawn are applied.  This keeps settings such as the
+    /// environment, stdio or a `pre_exec` hook in one place instead of
+    /// repeating them at every call site:
+    ///
+    /// ```rust,no_run
+    /// use procspawn::ProcConfig;
+    ///
+    /// ProcConfig::new()
+    ///     .builder_defaults(|builder| {
+    ///         builder.env_remove("AWS_SECRET_ACCESS_KEY");
+    ///     })
+    ///     .init();
+    /// ```
+    ///
+    /// Options set on a builder later override the defaults.  The callback
+    /// must not create builders itself.
+    pub fn builder_defaults<F: Fn(&mut Builder) + Send + Sync + 'static>(
+        &mut self,
+        f: F,
+    ) -> &mut Self {
+        self.builder_defaults = Some(Arc::new(f));
+        self
+    }
+
+    /// Forwards the `tracing` events of spawned functions to this process.
+    ///
+    /// Spawned processes ship the events of their functions over a
+    /// dedicated channel and this process emits them again with its own
+    /// subscriber.  The events are emitted with the `procspawn::child`
+    /// target, the `pid` field holds the process ID of the child and the
+    /// `child.target` field the original target.  Other fields of the event
+    /// are appended to the message.
+    ///
+    /// ```rust,no_run
+    /// procspawn::ProcConfig::new().forward_logs().init();
+    /// let handle = procspawn::spawn((), |()| tracing::info!("hello from the child"));
+    /// ```
+    ///
+    /// The child installs a subscriber for this when it runs a function.
+    /// If the child already installed a global subscriber before calling
+    /// [`init`](fn.init.html) its events are not forwarded.  Records of the
+    /// `log` crate are only forwarded if they are bridged to `tracing`.
+    ///
+    /// This requires the `tracing` feature.
+    #[cfg(feature = "tracing")]
+    pub fn forward_logs(&mut self) -> &mut Self {
+        self.forward_logs = true;
+        self
+    }
+
+    /// Warns about join handles that are dropped while their process runs.
+    ///
+    /// With leak detection enabled every join handle remembers where it was
+    /// created.  If it's dropped without being joined, killed or
+    /// [detached](struct.JoinHandle.html#method.detach) a warning with the
+    /// location is printed to stderr, including a backtrace if the
+    /// `backtrace` feature is enabled.  Pooled calls are only reported if
+    /// dropping their handle detaches them, see
+    /// [`HandleDropBehavior`](enum.HandleDropBehavior.html).
+    ///
+    /// This is meant for debugging, the default is to not track handles.
+    pub fn leak_detection(&mut self, enabled: bool) -> &mut Self {
+        self.leak_detection = enabled;
+        self
+    }
+
+    /// Forwards the given signals to all spawned processes.
+    ///
+    /// When this process receives one of these signals it is sent on to all
+    /// processes spawned by it that were not joined or killed yet, including
+    /// the workers of pools.  Afterwards the signal takes the effect it had
+    /// before, which for most signals means that this process terminates.
+    /// This lets command line tools shut down their workers when the user
+    /// hits Ctrl-C:
+    ///
+    /// ```rust,no_run
+    /// use procspawn::Signal;
+    ///
+    /// procspawn::ProcConfig::new()
+    ///     .forward_signals(&[Signal::Interrupt, Signal::Terminate])
+    ///     .init();
+    /// ```
+    ///
+    /// Signals that are ignored when `init` is called stay ignored.
+    ///
+    /// This is only available on unix platforms.
+    #[cfg(unix)]
+    pub fn forward_signals(&mut self, signals: &[Signal]) -> &mut Self {
+        self.forward_signals.extend_from_slice(signals);
+        self
+    }
+
+    /// Kills processes that outlive a forwarded signal.
+    ///
+    /// When a signal is forwarded with
+    /// [`forward_signals`](#method.forward_signals) the processes get the
+    /// given time to shut down after which they are killed.  The default is
+    /// to not kill processes that ignore the signal.
+    ///
+    /// This is only available on unix platforms.
+    #[cfg(unix)]
+    pub fn signal_escalation(&mut self, timeout: Duration) -> &mut Self {
+        self.signal_escalation = Some(timeout);
+        self
+    }
+
     /// Configure the automatic panic handling.
     ///
     /// The default behavior is that panics are caught and that a panic handler
@@ -173,16 +710,83 @@ impl ProcConfig {
     }
 
     /// Controls whether backtraces should be resolved.
+    ///
+    /// Resolving the symbols of a backtrace is expensive.  When disabled
+    /// the child only captures the addresses of the frames which the parent
+    /// can resolve on demand with
+    /// [`PanicInfo::resolve_backtrace`](struct.PanicInfo.html#method.resolve_backtrace).
     #[cfg(feature = "backtrace")]
     pub fn resolve_backtraces(&mut self, enabled: bool) -> &mut Self {
         self.resolve_backtraces = enabled;
         self
     }
 
+    /// Controls whether backtraces are trimmed to the frames of user code.
+    ///
+    /// When enabled the frames of the panic machinery, of procspawn and of
+    /// the crates it uses for IPC as well as all frames below the spawned
+    /// function are removed from captured backtraces, so the frame that
+    /// panicked comes first.  Unresolved backtraces are trimmed once they
+    /// are resolved with
+    /// [`PanicInfo::resolve_backtrace`](struct.PanicInfo.html#method.resolve_backtrace).
+    #[cfg(feature = "backtrace")]
+    pub fn trim_backtraces(&mut self, enabled: bool) -> &mut Self {
+        self.trim_backtraces = enabled;
+        self
+    }
+
     /// Consumes the config and initializes the process.
     pub fn init(&mut self) {
         mark_initialized();
         PASS_ARGS.store(self.pass_args, Ordering::SeqCst);
+        if let Some(depth) = self.max_nesting_depth {
+            MAX_NESTING_DEPTH.store(depth, Ordering::SeqCst);
+        }
+        if let Some(size) = self.max_message_size {
+            MAX_MESSAGE_SIZE.store(size, Ordering::SeqCst);
+        }
+        if let Some(threshold) = self.chunk_threshold {
+            set_chunk_threshold(threshold);
+        }
+        set_debug_ipc(self.debug_ipc || env::var_os(DEBUG_IPC_ENV_NAME).is_some_and(|x| x != "0"));
+        if let Some(backend) = self.spawn_backend.take() {
+            set_spawn_backend(backend);
+        }
+        if let Some(codec) = self.codec {
+            set_default_codec(codec);
+        }
+        if let Some(sink) = self.audit_sink.take() {
+            set_audit_sink(sink);
+        }
+        if let Some(hook) = self.spawn_hook.take() {
+            set_spawn_hook(hook);
+        }
+        if let Some(hook) = self.exit_hook.take() {
+            set_exit_hook(hook);
+        }
+        if let Some(hook) = self.message_hook.take() {
+            set_message_hook(hook);
+        }
+        if let Some(defaults) = self.builder_defaults.take() {
+            set_builder_defaults(defaults);
+        }
+        set_leak_detection(self.leak_detection);
+        #[cfg(feature = "tracing")]
+        set_log_forwarding(self.forward_logs);
+        #[cfg(unix)]
+        {
+            if !self.forward_signals.is_empty() {
+                forward_signals(&self.forward_signals, self.signal_escalation)
+                    .expect("failed to install signal handlers");
+            }
+        }
+        PANIC_HANDLING.store(self.panic_handling, Ordering::SeqCst);
+        *BACKTRACE_CAPTURE.lock().unwrap() = Some(self.backtrace_capture());
+        RUN_IN_PROCESS.store(self.run_in_process, Ordering::SeqCst);
+        set_coverage(self.coverage);
+        if let Some(policy) = self.env_policy.take() {
+            set_default_env_policy(policy);
+        }
 
         if let Ok(token) = env::var(ENV_NAME) {
             // permit nested invocations
@@ -192,6 +796,12 @@ impl ProcConfig {
             }
             bootstrap_ipc(token, self);
         }
+
+        // panics of functions run in this process are reported like the
+        // ones of spawned processes.
+        if self.panic_handling && run_in_process() {
+            init_panic_hook(self.backtrace_capture());
+        }
     }
 
     fn backtrace_capture(&self) -> BacktraceCapture {
@@ -199,8 +809,12 @@ impl ProcConfig {
         {
             match (self.capture_backtraces, self.resolve_backtraces) {
                 (false, _) => BacktraceCapture::No,
-                (true, true) => BacktraceCapture::Resolved,
-                (true, false) => BacktraceCapture::Unresolved,
+                (true, true) => BacktraceCapture::Resolved {
+                    trim: self.trim_backtraces,
+                },
+                (true, false) => BacktraceCapture::Unresolved {
+                    trim: self.trim_backtraces,
+                },
             }
         }
         #[cfg(not(feature = "backtrace"))]
@@ -221,6 +835,17 @@ pub fn init() {
     ProcConfig::default().init()
 }
 
+/// True if spawned functions run on a thread of this process.
+pub fn run_in_process() -> bool {
+    RUN_IN_PROCESS.load(Ordering::SeqCst)
+        || env::var_os(IN_PROCESS_ENV_NAME).is_some_and(|x| x != "0")
+}
+
+/// True if panics of calls are caught.
+pub fn panic_handling() -> bool {
+    PANIC_HANDLING.load(Ordering::SeqCst)
+}
+
 #[inline]
 pub fn assert_spawn_okay() {
     if !INITIALIZED.load(Ordering::SeqCst) {
@@ -243,10 +868,76 @@ fn is_benign_bootstrap_error(err: &io::Error) -> bool {
 }
 
 fn bootstrap_ipc(token: String, config: &ProcConfig) {
+    #[cfg(feature = "test-support")]
+    crate::testing::apply_faults();
+    #[cfg(unix)]
+    crate::user::verify_user();
     if config.panic_handling {
         init_panic_hook(config.backtrace_capture());
     }
+    #[cfg(unix)]
+    {
+        if coverage_enabled() {
+            crate::coverage::exit_on_terminate();
+        }
+    }
+    run_bootstrapped_call(token);
+}
+
+/// Prepares running a call in a forked process.
+///
+/// Reading the backtrace capture and installing a panic hook take locks,
+/// so this happens in the parent before forking.  Returns the capture for
+/// the child or `None` if panics are not handled.
+#[cfg(unix)]
+pub fn prepare_forked_call() -> Option<BacktraceCapture> {
+    if !PANIC_HANDLING.load(Ordering::SeqCst) {
+        return None;
+    }
+    init_forked_panic_hook();
+    Some(
+        BACKTRACE_CAPTURE
+            .lock()
+            .ok()
+            .and_then(|x| *x)
+            .unwrap_or_else(|| ProcConfig::default().backtrace_capture()),
+    )
+}
 
+/// Runs a function in a freshly forked process, sends back the result and exits.
+///
+/// The capture must come from [`prepare_forked_call`] in the parent.
+#[cfg(unix)]
+pub fn run_forked_call<A, R>(
+    func: fn(A) -> R,
+    args: A,
+    sender: IpcSender<Frame<ReturnMessage<R>>>,
+    capture: Option<BacktraceCapture>,
+) -> !
+where
+    R: Serialize + for<'de> Deserialize<'de>,
+{
+    if let Some(capture) = capture {
+        enable_forked_panic_hook(capture);
+    }
+    let codec = create_codec(None, None, max_message_size());
+    invoke_and_send(func, args, sender, capture.is_some(), codec, None);
+    crate::fdpass::wait_for_handoffs();
+
+    // the coverage profile is written by an exit handler.
+    if coverage_enabled() {
+        process::exit(0);
+    }
+
+    // we might have been forked from a multi threaded process so we can't
+    // rely on exit handlers being safe to run.  This includes flushing
+    // stdout whose lock another thread might have held, which only loses
+    // an incomplete last line as stdout is line buffered.
+    unsafe { libc::_exit(0) }
+}
+
+/// Connects to the parent, performs the call it sends and exits.
+pub fn run_bootstrapped_call(token: String) -> ! {
     {
         let connection_bootstrap: IpcSender<IpcSender<MarshalledCall>> =
             match IpcSender::connect(token) {
@@ -261,11 +952,116 @@ fn bootstrap_ipc(token: String, config: &ProcConfig) {
         let (tx, rx) = ipc::channel().unwrap();
         connection_bootstrap.send(tx).unwrap();
         let marshalled_call = rx.recv().unwrap();
-        marshalled_call.call(config.panic_handling);
+        marshalled_call.call(PANIC_HANDLING.load(Ordering::SeqCst));
     }
+    #[cfg(unix)]
+    crate::fdpass::wait_for_handoffs();
     process::exit(0);
 }
 
+/// Marshals a function pointer across process boundaries.
+///
+/// The function is resolved relative to the shared library it lives in
+/// which is only valid if the other process runs the same executable.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct MarshalledFn<A, R> {
+    lib_name: OsString,
+    fn_offset: isize,
+    _marker: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R> fmt::Debug for MarshalledFn<A, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MarshalledFn")
+            .field("lib_name", &self.lib_name)
+            .field("fn_offset", &self.fn_offset)
+            .finish()
+    }
+}
+
+impl<A, R> MarshalledFn<A, R> {
+    /// Marshals the function pointer.
+    pub fn new(f: fn(A) -> R) -> MarshalledFn<A, R> {
+        let (lib_name, offset) = find_library_name_and_offset(f as *const () as *const u8);
+        MarshalledFn {
+            lib_name,
+            fn_offset: f as *const () as isize - offset,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Resolves the function pointer in the current process.
+    pub fn get(&self) -> fn(A) -> R {
+        let lib_offset = find_shared_library_offset_by_name(&self.lib_name);
+        unsafe { mem::transmute(self.fn_offset + lib_offset as *const () as isize) }
+    }
+}
+
+/// Marshals a pointer to a function operating on some state.
+///
+/// This works like [`MarshalledFn`] for functions that are invoked with a
+/// mutable reference to state in the other process.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct MarshalledMethod<S, A, R> {
+    lib_name: OsString,
+    fn_offset: isize,
+    _marker: PhantomData<fn(&mut S, A) -> R>,
+}
+
+impl<S, A, R> MarshalledMethod<S, A, R> {
+    /// Marshals the function pointer.
+    pub fn new(f: fn(&mut S, A) -> R) -> MarshalledMethod<S, A, R> {
+        let (lib_name, offset) = find_library_name_and_offset(f as *const () as *const u8);
+        MarshalledMethod {
+            lib_name,
+            fn_offset: f as *const () as isize - offset,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Resolves the function pointer in the current process.
+    pub fn get(&self) -> fn(&mut S, A) -> R {
+        let lib_offset = find_shared_library_offset_by_name(&self.lib_name);
+        unsafe { mem::transmute(self.fn_offset + lib_offset as *const () as isize) }
+    }
+}
+
+type CallWrapper = fn(
+    &OsStr,
+    isize,
+    OpaqueIpcReceiver,
+    OpaqueIpcSender,
+    bool,
+    Option<Arc<dyn Codec>>,
+    Option<ResultStream>,
+);
+
+/// The size of the chunks streamed results are sent in.
+const RESULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The message the child sends back with the result of a call.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "R: Serialize + for<'x> Deserialize<'x>")]
+pub enum ReturnMessage<R> {
+    /// The result is contained in the message.
+    Value(Result<Coded<R>, PanicInfo>),
+    /// The result follows in chunks on the result stream.  This holds the
+    /// total size in bytes.
+    Streamed(u64),
+    /// The result exceeded the maximum message size.  This holds the size
+    /// and the limit in bytes.
+    TooLarge(usize, usize),
+}
+
+/// Where results above a size threshold are streamed to.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResultStream {
+    pub threshold: u64,
+    pub sender: IpcBytesSender,
+}
+
 /// Marshals a call across process boundaries.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MarshalledCall {
@@ -274,14 +1070,34 @@ pub struct MarshalledCall {
     pub wrapper_offset: isize,
     pub args_receiver: OpaqueIpcReceiver,
     pub return_sender: OpaqueIpcSender,
+    pub codec: Option<MarshalledFn<(), Arc<dyn Codec>>>,
+    pub compression: Option<CompressionConfig>,
+    pub max_message_size: Option<usize>,
+    pub result_stream: Option<ResultStream>,
+    pub progress: Option<IpcSender<Progress>>,
+    pub parent: Option<OpaqueIpcSender>,
+    pub capture: Option<IpcSender<CapturedOutput>>,
+    pub env: Vec<(OsString, OsString)>,
+    #[cfg(unix)]
+    pub reattach: Option<Reattach>,
+    #[cfg(unix)]
+    pub wait_for_debugger: bool,
+    #[cfg(feature = "tracing")]
+    pub span: Option<SpanContext>,
+    #[cfg(feature = "tracing")]
+    pub logs: Option<IpcSender<LogRecord>>,
 }
 
 impl MarshalledCall {
     /// Marshalls the call.
     pub fn marshal<A, R>(
         f: fn(A) -> R,
-        args_receiver: IpcReceiver<A>,
-        return_sender: IpcSender<Result<R, PanicInfo>>,
+        args_receiver: IpcReceiver<Frame<Coded<A>>>,
+        return_sender: IpcSender<Frame<ReturnMessage<R>>>,
+        codec: Option<CodecFactory>,
+        compression: Option<CompressionConfig>,
+        max_message_size: Option<usize>,
+        result_stream: Option<ResultStream>,
     ) -> MarshalledCall
     where
         A: Serialize + for<'de> Deserialize<'de>,
@@ -297,53 +1113,220 @@ impl MarshalledCall {
             wrapper_offset,
             args_receiver: args_receiver.to_opaque(),
             return_sender: return_sender.to_opaque(),
+            codec: codec.map(MarshalledFn::new),
+            compression,
+            max_message_size,
+            result_stream,
+            progress: None,
+            parent: None,
+            capture: None,
+            env: Vec::new(),
+            #[cfg(unix)]
+            reattach: None,
+            #[cfg(unix)]
+            wait_for_debugger: false,
+            #[cfg(feature = "tracing")]
+            span: SpanContext::current(),
+            #[cfg(feature = "tracing")]
+            logs: log_forwarder(),
         }
     }
 
+    /// Sets where the function reports its progress to.
+    pub fn with_progress(mut self, progress: Option<IpcSender<Progress>>) -> MarshalledCall {
+        self.progress = progress;
+        self
+    }
+
+    /// Sets the channel the function can send messages to its parent on.
+    pub fn with_parent(mut self, parent: Option<OpaqueIpcSender>) -> MarshalledCall {
+        self.parent = parent;
+        self
+    }
+
+    /// Sets where the output of the function is captured to.
+    pub fn with_capture(mut self, capture: Option<IpcSender<CapturedOutput>>) -> MarshalledCall {
+        self.capture = capture;
+        self
+    }
+
+    /// Sets environment variables that only apply while the function runs.
+    pub fn with_env(mut self, env: Vec<(OsString, OsString)>) -> MarshalledCall {
+        self.env = env;
+        self
+    }
+
+    /// Lets a new parent receive the result if the parent goes away.
+    #[cfg(unix)]
+    pub fn with_reattach(mut self, reattach: Option<Reattach>) -> MarshalledCall {
+        self.reattach = reattach;
+        self
+    }
+
+    /// Stops the process for a debugger before the function is invoked.
+    #[cfg(unix)]
+    pub fn with_wait_for_debugger(mut self, enabled: bool) -> MarshalledCall {
+        self.wait_for_debugger = enabled;
+        self
+    }
+
     /// Unmarshals and performs the call.
     pub fn call(self, panic_handling: bool) {
+        let _env = EnvGuard::apply(self.env);
+        #[cfg(unix)]
+        let _reattach = ReattachGuard::install(self.reattach);
+        let _progress = CurrentSenderGuard::install(self.progress);
+        let _parent = ParentGuard::install(self.parent);
+        let _capture = CaptureGuard::install(self.capture);
+        #[cfg(feature = "tracing")]
+        let _logs = LogForwardGuard::install(self.logs);
+        #[cfg(feature = "tracing")]
+        let _span = self.span.map(SpanContext::enter);
+        #[cfg(unix)]
+        {
+            if self.wait_for_debugger {
+                wait_for_debugger();
+            }
+        }
         unsafe {
             let ptr = self.wrapper_offset + init as *const () as isize;
-            let func: fn(&OsStr, isize, OpaqueIpcReceiver, OpaqueIpcSender, bool) =
-                mem::transmute(ptr);
+            let func: CallWrapper = mem::transmute(ptr);
             func(
                 &self.lib_name,
                 self.fn_offset,
                 self.args_receiver,
                 self.return_sender,
                 panic_handling,
+                create_codec(
+                    self.codec.map(|codec| codec.get()),
+                    self.compression,
+                    self.max_message_size,
+                ),
+                self.result_stream,
             );
         }
     }
 }
 
+/// Writes a diagnostic line to stderr.
+///
+/// This is used instead of `eprintln!` which the test harness captures, so
+/// messages of processes spawned from tests or printed after a test
+/// finished would otherwise be swallowed.
+pub fn log_stderr(args: fmt::Arguments<'_>) {
+    let mut stderr = io::stderr().lock();
+    io::Write::write_fmt(&mut stderr, format_args!("{}\n", args)).ok();
+}
+
+/// Announces the process and stops it until it is continued.
+#[cfg(unix)]
+fn wait_for_debugger() {
+    log_stderr(format_args!(
+        "procspawn: process {} is waiting for a debugger, continue it with `kill -CONT {}`",
+        process::id(),
+        process::id()
+    ));
+    unsafe {
+        libc::raise(libc::SIGSTOP);
+    }
+}
+
+/// Restores environment variables that were overridden for a call.
+///
+/// Restoring the saved values is only correct if calls with variables run
+/// one after another, which is why pools with concurrent workers refuse
+/// them.
+struct EnvGuard {
+    saved: Vec<(OsString, Option<OsString>)>,
+}
+
+impl EnvGuard {
+    fn apply(vars: Vec<(OsString, OsString)>) -> EnvGuard {
+        let mut saved = Vec::with_capacity(vars.len());
+        for (key, value) in vars {
+            saved.push((key.clone(), env::var_os(&key)));
+            env::set_var(key, value);
+        }
+        EnvGuard { saved }
+    }
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        // restored in reverse so that repeated keys end up with their
+        // original value.
+        for (key, value) in self.saved.drain(..).rev() {
+            match value {
+                Some(value) => env::set_var(key, value),
+                None => env::remove_var(key),
+            }
+        }
+    }
+}
+
 unsafe fn run_func<A, R>(
     lib_name: &OsStr,
     fn_offset: isize,
     args_recv: OpaqueIpcReceiver,
     sender: OpaqueIpcSender,
     panic_handling: bool,
+    codec: Option<Arc<dyn Codec>>,
+    result_stream: Option<ResultStream>,
 ) where
     A: Serialize + for<'de> Deserialize<'de>,
     R: Serialize + for<'de> Deserialize<'de>,
 {
     let lib_offset = find_shared_library_offset_by_name(lib_name);
     let function: fn(A) -> R = mem::transmute(fn_offset + lib_offset as *const () as isize);
-    let args = with_ipc_mode(|| args_recv.to().recv().unwrap());
+    let Coded(args) = with_codec(codec.as_ref(), || {
+        with_ipc_mode(|| chunked::recv(&args_recv.to::<Frame<Coded<A>>>()).unwrap())
+    });
+    invoke_and_send(
+        function,
+        args,
+        sender.to(),
+        panic_handling,
+        codec,
+        result_stream,
+    );
+}
+
+fn invoke_and_send<A, R>(
+    function: fn(A) -> R,
+    args: A,
+    sender: IpcSender<Frame<ReturnMessage<R>>>,
+    panic_handling: bool,
+    codec: Option<Arc<dyn Codec>>,
+    result_stream: Option<ResultStream>,
+) where
+    R: Serialize + for<'de> Deserialize<'de>,
+{
     let rv = if panic_handling {
         reset_panic_info();
         match panic::catch_unwind(panic::AssertUnwindSafe(|| function(args))) {
-            Ok(rv) => Ok(rv),
+            Ok(rv) => Ok(Coded(rv)),
             Err(panic) => Err(take_panic(&*panic)),
         }
     } else {
-        Ok(function(args))
+        Ok(Coded(function(args)))
+    };
+    finish_capture();
+
+    #[cfg(unix)]
+    let sender = match reattached_sender() {
+        Some(sender) => sender.to(),
+        None => sender,
     };
 
     // sending can fail easily because of bincode limitations.  If you see
-    // this in your tracebacks consider using the `Json` wrapper.
-    if let Err(err) = with_ipc_mode(|| sender.to().send(rv)) {
-        if let IpcErrorKind::Io(ref io) = *err {
+    // this in your tracebacks consider using the `Structural` or `Json`
+    // wrapper.
+    if let Err(err) = send_result(&sender, rv, codec.as_ref(), result_stream) {
+        if let Some((size, limit)) = take_oversized_message() {
+            // tell the parent instead of hanging up so it can report the
+            // offending size.
+            chunked::send(&sender, ReturnMessage::TooLarge(size, limit)).ok();
+        } else if let IpcErrorKind::Io(ref io) = *err {
             if io.kind() == io::ErrorKind::NotFound || io.kind() == io::ErrorKind::ConnectionReset {
                 // this error is okay.  this means nobody actually
                 // waited for the call, so we just ignore it.
@@ -353,3 +1336,67 @@ unsafe fn run_func<A, R>(
         }
     }
 }
+
+/// Sends the result back, streaming it in chunks if it is large.
+/// Sends what is written to it over a result stream in chunks.
+struct StreamWriter<'a> {
+    sender: &'a IpcBytesSender,
+    buf: Vec<u8>,
+}
+
+impl io::Write for StreamWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = data.len().min(RESULT_CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&data[..len]);
+        if self.buf.len() == RESULT_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.sender.send(&self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+fn send_result<R>(
+    sender: &IpcSender<Frame<ReturnMessage<R>>>,
+    rv: Result<Coded<R>, PanicInfo>,
+    codec: Option<&Arc<dyn Codec>>,
+    result_stream: Option<ResultStream>,
+) -> Result<(), ipc_channel::Error>
+where
+    R: Serialize + for<'de> Deserialize<'de>,
+{
+    #[cfg(feature = "test-support")]
+    {
+        if crate::testing::should_fail_serialization() {
+            return Err(serde::ser::Error::custom("injected serialization failure"));
+        }
+    }
+    if let Some(stream) = result_stream {
+        let size = with_codec(codec, || without_ipc_mode(|| bincode::serialized_size(&rv)));
+        if let Some(size) = size.ok().filter(|&size| size > stream.threshold) {
+            chunked::send(sender, ReturnMessage::Streamed(size))?;
+            // serialized straight into the stream so that the result is
+            // never held in memory a second time.
+            let mut writer = StreamWriter {
+                sender: &stream.sender,
+                buf: Vec::with_capacity(RESULT_CHUNK_SIZE),
+            };
+            with_codec(codec, || {
+                without_ipc_mode(|| bincode::serialize_into(&mut writer, &rv))
+            })?;
+            io::Write::flush(&mut writer)?;
+            return Ok(());
+        }
+    }
+    with_codec(codec, || {
+        with_ipc_mode(|| chunked::send(sender, ReturnMessage::Value(rv)))
+    })
+    .map(|_| ())
+}
@@ -0,0 +1,44 @@
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process;
+
+/// How a spawned process writes core dumps.
+#[derive(Debug, Clone)]
+pub enum CoreDumps {
+    /// No core dumps are written.
+    Disabled,
+    /// Core dumps are written to a directory.
+    Directory(PathBuf),
+}
+
+impl CoreDumps {
+    /// Configures the command to apply the core dump settings in the child.
+    pub fn apply(&self, cmd: &mut process::Command) {
+        let disable = match *self {
+            CoreDumps::Disabled => true,
+            CoreDumps::Directory(ref path) => {
+                // the kernel writes core dumps relative to the working
+                // directory of the crashing process.
+                cmd.current_dir(path);
+                false
+            }
+        };
+        unsafe {
+            cmd.pre_exec(move || {
+                let mut limit = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                limit.rlim_cur = if disable { 0 } else { limit.rlim_max };
+                if libc::setrlimit(libc::RLIMIT_CORE, &limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
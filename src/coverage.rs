@@ -0,0 +1,110 @@
+use std::env;
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::time::Duration;
+
+/// Where the profiling runtime of LLVM writes coverage data to.
+pub const PROFILE_FILE_ENV_NAME: &str = "LLVM_PROFILE_FILE";
+pub const COVERAGE_ENV_NAME: &str = "PROCSPAWN_COVERAGE";
+
+/// How long a process that is killed gets to write its profile.
+#[cfg(unix)]
+pub const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+static COVERAGE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_coverage(enabled: bool) {
+    COVERAGE.store(enabled, Ordering::SeqCst);
+}
+
+/// True if spawned processes are set up for code coverage.
+pub fn coverage_enabled() -> bool {
+    COVERAGE.load(Ordering::SeqCst) || env::var_os(COVERAGE_ENV_NAME).is_some_and(|x| x != "0")
+}
+
+/// Returns the profile file pattern for a spawned process.
+///
+/// All processes would write to the same file unless the pattern contains
+/// the process ID, so `%p` is added to patterns without it.  Returns `None`
+/// if the pattern can be inherited.
+pub fn child_profile_file() -> Option<OsString> {
+    let pattern = match env::var_os(PROFILE_FILE_ENV_NAME) {
+        Some(pattern) => pattern.into_string().ok()?,
+        None => "default.profraw".into(),
+    };
+    if pattern.contains("%p") {
+        return None;
+    }
+    Some(
+        match pattern.strip_suffix(".profraw") {
+            Some(stem) => format!("{}-%p.profraw", stem),
+            None => format!("{}-%p", pattern),
+        }
+        .into(),
+    )
+}
+
+/// Makes `SIGTERM` exit the process through the regular exit handlers.
+///
+/// The profiling runtime writes its data from an exit handler, which the
+/// default action of the signal skips.  Exit handlers cannot run in a
+/// signal handler, so the handler only wakes up a thread which exits.
+#[cfg(unix)]
+pub fn exit_on_terminate() {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::atomic::AtomicI32;
+    use std::{process, thread};
+
+    static TERMINATE_PIPE: AtomicI32 = AtomicI32::new(-1);
+    static TERMINATE_PID: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn on_terminate(signal: libc::c_int) {
+        if unsafe { libc::getpid() } != TERMINATE_PID.load(Ordering::SeqCst) {
+            // forked children do not have the exit thread.
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+            return;
+        }
+        let byte = 0u8;
+        unsafe {
+            libc::write(
+                TERMINATE_PIPE.load(Ordering::SeqCst),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return;
+        }
+        for &fd in &fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        TERMINATE_PIPE.store(fds[1], Ordering::SeqCst);
+        TERMINATE_PID.store(libc::getpid(), Ordering::SeqCst);
+    }
+    let mut read = unsafe { File::from_raw_fd(fds[0]) };
+    let spawned = thread::Builder::new()
+        .name("procspawn-coverage".into())
+        .spawn(move || {
+            if read.read(&mut [0]).unwrap_or(0) > 0 {
+                process::exit(128 + libc::SIGTERM);
+            }
+        });
+    if spawned.is_ok() {
+        unsafe {
+            libc::signal(
+                libc::SIGTERM,
+                on_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
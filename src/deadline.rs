@@ -0,0 +1,113 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often a join re-reads a deadline while it waits.
+pub(crate) const DEADLINE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A point in time by which a process has to be done.
+///
+/// Unlike the fixed timeout passed to
+/// [`JoinHandle::join_timeout`](struct.JoinHandle.html#method.join_timeout)
+/// a deadline can be moved while a join waits on it.  Clones of a deadline
+/// share the same point in time, so one thread can join with
+/// [`JoinHandle::join_deadline`](struct.JoinHandle.html#method.join_deadline)
+/// while another thread extends or shortens the deadline, for instance
+/// because a user asked to give a slow task more time:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use procspawn::Deadline;
+///
+/// let deadline = Deadline::after(Duration::from_secs(5));
+/// let mut handle = procspawn::spawn((), |()| {
+///     std::thread::sleep(Duration::from_secs(8));
+/// });
+/// std::thread::spawn({
+///     let deadline = deadline.clone();
+///     move || deadline.extend(Duration::from_secs(10))
+/// });
+/// handle.join_deadline(&deadline).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Deadline {
+    instant: Arc<Mutex<Option<Instant>>>,
+}
+
+impl fmt::Debug for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Deadline")
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+impl Deadline {
+    fn new(instant: Option<Instant>) -> Deadline {
+        Deadline {
+            instant: Arc::new(Mutex::new(instant)),
+        }
+    }
+
+    /// Creates a deadline that passes after the given duration.
+    pub fn after(timeout: Duration) -> Deadline {
+        Deadline::new(Instant::now().checked_add(timeout))
+    }
+
+    /// Creates a deadline that passes at the given point in time.
+    pub fn at(instant: Instant) -> Deadline {
+        Deadline::new(Some(instant))
+    }
+
+    /// Creates a deadline that never passes unless it is moved.
+    pub fn never() -> Deadline {
+        Deadline::new(None)
+    }
+
+    /// Returns the point in time of the deadline.
+    ///
+    /// This is `None` if the deadline never passes.
+    pub fn instant(&self) -> Option<Instant> {
+        *self.instant.lock().unwrap()
+    }
+
+    /// Returns the time left until the deadline passes.
+    ///
+    /// This is `None` if the deadline never passes.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.instant()
+            .map(|instant| instant.saturating_duration_since(Instant::now()))
+    }
+
+    /// True if the deadline has passed.
+    pub fn has_passed(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Moves the deadline to the given point in time.
+    pub fn set(&self, instant: Instant) {
+        *self.instant.lock().unwrap() = Some(instant);
+    }
+
+    /// Moves the deadline so that it passes after the given duration.
+    pub fn reset(&self, timeout: Duration) {
+        *self.instant.lock().unwrap() = Instant::now().checked_add(timeout);
+    }
+
+    /// Moves the deadline back by the given duration.
+    ///
+    /// A deadline that already passed is extended from its original point in
+    /// time, use [`reset`](#method.reset) to give it time from now on.
+    pub fn extend(&self, by: Duration) {
+        let mut instant = self.instant.lock().unwrap();
+        *instant = instant.and_then(|instant| instant.checked_add(by));
+    }
+
+    /// Moves the deadline forward by the given duration.
+    ///
+    /// This has no effect on deadlines that never pass.
+    pub fn shorten(&self, by: Duration) {
+        let mut instant = self.instant.lock().unwrap();
+        *instant = instant.map(|instant| instant.checked_sub(by).unwrap_or_else(Instant::now));
+    }
+}
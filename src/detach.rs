@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::process;
+
+/// A process spawned with
+/// [`Builder::spawn_detached`](struct.Builder.html#method.spawn_detached).
+///
+/// The process runs in a session of its own and is not a child of the
+/// process that spawned it.  Nothing connects the two once the call was
+/// handed over, so all that's left is the process ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetachedChild {
+    pid: u32,
+}
+
+impl DetachedChild {
+    pub(crate) fn new(pid: u32) -> DetachedChild {
+        DetachedChild { pid }
+    }
+
+    /// The process ID of the detached process.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+}
+
+/// Makes a spawned process detach itself before it executes.
+///
+/// The child starts a new session and forks again.  The intermediate
+/// process exits right away, which hands the grandchild over to init, and
+/// reports the pid of the grandchild over a pipe.
+pub struct Daemonize {
+    read: File,
+    write: File,
+}
+
+impl Daemonize {
+    /// Configures the command to detach in the child.
+    pub fn apply(cmd: &mut process::Command) -> io::Result<Daemonize> {
+        let mut fds = [0; 2];
+        let (read, write) = unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]))
+        };
+        for fd in &fds {
+            // the executed grandchild must not hold on to the pipe.
+            if unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let fd = write.as_raw_fd();
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::setsid() < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                match libc::fork() {
+                    -1 => Err(io::Error::last_os_error()),
+                    0 => Ok(()),
+                    pid => {
+                        let pid = (pid as u32).to_ne_bytes();
+                        libc::write(fd, pid.as_ptr() as *const libc::c_void, pid.len());
+                        libc::_exit(0);
+                    }
+                }
+            });
+        }
+        Ok(Daemonize { read, write })
+    }
+
+    /// Reaps the intermediate process and returns the pid of the detached
+    /// process.
+    pub fn finish(self, intermediate: &mut process::Child) -> io::Result<u32> {
+        intermediate.wait()?;
+        let Daemonize { mut read, write } = self;
+        drop(write);
+        let mut pid = [0; 4];
+        read.read_exact(&mut pid)?;
+        Ok(u32::from_ne_bytes(pid))
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
+use std::time::{Duration, SystemTime};
+
+static REGISTER_AT_EXIT: Once = Once::new();
+static LIVE_ENDPOINTS: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+/// Tracks a bootstrap endpoint created by this process.
+///
+/// The endpoint is removed when the guard is dropped.  Endpoints that are
+/// still registered when the process exits normally are removed by an
+/// exit handler.
+pub struct EndpointGuard {
+    path: PathBuf,
+}
+
+impl EndpointGuard {
+    pub fn register(token: &str) -> EndpointGuard {
+        REGISTER_AT_EXIT.call_once(|| unsafe {
+            libc::atexit(remove_live_endpoints);
+        });
+        let path = PathBuf::from(token);
+        LIVE_ENDPOINTS
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashSet::new)
+            .insert(path.clone());
+        EndpointGuard { path }
+    }
+}
+
+impl Drop for EndpointGuard {
+    fn drop(&mut self) {
+        if let Ok(mut endpoints) = LIVE_ENDPOINTS.lock() {
+            if let Some(ref mut endpoints) = *endpoints {
+                endpoints.remove(&self.path);
+            }
+        }
+        remove_endpoint(&self.path);
+    }
+}
+
+extern "C" fn remove_live_endpoints() {
+    // never block in an exit handler.  If another thread holds the lock
+    // we would rather leak the endpoint.
+    if let Ok(mut endpoints) = LIVE_ENDPOINTS.try_lock() {
+        if let Some(endpoints) = endpoints.take() {
+            for path in endpoints {
+                remove_endpoint(&path);
+            }
+        }
+    }
+}
+
+fn remove_endpoint(path: &Path) {
+    fs::remove_file(path).ok();
+    if let Some(parent) = path.parent() {
+        fs::remove_dir(parent).ok();
+    }
+}
+
+/// Removes bootstrap endpoints that were left behind by crashed processes.
+///
+/// When a process is spawned the parent creates a socket in a temporary
+/// directory which the child connects to.  These endpoints are removed
+/// automatically once the child connected, when the spawn fails and when
+/// the parent exits normally.  If the parent crashes or gets killed while
+/// a child is bootstrapping the endpoint is leaked.
+///
+/// This function scans `dir` (which is typically `std::env::temp_dir()`)
+/// for such endpoints and removes the ones that are older than
+/// `older_than` and that nobody is listening on any more.  It returns the
+/// number of endpoints removed.
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// let removed = procspawn::cleanup_stale_endpoints(
+///     std::env::temp_dir(),
+///     Duration::from_secs(3600),
+/// ).unwrap();
+/// ```
+pub fn cleanup_stale_endpoints<P: AsRef<Path>>(dir: P, older_than: Duration) -> io::Result<usize> {
+    let cutoff = SystemTime::now()
+        .checked_sub(older_than)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(".tmp") {
+            continue;
+        }
+        let socket_path = entry.path().join("socket");
+        if is_stale_endpoint(&entry.path(), &socket_path, cutoff) {
+            remove_endpoint(&socket_path);
+            if !entry.path().exists() {
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(unix)]
+fn is_stale_endpoint(dir: &Path, socket_path: &Path, cutoff: SystemTime) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::FileTypeExt;
+
+    match fs::read_dir(dir) {
+        Ok(mut entries) => match (entries.next(), entries.next()) {
+            (Some(Ok(entry)), None) if entry.file_name() == "socket" => {}
+            _ => return false,
+        },
+        Err(_) => return false,
+    }
+    match fs::symlink_metadata(socket_path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if metadata
+                .modified()
+                .map_or(true, |modified| modified > cutoff)
+            {
+                return false;
+            }
+        }
+        _ => return false,
+    }
+
+    // only a refused connection proves that nobody listens on it any more.
+    // The endpoints are seqpacket sockets so connecting with a stream socket
+    // would fail with a protocol error instead.
+    let path = match std::ffi::CString::new(socket_path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    unsafe {
+        let mut addr: libc::sockaddr_un = std::mem::zeroed();
+        let bytes = path.as_bytes_with_nul();
+        if bytes.len() > addr.sun_path.len() {
+            return false;
+        }
+        addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+        for (dst, src) in addr.sun_path.iter_mut().zip(bytes) {
+            *dst = *src as libc::c_char;
+        }
+        let fd = libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET, 0);
+        if fd < 0 {
+            return false;
+        }
+        let rv = libc::connect(
+            fd,
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_un>() as libc::socklen_t,
+        );
+        let refused =
+            rv != 0 && io::Error::last_os_error().kind() == io::ErrorKind::ConnectionRefused;
+        libc::close(fd);
+        refused
+    }
+}
+
+#[cfg(not(unix))]
+fn is_stale_endpoint(_dir: &Path, _socket_path: &Path, _cutoff: SystemTime) -> bool {
+    false
+}
@@ -0,0 +1,75 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::sync::Mutex;
+
+static DEFAULT_ENV_POLICY: Mutex<Option<EnvPolicy>> = Mutex::new(None);
+
+pub fn set_default_env_policy(policy: EnvPolicy) {
+    *DEFAULT_ENV_POLICY.lock().unwrap() = Some(policy);
+}
+
+/// Decides which environment variables spawned processes inherit.
+///
+/// Names in the lists match exactly unless they end in `*` which matches
+/// all variables starting with what comes before, so `AWS_*` covers all
+/// variables for AWS.
+///
+/// The policy is set for all spawns with
+/// [`ProcConfig::env_policy`](struct.ProcConfig.html#method.env_policy) or
+/// for a single one with
+/// [`Builder::env_policy`](struct.Builder.html#method.env_policy).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum EnvPolicy {
+    /// All variables are inherited.  This is the default.
+    #[default]
+    InheritAll,
+    /// No variables are inherited.
+    InheritNone,
+    /// Only the listed variables are inherited.
+    Allowlist(Vec<String>),
+    /// All variables but the listed ones are inherited.
+    Denylist(Vec<String>),
+}
+
+impl EnvPolicy {
+    /// True if a variable is inherited under this policy.
+    pub fn inherits<K: AsRef<OsStr>>(&self, name: K) -> bool {
+        let name = name.as_ref();
+        let listed = |patterns: &[String]| {
+            name.to_str().is_some_and(|name| {
+                patterns
+                    .iter()
+                    .any(|pattern| match pattern.strip_suffix('*') {
+                        Some(prefix) => name.starts_with(prefix),
+                        None => name == pattern,
+                    })
+            })
+        };
+        match *self {
+            EnvPolicy::InheritAll => true,
+            EnvPolicy::InheritNone => false,
+            EnvPolicy::Allowlist(ref names) => listed(names),
+            EnvPolicy::Denylist(ref names) => !listed(names),
+        }
+    }
+}
+
+/// Returns the variables of this process that spawned processes inherit by
+/// default.
+pub fn inherited_vars() -> impl Iterator<Item = (OsString, OsString)> {
+    let policy = DEFAULT_ENV_POLICY.lock().unwrap().clone();
+    env::vars_os().filter(move |(key, _)| policy.as_ref().map_or(true, |x| x.inherits(key)))
+}
+
+/// Returns the variables of this process that spawned processes do not
+/// inherit by default.
+pub fn uninherited_vars() -> Vec<OsString> {
+    let policy = match *DEFAULT_ENV_POLICY.lock().unwrap() {
+        None | Some(EnvPolicy::InheritAll) => return Vec::new(),
+        Some(ref policy) => policy.clone(),
+    };
+    env::vars_os()
+        .map(|(key, _)| key)
+        .filter(|key| !policy.inherits(key))
+        .collect()
+}
@@ -1,10 +1,21 @@
+use std::any::Any;
+#[cfg(feature = "backtrace")]
+use std::ffi::OsString;
 use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
 
 use ipc_channel::ipc::{IpcError, TryRecvError};
 use ipc_channel::{Error as BincodeError, ErrorKind as BincodeErrorKind};
 use serde::{Deserialize, Serialize};
 
+use crate::codec::take_oversized_message;
+#[cfg(feature = "backtrace")]
+use crate::core::shared_library_offset_by_name;
+#[cfg(feature = "backtrace")]
+use crate::panic::trim_backtrace;
+
 /// Represents a panic caugh across processes.
 ///
 /// This contains the marshalled panic information so that it can be used
@@ -15,8 +26,16 @@ use serde::{Deserialize, Serialize};
 pub struct PanicInfo {
     msg: String,
     pub(crate) location: Option<Location>,
+    pub(crate) thread: Option<String>,
     #[cfg(feature = "backtrace")]
     pub(crate) backtrace: Option<backtrace::Backtrace>,
+    /// The library and its load address of every frame of an unresolved
+    /// backtrace.
+    #[cfg(feature = "backtrace")]
+    pub(crate) frame_images: Vec<Option<(OsString, isize)>>,
+    /// Whether the backtrace is trimmed once it is resolved.
+    #[cfg(feature = "backtrace")]
+    pub(crate) trim_backtrace: bool,
 }
 
 /// Location of a panic.
@@ -60,8 +79,13 @@ impl PanicInfo {
         PanicInfo {
             msg: s.into(),
             location: None,
+            thread: None,
             #[cfg(feature = "backtrace")]
             backtrace: None,
+            #[cfg(feature = "backtrace")]
+            frame_images: Vec::new(),
+            #[cfg(feature = "backtrace")]
+            trim_backtrace: false,
         }
     }
 
@@ -75,6 +99,15 @@ impl PanicInfo {
         self.location.as_ref()
     }
 
+    /// Returns the name of the thread that panicked.
+    ///
+    /// This is `None` for unnamed threads.  Panics of other threads that the
+    /// spawned function resumes with `std::panic::resume_unwind` carry the
+    /// name of the thread they originally happened on.
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread.as_deref()
+    }
+
     /// Returns a reference to the backtrace.
     ///
     /// Typically this backtrace is already resolved because it's currently
@@ -83,6 +116,60 @@ impl PanicInfo {
     pub fn backtrace(&self) -> Option<&backtrace::Backtrace> {
         self.backtrace.as_ref()
     }
+
+    /// Returns the backtrace with resolved symbols.
+    ///
+    /// Backtraces are only unresolved if the child was configured with
+    /// [`ProcConfig::resolve_backtraces`](struct.ProcConfig.html#method.resolve_backtraces)
+    /// turned off.  The frames are moved to where their libraries are
+    /// loaded in this process before they are resolved, so this works for
+    /// executed children even though they load libraries at other addresses.
+    #[cfg(feature = "backtrace")]
+    pub fn resolve_backtrace(&self) -> Option<backtrace::Backtrace> {
+        let mut backtrace = self.backtrace.clone()?;
+        if !self.frame_images.is_empty() {
+            let frames: Vec<RelocatedFrame> = backtrace
+                .frames()
+                .iter()
+                .zip(self.frame_images.iter())
+                .map(|(frame, image)| {
+                    let delta = image
+                        .as_ref()
+                        .and_then(|(name, offset)| {
+                            Some(shared_library_offset_by_name(name)? - offset)
+                        })
+                        .unwrap_or(0);
+                    RelocatedFrame {
+                        ip: (frame.ip() as isize + delta) as usize,
+                        symbol_address: (frame.symbol_address() as isize + delta) as usize,
+                        module_base_address: None,
+                        symbols: None,
+                    }
+                })
+                .collect();
+            // frames can only be created by capturing or deserializing them.
+            if let Ok(frames) = bincode::serialize(&frames)
+                .and_then(|x| bincode::deserialize::<Vec<backtrace::BacktraceFrame>>(&x))
+            {
+                backtrace = backtrace::Backtrace::from(frames);
+            }
+        }
+        backtrace.resolve();
+        if self.trim_backtrace {
+            backtrace = trim_backtrace(&backtrace);
+        }
+        Some(backtrace)
+    }
+}
+
+/// The serialized form of an unresolved `backtrace::BacktraceFrame`.
+#[cfg(feature = "backtrace")]
+#[derive(Serialize)]
+struct RelocatedFrame {
+    ip: usize,
+    symbol_address: usize,
+    module_base_address: Option<usize>,
+    symbols: Option<()>,
 }
 
 impl fmt::Debug for PanicInfo {
@@ -90,6 +177,7 @@ impl fmt::Debug for PanicInfo {
         f.debug_struct("PanicInfo")
             .field("message", &self.message())
             .field("location", &self.location())
+            .field("thread_name", &self.thread_name())
             .field("backtrace", &{
                 #[cfg(feature = "backtrace")]
                 {
@@ -116,14 +204,43 @@ impl fmt::Display for PanicInfo {
 #[derive(Debug)]
 pub struct SpawnError {
     kind: SpawnErrorKind,
+    child_output: Option<String>,
+    exit_status: Option<ExitStatus>,
+    context: Option<Box<ErrorContext>>,
+}
+
+/// What is known about the process or call an error comes from.
+#[derive(Debug, Default)]
+pub(crate) struct ErrorContext {
+    pub pid: Option<u32>,
+    pub executable: Option<PathBuf>,
+    pub function: Option<String>,
+    pub spawn_location: Option<&'static std::panic::Location<'static>>,
 }
 
 #[derive(Debug)]
 enum SpawnErrorKind {
     Bincode(BincodeError),
     Io(io::Error),
-    Panic(PanicInfo),
+    Panic(Box<PanicInfo>),
+    App {
+        error: Box<dyn Any + Send + Sync>,
+        debug: String,
+    },
     IpcChannelClosed(io::Error),
+    BootstrapFailed {
+        status: Option<ExitStatus>,
+        timed_out: bool,
+    },
+    NestingLimitExceeded(usize),
+    ExecFailed(io::Error),
+    MessageTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    Quarantined,
+    PoolFailed,
+    ConcurrencyLimited(io::Error),
     Cancelled,
     TimedOut,
     Consumed,
@@ -133,7 +250,7 @@ impl SpawnError {
     /// If a panic ocurred this returns the captured panic info.
     pub fn panic_info(&self) -> Option<&PanicInfo> {
         if let SpawnErrorKind::Panic(ref info) = self.kind {
-            Some(info)
+            Some(&**info)
         } else {
             None
         }
@@ -144,6 +261,39 @@ impl SpawnError {
         self.panic_info().is_some()
     }
 
+    /// Returns the error the spawned function returned if it is of type `E`.
+    ///
+    /// Errors of functions are only reported as `SpawnError` by
+    /// [`JoinHandle::join_result`](struct.JoinHandle.html#method.join_result).
+    pub fn app_error<E: 'static>(&self) -> Option<&E> {
+        match self.kind {
+            SpawnErrorKind::App { ref error, .. } => error.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Converts this error into the error the spawned function returned.
+    ///
+    /// If the error did not come from the function or is not of type `E`
+    /// the error is returned unchanged.
+    pub fn into_app_error<E: 'static>(self) -> Result<E, SpawnError> {
+        match self.kind {
+            SpawnErrorKind::App { error, debug } => match error.downcast() {
+                Ok(error) => Ok(*error),
+                Err(error) => Err(SpawnError {
+                    kind: SpawnErrorKind::App { error, debug },
+                    ..self
+                }),
+            },
+            _ => Err(self),
+        }
+    }
+
+    /// True if this error was returned by the spawned function.
+    pub fn is_app_error(&self) -> bool {
+        matches!(self.kind, SpawnErrorKind::App { .. })
+    }
+
     /// True if this error indicates a cancellation.
     pub fn is_cancellation(&self) -> bool {
         matches!(self.kind, SpawnErrorKind::Cancelled)
@@ -159,32 +309,286 @@ impl SpawnError {
         matches!(self.kind, SpawnErrorKind::IpcChannelClosed(..))
     }
 
-    pub(crate) fn new_remote_close() -> SpawnError {
-        SpawnError {
-            kind: SpawnErrorKind::IpcChannelClosed(io::Error::new(
-                io::ErrorKind::ConnectionReset,
-                "remote closed",
-            )),
+    /// True if the child never connected back to the parent.
+    ///
+    /// This happens if the child exited before it reached the bootstrap
+    /// code or if it did not connect within the configured bootstrap timeout.
+    pub fn is_bootstrap_failure(&self) -> bool {
+        matches!(self.kind, SpawnErrorKind::BootstrapFailed { .. })
+    }
+
+    /// True if the spawn was refused because of the nesting depth limit.
+    ///
+    /// See [`ProcConfig::max_nesting_depth`](struct.ProcConfig.html#method.max_nesting_depth).
+    pub fn is_nesting_limit_exceeded(&self) -> bool {
+        matches!(self.kind, SpawnErrorKind::NestingLimitExceeded(..))
+    }
+
+    /// True if the child process could not be executed.
+    ///
+    /// This covers everything that fails between creating the process and
+    /// executing the binary: a failing `pre_exec` hook, failures to switch
+    /// the user or group and errors of `exec` itself.  The standard library
+    /// reports these errors from the child through a close-on-exec pipe so
+    /// they are not mistaken for a child that never connected back.
+    ///
+    /// As the standard library does not tell these errors apart from
+    /// failures to fork, errors that are caused by running out of resources
+    /// (such as `EAGAIN` or `EMFILE`) are never considered exec failures.
+    /// Errors of custom [spawn backends](trait.SpawnBackend.html) are not
+    /// classified either.
+    pub fn is_exec_failure(&self) -> bool {
+        matches!(self.kind, SpawnErrorKind::ExecFailed(..))
+    }
+
+    /// Returns the OS error code if the child could not be executed.
+    pub fn exec_errno(&self) -> Option<i32> {
+        match self.kind {
+            SpawnErrorKind::ExecFailed(ref err) => err.raw_os_error(),
+            _ => None,
         }
     }
 
-    pub(crate) fn new_cancelled() -> SpawnError {
-        SpawnError {
-            kind: SpawnErrorKind::Cancelled,
+    /// True if a message exceeded the configured maximum message size.
+    ///
+    /// See [`ProcConfig::max_message_size`](struct.ProcConfig.html#method.max_message_size).
+    pub fn is_message_too_large(&self) -> bool {
+        matches!(self.kind, SpawnErrorKind::MessageTooLarge { .. })
+    }
+
+    /// Returns the size in bytes of the message that was too large.
+    pub fn message_size(&self) -> Option<usize> {
+        match self.kind {
+            SpawnErrorKind::MessageTooLarge { size, .. } => Some(size),
+            _ => None,
         }
     }
 
-    pub(crate) fn new_timeout() -> SpawnError {
+    /// True if a value could not be serialized or deserialized.
+    ///
+    /// This includes messages that exceed the configured maximum message
+    /// size.  These errors come from the types involved and happen again
+    /// if the call is retried.
+    pub fn is_serialization(&self) -> bool {
+        matches!(
+            self.kind,
+            SpawnErrorKind::Bincode(_) | SpawnErrorKind::MessageTooLarge { .. }
+        )
+    }
+
+    /// True if the child process could not be started.
+    ///
+    /// This is the case for [bootstrap failures](#method.is_bootstrap_failure)
+    /// and [exec failures](#method.is_exec_failure).
+    pub fn is_spawn_failure(&self) -> bool {
+        matches!(
+            self.kind,
+            SpawnErrorKind::BootstrapFailed { .. } | SpawnErrorKind::ExecFailed(_)
+        )
+    }
+
+    /// True if the error comes from the processes or the communication
+    /// with them rather than from the call itself.
+    ///
+    /// These are I/O errors, [spawn failures](#method.is_spawn_failure),
+    /// children that went away without a result and pools that gave up on
+    /// their workers.  Panics, errors of the function, serialization errors,
+    /// timeouts, cancellations and refusals because of configured limits
+    /// are not infrastructure errors.  This is meant for policies that only
+    /// retry calls which could succeed on another attempt:
+    ///
+    /// ```rust,no_run
+    /// let mut attempts = 0;
+    /// let rv = loop {
+    ///     match procspawn::spawn(21, |x: i32| x * 2).join() {
+    ///         Err(err) if err.is_infrastructure() && attempts < 3 => attempts += 1,
+    ///         rv => break rv,
+    ///     }
+    /// };
+    /// ```
+    pub fn is_infrastructure(&self) -> bool {
+        matches!(
+            self.kind,
+            SpawnErrorKind::Io(_)
+                | SpawnErrorKind::IpcChannelClosed(_)
+                | SpawnErrorKind::BootstrapFailed { .. }
+                | SpawnErrorKind::ExecFailed(_)
+                | SpawnErrorKind::PoolFailed
+        )
+    }
+
+    /// True if a pooled call was refused because its arguments are quarantined.
+    ///
+    /// See [`PoolBuilder::quarantine`](struct.PoolBuilder.html#method.quarantine).
+    pub fn is_quarantined(&self) -> bool {
+        matches!(self.kind, SpawnErrorKind::Quarantined)
+    }
+
+    /// True if a pooled call failed because its pool gave up on crashing
+    /// workers.
+    ///
+    /// See [`PoolBuilder::max_restarts`](struct.PoolBuilder.html#method.max_restarts).
+    pub fn is_pool_failed(&self) -> bool {
+        matches!(self.kind, SpawnErrorKind::PoolFailed)
+    }
+
+    /// True if a concurrency limiter refused to hand out a permit.
+    ///
+    /// See [`ConcurrencyLimiter`](trait.ConcurrencyLimiter.html).
+    pub fn is_concurrency_limited(&self) -> bool {
+        matches!(self.kind, SpawnErrorKind::ConcurrencyLimited(_))
+    }
+
+    /// Returns the output captured from the child's stderr, if available.
+    ///
+    /// Output is only available if stderr was captured with
+    /// [`Builder::stderr`](struct.Builder.html#method.stderr) and the child
+    /// either never connected back or went away without sending a result.
+    /// In the latter case only the last 16KB of the output are kept.
+    pub fn child_output(&self) -> Option<&str> {
+        self.child_output.as_deref()
+    }
+
+    /// Returns the signal that terminated the child, if known.
+    ///
+    /// This is available if the child never connected back or went away
+    /// without sending a result.  It tells a child that crashed (for
+    /// instance `SIGSEGV` or `SIGABRT`) or was killed by the OOM killer
+    /// (`SIGKILL`) apart from one that exited.  Always `None` on platforms
+    /// other than unix.
+    pub fn signal(&self) -> Option<i32> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            self.exit_status.and_then(|status| status.signal())
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    /// Returns where the process or call that failed was spawned.
+    ///
+    /// This is available for errors returned by the join methods of a
+    /// [`JoinHandle`](struct.JoinHandle.html).
+    pub fn spawn_location(&self) -> Option<&'static std::panic::Location<'static>> {
+        self.context.as_ref().and_then(|x| x.spawn_location)
+    }
+
+    /// Returns the process ID of the child the error comes from.
+    ///
+    /// For pooled calls this is the worker that ran the call.  Like the
+    /// other context this is only available for errors returned by the join
+    /// methods of a [`JoinHandle`](struct.JoinHandle.html).
+    pub fn pid(&self) -> Option<u32> {
+        self.context.as_ref().and_then(|x| x.pid)
+    }
+
+    /// Returns the path of the executable the child ran.
+    ///
+    /// Symlinks such as `/proc/self/exe` are resolved.
+    pub fn executable(&self) -> Option<&Path> {
+        self.context.as_ref().and_then(|x| x.executable.as_deref())
+    }
+
+    /// Returns the name of the spawned function.
+    ///
+    /// Names are only resolved with the `backtrace` feature.
+    pub fn function(&self) -> Option<&str> {
+        self.context.as_ref().and_then(|x| x.function.as_deref())
+    }
+
+    fn from_kind(kind: SpawnErrorKind) -> SpawnError {
         SpawnError {
-            kind: SpawnErrorKind::TimedOut,
+            kind,
+            child_output: None,
+            exit_status: None,
+            context: None,
         }
     }
 
-    pub(crate) fn new_consumed() -> SpawnError {
+    pub(crate) fn new_remote_close() -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::IpcChannelClosed(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "remote closed",
+        )))
+    }
+
+    pub(crate) fn new_bootstrap_failure(
+        status: Option<ExitStatus>,
+        timed_out: bool,
+        child_output: Option<String>,
+    ) -> SpawnError {
         SpawnError {
-            kind: SpawnErrorKind::Consumed,
+            kind: SpawnErrorKind::BootstrapFailed { status, timed_out },
+            child_output,
+            exit_status: status,
+            context: None,
         }
     }
+
+    pub(crate) fn with_child_output(mut self, child_output: Option<String>) -> SpawnError {
+        self.child_output = child_output;
+        self
+    }
+
+    pub(crate) fn with_exit_status(mut self, exit_status: Option<ExitStatus>) -> SpawnError {
+        self.exit_status = exit_status;
+        self
+    }
+
+    pub(crate) fn new_app_error<E: fmt::Debug + Send + Sync + 'static>(error: E) -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::App {
+            debug: format!("{:?}", error),
+            error: Box::new(error),
+        })
+    }
+
+    /// Attaches the context of a join handle.
+    ///
+    /// The outermost handle wins, errors of pool workers carry the context
+    /// of the worker otherwise.
+    pub(crate) fn with_context(mut self, context: ErrorContext) -> SpawnError {
+        self.context = Some(Box::new(context));
+        self
+    }
+
+    pub(crate) fn new_nesting_limit_exceeded(max_depth: usize) -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::NestingLimitExceeded(max_depth))
+    }
+
+    pub(crate) fn new_exec_failure(err: io::Error) -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::ExecFailed(err))
+    }
+
+    pub(crate) fn new_message_too_large(size: usize, limit: usize) -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::MessageTooLarge { size, limit })
+    }
+
+    pub(crate) fn new_quarantined() -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::Quarantined)
+    }
+
+    pub(crate) fn new_pool_failed() -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::PoolFailed)
+    }
+
+    pub(crate) fn new_concurrency_limited(err: io::Error) -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::ConcurrencyLimited(err))
+    }
+
+    pub(crate) fn new_cancelled() -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::Cancelled)
+    }
+
+    pub(crate) fn new_timeout() -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::TimedOut)
+    }
+
+    pub(crate) fn new_consumed() -> SpawnError {
+        SpawnError::from_kind(SpawnErrorKind::Consumed)
+    }
 }
 
 impl std::error::Error for SpawnError {
@@ -193,6 +597,14 @@ impl std::error::Error for SpawnError {
             SpawnErrorKind::Bincode(ref err) => Some(err),
             SpawnErrorKind::Io(ref err) => Some(err),
             SpawnErrorKind::Panic(_) => None,
+            SpawnErrorKind::App { .. } => None,
+            SpawnErrorKind::BootstrapFailed { .. } => None,
+            SpawnErrorKind::NestingLimitExceeded(_) => None,
+            SpawnErrorKind::ExecFailed(ref err) => Some(err),
+            SpawnErrorKind::MessageTooLarge { .. } => None,
+            SpawnErrorKind::Quarantined => None,
+            SpawnErrorKind::PoolFailed => None,
+            SpawnErrorKind::ConcurrencyLimited(ref err) => Some(err),
             SpawnErrorKind::Cancelled => None,
             SpawnErrorKind::TimedOut => None,
             SpawnErrorKind::Consumed => None,
@@ -207,26 +619,104 @@ impl fmt::Display for SpawnError {
             SpawnErrorKind::Bincode(_) => write!(f, "process spawn error: bincode error"),
             SpawnErrorKind::Io(_) => write!(f, "process spawn error: i/o error"),
             SpawnErrorKind::Panic(ref p) => write!(f, "process spawn error: panic: {}", p),
+            SpawnErrorKind::App { ref debug, .. } => {
+                write!(f, "process spawn error: function failed: {}", debug)
+            }
+            SpawnErrorKind::BootstrapFailed {
+                timed_out: true, ..
+            } => write!(
+                f,
+                "process spawn error: child did not connect within bootstrap timeout"
+            ),
+            SpawnErrorKind::BootstrapFailed {
+                status: Some(status),
+                ..
+            } => write!(
+                f,
+                "process spawn error: child exited before connecting ({})",
+                status
+            ),
+            SpawnErrorKind::BootstrapFailed { status: None, .. } => {
+                write!(f, "process spawn error: child exited before connecting")
+            }
+            SpawnErrorKind::NestingLimitExceeded(max_depth) => write!(
+                f,
+                "process spawn error: nesting depth limit of {} exceeded",
+                max_depth
+            ),
+            SpawnErrorKind::ExecFailed(ref err) => {
+                write!(f, "process spawn error: could not execute child: {}", err)
+            }
+            SpawnErrorKind::MessageTooLarge { size, limit } => write!(
+                f,
+                "process spawn error: message of {} bytes exceeds the limit of {} bytes",
+                size, limit
+            ),
+            SpawnErrorKind::Quarantined => write!(
+                f,
+                "process spawn error: arguments are quarantined after an earlier failure"
+            ),
+            SpawnErrorKind::PoolFailed => write!(
+                f,
+                "process spawn error: pool failed after too many worker restarts"
+            ),
+            SpawnErrorKind::ConcurrencyLimited(_) => {
+                write!(
+                    f,
+                    "process spawn error: concurrency limiter refused a permit"
+                )
+            }
             SpawnErrorKind::Cancelled => write!(f, "process spawn error: call cancelled"),
             SpawnErrorKind::TimedOut => write!(f, "process spawn error: timed out"),
             SpawnErrorKind::Consumed => write!(f, "process spawn error: result already consumed"),
-            SpawnErrorKind::IpcChannelClosed(_) => write!(
-                f,
-                "process spawn error: remote side closed (might have panicked on serialization)"
-            ),
+            SpawnErrorKind::IpcChannelClosed(_) => match self.signal() {
+                Some(signal) => write!(
+                    f,
+                    "process spawn error: child was terminated by signal {}",
+                    signal
+                ),
+                None => write!(
+                    f,
+                    "process spawn error: remote side closed (might have panicked on serialization)"
+                ),
+            },
+        }?;
+        if let Some(ref context) = self.context {
+            let mut parts = Vec::new();
+            if let Some(pid) = context.pid {
+                parts.push(format!("pid {}", pid));
+            }
+            if let Some(ref executable) = context.executable {
+                parts.push(format!("executable {}", executable.display()));
+            }
+            if let Some(ref function) = context.function {
+                parts.push(format!("function {}", function));
+            }
+            if let Some(location) = context.spawn_location {
+                parts.push(format!("spawned at {}", location));
+            }
+            if !parts.is_empty() {
+                write!(f, " ({})", parts.join(", "))?;
+            }
+        }
+        if let Some(ref output) = self.child_output {
+            write!(f, "\n\nchild output:\n{}", output.trim_end())?;
         }
+        Ok(())
     }
 }
 
 impl From<BincodeError> for SpawnError {
     fn from(err: BincodeError) -> SpawnError {
+        // the codec rejected a message because of its size
+        if let Some((size, limit)) = take_oversized_message() {
+            return SpawnError::new_message_too_large(size, limit);
+        }
         // unwrap nested IO errors
         if let BincodeErrorKind::Io(io_err) = *err {
             return SpawnError::from(io_err);
         }
-        SpawnError {
-            kind: SpawnErrorKind::Bincode(err),
-        }
+        SpawnError::from_kind(SpawnErrorKind::Bincode(err))
     }
 }
 
@@ -253,20 +743,14 @@ impl From<IpcError> for SpawnError {
 impl From<io::Error> for SpawnError {
     fn from(err: io::Error) -> SpawnError {
         if let io::ErrorKind::ConnectionReset = err.kind() {
-            return SpawnError {
-                kind: SpawnErrorKind::IpcChannelClosed(err),
-            };
-        }
-        SpawnError {
-            kind: SpawnErrorKind::Io(err),
+            return SpawnError::from_kind(SpawnErrorKind::IpcChannelClosed(err));
         }
+        SpawnError::from_kind(SpawnErrorKind::Io(err))
     }
 }
 
 impl From<PanicInfo> for SpawnError {
     fn from(panic: PanicInfo) -> SpawnError {
-        SpawnError {
-            kind: SpawnErrorKind::Panic(panic),
-        }
+        SpawnError::from_kind(SpawnErrorKind::Panic(Box::new(panic)))
     }
 }
@@ -0,0 +1,322 @@
+use std::env;
+use std::fs::{DirBuilder, File};
+use std::io;
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::endpoints::EndpointGuard;
+use crate::serde::in_ipc_mode;
+
+/// How long an offered descriptor waits to be picked up.
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(60);
+
+static HANDOFF_COUNTER: AtomicUsize = AtomicUsize::new(0);
+static PENDING_HANDOFFS: Mutex<usize> = Mutex::new(0);
+static HANDOFF_DONE: Condvar = Condvar::new();
+
+/// Counts a handoff as pending for as long as it is alive.
+struct PendingHandoff;
+
+impl PendingHandoff {
+    fn new() -> PendingHandoff {
+        *PENDING_HANDOFFS.lock().unwrap() += 1;
+        PendingHandoff
+    }
+}
+
+impl Drop for PendingHandoff {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = PENDING_HANDOFFS.lock() {
+            *pending -= 1;
+        }
+        HANDOFF_DONE.notify_all();
+    }
+}
+
+/// Waits until all descriptors offered by this process were picked up.
+///
+/// Spawned processes call this before they exit so that descriptors in
+/// return values reach the parent.
+pub fn wait_for_handoffs() {
+    let pending = PENDING_HANDOFFS.lock().unwrap();
+    HANDOFF_DONE
+        .wait_timeout_while(pending, HANDOFF_TIMEOUT, |pending| *pending > 0)
+        .ok();
+}
+
+/// Offers a descriptor on a fresh unix socket and returns its path.
+///
+/// The first process that connects to the socket receives the descriptor.
+/// If nobody picks it up within the timeout it is closed.
+pub fn offer_fd(fd: OwnedFd) -> io::Result<PathBuf> {
+    let dir = loop {
+        let dir = env::temp_dir().join(format!(
+            ".tmpprocspawnfd{}x{}",
+            std::process::id(),
+            HANDOFF_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => break dir,
+            // left behind by an earlier process with the same pid
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    };
+    let path = dir.join("socket");
+    let guard = EndpointGuard::register(&path.to_string_lossy());
+    let listener = UnixListener::bind(&path)?;
+    let pending = PendingHandoff::new();
+    thread::Builder::new()
+        .name("procspawn-fd-handoff".into())
+        .spawn(move || {
+            let _guard = guard;
+            let _pending = pending;
+            if wait_readable(&listener, HANDOFF_TIMEOUT) {
+                if let Ok((stream, _)) = listener.accept() {
+                    send_fd(&stream, fd.as_raw_fd()).ok();
+                }
+            }
+        })?;
+    Ok(path)
+}
+
+/// Picks up a descriptor offered with [`offer_fd`].
+pub fn take_fd(path: &Path) -> io::Result<OwnedFd> {
+    let stream = UnixStream::connect(path)?;
+    recv_fd(&stream)
+}
+
+fn wait_readable(listener: &UnixListener, timeout: Duration) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd: listener.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) > 0 }
+}
+
+fn cmsg_space() -> usize {
+    unsafe { libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as usize }
+}
+
+fn send_fd(stream: &UnixStream, fd: RawFd) -> io::Result<()> {
+    let mut byte = [0u8];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+        iov_len: 1,
+    };
+    // u64 keeps the control buffer aligned for the header
+    let mut control = vec![0u64; cmsg_space() / mem::size_of::<u64>() + 1];
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = cmsg_space() as _;
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+        if libc::sendmsg(stream.as_raw_fd(), &msg, 0) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn recv_fd(stream: &UnixStream) -> io::Result<OwnedFd> {
+    let mut byte = [0u8];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+        iov_len: 1,
+    };
+    let mut control = vec![0u64; cmsg_space() / mem::size_of::<u64>() + 1];
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = cmsg_space() as _;
+        if libc::recvmsg(stream.as_raw_fd(), &mut msg, 0) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null()
+            || (*cmsg).cmsg_level != libc::SOL_SOCKET
+            || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file descriptor was not handed over",
+            ));
+        }
+        let fd = OwnedFd::from_raw_fd(ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd));
+        if libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(fd)
+    }
+}
+
+pub(crate) fn serialize_fd<S: Serializer>(
+    fd: io::Result<OwnedFd>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if !in_ipc_mode() {
+        return Err(ser::Error::custom(
+            "file descriptors can only be sent to processes spawned by procspawn",
+        ));
+    }
+    let path = fd.and_then(offer_fd).map_err(ser::Error::custom)?;
+    path.serialize(serializer)
+}
+
+pub(crate) fn deserialize_fd<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<OwnedFd, D::Error> {
+    if !in_ipc_mode() {
+        return Err(de::Error::custom(
+            "file descriptors can only be received from procspawn",
+        ));
+    }
+    let path = PathBuf::deserialize(deserializer)?;
+    take_fd(&path).map_err(de::Error::custom)
+}
+
+/// A file descriptor that can be sent to a spawned process.
+///
+/// When sent to a process the descriptor is duplicated into the receiving
+/// process, the sender keeps its own copy.  This can be used to hand pipes,
+/// sockets, memfds or any other descriptor to a child.  Descriptors can only
+/// be sent as part of arguments or return values of procspawn, trying to
+/// serialize them otherwise or with a [codec](../codec/index.html) fails.
+///
+/// ```rust,no_run
+/// use std::io::Read;
+/// use std::os::unix::io::OwnedFd;
+/// use procspawn::serde::IpcFd;
+///
+/// let (read, write) = std::os::unix::net::UnixStream::pair().unwrap();
+/// let handle = procspawn::spawn(IpcFd(OwnedFd::from(write)), |IpcFd(fd)| {
+///     let mut stream = std::os::unix::net::UnixStream::from(fd);
+///     std::io::Write::write_all(&mut stream, b"hello").unwrap();
+/// });
+/// ```
+///
+/// The descriptor is handed over through a unix socket only accessible to
+/// the current user, so it cannot be received by processes running as
+/// another user.
+///
+/// This is only available on unix platforms.
+#[derive(Debug)]
+pub struct IpcFd(pub OwnedFd);
+
+impl Serialize for IpcFd {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_fd(self.0.try_clone(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpcFd {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<IpcFd, D::Error> {
+        deserialize_fd(deserializer).map(IpcFd)
+    }
+}
+
+/// An open file that can be sent to a spawned process.
+///
+/// This works like [`IpcFd`](struct.IpcFd.html) but holds a
+/// [`File`](std::fs::File).  Both processes share the file offset.
+///
+/// This is only available on unix platforms.
+#[derive(Debug)]
+pub struct IpcFile(pub File);
+
+impl Serialize for IpcFile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_fd(self.0.try_clone().map(OwnedFd::from), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpcFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<IpcFile, D::Error> {
+        deserialize_fd(deserializer).map(|fd| IpcFile(File::from(fd)))
+    }
+}
+
+macro_rules! socket_wrapper {
+    ($(#[$attr:meta])* $name:ident($ty:ty)) => {
+        $(#[$attr])*
+        ///
+        /// This is only available on unix platforms.
+        #[derive(Debug)]
+        pub struct $name(pub $ty);
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_fd(self.0.try_clone().map(OwnedFd::from), serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+                deserialize_fd(deserializer).map(|fd| $name(<$ty>::from(fd)))
+            }
+        }
+    };
+}
+
+socket_wrapper! {
+    /// A listening TCP socket that can be sent to a spawned process.
+    ///
+    /// This works like [`IpcFd`](struct.IpcFd.html) but holds a
+    /// [`TcpListener`](std::net::TcpListener).  Both processes can accept
+    /// connections on the socket, which allows a parent to bind a port and
+    /// hand it over to a worker, or to replace a worker without ever closing
+    /// the port:
+    ///
+    /// ```rust,no_run
+    /// use std::net::TcpListener;
+    /// use procspawn::serde::IpcTcpListener;
+    ///
+    /// let listener = TcpListener::bind("127.0.0.1:8000").unwrap();
+    /// let handle = procspawn::spawn(IpcTcpListener(listener), |IpcTcpListener(listener)| {
+    ///     for stream in listener.incoming() {
+    ///         // handle the connection
+    ///     }
+    /// });
+    /// ```
+    IpcTcpListener(TcpListener)
+}
+
+socket_wrapper! {
+    /// A connected TCP socket that can be sent to a spawned process.
+    ///
+    /// This works like [`IpcFd`](struct.IpcFd.html) but holds a
+    /// [`TcpStream`](std::net::TcpStream).  It lets a parent accept
+    /// connections and delegate handling each of them to an isolated process.
+    /// The parent keeps its own copy of the socket, the connection is only
+    /// closed once both copies are dropped.
+    IpcTcpStream(TcpStream)
+}
+
+socket_wrapper! {
+    /// A listening unix socket that can be sent to a spawned process.
+    ///
+    /// This works like [`IpcTcpListener`](struct.IpcTcpListener.html) but
+    /// holds a [`UnixListener`](std::os::unix::net::UnixListener).
+    IpcUnixListener(UnixListener)
+}
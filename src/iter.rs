@@ -0,0 +1,439 @@
+//! Utilities for data parallelism on top of process pools.
+//!
+//! The [`ParallelBridge`](trait.ParallelBridge.html) trait lets you map
+//! over the items of a regular iterator in the processes of a
+//! [`Pool`](../struct.Pool.html):
+//!
+//! ```rust,no_run
+//! use procspawn::{iter::ParallelBridge, Pool};
+//!
+//! let pool = Pool::new(4).unwrap();
+//! let squares = (0..100u64)
+//!     .par_bridge(&pool)
+//!     .chunk_size(10)
+//!     .map(|x| x * x)
+//!     .collect::<Result<Vec<_>, _>>()
+//!     .unwrap();
+//! ```
+//!
+//! With [`spawn_iter`](fn.spawn_iter.html) a spawned function can produce
+//! its results one by one instead of returning them all at once:
+//!
+//! ```rust,no_run
+//! let squares = procspawn::iter::spawn_iter(100u64, |n| (0..n).map(|x| x * x));
+//! for square in squares {
+//!     println!("{}", square.unwrap());
+//! }
+//! ```
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec;
+
+use ipc_channel::ipc::{self, IpcError, IpcReceiver};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::core::MarshalledFn;
+use crate::error::SpawnError;
+use crate::pool::Pool;
+use crate::proc::{spawn, JoinHandle};
+use crate::serde::with_ipc_mode;
+
+/// Bridges a serial iterator into a process pool.
+///
+/// This is implemented for all iterators with serializable items.
+pub trait ParallelBridge: Iterator + Sized {
+    /// Creates a bridge that distributes the items over the given pool.
+    fn par_bridge(self, pool: &Pool) -> Bridge<'_, Self>;
+}
+
+impl<I> ParallelBridge for I
+where
+    I: Iterator,
+    I::Item: Serialize + DeserializeOwned,
+{
+    fn par_bridge(self, pool: &Pool) -> Bridge<'_, Self> {
+        Bridge {
+            iter: self,
+            pool,
+            chunk_size: 1,
+            target_overhead: None,
+            max_in_flight: pool.size() * 2,
+            unordered: false,
+        }
+    }
+}
+
+/// A serial iterator bridged into a pool.
+///
+/// Created by [`ParallelBridge::par_bridge`](trait.ParallelBridge.html#tymethod.par_bridge).
+pub struct Bridge<'a, I> {
+    iter: I,
+    pool: &'a Pool,
+    chunk_size: usize,
+    target_overhead: Option<f64>,
+    max_in_flight: usize,
+    unordered: bool,
+}
+
+impl<'a, I> fmt::Debug for Bridge<'a, I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Bridge")
+            .field("pool", &self.pool)
+            .field("chunk_size", &self.chunk_size)
+            .field("target_overhead", &self.target_overhead)
+            .field("max_in_flight", &self.max_in_flight)
+            .finish()
+    }
+}
+
+impl<'a, I> Bridge<'a, I>
+where
+    I: Iterator,
+    I::Item: Serialize + DeserializeOwned,
+{
+    /// Sets how many items are sent to a worker as a single job.
+    ///
+    /// Every job has a fixed IPC overhead so for cheap functions it's a
+    /// good idea to process more than one item per job.  The default is 1.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Enables adaptive chunk sizing.
+    ///
+    /// Instead of using a fixed chunk size the chunk size is adjusted while
+    /// the items are processed.  The bridge starts out with the configured
+    /// [`chunk_size`](#method.chunk_size) and measures how long the function
+    /// runs in the worker compared to the overall time a job takes.  Chunks
+    /// are then grown until the per-job overhead is below the given fraction
+    /// of the total time (for instance `0.05` for 5%).  To adjust quickly
+    /// the chunk size at most doubles with every completed job.
+    pub fn adaptive_chunk_size(mut self, target_overhead: f64) -> Self {
+        self.target_overhead = Some(target_overhead.clamp(0.001, 1.0));
+        self
+    }
+
+    /// Sets how many jobs may be queued or running at the same time.
+    ///
+    /// Items are only pulled from the source iterator when a job slot is
+    /// available.  The default is twice the size of the pool.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Yields results as soon as their job is done.
+    ///
+    /// By default results are yielded in the order of the source iterator,
+    /// so one slow job holds back the results of all jobs after it.  With
+    /// this the results of a job are yielded as soon as it's done.  The
+    /// results of a single job keep their order.
+    pub fn unordered(mut self) -> Self {
+        self.unordered = true;
+        self
+    }
+
+    /// Maps all items with the given function in the pool.
+    ///
+    /// The results are yielded in the order of the source iterator unless
+    /// [`unordered`](#method.unordered) is enabled.  If a job fails a single
+    /// error is yielded in place of the results of all items in that job.
+    pub fn map<R>(self, f: fn(I::Item) -> R) -> Map<'a, I, R>
+    where
+        R: Serialize + DeserializeOwned + Send + 'static,
+    {
+        let (done_tx, done_rx) = mpsc::channel();
+        Map {
+            sizer: ChunkSizer::new(self.chunk_size, self.target_overhead),
+            bridge: self,
+            func: f,
+            pending: VecDeque::new(),
+            in_flight: 0,
+            done_tx,
+            done_rx,
+            results: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// Iterator over the results of a bridged map.
+///
+/// Created by [`Bridge::map`](struct.Bridge.html#method.map).
+pub struct Map<'a, I: Iterator, R> {
+    bridge: Bridge<'a, I>,
+    sizer: ChunkSizer,
+    func: fn(<I as Iterator>::Item) -> R,
+    /// The jobs in the order of the source iterator.
+    pending: VecDeque<PendingChunk<R>>,
+    /// The number of jobs that report to `done_tx` once they are done.
+    in_flight: usize,
+    done_tx: mpsc::Sender<DoneChunk<R>>,
+    done_rx: mpsc::Receiver<DoneChunk<R>>,
+    results: vec::IntoIter<R>,
+}
+
+struct PendingChunk<R> {
+    submitted: Instant,
+    len: usize,
+    handle: JoinHandle<(Vec<R>, Duration)>,
+}
+
+impl<R: Serialize + DeserializeOwned> PendingChunk<R> {
+    fn join(self) -> DoneChunk<R> {
+        DoneChunk {
+            elapsed: self.submitted.elapsed(),
+            len: self.len,
+            rv: self.handle.join(),
+        }
+    }
+}
+
+struct DoneChunk<R> {
+    elapsed: Duration,
+    len: usize,
+    rv: Result<(Vec<R>, Duration), SpawnError>,
+}
+
+impl<'a, I, R> fmt::Debug for Map<'a, I, R>
+where
+    I: Iterator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Map")
+            .field("bridge", &self.bridge)
+            .field("chunk_size", &self.chunk_size())
+            .field("pending", &(self.pending.len() + self.in_flight))
+            .finish()
+    }
+}
+
+impl<'a, I: Iterator, R> Map<'a, I, R> {
+    /// Returns the chunk size used for the next job.
+    ///
+    /// This is only interesting with adaptive chunk sizing.
+    pub fn chunk_size(&self) -> usize {
+        self.sizer.chunk_size
+    }
+}
+
+impl<'a, I, R> Map<'a, I, R>
+where
+    I: Iterator,
+    I::Item: Serialize + DeserializeOwned,
+    R: Serialize + DeserializeOwned + Send + 'static,
+{
+    fn submit_chunks(&mut self) {
+        while self.pending.len() + self.in_flight < self.bridge.max_in_flight {
+            let chunk: Vec<_> = self
+                .bridge
+                .iter
+                .by_ref()
+                .take(self.sizer.chunk_size)
+                .collect();
+            if chunk.is_empty() {
+                break;
+            }
+            let chunk = PendingChunk {
+                submitted: Instant::now(),
+                len: chunk.len(),
+                handle: self.bridge.pool.spawn(
+                    (MarshalledFn::new(self.func), chunk),
+                    |(func, chunk)| {
+                        let started = Instant::now();
+                        let results = chunk.into_iter().map(func.get()).collect();
+                        (results, started.elapsed())
+                    },
+                ),
+            };
+            if self.bridge.unordered {
+                // pooled results are only handed over when they are
+                // joined, so every job is waited for on its own thread.
+                let done_tx = self.done_tx.clone();
+                thread::Builder::new()
+                    .name("procspawn-map".into())
+                    .spawn(move || done_tx.send(chunk.join()).ok())
+                    .unwrap();
+                self.in_flight += 1;
+            } else {
+                self.pending.push_back(chunk);
+            }
+        }
+    }
+
+    fn next_done(&mut self) -> Option<DoneChunk<R>> {
+        if self.bridge.unordered {
+            if self.in_flight == 0 {
+                return None;
+            }
+            self.in_flight -= 1;
+            self.done_rx.recv().ok()
+        } else {
+            self.pending.pop_front().map(PendingChunk::join)
+        }
+    }
+}
+
+impl<'a, I, R> Iterator for Map<'a, I, R>
+where
+    I: Iterator,
+    I::Item: Serialize + DeserializeOwned,
+    R: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Item = Result<R, SpawnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(rv) = self.results.next() {
+                return Some(Ok(rv));
+            }
+            self.submit_chunks();
+            let chunk = self.next_done()?;
+            match chunk.rv {
+                Ok((results, runtime)) => {
+                    self.sizer.record(chunk.len, chunk.elapsed, runtime);
+                    self.results = results.into_iter();
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Picks chunk sizes based on measured job overhead.
+struct ChunkSizer {
+    chunk_size: usize,
+    target_overhead: Option<f64>,
+    overhead: Option<Duration>,
+}
+
+impl ChunkSizer {
+    fn new(chunk_size: usize, target_overhead: Option<f64>) -> ChunkSizer {
+        ChunkSizer {
+            chunk_size,
+            target_overhead,
+            overhead: None,
+        }
+    }
+
+    fn record(&mut self, len: usize, elapsed: Duration, runtime: Duration) {
+        let target_overhead = match self.target_overhead {
+            Some(target_overhead) => target_overhead,
+            None => return,
+        };
+
+        // the time a job took also includes the time it was waiting in the
+        // queue, so the smallest difference is the best estimate for the
+        // fixed cost of a job.
+        let overhead = elapsed.saturating_sub(runtime);
+        let overhead = self.overhead.map_or(overhead, |x| x.min(overhead));
+        self.overhead = Some(overhead);
+
+        let per_item = runtime.as_secs_f64() / len.max(1) as f64;
+        let wanted = if per_item > 0.0 {
+            overhead.as_secs_f64() * (1.0 - target_overhead) / (target_overhead * per_item)
+        } else {
+            f64::INFINITY
+        };
+        self.chunk_size = (wanted.ceil() as usize).clamp(1, self.chunk_size.saturating_mul(2));
+    }
+}
+
+/// Spawns a function whose results are streamed back one by one.
+///
+/// The function runs in a new process and returns an iterator.  Every item
+/// is sent back to the parent as soon as the child produced it and the
+/// returned [`SpawnIter`](struct.SpawnIter.html) yields the items as they
+/// arrive.  This is useful for long computations that report intermediate
+/// results.
+///
+/// The items are sent individually through an IPC channel, so they are
+/// neither encoded with the configured codec nor split up if they are
+/// large.
+#[track_caller]
+pub fn spawn_iter<A, I>(args: A, f: fn(A) -> I) -> SpawnIter<I::Item>
+where
+    A: Serialize + DeserializeOwned,
+    I: IntoIterator,
+    I::Item: Serialize + DeserializeOwned,
+{
+    let (tx, rx) = ipc::channel().unwrap();
+    let handle = spawn((MarshalledFn::new(f), args, tx), |(func, args, tx)| {
+        for item in func.get()(args) {
+            // the parent went away, nobody is interested in more items.
+            if with_ipc_mode(|| tx.send(item)).is_err() {
+                break;
+            }
+        }
+    });
+    SpawnIter {
+        rx,
+        handle: Some(handle),
+    }
+}
+
+/// Iterator over the items streamed back by a spawned function.
+///
+/// Created by [`spawn_iter`](fn.spawn_iter.html).  Once all items were
+/// received the iterator joins the process.  If the process failed, for
+/// instance because it panicked, a final error is yielded.  Dropping the
+/// iterator before it was exhausted kills the process.
+pub struct SpawnIter<T> {
+    rx: IpcReceiver<T>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl<T> fmt::Debug for SpawnIter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpawnIter")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl<T> SpawnIter<T> {
+    /// Returns the process ID of the spawned process.
+    ///
+    /// This is `None` once the process was joined.
+    pub fn pid(&self) -> Option<u32> {
+        self.handle.as_ref().and_then(JoinHandle::pid)
+    }
+
+    /// Kills the process and ends the iterator.
+    pub fn kill(&mut self) -> Result<(), SpawnError> {
+        match self.handle.take() {
+            Some(mut handle) => handle.kill(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Iterator for SpawnIter<T> {
+    type Item = Result<T, SpawnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let handle = self.handle.take()?;
+        match with_ipc_mode(|| self.rx.recv()) {
+            Ok(item) => {
+                self.handle = Some(handle);
+                Some(Ok(item))
+            }
+            // the process is done sending items
+            Err(IpcError::Disconnected) => handle.join().err().map(Err),
+            Err(err) => {
+                let mut handle = handle;
+                handle.kill().ok();
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+impl<T> Drop for SpawnIter<T> {
+    fn drop(&mut self) {
+        self.kill().ok();
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::deadline::DEADLINE_POLL_INTERVAL;
+use crate::error::SpawnError;
+use crate::proc::JoinHandle;
+
+/// Polls handles with a growing interval until one of them is done.
+///
+/// Returns the index of the handle and its result, or `None` if the
+/// deadline passed first.
+fn poll_any<T: Serialize + DeserializeOwned>(
+    handles: &mut [JoinHandle<T>],
+    deadline: Option<Instant>,
+) -> Option<(usize, Result<T, SpawnError>)> {
+    let mut to_sleep = Duration::from_millis(1);
+    loop {
+        for (index, handle) in handles.iter_mut().enumerate() {
+            if let Some(result) = handle.try_join() {
+                return Some((index, result));
+            }
+        }
+        let wait = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::ZERO => remaining.min(to_sleep),
+                _ => return None,
+            },
+            None => to_sleep,
+        };
+        thread::sleep(wait);
+        to_sleep = (to_sleep * 2).min(DEADLINE_POLL_INTERVAL);
+    }
+}
+
+/// Joins all handles and returns their results in the order of the handles.
+///
+/// The handles are waited on at the same time without extra threads, so
+/// pooled calls can finish in any order:
+///
+/// ```rust,no_run
+/// let handles = (0..4u64).map(|i| procspawn::spawn(i, |i| i * 2));
+/// let results = procspawn::join_all(handles);
+/// assert_eq!(results.into_iter().map(Result::unwrap).sum::<u64>(), 12);
+/// ```
+pub fn join_all<T, I>(handles: I) -> Vec<Result<T, SpawnError>>
+where
+    T: Serialize + DeserializeOwned,
+    I: IntoIterator<Item = JoinHandle<T>>,
+{
+    join_all_until(handles.into_iter().collect(), None)
+}
+
+/// Like [`join_all`](fn.join_all.html) but gives up after a timeout.
+///
+/// Handles that did not finish in time are killed and their result is a
+/// timeout error.
+pub fn join_all_timeout<T, I>(handles: I, timeout: Duration) -> Vec<Result<T, SpawnError>>
+where
+    T: Serialize + DeserializeOwned,
+    I: IntoIterator<Item = JoinHandle<T>>,
+{
+    join_all_until(
+        handles.into_iter().collect(),
+        Instant::now().checked_add(timeout),
+    )
+}
+
+fn join_all_until<T: Serialize + DeserializeOwned>(
+    mut handles: Vec<JoinHandle<T>>,
+    deadline: Option<Instant>,
+) -> Vec<Result<T, SpawnError>> {
+    let mut results: Vec<Option<Result<T, SpawnError>>> = handles.iter().map(|_| None).collect();
+    let mut indexes: Vec<usize> = (0..handles.len()).collect();
+    while !handles.is_empty() {
+        match poll_any(&mut handles, deadline) {
+            Some((index, result)) => {
+                handles.swap_remove(index);
+                results[indexes.swap_remove(index)] = Some(result);
+            }
+            None => {
+                for (mut handle, index) in handles.drain(..).zip(indexes.drain(..)) {
+                    handle.kill().ok();
+                    results[index] = Some(Err(SpawnError::new_timeout()));
+                }
+            }
+        }
+    }
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+/// Waits for the first of many handles to finish.
+///
+/// The finished handle is removed from the vector and its index is returned
+/// together with its result, the other handles keep their order:
+///
+/// ```rust,no_run
+/// let mut handles = vec![
+///     procspawn::spawn(2, |secs| std::thread::sleep(std::time::Duration::from_secs(secs))),
+///     procspawn::spawn(1, |secs| std::thread::sleep(std::time::Duration::from_secs(secs))),
+/// ];
+/// let (index, result) = procspawn::wait_any(&mut handles);
+/// result.unwrap();
+/// assert_eq!(index, 1);
+/// ```
+///
+/// # Panics
+///
+/// Panics if there are no handles.
+pub fn wait_any<T: Serialize + DeserializeOwned>(
+    handles: &mut Vec<JoinHandle<T>>,
+) -> (usize, Result<T, SpawnError>) {
+    assert!(!handles.is_empty(), "wait_any needs at least one handle");
+    wait_any_until(handles, None).unwrap()
+}
+
+/// Like [`wait_any`](fn.wait_any.html) but gives up after a timeout.
+///
+/// Returns `None` if no handle finished in time, in which case all handles
+/// stay in the vector.  Unlike `wait_any` this also returns `None` if there
+/// are no handles.
+pub fn wait_any_timeout<T: Serialize + DeserializeOwned>(
+    handles: &mut Vec<JoinHandle<T>>,
+    timeout: Duration,
+) -> Option<(usize, Result<T, SpawnError>)> {
+    if handles.is_empty() {
+        return None;
+    }
+    wait_any_until(handles, Instant::now().checked_add(timeout))
+}
+
+fn wait_any_until<T: Serialize + DeserializeOwned>(
+    handles: &mut Vec<JoinHandle<T>>,
+    deadline: Option<Instant>,
+) -> Option<(usize, Result<T, SpawnError>)> {
+    let (index, result) = poll_any(handles, deadline)?;
+    handles.remove(index);
+    Some((index, result))
+}
+
+/// A collection of join handles that are joined in the order they finish.
+///
+/// Handles of spawned processes and of pooled calls can be mixed freely.
+/// [`next`](#method.next) returns the result of whichever handle finishes
+/// first, which avoids polling loops over vectors of handles:
+///
+/// ```rust,no_run
+/// use procspawn::JoinSet;
+///
+/// let mut set = JoinSet::new();
+/// for i in 0..10u64 {
+///     set.insert(procspawn::spawn(i, |i| {
+///         std::thread::sleep(std::time::Duration::from_millis(100 * (10 - i)));
+///         i
+///     }));
+/// }
+/// while let Some(result) = set.next() {
+///     println!("finished: {}", result.unwrap());
+/// }
+/// ```
+///
+/// The handles are waited on without extra threads.  Handles that are
+/// still in the set when it's dropped are killed.
+pub struct JoinSet<T> {
+    handles: Vec<JoinHandle<T>>,
+}
+
+impl<T> fmt::Debug for JoinSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinSet")
+            .field("len", &self.handles.len())
+            .finish()
+    }
+}
+
+impl<T> Default for JoinSet<T> {
+    fn default() -> JoinSet<T> {
+        JoinSet::new()
+    }
+}
+
+impl<T> JoinSet<T> {
+    /// Creates an empty set.
+    pub fn new() -> JoinSet<T> {
+        JoinSet {
+            handles: Vec::new(),
+        }
+    }
+
+    /// Adds a handle to the set.
+    pub fn insert(&mut self, handle: JoinHandle<T>) {
+        self.handles.push(handle);
+    }
+
+    /// The number of handles that were not joined yet.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns `true` if there are no handles left.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Kills all handles that are left and empties the set.
+    pub fn kill_all(&mut self) {
+        for mut handle in self.handles.drain(..) {
+            handle.kill().ok();
+        }
+    }
+
+    /// Detaches all handles that are left and empties the set.
+    pub fn detach_all(&mut self) {
+        for handle in self.handles.drain(..) {
+            handle.detach();
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> JoinSet<T> {
+    /// Waits for the next handle to finish and returns its result.
+    ///
+    /// Returns `None` once the set is empty.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<T, SpawnError>> {
+        self.next_until(None)
+    }
+
+    /// Like `next` but gives up after a timeout.
+    ///
+    /// Returns `None` if the set is empty or no handle finished in time,
+    /// in which case all handles stay in the set.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<T, SpawnError>> {
+        self.next_until(Instant::now().checked_add(timeout))
+    }
+
+    fn next_until(&mut self, deadline: Option<Instant>) -> Option<Result<T, SpawnError>> {
+        if self.handles.is_empty() {
+            return None;
+        }
+        let (index, result) = poll_any(&mut self.handles, deadline)?;
+        self.handles.swap_remove(index);
+        Some(result)
+    }
+}
+
+impl<T> Drop for JoinSet<T> {
+    fn drop(&mut self) {
+        self.kill_all();
+    }
+}
@@ -0,0 +1,73 @@
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::core::log_stderr;
+
+static LEAK_DETECTION: AtomicBool = AtomicBool::new(false);
+
+pub fn set_leak_detection(enabled: bool) {
+    LEAK_DETECTION.store(enabled, Ordering::SeqCst);
+}
+
+/// Where a join handle was created.
+struct Origin {
+    location: &'static Location<'static>,
+    #[cfg(feature = "backtrace")]
+    backtrace: backtrace::Backtrace,
+}
+
+/// Warns when a join handle is dropped while its process might still be
+/// running.
+///
+/// The check is disarmed once the handle was joined, killed or detached.
+pub struct LeakCheck {
+    origin: Option<Box<Origin>>,
+}
+
+impl LeakCheck {
+    /// Records the caller as the origin of a handle if leak detection is
+    /// enabled.
+    #[track_caller]
+    pub fn new() -> LeakCheck {
+        let location = Location::caller();
+        LeakCheck {
+            origin: if LEAK_DETECTION.load(Ordering::SeqCst) {
+                Some(Box::new(Origin {
+                    location,
+                    #[cfg(feature = "backtrace")]
+                    backtrace: backtrace::Backtrace::new_unresolved(),
+                }))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// A check for handles that never had a process.
+    pub fn disarmed() -> LeakCheck {
+        LeakCheck { origin: None }
+    }
+
+    pub fn disarm(&mut self) {
+        self.origin = None;
+    }
+}
+
+impl Drop for LeakCheck {
+    fn drop(&mut self) {
+        if let Some(origin) = self.origin.take() {
+            #[cfg(feature = "backtrace")]
+            let backtrace = {
+                let mut backtrace = origin.backtrace;
+                backtrace.resolve();
+                format!("\nhandle created at:\n{:?}", backtrace)
+            };
+            #[cfg(not(feature = "backtrace"))]
+            let backtrace = "";
+            log_stderr(format_args!(
+                "procspawn: join handle created at {} was dropped without being joined, killed or detached{}",
+                origin.location, backtrace
+            ));
+        }
+    }
+}
@@ -45,7 +45,9 @@
 //!
 //! [`spawn`](fn.spawn.html) can pass arbitrary serializable data, including
 //! IPC senders and receivers from the [`ipc-channel`](https://crates.io/crates/ipc-channel)
-//! crate, down to the new process.
+//! crate, down to the new process.  The [`channel`](fn.channel.html) function
+//! creates such a pair of a sender and a receiver for talking to a process
+//! while it's running.
 //!
 //! # Pools
 //!
@@ -55,6 +57,9 @@
 //! will stay around which also means the can keep state between calls if
 //! needed.
 //!
+//! To map over the items of an iterator in a pool the
+//! [`ParallelBridge`](iter/trait.ParallelBridge.html) trait can be used.
+//!
 //! # Panics
 //!
 //! By default panics are captured and serialized across process boundaries.
@@ -75,9 +80,47 @@
 //!   backtraces are captured with the `backtrace-rs` crate and serialized
 //!   across process boundaries.
 //! * `test-support`: when this feature is enabled procspawn can be used
-//!   with rusttest.  See [`testing`](#testing) for more information.
+//!   with rusttest.  See [`testing`](#testing) for more information.  This
+//!   also enables [fault injection](testing/index.html).
 //! * `json`: enables optional JSON serialization.  For more information see
 //!   [Bincode Limitations](#bincode-limitations).
+//! * `msgpack`: enables the MessagePack [codec](codec/struct.MessagePack.html).
+//! * `cbor`: enables the CBOR [codec](codec/struct.Cbor.html) and the
+//!   [`Structural`](serde/struct.Structural.html) wrapper.
+//! * `postcard`: enables the postcard [codec](codec/struct.Postcard.html) and
+//!   the [`Postcard`](serde/struct.Postcard.html) wrapper.  Postcard produces
+//!   smaller messages than bincode and can be made the default with
+//!   [`ProcConfig::codec`](struct.ProcConfig.html#method.codec).
+//! * `lz4`, `zstd`: enable the respective
+//!   [compression](codec/enum.Compression.html) of large payloads.
+//! * `zerocopy`: enables the [`TypedShmem`](serde/struct.TypedShmem.html)
+//!   wrapper which gives typed access to shared memory without
+//!   deserialization.
+//! * `tracing`: the span that is current when a function is spawned is
+//!   recreated in the child so that events of the child are correlated with
+//!   the parent.  See [`spawn`](fn.spawn.html) for more information.  This
+//!   also enables [`ProcConfig::forward_logs`](struct.ProcConfig.html#method.forward_logs).
+//! * `metrics`: emits metrics through the [`metrics`](https://crates.io/crates/metrics)
+//!   facade.  See [Metrics](#metrics) for the emitted metrics.
+//!
+//! # Metrics
+//!
+//! With the `metrics` feature procspawn emits the following metrics through
+//! whatever recorder is installed in the process:
+//!
+//! * `procspawn_spawns_total`: counter of started spawns.
+//! * `procspawn_spawn_failures_total`: counter of spawns that failed.
+//! * `procspawn_spawn_duration_seconds`: histogram of the time it took to
+//!   spawn a process.
+//! * `procspawn_panics_total`: counter of spawned functions and pooled calls
+//!   that panicked.
+//! * `procspawn_args_bytes`: histogram of the size of the arguments sent to
+//!   spawned functions and pooled calls.
+//! * `procspawn_pool_queue_depth`: gauge of the calls that wait in the
+//!   queues of all pools.
+//!
+//! The spawn metrics carry a `kind` label which is `exec` for executed and
+//! `fork` for forked processes.
 //!
 //! # Bincode Limitations
 //!
@@ -86,7 +129,11 @@
 //! which make some serde features incompatible with it.  Most notably if you
 //! use `#[serde(flatten)]` data cannot be sent across the processes.  To
 //! work around this you can enable the `json` feature and wrap affected objects
-//! in the [`Json`](serde/struct.Json.html) wrapper to force JSON serialization.
+//! in the [`Json`](serde/struct.Json.html) wrapper to force JSON serialization,
+//! or enable the `cbor` feature and use the
+//! [`Structural`](serde/struct.Structural.html) wrapper which keeps the data
+//! binary.  Alternatively a different [codec](codec/index.html) can be selected for
+//! entire spawns.
 //!
 //! # Testing
 //!
@@ -105,7 +152,9 @@
 //!   which doesn't do anything when called directly, but acts as the spawning
 //!   helper for all `spawn` calls.
 //! * stdout is silenced by default unless `--show-output` or `--nocapture`
-//!   is passed to tests.
+//!   is passed to tests.  With
+//!   [`testing::forward_output`](testing/fn.forward_output.html) the output
+//!   is instead captured together with the output of the test.
 //! * when trying to spawn with intercepted `stdout` be aware that there is
 //!   extra noise that will be emitted by rusttest.
 //!
@@ -176,25 +225,104 @@
 #[macro_use]
 mod proc;
 
+mod adoption;
+mod audit;
+mod backend;
+mod capture;
+mod channel;
+mod chunked;
 mod core;
+#[cfg(unix)]
+mod coredump;
+mod coverage;
+mod deadline;
+#[cfg(unix)]
+mod detach;
+mod endpoints;
+mod env_policy;
 mod error;
+#[cfg(unix)]
+mod fdpass;
+mod join;
+mod leak;
+mod lifecycle;
+mod limiter;
+#[cfg(feature = "tracing")]
+mod logs;
+mod messages;
+#[cfg(unix)]
+mod mmap;
 mod panic;
 mod pool;
+mod progress;
+mod quarantine;
+#[cfg(unix)]
+mod reattach;
+mod service;
+#[cfg(unix)]
+mod shm;
+#[cfg(unix)]
+mod signals;
+#[cfg(feature = "tracing")]
+mod span;
+#[cfg(unix)]
+mod spill;
+#[cfg(feature = "metrics")]
+mod telemetry;
+#[cfg(unix)]
+mod user;
+#[cfg(unix)]
+mod zygote;
 
 #[cfg(feature = "json")]
 mod json;
+#[cfg(feature = "postcard")]
+mod postcard;
+#[cfg(feature = "cbor")]
+mod structural;
+#[cfg(feature = "zerocopy")]
+mod typed_shmem;
 
 #[doc(hidden)]
 pub mod testsupport;
 
+pub mod bench;
+pub mod call;
+pub mod codec;
+pub mod iter;
+pub mod remote;
 pub mod serde;
+#[cfg(feature = "test-support")]
+pub mod testing;
+pub mod transport;
 
 mod macros;
 
-pub use self::core::{assert_spawn_is_safe, init, ProcConfig};
+pub use self::audit::AuditRecord;
+pub use self::backend::{ExecBackend, SpawnBackend};
+pub use self::capture::CapturedOutput;
+pub use self::channel::{channel, parent, Receiver, Sender};
+pub use self::core::{assert_spawn_is_safe, init, refresh_shared_libraries, ProcConfig};
+pub use self::deadline::Deadline;
+#[cfg(unix)]
+pub use self::detach::DetachedChild;
+pub use self::endpoints::cleanup_stale_endpoints;
+pub use self::env_policy::EnvPolicy;
 pub use self::error::{Location, PanicInfo, SpawnError};
-pub use self::pool::{Pool, PoolBuilder};
+pub use self::join::{join_all, join_all_timeout, wait_any, wait_any_timeout, JoinSet};
+pub use self::lifecycle::{ExitInfo, ExitOutcome, SpawnInfo};
+pub use self::limiter::ConcurrencyLimiter;
+pub use self::messages::{MessageInfo, MessageKind};
+pub use self::pool::{
+    with_worker_state, DropBehavior, FullError, HandleDropBehavior, JobOutcome, JobStats, Pool,
+    PoolBuilder, PoolHealth, PoolStats, Priority, WorkerExit, WorkerInfo,
+};
 pub use self::proc::{spawn, Builder, JoinHandle};
-
-#[cfg(feature = "async")]
-pub use self::asyncsupport::{spawn_async, AsyncJoinHandle};
+pub use self::progress::{Progress, ProgressSender};
+#[cfg(unix)]
+pub use self::reattach::ReattachToken;
+pub use self::service::{service, Service};
+#[cfg(unix)]
+pub use self::signals::Signal;
+#[cfg(unix)]
+pub use self::zygote::Zygote;
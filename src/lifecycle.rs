@@ -0,0 +1,178 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub type SpawnHook = dyn Fn(&SpawnInfo) + Send + Sync;
+pub type ExitHook = dyn Fn(&ExitInfo) + Send + Sync;
+
+static SPAWN_HOOK: Mutex<Option<Arc<SpawnHook>>> = Mutex::new(None);
+static EXIT_HOOK: Mutex<Option<Arc<ExitHook>>> = Mutex::new(None);
+
+pub fn set_spawn_hook(hook: Arc<SpawnHook>) {
+    *SPAWN_HOOK.lock().unwrap() = Some(hook);
+}
+
+pub fn set_exit_hook(hook: Arc<ExitHook>) {
+    *EXIT_HOOK.lock().unwrap() = Some(hook);
+}
+
+/// Describes a process that was spawned.
+///
+/// Passed to the hook configured with
+/// [`ProcConfig::on_spawn`](struct.ProcConfig.html#method.on_spawn).
+#[derive(Debug, Clone)]
+pub struct SpawnInfo {
+    pid: u32,
+    sequence: u64,
+    function: Option<String>,
+    forked: bool,
+}
+
+impl SpawnInfo {
+    /// The process ID of the spawned process.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// The spawn sequence number of the process.
+    ///
+    /// See [`JoinHandle::sequence`](struct.JoinHandle.html#method.sequence).
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// The name of the spawned function if known.
+    ///
+    /// Names are only resolved with the `backtrace` feature.
+    pub fn function(&self) -> Option<&str> {
+        self.function.as_deref()
+    }
+
+    /// True if the process was forked instead of executed.
+    pub fn forked(&self) -> bool {
+        self.forked
+    }
+}
+
+/// How a spawned process ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitOutcome {
+    /// The function returned a value.
+    Returned,
+    /// The function panicked.
+    Panicked,
+    /// The process went away without a result or the result could not be
+    /// received.
+    Failed,
+    /// The process was killed.
+    Killed,
+}
+
+/// Describes a spawned process that ended.
+///
+/// Passed to the hook configured with
+/// [`ProcConfig::on_exit`](struct.ProcConfig.html#method.on_exit).
+#[derive(Debug, Clone)]
+pub struct ExitInfo {
+    pid: u32,
+    sequence: u64,
+    function: Option<String>,
+    duration: Duration,
+    outcome: ExitOutcome,
+}
+
+impl ExitInfo {
+    /// The process ID of the process.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// The spawn sequence number of the process.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// The name of the spawned function if known.
+    pub fn function(&self) -> Option<&str> {
+        self.function.as_deref()
+    }
+
+    /// The time from the spawn until the end of the process was noticed.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// How the process ended.
+    pub fn outcome(&self) -> ExitOutcome {
+        self.outcome
+    }
+}
+
+/// Resolves the name of a function from its address.
+#[cfg(feature = "backtrace")]
+pub(crate) fn function_name(func: *const ()) -> Option<String> {
+    let mut name = None;
+    // addresses are resolved like return addresses which point behind the
+    // call, so the start of the function has to be moved by one.
+    let addr = (func as usize + 1) as *mut std::ffi::c_void;
+    backtrace::resolve(addr, |symbol| {
+        if name.is_none() {
+            name = symbol.name().map(|x| format!("{:#}", x));
+        }
+    });
+    name
+}
+
+#[cfg(not(feature = "backtrace"))]
+pub(crate) fn function_name(_func: *const ()) -> Option<String> {
+    None
+}
+
+/// Tracks a spawned process so that its end can be reported.
+#[derive(Debug)]
+pub struct Lifecycle {
+    pid: u32,
+    sequence: u64,
+    function: Option<String>,
+    started: Instant,
+}
+
+impl Lifecycle {
+    /// Reports a spawned process to the spawn hook.
+    ///
+    /// Returns `None` if no hooks are configured.
+    pub fn spawned(func: *const (), pid: u32, sequence: u64, forked: bool) -> Option<Lifecycle> {
+        let on_spawn = SPAWN_HOOK.lock().unwrap().clone();
+        if on_spawn.is_none() && EXIT_HOOK.lock().unwrap().is_none() {
+            return None;
+        }
+        let function = function_name(func);
+        if let Some(on_spawn) = on_spawn {
+            on_spawn(&SpawnInfo {
+                pid,
+                sequence,
+                function: function.clone(),
+                forked,
+            });
+        }
+        Some(Lifecycle {
+            pid,
+            sequence,
+            function,
+            started: Instant::now(),
+        })
+    }
+
+    /// Reports the end of the process to the exit hook.
+    pub fn exited(self, outcome: ExitOutcome) {
+        let on_exit = EXIT_HOOK.lock().unwrap().clone();
+        if let Some(on_exit) = on_exit {
+            on_exit(&ExitInfo {
+                pid: self.pid,
+                sequence: self.sequence,
+                function: self.function,
+                duration: self.started.elapsed(),
+                outcome,
+            });
+        }
+    }
+}
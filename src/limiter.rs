@@ -0,0 +1,58 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use crate::error::SpawnError;
+
+/// Limits how many processes and pooled calls run at the same time.
+///
+/// procspawn calls [`acquire`](#tymethod.acquire) before it launches a
+/// process or dispatches a pooled call to a worker and
+/// [`release`](#tymethod.release) once the process exited or the call
+/// finished.  This lets spawns cooperate with application-wide semaphores or
+/// external admission controllers that are shared with other consumers of
+/// the same resources.
+///
+/// Acquiring may block until capacity is available.  If it fails the spawn
+/// or call fails with an error for which
+/// [`SpawnError::is_concurrency_limited`](struct.SpawnError.html#method.is_concurrency_limited)
+/// returns `true` and `release` is not called.
+///
+/// Limiters are configured with
+/// [`Builder::concurrency_limiter`](struct.Builder.html#method.concurrency_limiter)
+/// and [`PoolBuilder::concurrency_limiter`](struct.PoolBuilder.html#method.concurrency_limiter).
+pub trait ConcurrencyLimiter: Send + Sync + 'static {
+    /// Acquires a permit for a process or pooled call.
+    fn acquire(&self) -> io::Result<()>;
+
+    /// Gives back a permit that was acquired earlier.
+    fn release(&self);
+}
+
+/// A permit acquired from a limiter which is released on drop.
+pub struct Permit {
+    limiter: Arc<dyn ConcurrencyLimiter>,
+}
+
+impl Permit {
+    pub fn acquire(limiter: &Arc<dyn ConcurrencyLimiter>) -> Result<Permit, SpawnError> {
+        limiter
+            .acquire()
+            .map_err(SpawnError::new_concurrency_limited)?;
+        Ok(Permit {
+            limiter: limiter.clone(),
+        })
+    }
+}
+
+impl fmt::Debug for Permit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Permit").finish()
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.release();
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::{self, Write};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use ipc_channel::ipc::{self, IpcSender};
+use serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+
+static FORWARD_LOGS: AtomicBool = AtomicBool::new(false);
+
+/// The sender the children of this process forward their logs to.
+static FORWARDER: Mutex<Option<IpcSender<LogRecord>>> = Mutex::new(None);
+
+/// The sender to the parent of the call that is running in this process.
+static CURRENT_LOGS: Mutex<Option<IpcSender<LogRecord>>> = Mutex::new(None);
+
+pub fn set_log_forwarding(enabled: bool) {
+    FORWARD_LOGS.store(enabled, Ordering::SeqCst);
+}
+
+/// An event that was emitted in a child.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogRecord {
+    pid: u32,
+    level: u8,
+    target: String,
+    message: String,
+}
+
+impl LogRecord {
+    fn from_event(event: &Event<'_>) -> LogRecord {
+        let metadata = event.metadata();
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        LogRecord {
+            pid: process::id(),
+            level: match *metadata.level() {
+                Level::ERROR => 1,
+                Level::WARN => 2,
+                Level::INFO => 3,
+                Level::DEBUG => 4,
+                Level::TRACE => 5,
+            },
+            target: metadata.target().to_string(),
+            message: visitor.0,
+        }
+    }
+
+    /// Emits the record with the subscriber of this process.
+    fn emit(&self) {
+        macro_rules! emit {
+            ($level:expr) => {
+                tracing::event!(
+                    target: "procspawn::child",
+                    $level,
+                    pid = self.pid,
+                    child.target = %self.target,
+                    "{}",
+                    self.message
+                )
+            };
+        }
+        match self.level {
+            1 => emit!(Level::ERROR),
+            2 => emit!(Level::WARN),
+            3 => emit!(Level::INFO),
+            4 => emit!(Level::DEBUG),
+            _ => emit!(Level::TRACE),
+        }
+    }
+}
+
+/// Renders the message of an event followed by its other fields.
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let rest = std::mem::take(&mut self.0);
+            write!(self.0, "{:?}{}", value, rest).ok();
+        } else {
+            write!(self.0, " {}={:?}", field.name(), value).ok();
+        }
+    }
+}
+
+/// Returns the sender children forward their logs to if forwarding is
+/// enabled.
+///
+/// The first call starts the thread that emits the forwarded records.
+pub fn log_forwarder() -> Option<IpcSender<LogRecord>> {
+    if !FORWARD_LOGS.load(Ordering::SeqCst) {
+        return None;
+    }
+    let mut forwarder = FORWARDER.lock().unwrap();
+    if forwarder.is_none() {
+        let (sender, receiver) = ipc::channel::<LogRecord>().ok()?;
+        thread::Builder::new()
+            .name("procspawn-logs".into())
+            .spawn(move || {
+                while let Ok(record) = receiver.recv() {
+                    record.emit();
+                }
+            })
+            .ok()?;
+        *forwarder = Some(sender);
+    }
+    forwarder.clone()
+}
+
+/// Ships the events of this process to the parent.
+struct ForwardingSubscriber {
+    next_id: AtomicU64,
+}
+
+impl Subscriber for ForwardingSubscriber {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // whether events are forwarded changes with every call.
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        CURRENT_LOGS.lock().is_ok_and(|x| x.is_some())
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let sender = CURRENT_LOGS.lock().ok().and_then(|x| x.clone());
+        if let Some(sender) = sender {
+            // the parent might be gone already.
+            sender.send(LogRecord::from_event(event)).ok();
+        }
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+/// Forwards the logs of the running call to the parent until the guard is
+/// dropped.
+pub struct LogForwardGuard(());
+
+impl LogForwardGuard {
+    pub fn install(sender: Option<IpcSender<LogRecord>>) -> LogForwardGuard {
+        if sender.is_some() {
+            // if the process already installed a subscriber it keeps it and
+            // nothing is forwarded.
+            tracing::subscriber::set_global_default(ForwardingSubscriber {
+                next_id: AtomicU64::new(1),
+            })
+            .ok();
+        }
+        *CURRENT_LOGS.lock().unwrap() = sender;
+        LogForwardGuard(())
+    }
+}
+
+impl Drop for LogForwardGuard {
+    fn drop(&mut self) {
+        if let Ok(mut sender) = CURRENT_LOGS.lock() {
+            *sender = None;
+        }
+    }
+}
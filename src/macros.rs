@@ -169,3 +169,111 @@ macro_rules! _spawn_decl_arg {
 macro_rules! _spawn_unexpected {
     () => {};
 }
+
+/// Declares a trait together with a client that calls it in a [`Service`](struct.Service.html).
+///
+/// The trait is declared as usual but all of its methods have to take
+/// `&mut self` and all arguments and return values have to be serializable.
+/// After the trait a client type is declared with `client`.  The client
+/// wraps a service whose state implements the trait and has a method for
+/// every method of the trait which sends the call to the service:
+///
+/// ```rust,no_run
+/// procspawn::interface! {
+///     pub trait Counter {
+///         fn add(&mut self, x: u32) -> u32;
+///         fn reset(&mut self);
+///     }
+///     pub client CounterClient;
+/// }
+///
+/// struct MyCounter(u32);
+///
+/// impl Counter for MyCounter {
+///     fn add(&mut self, x: u32) -> u32 {
+///         self.0 += x;
+///         self.0
+///     }
+///
+///     fn reset(&mut self) {
+///         self.0 = 0;
+///     }
+/// }
+///
+/// let counter = CounterClient::new(procspawn::service((), |()| MyCounter(0)));
+/// assert_eq!(counter.add(2).unwrap(), 2);
+/// counter.reset().unwrap();
+/// ```
+///
+/// The methods of the client return a `Result` with the return value of
+/// the method or a [`SpawnError`](struct.SpawnError.html) if the call failed.
+#[macro_export]
+macro_rules! interface {
+    (
+        $(#[$trait_meta:meta])*
+        $vis:vis trait $trait:ident {
+            $(
+                $(#[$method_meta:meta])*
+                fn $method:ident(&mut self $(, $arg:ident: $arg_ty:ty)* $(,)?) $(-> $ret:ty)?;
+            )*
+        }
+        $(#[$client_meta:meta])*
+        $client_vis:vis client $client:ident;
+    ) => {
+        $(#[$trait_meta])*
+        $vis trait $trait {
+            $(
+                $(#[$method_meta])*
+                fn $method(&mut self $(, $arg: $arg_ty)*) $(-> $ret)?;
+            )*
+        }
+
+        $(#[$client_meta])*
+        $client_vis struct $client<S> {
+            service: $crate::Service<S>,
+        }
+
+        impl<S: $trait + 'static> $client<S> {
+            /// Wraps a service whose state implements the interface.
+            pub fn new(service: $crate::Service<S>) -> Self {
+                $client { service }
+            }
+
+            /// Returns the wrapped service.
+            pub fn service(&self) -> &$crate::Service<S> {
+                &self.service
+            }
+
+            /// Unwraps the service.
+            pub fn into_service(self) -> $crate::Service<S> {
+                self.service
+            }
+
+            $(
+                $(#[$method_meta])*
+                pub fn $method(
+                    &self
+                    $(, $arg: $arg_ty)*
+                ) -> ::std::result::Result<$crate::_interface_ret!($($ret)?), $crate::SpawnError> {
+                    self.service.call(
+                        |state: &mut S, ($($arg,)*): ($($arg_ty,)*)| {
+                            <S as $trait>::$method(state $(, $arg)*)
+                        },
+                        ($($arg,)*),
+                    )
+                }
+            )*
+        }
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! _interface_ret {
+    () => {
+        ()
+    };
+    ($ret:ty) => {
+        $ret
+    };
+}
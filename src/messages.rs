@@ -0,0 +1,58 @@
+use std::sync::{Arc, Mutex};
+
+pub type MessageHook = dyn Fn(&MessageInfo) + Send + Sync;
+
+static MESSAGE_HOOK: Mutex<Option<Arc<MessageHook>>> = Mutex::new(None);
+
+pub fn set_message_hook(hook: Arc<MessageHook>) {
+    *MESSAGE_HOOK.lock().unwrap() = Some(hook);
+}
+
+/// The kind of value a message carried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// The arguments sent to a call.
+    Args,
+    /// The return value received from a call.
+    Return,
+}
+
+/// Describes a value that was sent to or received from another process.
+///
+/// Passed to the hook configured with
+/// [`ProcConfig::on_message`](struct.ProcConfig.html#method.on_message).
+#[derive(Debug, Clone)]
+pub struct MessageInfo {
+    kind: MessageKind,
+    size: usize,
+    type_name: &'static str,
+}
+
+impl MessageInfo {
+    /// Whether the message carried arguments or a return value.
+    pub fn kind(&self) -> MessageKind {
+        self.kind
+    }
+
+    /// The serialized size of the message in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The name of the type of the arguments or the return value.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+/// Reports a message to the hook if one is configured.
+pub fn report_message(kind: MessageKind, size: Option<usize>, type_name: &'static str) {
+    let hook = MESSAGE_HOOK.lock().unwrap().clone();
+    if let (Some(hook), Some(size)) = (hook, size) {
+        hook(&MessageInfo {
+            kind,
+            size,
+            type_name,
+        });
+    }
+}
@@ -0,0 +1,205 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::fdpass::{offer_fd, take_fd};
+use crate::serde::in_ipc_mode;
+
+fn page_size() -> u64 {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+/// A read-only memory map of a range of a file that can be sent to other
+/// processes.
+///
+/// This is the built-in version of the pattern shown in the
+/// [custom serialization example](https://github.com/mitsuhiko/procspawn/blob/master/examples/custom-serialization.rs):
+/// when sent to a process spawned by procspawn only the path and the range
+/// are serialized and the receiving process maps the file itself instead of
+/// receiving a copy of the bytes.
+///
+/// ```rust,no_run
+/// use procspawn::serde::MmapPayload;
+///
+/// let payload = MmapPayload::open("model.bin").unwrap();
+/// let handle = procspawn::spawn(payload, |payload| {
+///     payload.iter().map(|&x| x as u64).sum::<u64>()
+/// });
+/// ```
+///
+/// Together with the path an open descriptor of the file is handed to the
+/// receiving process, which maps the file through that descriptor.  Because
+/// of this the receiving process always maps the same file as the sender,
+/// even if the file is deleted or replaced in the meantime, and the file
+/// stays around for as long as any process still maps it.  When the payload
+/// is sent with a [codec](../codec/index.html) or serialized outside of
+/// procspawn only the path and the range are serialized and the file is
+/// mapped again by path.
+///
+/// The mapping assumes that the file is not modified while it's mapped.
+///
+/// This is only available on unix platforms.
+pub struct MmapPayload {
+    path: PathBuf,
+    offset: u64,
+    file: File,
+    ptr: NonNull<u8>,
+    len: usize,
+    /// The distance of `ptr` from the page aligned start of the mapping.
+    map_offset: usize,
+}
+
+// the mapping is read-only, so it can be shared freely.
+unsafe impl Send for MmapPayload {}
+unsafe impl Sync for MmapPayload {}
+
+impl fmt::Debug for MmapPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MmapPayload")
+            .field("path", &self.path)
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl MmapPayload {
+    /// Maps a whole file.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MmapPayload> {
+        let file = File::open(path.as_ref())?;
+        let len = file.metadata()?.len();
+        let len =
+            usize::try_from(len).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        MmapPayload::map(path.as_ref().to_path_buf(), file, 0, len)
+    }
+
+    /// Maps `len` bytes of a file starting at `offset`.
+    pub fn open_range<P: AsRef<Path>>(path: P, offset: u64, len: usize) -> io::Result<MmapPayload> {
+        let file = File::open(path.as_ref())?;
+        MmapPayload::map(path.as_ref().to_path_buf(), file, offset, len)
+    }
+
+    fn map(path: PathBuf, file: File, offset: u64, len: usize) -> io::Result<MmapPayload> {
+        let file_len = file.metadata()?.len();
+        if offset
+            .checked_add(len as u64)
+            .map_or(true, |end| end > file_len)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "range is out of bounds of the file",
+            ));
+        }
+
+        // empty mappings are not allowed
+        if len == 0 {
+            return Ok(MmapPayload {
+                path,
+                offset,
+                file,
+                ptr: NonNull::dangling(),
+                len,
+                map_offset: 0,
+            });
+        }
+
+        // mappings have to start at page boundaries.
+        let map_offset = (offset % page_size()) as usize;
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len + map_offset,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                (offset - map_offset as u64) as libc::off_t,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MmapPayload {
+            path,
+            offset,
+            file,
+            ptr: unsafe { NonNull::new_unchecked((ptr as *mut u8).add(map_offset)) },
+            len,
+            map_offset,
+        })
+    }
+
+    /// Returns the path of the mapped file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns where the mapped range starts in the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the mapped bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl std::ops::Deref for MmapPayload {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Drop for MmapPayload {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe {
+                libc::munmap(
+                    self.ptr.as_ptr().sub(self.map_offset) as *mut libc::c_void,
+                    self.len + self.map_offset,
+                );
+            }
+        }
+    }
+}
+
+impl Serialize for MmapPayload {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if in_ipc_mode() {
+            let fd = self
+                .file
+                .try_clone()
+                .map(OwnedFd::from)
+                .and_then(offer_fd)
+                .map_err(ser::Error::custom)?;
+            (&self.path, self.offset, self.len, fd).serialize(serializer)
+        } else {
+            (&self.path, self.offset, self.len).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MmapPayload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MmapPayload, D::Error> {
+        if in_ipc_mode() {
+            let (path, offset, len, fd) =
+                <(PathBuf, u64, usize, PathBuf)>::deserialize(deserializer)?;
+            let file = take_fd(&fd).map(File::from).map_err(de::Error::custom)?;
+            MmapPayload::map(path, file, offset, len).map_err(de::Error::custom)
+        } else {
+            let (path, offset, len) = <(PathBuf, u64, usize)>::deserialize(deserializer)?;
+            MmapPayload::open_range(path, offset, len).map_err(de::Error::custom)
+        }
+    }
+}
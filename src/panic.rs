@@ -1,34 +1,100 @@
 use std::any::Any;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::panic;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::sync::Once;
+use std::thread;
 
+#[cfg(feature = "backtrace")]
+use crate::core::library_name_and_offset;
 use crate::error::{Location, PanicInfo};
 
 thread_local! {
     static PANIC_INFO: RefCell<Option<PanicInfo>> = const { RefCell::new(None) };
+    static IS_CALL_THREAD: Cell<bool> = const { Cell::new(false) };
 }
 
+/// The last panic of a thread other than the one running the call.
+///
+/// Functions that join their own threads commonly resume the panics of
+/// these threads, which does not invoke the panic hook again.
+static THREAD_PANIC: Mutex<Option<PanicInfo>> = Mutex::new(None);
+
+/// The backtrace capture of the call in a forked process.
+///
+/// Zero outside of forked processes.  This is an atomic as locks that
+/// other threads held at the time of the fork stay locked in the child.
+#[cfg(unix)]
+static FORKED_CAPTURE: AtomicU8 = AtomicU8::new(0);
+
+#[cfg(unix)]
+static FORKED_PANIC_HOOK: Once = Once::new();
+
 #[derive(Copy, Clone)]
 pub enum BacktraceCapture {
     No,
     #[cfg(feature = "backtrace")]
-    Resolved,
+    Resolved {
+        trim: bool,
+    },
     #[cfg(feature = "backtrace")]
-    Unresolved,
+    Unresolved {
+        trim: bool,
+    },
+}
+
+impl BacktraceCapture {
+    /// Packs the mode into a number so that it can be kept in an atomic.
+    #[cfg(unix)]
+    fn to_bits(self) -> u8 {
+        match self {
+            BacktraceCapture::No => 1,
+            #[cfg(feature = "backtrace")]
+            BacktraceCapture::Resolved { trim } => 2 + trim as u8,
+            #[cfg(feature = "backtrace")]
+            BacktraceCapture::Unresolved { trim } => 4 + trim as u8,
+        }
+    }
+
+    /// Unpacks a mode packed by [`to_bits`](#method.to_bits).
+    #[cfg(unix)]
+    fn from_bits(bits: u8) -> Option<BacktraceCapture> {
+        match bits {
+            1 => Some(BacktraceCapture::No),
+            #[cfg(feature = "backtrace")]
+            2 | 3 => Some(BacktraceCapture::Resolved { trim: bits == 3 }),
+            #[cfg(feature = "backtrace")]
+            4 | 5 => Some(BacktraceCapture::Unresolved { trim: bits == 5 }),
+            _ => None,
+        }
+    }
 }
 
 pub fn reset_panic_info() {
+    IS_CALL_THREAD.with(|x| x.set(true));
     PANIC_INFO.with(|pi| {
         *pi.borrow_mut() = None;
     });
+    *THREAD_PANIC.lock().unwrap() = None;
 }
 
 pub fn take_panic(panic: &dyn Any) -> PanicInfo {
     PANIC_INFO
         .with(|pi| pi.borrow_mut().take())
-        .unwrap_or_else(move || serialize_panic(panic))
+        .unwrap_or_else(move || {
+            let fallback = serialize_panic(panic);
+            match THREAD_PANIC.lock().unwrap().take() {
+                Some(resumed) if resumed.message() == fallback.message() => resumed,
+                _ => fallback,
+            }
+        })
 }
 
+// `PanicHookInfo` is only available from Rust 1.81 onwards.
+#[allow(deprecated)]
 pub fn panic_handler(info: &panic::PanicInfo<'_>, capture_backtraces: BacktraceCapture) {
     PANIC_INFO.with(|pi| {
         #[allow(unused_mut)]
@@ -36,16 +102,35 @@ pub fn panic_handler(info: &panic::PanicInfo<'_>, capture_backtraces: BacktraceC
         match capture_backtraces {
             BacktraceCapture::No => {}
             #[cfg(feature = "backtrace")]
-            BacktraceCapture::Resolved => {
-                panic.backtrace = Some(backtrace::Backtrace::new());
+            BacktraceCapture::Resolved { trim } => {
+                let backtrace = backtrace::Backtrace::new();
+                panic.backtrace = Some(if trim {
+                    trim_backtrace(&backtrace)
+                } else {
+                    backtrace
+                });
             }
             #[cfg(feature = "backtrace")]
-            BacktraceCapture::Unresolved => {
-                panic.backtrace = Some(backtrace::Backtrace::new_unresolved());
+            BacktraceCapture::Unresolved { trim } => {
+                let backtrace = backtrace::Backtrace::new_unresolved();
+                // the parent needs to know where the frames are located in
+                // their libraries as these are loaded at other addresses
+                // there.
+                panic.frame_images = backtrace
+                    .frames()
+                    .iter()
+                    .map(|frame| library_name_and_offset(frame.ip() as *const u8))
+                    .collect();
+                panic.trim_backtrace = trim;
+                panic.backtrace = Some(backtrace);
             }
         }
         panic.location = info.location().map(Location::from_std);
-        *pi.borrow_mut() = Some(panic);
+        if IS_CALL_THREAD.with(|x| x.get()) {
+            *pi.borrow_mut() = Some(panic);
+        } else if let Ok(mut thread_panic) = THREAD_PANIC.lock() {
+            *thread_panic = Some(panic);
+        }
     });
 }
 
@@ -57,12 +142,111 @@ pub fn init_panic_hook(capture_backtraces: BacktraceCapture) {
     }));
 }
 
+/// Installs the panic hook for calls in forked processes.
+///
+/// Installing a hook takes a lock which might be held by another thread
+/// at the time of a fork, so this happens before forking.  The hook does
+/// nothing until it is enabled in the forked process.
+#[cfg(unix)]
+pub fn init_forked_panic_hook() {
+    FORKED_PANIC_HOOK.call_once(|| {
+        let next = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(capture) =
+                BacktraceCapture::from_bits(FORKED_CAPTURE.load(Ordering::SeqCst))
+            {
+                panic_handler(info, capture);
+            }
+            next(info);
+        }));
+    });
+}
+
+/// Enables the panic hook for forked calls in a forked process.
+#[cfg(unix)]
+pub fn enable_forked_panic_hook(capture: BacktraceCapture) {
+    FORKED_CAPTURE.store(capture.to_bits(), Ordering::SeqCst);
+}
+
 fn serialize_panic(panic: &dyn Any) -> PanicInfo {
-    PanicInfo::new(match panic.downcast_ref::<&'static str>() {
+    let mut info = PanicInfo::new(match panic.downcast_ref::<&'static str>() {
         Some(s) => s,
         None => match panic.downcast_ref::<String>() {
             Some(s) => &s[..],
             None => "Box<Any>",
         },
-    })
+    });
+    info.thread = thread::current().name().map(Into::into);
+    info
+}
+
+/// Frames of the panic machinery on top of the panicking frame.
+#[cfg(feature = "backtrace")]
+const PANIC_FRAMES: &[&str] = &[
+    "backtrace::",
+    "procspawn::panic::",
+    "std::thread::local::",
+    "<alloc::boxed::Box<",
+    "std::panicking::",
+    "core::panicking::",
+    "std::sys::backtrace::",
+    "std::sys_common::backtrace::",
+    "__rustc::",
+    "rust_begin_unwind",
+];
+
+/// Frames that call the spawned function below the frame invoking it.
+#[cfg(feature = "backtrace")]
+const CALL_FRAMES: &[&str] = &[
+    "core::ops::function::",
+    "<core::panic::unwind_safe::AssertUnwindSafe<",
+    "std::panicking::",
+    "std::panic::",
+    "__rust_try",
+];
+
+/// Frames of procspawn and of the crates it uses for IPC.
+#[cfg(feature = "backtrace")]
+const INTERNAL_FRAMES: &[&str] = &["procspawn::", "ipc_channel::", "bincode::"];
+
+#[cfg(feature = "backtrace")]
+fn frame_matches(frame: &backtrace::BacktraceFrame, prefixes: &[&str]) -> bool {
+    frame
+        .symbols()
+        .first()
+        .and_then(|x| x.name())
+        .is_some_and(|name| {
+            let name = format!("{:#}", name);
+            prefixes.iter().any(|prefix| name.starts_with(prefix))
+        })
+}
+
+/// Removes the frames of the panic machinery, of procspawn and of its IPC
+/// dependencies as well as everything below the spawned function.
+///
+/// This only works for resolved backtraces.
+#[cfg(feature = "backtrace")]
+pub fn trim_backtrace(backtrace: &backtrace::Backtrace) -> backtrace::Backtrace {
+    let frames = backtrace.frames();
+    let start = frames
+        .iter()
+        .position(|frame| !frame.symbols().is_empty() && !frame_matches(frame, PANIC_FRAMES))
+        .unwrap_or(0);
+    let mut end = frames
+        .iter()
+        .position(|frame| frame_matches(frame, &["procspawn::core::invoke_and_send"]))
+        .unwrap_or(frames.len())
+        .max(start);
+    while end > start && frame_matches(&frames[end - 1], CALL_FRAMES) {
+        end -= 1;
+    }
+    let trimmed: Vec<_> = frames[start..end]
+        .iter()
+        .filter(|frame| !frame_matches(frame, INTERNAL_FRAMES))
+        .cloned()
+        .collect();
+    if trimmed.is_empty() {
+        return backtrace.clone();
+    }
+    backtrace::Backtrace::from(trimmed)
 }
@@ -1,27 +1,114 @@
-use std::ffi::OsStr;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use ipc_channel::ipc;
+use ipc_channel::ipc::{self, IpcReceiver, OpaqueIpcReceiver};
 use serde::{de::DeserializeOwned, Serialize};
 
-use crate::core::MarshalledCall;
+use crate::adoption::{adopt_workers, AdoptedWorker, Adoption};
+use crate::call::Call;
+use crate::capture::CapturedOutput;
+use crate::channel::parent_channel;
+use crate::chunked;
+use crate::codec::{
+    codec_factory, create_codec, default_codec, with_codec, Bincode, Codec, CodecFactory,
+    CompressionConfig,
+};
+use crate::core::{max_message_size, MarshalledCall, MarshalledFn};
+use crate::deadline::{Deadline, DEADLINE_POLL_INTERVAL};
+use crate::env_policy::EnvPolicy;
 use crate::error::SpawnError;
-use crate::proc::{Builder, JoinHandle, JoinHandleInner, ProcCommon, ProcessHandleState};
+use crate::iter::{Map, ParallelBridge};
+use crate::limiter::Permit;
+use crate::proc::{
+    next_spawn_sequence, result_stream, send_args, unpack_result, Builder, JoinHandle,
+    JoinHandleInner, ProcCommon, ProcessHandleState, ProgressFunc,
+};
+use crate::progress::ProgressReceiver;
+use crate::quarantine::{Admission, Quarantine};
 use crate::serde::with_ipc_mode;
+#[cfg(feature = "metrics")]
+use crate::telemetry;
 
-type WaitFunc = Box<dyn FnOnce() -> bool + Send>;
+type WaitFunc = Box<dyn FnOnce(Option<Permit>) -> bool + Send>;
 type NotifyErrorFunc = Box<dyn FnMut(SpawnError) + Send>;
+type WorkerInitArgs = Box<dyn FnOnce() -> Result<(), SpawnError> + Send>;
+
+thread_local! {
+    /// The state created by the worker initialization function.
+    static WORKER_STATE: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+}
+
+/// Invokes a function with the state of the pool worker running the call.
+///
+/// The state is created by the function passed to
+/// [`PoolBuilder::worker_init`](struct.PoolBuilder.html#method.worker_init).
+/// This returns `None` outside of pool workers, if the pool has no worker
+/// initialization function or if its state is not of type `S`.
+///
+/// The state lives on the thread that runs the calls, so this has to be
+/// invoked by the spawned function itself and not by threads it spawned.
+pub fn with_worker_state<S: 'static, R, F: FnOnce(&mut S) -> R>(f: F) -> Option<R> {
+    WORKER_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state
+            .as_mut()
+            .and_then(|state| state.downcast_mut::<S>())
+            .map(f)
+    })
+}
+
+/// Creates the call that initializes a new worker.
+///
+/// The arguments are sent by the returned function once the worker runs.
+struct WorkerInit(Box<dyn Fn() -> (Call, WorkerInitArgs) + Send + Sync>);
+
+impl fmt::Debug for WorkerInit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WorkerInit").finish()
+    }
+}
 
-#[derive(Debug)]
 pub struct PooledHandleState {
+    pub call_id: u64,
     pub cancelled: AtomicBool,
     pub process_handle_state: Mutex<Option<Arc<ProcessHandleState>>>,
+    /// The worker that ran the call, kept once the call is done.
+    worker: Mutex<Option<Arc<ProcessHandleState>>>,
+    pub progress: Mutex<Option<Arc<ProgressFunc>>>,
+    pub fingerprint: Mutex<Option<u64>>,
+    pub timeout: Option<Duration>,
+    spawned: Instant,
+    /// When a worker started to run the call.
+    started: Mutex<Option<Instant>>,
+    /// Set once the return value was received, guards `timed_out`.
+    returned: Mutex<bool>,
+    timed_out: AtomicBool,
+    /// The output of the call if it's captured.
+    output: Mutex<Option<CapturedOutput>>,
+}
+
+impl fmt::Debug for PooledHandleState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledHandleState")
+            .field("call_id", &self.call_id)
+            .field("cancelled", &self.cancelled)
+            .field("process_handle_state", &self.process_handle_state)
+            .field("fingerprint", &self.fingerprint)
+            .finish()
+    }
 }
 
 impl PooledHandleState {
@@ -31,52 +118,552 @@ impl PooledHandleState {
             process_handle_state.kill();
         }
     }
+
+    /// Kills the process of a call that ran out of time.
+    ///
+    /// Calls that already returned are left alone.
+    fn time_out(&self) {
+        let returned = self.returned.lock().unwrap();
+        if !*returned {
+            self.timed_out.store(true, Ordering::SeqCst);
+            if let Some(ref process_handle_state) = *self.process_handle_state.lock().unwrap() {
+                process_handle_state.kill();
+            }
+        }
+    }
+
+    /// Marks the call as returned unless it timed out.
+    fn mark_returned(&self) -> bool {
+        let mut returned = self.returned.lock().unwrap();
+        if self.timed_out.load(Ordering::SeqCst) {
+            return false;
+        }
+        *returned = true;
+        true
+    }
+
+    /// Quarantines the arguments of the call.
+    fn quarantine(&self, quarantine: Option<&Quarantine>) {
+        if let (Some(quarantine), Some(fingerprint)) =
+            (quarantine, *self.fingerprint.lock().unwrap())
+        {
+            quarantine.insert(fingerprint);
+        }
+    }
+}
+
+/// What happens to a pooled call when its join handle is dropped.
+///
+/// This is configured per pool with
+/// [`PoolBuilder::drop_behavior`](struct.PoolBuilder.html#method.drop_behavior).
+/// It only applies to handles that are dropped before their result was
+/// received, for instance when a handle is dropped early because the code
+/// waiting for it was aborted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleDropBehavior {
+    /// The call keeps running and its result is discarded.
+    Detach,
+    /// The call is cancelled if it was not yet scheduled to a process,
+    /// calls that are already running are left alone.
+    Cancel,
+    /// Like `Cancel` but calls that are already running are stopped by
+    /// killing their process.  The pool then replaces the process.
+    Kill,
+}
+
+/// What happens to the calls of a pool when the pool is dropped.
+///
+/// This is configured with
+/// [`PoolBuilder::on_drop`](struct.PoolBuilder.html#method.on_drop).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DropBehavior {
+    /// The processes are killed right away, waiting and running calls
+    /// fail.
+    #[default]
+    Kill,
+    /// Waits for all waiting and running calls to finish before the
+    /// processes are killed.
+    Wait,
+    /// Like `Wait` but gives up and kills the processes after a timeout.
+    WaitTimeout(Duration),
+}
+
+/// Counts a call as unfinished until it's dropped.
+struct Unfinished(Arc<AtomicUsize>);
+
+impl Unfinished {
+    fn new(count: &Arc<AtomicUsize>) -> Unfinished {
+        count.fetch_add(1, Ordering::SeqCst);
+        Unfinished(count.clone())
+    }
+}
+
+impl Drop for Unfinished {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The error returned by [`Pool::try_spawn`](struct.Pool.html#method.try_spawn)
+/// when the queue of the pool is full.
+///
+/// It hands back the arguments of the call.
+pub struct FullError<A> {
+    args: A,
+}
+
+impl<A> FullError<A> {
+    /// Returns the arguments of the call that was not spawned.
+    pub fn into_inner(self) -> A {
+        self.args
+    }
+}
+
+impl<A> fmt::Debug for FullError<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FullError").finish()
+    }
+}
+
+impl<A> fmt::Display for FullError<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the queue of the pool is full")
+    }
+}
+
+impl<A> std::error::Error for FullError<A> {}
+
+/// The state of a pool.
+///
+/// This is returned by [`Pool::health`](struct.Pool.html#method.health).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolHealth {
+    /// The pool accepts calls.
+    Healthy,
+    /// The pool gave up because its workers kept crashing.
+    Failed,
+    /// The pool was killed.
+    Killed,
+}
+
+/// How a call is handed to the workers.
+#[derive(Default)]
+struct CallOptions {
+    worker: Option<usize>,
+    priority: Priority,
+    due: Option<Instant>,
+    timeout: Option<Duration>,
+    env: Vec<(OsString, OsString)>,
+}
+
+/// The priority of a pooled call.
+///
+/// Calls with a higher priority are handed to workers before calls with a
+/// lower priority that are still waiting.  Calls of the same priority are
+/// handed out in the order they were spawned.  Running calls are never
+/// interrupted.
+///
+/// Priorities are set with
+/// [`Pool::spawn_with_priority`](struct.Pool.html#method.spawn_with_priority).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// For batch work that can wait.
+    Low,
+    /// The priority of calls spawned with [`Pool::spawn`](struct.Pool.html#method.spawn).
+    #[default]
+    Normal,
+    /// For latency critical calls.
+    High,
 }
 
 pub struct PooledHandle<T> {
     waiter_rx: mpsc::Receiver<Result<T, SpawnError>>,
+    pub(crate) reports: Option<ProgressReceiver>,
+    pub(crate) child_rx: Option<OpaqueIpcReceiver>,
     shared: Arc<PooledHandleState>,
+    pool_failed: Arc<AtomicBool>,
+    drop_behavior: HandleDropBehavior,
+    finished: bool,
+    /// A result that was received to check if the call is finished.
+    ready: Option<Result<T, SpawnError>>,
 }
 
 impl<T> PooledHandle<T> {
+    pub fn call_id(&self) -> u64 {
+        self.shared.call_id
+    }
+
+    pub fn drop_behavior(&self) -> HandleDropBehavior {
+        self.drop_behavior
+    }
+
+    pub fn detach(&mut self) {
+        self.drop_behavior = HandleDropBehavior::Detach;
+    }
+
     pub fn process_handle_state(&self) -> Option<Arc<ProcessHandleState>> {
         self.shared.process_handle_state.lock().unwrap().clone()
     }
 
+    /// Returns the state of the worker that ran the call, even once the
+    /// call is done.
+    pub fn worker_state(&self) -> Option<Arc<ProcessHandleState>> {
+        self.shared.worker.lock().unwrap().clone()
+    }
+
     pub fn kill(&mut self) -> Result<(), SpawnError> {
         self.shared.kill();
         Ok(())
     }
+
+    pub fn set_progress(&mut self, progress: Arc<ProgressFunc>) {
+        *self.shared.progress.lock().unwrap() = Some(progress);
+    }
+
+    pub fn take_output(&mut self) -> Option<CapturedOutput> {
+        self.shared.output.lock().unwrap().take()
+    }
+}
+
+impl<T> Drop for PooledHandle<T> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        match self.drop_behavior {
+            HandleDropBehavior::Detach => {}
+            HandleDropBehavior::Cancel => self.shared.cancelled.store(true, Ordering::SeqCst),
+            HandleDropBehavior::Kill => self.shared.kill(),
+        }
+    }
 }
 
 impl<T: Serialize + DeserializeOwned> PooledHandle<T> {
     pub fn join(&mut self) -> Result<T, SpawnError> {
+        self.finished = true;
+        if let Some(rv) = self.ready.take() {
+            return rv;
+        }
         match self.waiter_rx.recv() {
             Ok(Ok(rv)) => Ok(rv),
             Ok(Err(err)) => Err(err),
-            Err(..) => Err(SpawnError::new_remote_close()),
+            Err(..) => Err(self.disconnected()),
         }
     }
 
-    pub fn join_timeout(&mut self, timeout: Duration) -> Result<T, SpawnError> {
-        match self.waiter_rx.recv_timeout(timeout) {
-            Ok(Ok(rv)) => Ok(rv),
-            Ok(Err(err)) => Err(err),
-            Err(mpsc::RecvTimeoutError::Timeout) => {
-                self.kill().ok();
-                Err(SpawnError::new_timeout())
+    /// The error for calls that were dropped by the pool.
+    fn disconnected(&self) -> SpawnError {
+        if self.pool_failed.load(Ordering::SeqCst) {
+            SpawnError::new_pool_failed()
+        } else {
+            SpawnError::new_remote_close()
+        }
+    }
+
+    pub fn try_join(&mut self) -> Option<Result<T, SpawnError>> {
+        let rv = match self.ready.take() {
+            Some(rv) => rv,
+            None => match self.waiter_rx.try_recv() {
+                Ok(rv) => rv,
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => Err(self.disconnected()),
+            },
+        };
+        self.finished = true;
+        Some(rv)
+    }
+
+    /// Checks if the result of the call is available without taking it.
+    pub fn is_finished(&mut self) -> bool {
+        if self.ready.is_none() {
+            self.ready = match self.waiter_rx.try_recv() {
+                Ok(rv) => Some(rv),
+                Err(mpsc::TryRecvError::Empty) => None,
+                Err(mpsc::TryRecvError::Disconnected) => Some(Err(self.disconnected())),
+            };
+        }
+        self.ready.is_some()
+    }
+
+    pub fn join_deadline(&mut self, deadline: &Deadline) -> Result<T, SpawnError> {
+        if let Some(rv) = self.ready.take() {
+            self.finished = true;
+            return rv;
+        }
+        loop {
+            // the deadline can move while we wait so it is re-read
+            // regularly.
+            let wait = match deadline.remaining() {
+                Some(remaining) => remaining.min(DEADLINE_POLL_INTERVAL),
+                None => DEADLINE_POLL_INTERVAL,
+            };
+            match self.waiter_rx.recv_timeout(wait) {
+                Ok(rv) => {
+                    self.finished = true;
+                    return rv;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if deadline.has_passed() {
+                        self.finished = true;
+                        self.kill().ok();
+                        return Err(SpawnError::new_timeout());
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    self.finished = true;
+                    return Err(self.disconnected());
+                }
             }
-            Err(mpsc::RecvTimeoutError::Disconnected) => Err(SpawnError::new_remote_close()),
         }
     }
 }
 
-type PoolSender = mpsc::Sender<(
+type Job = (
     MarshalledCall,
     Arc<PooledHandleState>,
     WaitFunc,
     NotifyErrorFunc,
-)>;
+);
+
+/// Waiting calls ordered by priority.
+#[derive(Default)]
+struct JobLanes([VecDeque<Job>; 3]);
+
+impl JobLanes {
+    fn push(&mut self, job: Job, priority: Priority) {
+        self.0[priority as usize].push_back(job);
+    }
+
+    fn front_priority(&self) -> Option<Priority> {
+        [Priority::High, Priority::Normal, Priority::Low]
+            .iter()
+            .copied()
+            .find(|&priority| !self.0[priority as usize].is_empty())
+    }
+
+    fn pop_front(&mut self, priority: Priority) -> Option<Job> {
+        self.0[priority as usize].pop_front()
+    }
+}
+
+/// The calls waiting for a worker.
+///
+/// Calls are either picked up by the first idle worker or routed to a
+/// specific worker.  A worker takes the call with the highest priority and
+/// prefers calls routed to it over other calls of the same priority.
+struct JobQueue {
+    state: Mutex<JobQueueState>,
+    condvar: Condvar,
+    space_condvar: Condvar,
+}
+
+/// How a call takes its place in a queue with a limit.
+#[derive(Clone, Copy)]
+enum Reserve {
+    /// Takes a place even if that exceeds the limit.
+    Always,
+    /// Only takes a place within the limit.
+    Try,
+    /// Waits for a place within the limit.
+    Wait,
+}
+
+enum Pop {
+    Job(Box<Job>),
+    Idle,
+    Closed,
+}
+
+/// A call that is only handed to a worker once it's due.
+struct ScheduledJob {
+    due: Instant,
+    worker: Option<usize>,
+    priority: Priority,
+    job: Job,
+}
+
+struct JobQueueState {
+    any: JobLanes,
+    routed: Vec<JobLanes>,
+    scheduled: Vec<ScheduledJob>,
+    /// The number of calls that took a place in the queue, including
+    /// calls that are not pushed yet.
+    reserved: usize,
+    limit: Option<usize>,
+    closed: bool,
+}
+
+impl JobQueueState {
+    fn push_now(&mut self, job: Job, worker: Option<usize>, priority: Priority) {
+        match worker {
+            Some(worker) => self.routed[worker].push(job, priority),
+            None => self.any.push(job, priority),
+        }
+    }
+
+    /// Queues the scheduled calls that are due.
+    ///
+    /// Returns when the next scheduled call is due.
+    fn push_due(&mut self) -> Option<Instant> {
+        let now = Instant::now();
+        let mut index = 0;
+        while index < self.scheduled.len() {
+            if self.scheduled[index].due <= now {
+                let scheduled = self.scheduled.remove(index);
+                self.push_now(scheduled.job, scheduled.worker, scheduled.priority);
+            } else {
+                index += 1;
+            }
+        }
+        self.scheduled.iter().map(|scheduled| scheduled.due).min()
+    }
+}
+
+impl JobQueue {
+    fn new(size: usize, limit: Option<usize>) -> JobQueue {
+        JobQueue {
+            state: Mutex::new(JobQueueState {
+                any: JobLanes::default(),
+                routed: (0..size).map(|_| JobLanes::default()).collect(),
+                scheduled: Vec::new(),
+                reserved: 0,
+                limit,
+                closed: false,
+            }),
+            condvar: Condvar::new(),
+            space_condvar: Condvar::new(),
+        }
+    }
+
+    /// Takes a place in the queue for a call that is pushed later.
+    ///
+    /// Returns `false` if there is no space.
+    fn reserve(&self, reserve: Reserve) -> bool {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let full = state.limit.is_some_and(|limit| state.reserved >= limit);
+            match reserve {
+                _ if !full || state.closed => break,
+                Reserve::Always => break,
+                Reserve::Try => return false,
+                Reserve::Wait => state = self.space_condvar.wait(state).unwrap(),
+            }
+        }
+        state.reserved += 1;
+        #[cfg(feature = "metrics")]
+        telemetry::pool_queue_changed(1.0);
+        true
+    }
+
+    /// Gives up a place for a call that was never pushed.
+    fn release(&self) {
+        self.state.lock().unwrap().reserved -= 1;
+        #[cfg(feature = "metrics")]
+        telemetry::pool_queue_changed(-1.0);
+        self.space_condvar.notify_one();
+    }
+
+    fn push(&self, job: Job, worker: Option<usize>, priority: Priority, due: Option<Instant>) {
+        let mut state = self.state.lock().unwrap();
+        // nobody takes jobs from a closed queue, dropping them fails their
+        // handles.
+        if state.closed {
+            return;
+        }
+        match due {
+            Some(due) if due > Instant::now() => state.scheduled.push(ScheduledJob {
+                due,
+                worker,
+                priority,
+                job,
+            }),
+            _ => state.push_now(job, worker, priority),
+        }
+        drop(state);
+        // a routed job can only be taken by its worker so everybody has to
+        // check.
+        self.condvar.notify_all();
+    }
+
+    /// Waits for the next job for a worker.
+    ///
+    /// Without a timeout this waits until a job arrives or the queue is
+    /// closed.
+    fn pop(&self, worker: usize, timeout: Option<Duration>) -> Pop {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return Pop::Closed;
+            }
+            let next_due = state.push_due();
+            let routed = state.routed[worker].front_priority();
+            let any = state.any.front_priority();
+            let job = match (routed, any) {
+                (Some(routed), any) if Some(routed) >= any => {
+                    state.routed[worker].pop_front(routed)
+                }
+                (_, Some(any)) => state.any.pop_front(any),
+                _ => None,
+            };
+            if let Some(job) = job {
+                state.reserved -= 1;
+                drop(state);
+                #[cfg(feature = "metrics")]
+                telemetry::pool_queue_changed(-1.0);
+                self.space_condvar.notify_one();
+                return Pop::Job(Box::new(job));
+            }
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Pop::Idle;
+            }
+            let wake_up = match (deadline, next_due) {
+                (Some(deadline), Some(due)) => Some(deadline.min(due)),
+                (deadline, due) => deadline.or(due),
+            };
+            state = match wake_up {
+                Some(wake_up) => {
+                    self.condvar
+                        .wait_timeout(state, wake_up.saturating_duration_since(now))
+                        .unwrap()
+                        .0
+                }
+                None => self.condvar.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Removes all waiting jobs.
+    fn drain(&self) -> Vec<Job> {
+        let mut state = self.state.lock().unwrap();
+        let mut jobs = Vec::new();
+        let JobQueueState {
+            ref mut any,
+            ref mut routed,
+            ref mut scheduled,
+            ..
+        } = *state;
+        for lanes in Some(any).into_iter().chain(routed.iter_mut()) {
+            for lane in lanes.0.iter_mut() {
+                jobs.extend(lane.drain(..));
+            }
+        }
+        jobs.extend(scheduled.drain(..).map(|scheduled| scheduled.job));
+        #[cfg(feature = "metrics")]
+        telemetry::pool_queue_changed(-(state.reserved as f64));
+        state.reserved = 0;
+        drop(state);
+        self.space_condvar.notify_all();
+        jobs
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.condvar.notify_all();
+        self.space_condvar.notify_all();
+    }
+}
 
 /// A process pool.
 ///
@@ -85,14 +672,26 @@ type PoolSender = mpsc::Sender<(
 /// automatically restart broken processes.
 ///
 /// Note that it's not possible to intercept streams of processes spawned
-/// through the pool.
+/// through the pool.  Instead the output of individual calls can be
+/// captured with [`PoolBuilder::capture_output`](struct.PoolBuilder.html#method.capture_output).
 ///
-/// When the process pool is dropped all processes are killed.
+/// When the process pool is dropped all processes are killed, unless it's
+/// configured to wait for its calls with
+/// [`PoolBuilder::on_drop`](struct.PoolBuilder.html#method.on_drop).
 ///
 /// This requires the `pool` feature.
 pub struct Pool {
-    sender: Mutex<PoolSender>,
     shared: Arc<PoolShared>,
+    drop_behavior: HandleDropBehavior,
+    on_drop: DropBehavior,
+    codec: Option<CodecFactory>,
+    compression: Option<CompressionConfig>,
+    stream_threshold: Option<u64>,
+    progress: bool,
+    parent_channel: bool,
+    capture_output: bool,
+    worker_concurrency: usize,
+    quarantine: Option<Arc<Quarantine>>,
 }
 
 impl fmt::Debug for Pool {
@@ -121,6 +720,37 @@ impl Pool {
         self.shared.monitors.lock().unwrap().len()
     }
 
+    /// Returns information about the worker processes of the pool.
+    ///
+    /// There is one entry per worker in a stable order, so the index of a
+    /// worker identifies it across restarts of its process.
+    pub fn workers(&self) -> Vec<WorkerInfo> {
+        self.shared
+            .monitors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(WorkerMonitor::info)
+            .collect()
+    }
+
+    /// Returns statistics of the calls and workers of the pool.
+    ///
+    /// Counters start when the pool is built.
+    pub fn stats(&self) -> PoolStats {
+        let calls = &self.shared.call_stats;
+        PoolStats {
+            completed: calls.completed.load(Ordering::Relaxed),
+            panicked: calls.panicked.load(Ordering::Relaxed),
+            failed: calls.failed.load(Ordering::Relaxed),
+            queued: self.queued_count(),
+            active: self.active_count(),
+            queue_depths: calls.queue_depths.lock().unwrap().iter().copied().collect(),
+            average_latency: calls.average_latency(),
+            workers: self.workers(),
+        }
+    }
+
     /// Returns the number of jobs waiting to executed in the pool.
     pub fn queued_count(&self) -> usize {
         self.shared.queued_count.load(Ordering::Relaxed)
@@ -131,10 +761,51 @@ impl Pool {
         self.shared.active_count.load(Ordering::SeqCst)
     }
 
+    /// Maps the items of an iterator with a function in the pool.
+    ///
+    /// The items are distributed over the workers and the results are
+    /// yielded in the order of the items as they come in:
+    ///
+    /// ```rust,no_run
+    /// use procspawn::Pool;
+    ///
+    /// let pool = Pool::new(4).unwrap();
+    /// for square in pool.map(0..100u64, |x| x * x) {
+    ///     println!("{}", square.unwrap());
+    /// }
+    /// ```
+    ///
+    /// This is a shortcut for [`par_bridge`](iter/trait.ParallelBridge.html)
+    /// which has more options, for instance to send more than one item per
+    /// call.
+    pub fn map<I, R>(&self, iter: I, f: fn(I::Item) -> R) -> Map<'_, I::IntoIter, R>
+    where
+        I: IntoIterator,
+        I::Item: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    {
+        iter.into_iter().par_bridge(self).map(f)
+    }
+
+    /// Maps the items of an iterator with a function in the pool, yielding
+    /// results as they are done.
+    ///
+    /// This works like [`map`](#method.map) but the results are yielded in
+    /// the order the calls finished.
+    pub fn map_unordered<I, R>(&self, iter: I, f: fn(I::Item) -> R) -> Map<'_, I::IntoIter, R>
+    where
+        I: IntoIterator,
+        I::Item: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    {
+        iter.into_iter().par_bridge(self).unordered().map(f)
+    }
+
     /// Spawns a closure into a process of the pool.
     ///
     /// This works exactly like [`procspawn::spawn`](fn.spawn.html) but instead
     /// of spawning a new process, it reuses a process from the pool.
+    #[track_caller]
     pub fn spawn<
         A: Serialize + DeserializeOwned,
         R: Serialize + DeserializeOwned + Send + 'static,
@@ -143,46 +814,470 @@ impl Pool {
         args: A,
         func: fn(A) -> R,
     ) -> JoinHandle<R> {
+        self.reserve(Reserve::Always);
+        self.spawn_helper(CallOptions::default(), args, func)
+    }
+
+    /// Spawns a closure into a process of the pool once for every item of
+    /// an iterator.
+    ///
+    /// This works like calling [`spawn`](#method.spawn) with every item.
+    #[track_caller]
+    pub fn spawn_many<I, A, R>(&self, args: I, func: fn(A) -> R) -> Vec<JoinHandle<R>>
+    where
+        I: IntoIterator<Item = A>,
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    {
+        // a plain loop so that the handles record the location of the caller.
+        let mut handles = Vec::new();
+        for args in args {
+            self.reserve(Reserve::Always);
+            handles.push(self.spawn_helper(CallOptions::default(), args, func));
+        }
+        handles
+    }
+
+    /// Spawns a closure into a process of the pool unless its queue is full.
+    ///
+    /// This works like [`spawn`](#method.spawn) but if the pool has a
+    /// [`queue_limit`](struct.PoolBuilder.html#method.queue_limit) and as
+    /// many calls are already waiting for a worker, the arguments are
+    /// handed back in a [`FullError`](struct.FullError.html) instead.
+    #[track_caller]
+    pub fn try_spawn<
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        args: A,
+        func: fn(A) -> R,
+    ) -> Result<JoinHandle<R>, FullError<A>> {
+        if !self.reserve(Reserve::Try) {
+            return Err(FullError { args });
+        }
+        Ok(self.spawn_helper(CallOptions::default(), args, func))
+    }
+
+    /// Spawns a closure into a process of the pool once its queue has space.
+    ///
+    /// This works like [`spawn`](#method.spawn) but if the pool has a
+    /// [`queue_limit`](struct.PoolBuilder.html#method.queue_limit) this
+    /// blocks until less calls than the limit are waiting for a worker.
+    #[track_caller]
+    pub fn spawn_wait<
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        self.reserve(Reserve::Wait);
+        self.spawn_helper(CallOptions::default(), args, func)
+    }
+
+    /// Spawns a closure into a process of the pool with a priority.
+    ///
+    /// This works like [`spawn`](#method.spawn) but waiting calls with a
+    /// higher [`Priority`](enum.Priority.html) are handed to the workers
+    /// first, so latency critical calls do not have to wait for a backlog
+    /// of batch work.
+    #[track_caller]
+    pub fn spawn_with_priority<
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        priority: Priority,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        self.reserve(Reserve::Always);
+        self.spawn_helper(
+            CallOptions {
+                priority,
+                ..CallOptions::default()
+            },
+            args,
+            func,
+        )
+    }
+
+    /// Spawns a closure into a process of the pool with a timeout.
+    ///
+    /// If the call runs for longer than the timeout its worker is killed
+    /// and restarted, and the call fails with an error for which
+    /// [`SpawnError::is_timeout`](struct.SpawnError.html#method.is_timeout)
+    /// returns `true`.  Unlike [`JoinHandle::join_timeout`](struct.JoinHandle.html#method.join_timeout)
+    /// this is enforced by the pool, so it also applies to calls whose
+    /// handle is never joined.  The time a call waits for a worker does not
+    /// count towards the timeout.
+    #[track_caller]
+    pub fn spawn_with_timeout<
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        timeout: Duration,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        self.reserve(Reserve::Always);
+        self.spawn_helper(
+            CallOptions {
+                timeout: Some(timeout),
+                ..CallOptions::default()
+            },
+            args,
+            func,
+        )
+    }
+
+    /// Spawns a closure into a process of the pool with extra environment
+    /// variables.
+    ///
+    /// The worker sets the variables right before the call runs and
+    /// restores their previous values once it's done, so they don't leak
+    /// into later calls.  This is useful for libraries that are configured
+    /// through the environment.  Note that the environment of the worker is
+    /// shared by all of its threads.
+    ///
+    /// Workers that run several calls at once can't give every call an
+    /// environment of its own, so this fails for pools with a
+    /// [`worker_concurrency`](struct.PoolBuilder.html#method.worker_concurrency)
+    /// above one.
+    #[track_caller]
+    pub fn spawn_with_env<
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        vars: I,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        if self.worker_concurrency > 1 {
+            return JoinHandle::new(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "worker concurrency cannot be combined with per-call environment variables",
+            )
+            .into()));
+        }
+        self.reserve(Reserve::Always);
+        self.spawn_helper(
+            CallOptions {
+                env: vars
+                    .into_iter()
+                    .map(|(key, value)| (key.as_ref().to_owned(), value.as_ref().to_owned()))
+                    .collect(),
+                ..CallOptions::default()
+            },
+            args,
+            func,
+        )
+    }
+
+    /// Spawns a closure into a process of the pool after a delay.
+    ///
+    /// See [`spawn_at`](#method.spawn_at) for details.
+    #[track_caller]
+    pub fn spawn_after<
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        delay: Duration,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        self.spawn_at(Instant::now() + delay, args, func)
+    }
+
+    /// Spawns a closure into a process of the pool at a point in time.
+    ///
+    /// The call is only handed to a worker once it's due, there is no need
+    /// for a separate timer thread.  Until then it is counted as queued, so
+    /// it counts towards the [`queue_limit`](struct.PoolBuilder.html#method.queue_limit)
+    /// and [`join`](#method.join) waits for it.  Calls that are due are
+    /// handed out in the order of their priority like any other call.
+    ///
+    /// The arguments are sent right away.  Very large arguments are only
+    /// sent as fast as the worker reads them, which means that this can
+    /// block until the call is due.
+    #[track_caller]
+    pub fn spawn_at<
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        at: Instant,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        self.reserve(Reserve::Always);
+        self.spawn_helper(
+            CallOptions {
+                due: Some(at),
+                ..CallOptions::default()
+            },
+            args,
+            func,
+        )
+    }
+
+    /// Spawns a closure into the process of the pool that is picked by a key.
+    ///
+    /// All calls with the same key go to the same worker, even if other
+    /// workers are idle.  This makes sure that state a worker keeps between
+    /// calls, for instance a cache, is reused for calls concerning the same
+    /// key.  Keyed calls are executed in the order they were spawned and
+    /// before calls of the same priority spawned with [`spawn`](#method.spawn)
+    /// that the worker would otherwise pick up.
+    ///
+    /// The worker is picked by hashing the key so different keys can be
+    /// routed to the same worker.  If the process of the worker is replaced
+    /// the calls for its keys go to the new process.  A pool without
+    /// workers has nothing to route to, so the call is queued like one of
+    /// [`spawn`](#method.spawn).
+    #[track_caller]
+    pub fn spawn_keyed<
+        K: Hash + ?Sized,
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        key: &K,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        let worker = match self.size() {
+            0 => None,
+            size => {
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+                Some((hasher.finish() % size as u64) as usize)
+            }
+        };
+        self.reserve(Reserve::Always);
+        self.spawn_helper(
+            CallOptions {
+                worker,
+                ..CallOptions::default()
+            },
+            args,
+            func,
+        )
+    }
+
+    fn reserve(&self, reserve: Reserve) -> bool {
         self.assert_alive();
+        self.shared.queue.reserve(reserve)
+    }
+
+    /// Spawns a call that took a place in the queue.
+    #[track_caller]
+    fn spawn_helper<
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+    >(
+        &self,
+        mut options: CallOptions,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        if self.shared.failed.load(Ordering::SeqCst) {
+            self.shared.queue.release();
+            return JoinHandle::new(Err(SpawnError::new_pool_failed()));
+        }
         let (args_tx, args_rx) = ipc::channel().unwrap();
         let (return_tx, return_rx) = ipc::channel().unwrap();
 
-        let call = MarshalledCall::marshal::<A, R>(func, args_rx, return_tx);
-        let (waiter_tx, waiter_rx) = mpsc::sync_channel(0);
+        // quarantined arguments are identified by their encoded bytes
+        let codec_factory = self
+            .codec
+            .or_else(default_codec)
+            .or_else(|| self.quarantine.as_ref().map(|_| codec_factory::<Bincode>()));
+        let max_message_size = max_message_size();
+        let codec = create_codec(codec_factory, self.compression, max_message_size);
+        let (result_stream, stream_rx) = result_stream(self.stream_threshold).unwrap();
+        let (progress_tx, reports) = ProgressReceiver::channel(self.progress).unwrap();
+        let (parent_tx, child_rx) = parent_channel(self.parent_channel).unwrap();
+        let (capture_tx, capture_rx) = if self.capture_output {
+            let (tx, rx) = ipc::channel().unwrap();
+            (Some(tx), Some(rx))
+        } else {
+            (None, None)
+        };
+        let call = MarshalledCall::marshal::<A, R>(
+            func,
+            args_rx,
+            return_tx,
+            codec_factory,
+            self.compression,
+            max_message_size,
+            result_stream,
+        )
+        .with_progress(progress_tx)
+        .with_parent(parent_tx)
+        .with_capture(capture_tx)
+        .with_env(mem::take(&mut options.env));
+        // the result is buffered so that workers can go on with the next
+        // call before the handle is joined.
+        let (waiter_tx, waiter_rx) = mpsc::sync_channel(1);
         let error_waiter_tx = waiter_tx.clone();
-        self.shared.queued_count.fetch_add(1, Ordering::SeqCst);
 
         let shared = Arc::new(PooledHandleState {
+            call_id: self.shared.next_call_id.fetch_add(1, Ordering::SeqCst) + 1,
             cancelled: AtomicBool::new(false),
             process_handle_state: Mutex::new(None),
+            worker: Mutex::new(None),
+            progress: Mutex::new(None),
+            fingerprint: Mutex::new(None),
+            timeout: options.timeout,
+            spawned: Instant::now(),
+            started: Mutex::new(None),
+            returned: Mutex::new(false),
+            timed_out: AtomicBool::new(false),
+            output: Mutex::new(None),
         });
 
-        self.sender
-            .lock()
-            .expect("pool sender poisoned")
-            .send((
-                call,
-                shared.clone(),
-                Box::new(move || {
-                    with_ipc_mode(|| {
-                        if let Ok(rv) = return_rx.recv() {
-                            waiter_tx.send(rv.map_err(Into::into)).is_ok()
-                        } else {
-                            false
+        let job: Job = (
+            call,
+            shared.clone(),
+            Box::new({
+                let codec = codec.clone();
+                let shared = shared.clone();
+                let quarantine = self.quarantine.clone();
+                let call_stats = self.shared.call_stats.clone();
+                let unfinished = Unfinished::new(&self.shared.unfinished_count);
+                move |permit: Option<Permit>| {
+                    // the output is sent before the result.
+                    if let Some(ref capture_rx) = capture_rx {
+                        *shared.output.lock().unwrap() = capture_rx.recv().ok();
+                    }
+                    let msg = with_codec(codec.as_ref(), || {
+                        with_ipc_mode(|| chunked::recv(&return_rx))
+                    });
+                    // the call is done, the caller might only join it after
+                    // other calls that still need a permit.
+                    drop(permit);
+                    drop(unfinished);
+                    if !shared.mark_returned() {
+                        return false;
+                    }
+                    if let Ok(msg) = msg {
+                        let progress = shared.progress.lock().unwrap().clone();
+                        let rv = unpack_result(
+                            msg,
+                            stream_rx.as_ref(),
+                            codec.as_ref(),
+                            progress.as_deref(),
+                        );
+                        if rv.as_ref().is_err_and(SpawnError::is_panic) {
+                            shared.quarantine(quarantine.as_deref());
                         }
-                    })
-                }),
-                Box::new(move |error| {
+                        call_stats.record_return(&rv, &shared);
+                        // the handle might have been dropped, that does not
+                        // make the worker unhealthy.
+                        waiter_tx.send(rv).ok();
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }),
+            Box::new({
+                let shared = shared.clone();
+                let quarantine = self.quarantine.clone();
+                let call_stats = self.shared.call_stats.clone();
+                let pool_failed = self.shared.failed.clone();
+                move |error| {
+                    let error = if shared.timed_out.load(Ordering::SeqCst) {
+                        SpawnError::new_timeout()
+                    } else if pool_failed.load(Ordering::SeqCst) {
+                        SpawnError::new_pool_failed()
+                    } else {
+                        error
+                    };
+                    call_stats.record_error(&error, &shared);
+                    // killed and refused calls did not crash their worker
+                    if !shared.cancelled.load(Ordering::SeqCst) && !error.is_concurrency_limited() {
+                        shared.quarantine(quarantine.as_deref());
+                    }
                     error_waiter_tx.send(Err(error)).ok();
-                }),
-            ))
-            .ok();
+                }
+            }),
+        );
 
-        args_tx.send(args).unwrap();
+        let sent = match self.quarantine {
+            None => {
+                self.shared.enqueue(job, &options);
+                with_codec(codec.as_ref(), || {
+                    with_ipc_mode(|| send_args(&args_tx, args))
+                })
+            }
+            Some(ref quarantine) => {
+                // the call is only queued once the arguments were admitted
+                // so that quarantined arguments never reach a worker.
+                let pending = Arc::new(Mutex::new(Some(job)));
+                let quarantined = Arc::new(AtomicBool::new(false));
+                let admission: Arc<dyn Codec> = Arc::new(Admission::new(codec.clone().unwrap(), {
+                    let pending = pending.clone();
+                    let quarantined = quarantined.clone();
+                    let quarantine = quarantine.clone();
+                    let shared = shared.clone();
+                    let pool_shared = self.shared.clone();
+                    move |fingerprint| {
+                        if quarantine.contains(fingerprint) {
+                            quarantined.store(true, Ordering::SeqCst);
+                            return Err("arguments are quarantined".into());
+                        }
+                        *shared.fingerprint.lock().unwrap() = Some(fingerprint);
+                        if let Some(job) = pending.lock().unwrap().take() {
+                            pool_shared.enqueue(job, &options);
+                        }
+                        Ok(())
+                    }
+                }));
+                let sent = with_codec(Some(&admission), || {
+                    with_ipc_mode(|| send_args(&args_tx, args))
+                });
+                if pending.lock().unwrap().take().is_some() {
+                    self.shared.queue.release();
+                }
+                if quarantined.load(Ordering::SeqCst) {
+                    return JoinHandle::new(Err(SpawnError::new_quarantined()));
+                }
+                sent
+            }
+        };
 
-        JoinHandle {
-            inner: Ok(JoinHandleInner::Pooled(PooledHandle { waiter_rx, shared })),
+        // if the call was already cancelled its handle reports why.
+        if let Err(err) = sent.map_err(SpawnError::from) {
+            if !shared.cancelled.load(Ordering::SeqCst) {
+                if !err.is_message_too_large() {
+                    panic!("could not send arguments to pool: {}", err);
+                }
+                shared.cancelled.store(true, Ordering::SeqCst);
+                return JoinHandle::new(Err(err));
+            }
         }
+
+        JoinHandle::new(Ok(JoinHandleInner::Pooled(PooledHandle {
+            waiter_rx,
+            reports,
+            child_rx,
+            shared,
+            pool_failed: self.shared.failed.clone(),
+            drop_behavior: self.drop_behavior,
+            finished: false,
+            ready: None,
+        })))
+        .with_function(func as *const ())
     }
 
     /// Joins the process pool.
@@ -227,19 +1322,26 @@ impl Pool {
     ///
     /// After calling this the pool cannot be used any more.
     pub fn kill(&self) {
-        if self.shared.dead.load(Ordering::SeqCst) {
-            return;
-        }
-        self.shared.dead.store(true, Ordering::SeqCst);
-        for monitor in self.shared.monitors.lock().unwrap().iter_mut() {
-            if let Some(mut join_handle) = monitor.join_handle.lock().unwrap().take() {
-                join_handle.kill().ok();
-            }
+        self.shared.kill();
+    }
+
+    /// Returns whether the pool still works.
+    ///
+    /// A pool fails once its workers were restarted more often than allowed
+    /// by [`PoolBuilder::max_restarts`](struct.PoolBuilder.html#method.max_restarts).
+    pub fn health(&self) -> PoolHealth {
+        if self.shared.failed.load(Ordering::SeqCst) {
+            PoolHealth::Failed
+        } else if self.shared.dead.load(Ordering::SeqCst) {
+            PoolHealth::Killed
+        } else {
+            PoolHealth::Healthy
         }
     }
 
     fn assert_alive(&self) {
-        if self.shared.dead.load(Ordering::SeqCst) {
+        // calls to failed pools fail instead.
+        if self.shared.dead.load(Ordering::SeqCst) && !self.shared.failed.load(Ordering::SeqCst) {
             panic!("The process pool is dead");
         }
     }
@@ -254,100 +1356,546 @@ pub struct PoolBuilder {
     disable_stdin: bool,
     disable_stdout: bool,
     disable_stderr: bool,
+    capture_output: bool,
+    state_file: Option<PathBuf>,
+    adoption_timeout: Duration,
+    drop_behavior: HandleDropBehavior,
+    on_drop: DropBehavior,
+    quarantine: Option<Duration>,
+    worker_init: Option<Arc<WorkerInit>>,
+    idle_timeout: Option<Duration>,
+    min_idle: usize,
+    queue_limit: Option<usize>,
+    hooks: Hooks,
+    max_restarts: Option<(usize, Duration)>,
+    restart_backoff: Option<(Duration, Duration)>,
+    worker_concurrency: usize,
     common: ProcCommon,
 }
 
-impl PoolBuilder {
-    /// Create a new pool builder.
+impl PoolBuilder {
+    /// Create a new pool builder.
+    ///
+    /// The size of the process pool is mandatory.  If you want to
+    /// make it depending on the processor count you can use the
+    /// `num_cpus` crate.
+    fn new(size: usize) -> PoolBuilder {
+        PoolBuilder {
+            size,
+            disable_stdin: false,
+            disable_stdout: false,
+            disable_stderr: false,
+            capture_output: false,
+            state_file: None,
+            adoption_timeout: Duration::from_secs(60),
+            drop_behavior: HandleDropBehavior::Detach,
+            on_drop: DropBehavior::Kill,
+            quarantine: None,
+            worker_init: None,
+            idle_timeout: None,
+            min_idle: 0,
+            queue_limit: None,
+            hooks: Hooks::default(),
+            max_restarts: None,
+            restart_backoff: None,
+            worker_concurrency: 1,
+            // pools inherit the defaults of builders, the stdio defaults
+            // apply when the workers are spawned.
+            common: Builder::new().into_common(),
+        }
+    }
+
+    define_common_methods!();
+
+    /// Redirects stdin to `/dev/null`.
+    pub fn disable_stdin(&mut self) -> &mut Self {
+        self.disable_stdin = true;
+        self
+    }
+
+    /// Redirects stdout to `/dev/null`.
+    pub fn disable_stdout(&mut self) -> &mut Self {
+        self.disable_stdout = true;
+        self
+    }
+
+    /// Redirects stderr to `/dev/null`.
+    pub fn disable_stderr(&mut self) -> &mut Self {
+        self.disable_stderr = true;
+        self
+    }
+
+    /// Captures what each call writes to stdout and stderr.
+    ///
+    /// While a call runs the worker redirects its stdout and stderr into
+    /// pipes.  The output is returned together with the result by
+    /// [`JoinHandle::join_with_output`](struct.JoinHandle.html#method.join_with_output)
+    /// so that it can be attributed to the call that produced it.  Output of
+    /// calls that crash or are killed is lost, as is output written by other
+    /// threads of the worker while the call runs.
+    ///
+    /// This is only available on unix platforms.
+    #[cfg(unix)]
+    pub fn capture_output(&mut self) -> &mut Self {
+        self.capture_output = true;
+        self
+    }
+
+    /// Makes the workers of the pool adoptable by a future pool.
+    ///
+    /// Normally the workers of a pool go away together with the process
+    /// that created the pool.  With this enabled workers that lose their
+    /// parent (for instance because it crashed or was restarted) stay
+    /// around, register themselves in the given state file and wait for a
+    /// new pool configured with the same state file to adopt them.  This
+    /// keeps the state the workers accumulated between calls.
+    ///
+    /// Workers are only adopted by processes running the very same
+    /// executable.  Workers that are not adopted within the
+    /// [`adoption_timeout`](#method.adoption_timeout) shut down.
+    ///
+    /// Note that workers of a pool that is killed or dropped are not
+    /// adoptable.
+    pub fn adoptable<P: AsRef<Path>>(&mut self, state_file: P) -> &mut Self {
+        self.state_file = Some(state_file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets how long orphaned workers wait to be adopted.
+    ///
+    /// The default is 60 seconds.  This only has an effect on pools that
+    /// are [`adoptable`](#method.adoptable).
+    pub fn adoption_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.adoption_timeout = timeout;
+        self
+    }
+
+    /// Sets what happens to calls whose join handle is dropped unjoined.
+    ///
+    /// By default such calls are detached and keep running.  When join
+    /// handles are dropped because the code waiting for them gave up (for
+    /// instance after a timeout or when only the first few results of a
+    /// [`par_bridge`](iter/trait.ParallelBridge.html) are consumed) the
+    /// work would otherwise continue in the background.  See
+    /// [`HandleDropBehavior`](enum.HandleDropBehavior.html) for the options.
+    pub fn drop_behavior(&mut self, behavior: HandleDropBehavior) -> &mut Self {
+        self.drop_behavior = behavior;
+        self
+    }
+
+    /// Sets what happens to waiting and running calls when the pool is
+    /// dropped.
+    ///
+    /// By default the processes of a dropped pool are killed right away.
+    /// With [`DropBehavior::Wait`](enum.DropBehavior.html#variant.Wait)
+    /// dropping the pool blocks until all calls finished so that no work is
+    /// lost, for instance when the pool goes out of scope while shutting
+    /// down.  Results of calls whose handles are still around can be
+    /// joined after the pool was dropped.
+    pub fn on_drop(&mut self, behavior: DropBehavior) -> &mut Self {
+        self.on_drop = behavior;
+        self
+    }
+
+    /// Quarantines the arguments of calls that panic or crash their worker.
+    ///
+    /// A single bad input can otherwise take down one warm worker after the
+    /// other when it is retried.  With this enabled the pool remembers a
+    /// fingerprint of the encoded arguments of every call that panicked or
+    /// brought down its process.  Calls with identical arguments submitted
+    /// within the given period fail right away with an error for which
+    /// [`SpawnError::is_quarantined`](struct.SpawnError.html#method.is_quarantined)
+    /// returns `true` and never reach a worker.
+    ///
+    /// Calls that are cancelled or killed through their join handle are not
+    /// quarantined.  Because the fingerprint is taken from the encoded
+    /// arguments, arguments are encoded with [`Bincode`](codec/struct.Bincode.html)
+    /// unless a [codec](codec/index.html) is selected.
+    pub fn quarantine(&mut self, period: Duration) -> &mut Self {
+        self.quarantine = Some(period);
+        self
+    }
+
+    /// Runs a function in every worker before it handles calls.
+    ///
+    /// This is useful for expensive setup such as loading a model or
+    /// opening a database connection.  The function runs whenever a worker
+    /// process is spawned, including when a worker is restarted after its
+    /// process went away, and receives a clone of `args`.  Unlike with
+    /// [`ProcConfig::config_callback`](struct.ProcConfig.html#method.config_callback)
+    /// it only runs in the workers of this pool.
+    ///
+    /// The value returned by the function stays in the worker and calls can
+    /// access it with [`with_worker_state`](fn.with_worker_state.html):
+    ///
+    /// ```rust,no_run
+    /// use std::collections::HashMap;
+    /// use procspawn::Pool;
+    ///
+    /// let pool = Pool::builder(4)
+    ///     .worker_init((), |()| HashMap::<u32, u64>::new())
+    ///     .build()
+    ///     .unwrap();
+    /// let handle = pool.spawn(42, |key| {
+    ///     procspawn::with_worker_state(|cache: &mut HashMap<u32, u64>| {
+    ///         *cache.entry(key).or_insert_with(|| u64::from(key) * 2)
+    ///     })
+    ///     .unwrap()
+    /// });
+    /// ```
+    ///
+    /// If the function panics while the pool is built, building the pool
+    /// fails.  If it panics after a restart the process of the worker is
+    /// killed and the calls sent to it fail until it was restarted
+    /// successfully.  Adopted workers keep the state they were initialized
+    /// with.
+    pub fn worker_init<A, S>(&mut self, args: A, init: fn(A) -> S) -> &mut Self
+    where
+        A: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+        S: 'static,
+    {
+        self.worker_init = Some(Arc::new(WorkerInit(Box::new(move || {
+            let (call, args_tx) = Call::new(|(init, args): (MarshalledFn<A, S>, A)| {
+                let state = init.get()(args);
+                WORKER_STATE.with(|x| *x.borrow_mut() = Some(Box::new(state)));
+            });
+            let args = args.clone();
+            let send_args: WorkerInitArgs =
+                Box::new(move || args_tx.send((MarshalledFn::new(init), args))?.recv());
+            (call, send_args)
+        }))));
+        self
+    }
+
+    /// Shuts down the processes of workers that have been idle for a while.
+    ///
+    /// By default the processes of all workers keep running for as long as
+    /// the pool exists.  With this enabled the process of a worker that did
+    /// not receive a call within the given duration is shut down, and it's
+    /// only spawned again once a call is sent to the worker.  This keeps the
+    /// memory usage of large pools low between bursts of calls.  Use
+    /// [`min_idle`](#method.min_idle) to keep some processes around for
+    /// calls that should not wait for a process to spawn.
+    ///
+    /// Workers without a process have no `pid` in
+    /// [`Pool::workers`](struct.Pool.html#method.workers).
+    pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how many worker processes are kept when they are idle.
+    ///
+    /// The default is `0`.  This only has an effect together with
+    /// [`idle_timeout`](#method.idle_timeout).
+    pub fn min_idle(&mut self, count: usize) -> &mut Self {
+        self.min_idle = count;
+        self
+    }
+
+    /// Limits how many calls can wait for a worker.
+    ///
+    /// By default the queue of the pool is unbounded so a fast producer can
+    /// queue up any amount of work.  With a limit
+    /// [`Pool::try_spawn`](struct.Pool.html#method.try_spawn) fails and
+    /// [`Pool::spawn_wait`](struct.Pool.html#method.spawn_wait) blocks
+    /// while the queue is full.  Calls spawned with the other methods count
+    /// towards the limit but are always queued.
+    pub fn queue_limit(&mut self, limit: usize) -> &mut Self {
+        self.queue_limit = Some(limit);
+        self
+    }
+
+    /// Gives up on the pool if its workers keep crashing.
     ///
-    /// The size of the process pool is mandatory.  If you want to
-    /// make it depending on the processor count you can use the
-    /// `num_cpus` crate.
-    fn new(size: usize) -> PoolBuilder {
-        PoolBuilder {
-            size,
-            disable_stdin: false,
-            disable_stdout: false,
-            disable_stderr: false,
-            common: ProcCommon::default(),
-        }
+    /// By default crashed workers are restarted forever, even if they crash
+    /// again right away, for instance because of a bad configuration.  With
+    /// this enabled the pool fails once its workers were restarted more
+    /// than `max_restarts` times within the given duration.  A failed pool
+    /// kills its workers, waiting and running calls fail with an error for
+    /// which [`SpawnError::is_pool_failed`](struct.SpawnError.html#method.is_pool_failed)
+    /// returns `true`, and so do all calls spawned afterwards.  The state
+    /// of the pool is returned by [`Pool::health`](struct.Pool.html#method.health).
+    pub fn max_restarts(&mut self, max_restarts: usize, within: Duration) -> &mut Self {
+        self.max_restarts = Some((max_restarts, within));
+        self
     }
 
-    define_common_methods!();
+    /// Waits before restarting workers that crash over and over.
+    ///
+    /// By default a crashed worker is restarted right away.  With this
+    /// enabled the restart is delayed by `initial`, and the delay doubles
+    /// for every further restart up to `max`.  Once the worker handled a
+    /// call without crashing the delay starts over.  Calls routed to the
+    /// worker wait for it in the meantime.
+    pub fn restart_backoff(&mut self, initial: Duration, max: Duration) -> &mut Self {
+        self.restart_backoff = Some((initial, max));
+        self
+    }
 
-    /// Redirects stdin to `/dev/null`.
-    pub fn disable_stdin(&mut self) -> &mut Self {
-        self.disable_stdin = true;
+    /// Lets every worker run up to `concurrency` calls at the same time.
+    ///
+    /// By default a worker runs one call after the other.  With a higher
+    /// concurrency each call runs on its own thread in the worker, which
+    /// cuts down the number of processes needed for calls that mostly
+    /// wait on IO.  Panics of such calls are caught so that they only fail
+    /// their own call, but a worker that crashes, or is killed because a
+    /// call timed out, takes all of its running calls with it.
+    ///
+    /// The calls of a worker share the state of its process, so
+    /// [`capture_output`](#method.capture_output), progress reports and the
+    /// channel to the parent can't tell them apart and [`build`](#method.build)
+    /// fails if they are combined with this.  For the same reason
+    /// [`Pool::spawn_with_env`](struct.Pool.html#method.spawn_with_env)
+    /// fails on such pools.  [`with_worker_state`](fn.with_worker_state.html)
+    /// is only available on the main thread of the worker and thus returns
+    /// `None` in calls of concurrent workers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `concurrency` is `0`.
+    pub fn worker_concurrency(&mut self, concurrency: usize) -> &mut Self {
+        assert!(concurrency > 0, "worker concurrency must be at least 1");
+        self.worker_concurrency = concurrency;
         self
     }
 
-    /// Redirects stdout to `/dev/null`.
-    pub fn disable_stdout(&mut self) -> &mut Self {
-        self.disable_stdout = true;
+    /// Registers a callback for worker processes that were started.
+    ///
+    /// The callback receives the process ID of every process the pool
+    /// spawns or adopts, including processes that replace crashed ones.
+    ///
+    /// Callbacks are invoked on the threads that manage the workers, so
+    /// they should return quickly.
+    pub fn on_worker_start<F: Fn(u32) + Send + Sync + 'static>(&mut self, f: F) -> &mut Self {
+        self.hooks.on_worker_start = Some(Arc::new(f));
         self
     }
 
-    /// Redirects stderr to `/dev/null`.
-    pub fn disable_stderr(&mut self) -> &mut Self {
-        self.disable_stderr = true;
+    /// Registers a callback for worker processes that went away.
+    ///
+    /// The callback receives the process ID and the reason as a
+    /// [`WorkerExit`](enum.WorkerExit.html).
+    pub fn on_worker_exit<F: Fn(u32, &WorkerExit) + Send + Sync + 'static>(
+        &mut self,
+        f: F,
+    ) -> &mut Self {
+        self.hooks.on_worker_exit = Some(Arc::new(f));
+        self
+    }
+
+    /// Registers a callback for calls that ended.
+    ///
+    /// The callback receives the [`JobStats`](struct.JobStats.html) of
+    /// every call that returned or failed, before the result is handed to
+    /// its join handle.
+    pub fn on_job_complete<F: Fn(&JobStats) + Send + Sync + 'static>(&mut self, f: F) -> &mut Self {
+        self.hooks.on_job_complete = Some(Arc::new(f));
         self
     }
 
+    fn adoption(&self) -> Option<Adoption> {
+        self.state_file.as_ref().map(|state_file| Adoption {
+            state_file: state_file.clone(),
+            timeout: self.adoption_timeout,
+        })
+    }
+
     /// Creates the pool.
+    ///
+    /// If the pool is [`adoptable`](#method.adoptable) orphaned workers are
+    /// adopted first and only the remaining workers are spawned.
     pub fn build(&mut self) -> Result<Pool, SpawnError> {
-        let (tx, rx) = mpsc::channel();
-
+        if self.worker_concurrency > 1
+            && (self.common.progress || self.common.parent_channel || self.capture_output)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "worker concurrency cannot be combined with progress reports, \
+                 the parent channel or captured output",
+            )
+            .into());
+        }
         let shared = Arc::new(PoolShared {
-            call_receiver: Mutex::new(rx),
+            queue: JobQueue::new(self.size, self.queue_limit),
             empty_trigger: Mutex::new(()),
             empty_condvar: Condvar::new(),
             join_generation: AtomicUsize::new(0),
             monitors: Mutex::new(Vec::with_capacity(self.size)),
+            next_call_id: AtomicU64::new(0),
             queued_count: AtomicUsize::new(0),
+            unfinished_count: Arc::new(AtomicUsize::new(0)),
             active_count: AtomicUsize::new(0),
+            running_count: AtomicUsize::new(self.size),
+            call_stats: Arc::new(CallStats {
+                on_job_complete: self.hooks.on_job_complete.clone(),
+                ..CallStats::default()
+            }),
+            hooks: self.hooks.clone(),
+            restart_limit: self.max_restarts,
+            restarts: Mutex::new(VecDeque::new()),
+            failed: Arc::new(AtomicBool::new(false)),
             dead: AtomicBool::new(false),
         });
 
-        {
-            let mut monitors = shared.monitors.lock().unwrap();
-            for _ in 0..self.size {
-                monitors.push(spawn_worker(shared.clone(), self)?);
-            }
+        let mut adopted = match self.state_file {
+            Some(ref state_file) => adopt_workers(state_file, self.size),
+            None => Vec::new(),
+        };
+
+        // the pool is created first so that the workers spawned so far are
+        // killed if spawning one of them fails.
+        let pool = Pool {
+            shared: shared.clone(),
+            drop_behavior: self.drop_behavior,
+            on_drop: self.on_drop,
+            codec: self.common.codec,
+            compression: self.common.compression,
+            stream_threshold: self.common.stream_threshold,
+            progress: self.common.progress,
+            parent_channel: self.common.parent_channel,
+            capture_output: self.capture_output,
+            worker_concurrency: self.worker_concurrency,
+            quarantine: self
+                .quarantine
+                .map(|period| Arc::new(Quarantine::new(period))),
+        };
+
+        for index in 0..self.size {
+            let monitor = spawn_worker(shared.clone(), self, index, adopted.pop())?;
+            shared.monitors.lock().unwrap().push(monitor);
         }
 
-        Ok(Pool {
-            sender: Mutex::new(tx),
-            shared,
-        })
+        Ok(pool)
     }
 }
 
 impl Drop for Pool {
     fn drop(&mut self) {
+        // a killed pool has no calls left that could finish.
+        if !self.shared.dead.load(Ordering::SeqCst) {
+            match self.on_drop {
+                DropBehavior::Kill => {}
+                DropBehavior::Wait => self.shared.wait_unfinished(None),
+                DropBehavior::WaitTimeout(timeout) => self
+                    .shared
+                    .wait_unfinished(Instant::now().checked_add(timeout)),
+            }
+        }
         self.kill();
     }
 }
 
 struct PoolShared {
-    #[allow(clippy::type_complexity)]
-    call_receiver: Mutex<
-        mpsc::Receiver<(
-            MarshalledCall,
-            Arc<PooledHandleState>,
-            WaitFunc,
-            NotifyErrorFunc,
-        )>,
-    >,
+    queue: JobQueue,
     empty_trigger: Mutex<()>,
     empty_condvar: Condvar,
     join_generation: AtomicUsize,
     monitors: Mutex<Vec<WorkerMonitor>>,
+    next_call_id: AtomicU64,
     queued_count: AtomicUsize,
+    /// Calls that did not finish running.  Unlike `active_count` calls
+    /// whose result waits to be joined are not counted.
+    unfinished_count: Arc<AtomicUsize>,
     active_count: AtomicUsize,
+    /// The number of workers that have a process.
+    running_count: AtomicUsize,
+    call_stats: Arc<CallStats>,
+    hooks: Hooks,
+    restart_limit: Option<(usize, Duration)>,
+    /// When workers were restarted within the restart limit.
+    restarts: Mutex<VecDeque<Instant>>,
+    failed: Arc<AtomicBool>,
     dead: AtomicBool,
 }
 
 impl PoolShared {
+    fn kill(&self) {
+        if self.dead.load(Ordering::SeqCst) {
+            return;
+        }
+        self.dead.store(true, Ordering::SeqCst);
+        self.queue.close();
+        for monitor in self.monitors.lock().unwrap().iter_mut() {
+            if let Some(mut join_handle) = monitor.worker_handle.lock().unwrap().take() {
+                join_handle.kill().ok();
+                self.hooks
+                    .worker_exited(join_handle.pid(), &WorkerExit::Shutdown);
+            }
+        }
+    }
+
+    /// Records a restart of a worker.
+    ///
+    /// Returns `false` if this exceeds the restart limit.
+    fn record_restart(&self) -> bool {
+        let (max_restarts, within) = match self.restart_limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let now = Instant::now();
+        let mut restarts = self.restarts.lock().unwrap();
+        while restarts
+            .front()
+            .is_some_and(|&restart| now.duration_since(restart) > within)
+        {
+            restarts.pop_front();
+        }
+        restarts.push_back(now);
+        restarts.len() <= max_restarts
+    }
+
+    /// Gives up on the pool because its workers keep crashing.
+    ///
+    /// All workers are killed and all calls fail.
+    fn fail(&self) {
+        self.failed.store(true, Ordering::SeqCst);
+        self.kill();
+        let err = SpawnError::new_pool_failed();
+        for (_, state, _, _) in self.queue.drain() {
+            self.queued_count.fetch_sub(1, Ordering::SeqCst);
+            self.call_stats.record_error(&err, &state);
+        }
+        self.no_work_notify_all();
+    }
+
+    /// Claims the shutdown of an idle worker unless it's needed to keep
+    /// `min_idle` processes around.
+    fn claim_idle_shutdown(&self, min_idle: usize) -> bool {
+        self.running_count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                if count > min_idle {
+                    Some(count - 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    fn enqueue(&self, job: Job, options: &CallOptions) {
+        let depth = self.queued_count.fetch_add(1, Ordering::SeqCst) + 1;
+        self.call_stats.record_queue_depth(depth);
+        self.queue
+            .push(job, options.worker, options.priority, options.due);
+    }
+
+    /// Waits until all calls finished running or the deadline passed.
+    fn wait_unfinished(&self, deadline: Option<Instant>) {
+        let mut to_sleep = Duration::from_millis(1);
+        while self.unfinished_count.load(Ordering::SeqCst) > 0 {
+            let wait = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if remaining > Duration::ZERO => remaining.min(to_sleep),
+                    _ => return,
+                },
+                None => to_sleep,
+            };
+            thread::sleep(wait);
+            to_sleep = (to_sleep * 2).min(DEADLINE_POLL_INTERVAL);
+        }
+    }
+
     fn has_work(&self) -> bool {
         self.queued_count.load(Ordering::SeqCst) > 0 || self.active_count.load(Ordering::SeqCst) > 0
     }
@@ -364,28 +1912,479 @@ impl PoolShared {
     }
 }
 
+/// Information about a worker process of a pool.
+///
+/// This is returned by [`Pool::workers`](struct.Pool.html#method.workers)
+/// and reflects the state of the worker at the time of the call.
+#[derive(Debug, Clone)]
+pub struct WorkerInfo {
+    pid: Option<u32>,
+    uptime: Duration,
+    jobs_handled: u64,
+    restart_count: u64,
+    current_call_id: Option<u64>,
+}
+
+impl WorkerInfo {
+    /// The process ID of the worker.
+    ///
+    /// This is `None` while the worker is being restarted or while it has
+    /// no process because it was idle.
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// How long the current process of the worker has been running.
+    pub fn uptime(&self) -> Duration {
+        self.uptime
+    }
+
+    /// The number of calls the worker ran, across restarts.
+    pub fn jobs_handled(&self) -> u64 {
+        self.jobs_handled
+    }
+
+    /// How often the process of the worker was replaced.
+    pub fn restart_count(&self) -> u64 {
+        self.restart_count
+    }
+
+    /// The call the worker is running right now.
+    ///
+    /// This matches [`JoinHandle::call_id`](struct.JoinHandle.html#method.call_id)
+    /// of the call.
+    pub fn current_call_id(&self) -> Option<u64> {
+        self.current_call_id
+    }
+}
+
+/// Why the process of a worker went away.
+///
+/// This is passed to the callback registered with
+/// [`PoolBuilder::on_worker_exit`](struct.PoolBuilder.html#method.on_worker_exit).
+#[derive(Debug)]
+pub enum WorkerExit {
+    /// The process crashed, was killed or exited on its own.  The worker
+    /// is restarted.
+    Crashed(SpawnError),
+    /// The process was shut down because the worker was idle.
+    Idle,
+    /// The process was killed because the pool was killed or dropped.
+    Shutdown,
+}
+
+/// How a pooled call ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobOutcome {
+    /// The call returned a value.
+    Completed,
+    /// The call panicked.
+    Panicked,
+    /// The call failed otherwise, for instance because it crashed its
+    /// worker, timed out or was cancelled.
+    Failed,
+}
+
+/// Statistics of a single pooled call.
+///
+/// This is passed to the callback registered with
+/// [`PoolBuilder::on_job_complete`](struct.PoolBuilder.html#method.on_job_complete).
+#[derive(Debug, Clone)]
+pub struct JobStats {
+    call_id: u64,
+    pid: Option<u32>,
+    outcome: JobOutcome,
+    latency: Duration,
+    run_time: Option<Duration>,
+}
+
+impl JobStats {
+    /// The ID of the call.
+    ///
+    /// This matches [`JoinHandle::call_id`](struct.JoinHandle.html#method.call_id).
+    pub fn call_id(&self) -> u64 {
+        self.call_id
+    }
+
+    /// The process ID of the worker that ran the call.
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// How the call ended.
+    pub fn outcome(&self) -> JobOutcome {
+        self.outcome
+    }
+
+    /// The time from spawning the call until it ended.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// The time the call ran in its worker.
+    ///
+    /// This is `None` for calls that never reached a worker.
+    pub fn run_time(&self) -> Option<Duration> {
+        self.run_time
+    }
+}
+
+type WorkerStartFunc = dyn Fn(u32) + Send + Sync;
+type WorkerExitFunc = dyn Fn(u32, &WorkerExit) + Send + Sync;
+type JobCompleteFunc = dyn Fn(&JobStats) + Send + Sync;
+
+/// The lifecycle callbacks of a pool.
+#[derive(Clone, Default)]
+struct Hooks {
+    on_worker_start: Option<Arc<WorkerStartFunc>>,
+    on_worker_exit: Option<Arc<WorkerExitFunc>>,
+    on_job_complete: Option<Arc<JobCompleteFunc>>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hooks").finish()
+    }
+}
+
+impl Hooks {
+    fn worker_started(&self, pid: Option<u32>) {
+        if let (Some(ref f), Some(pid)) = (&self.on_worker_start, pid) {
+            f(pid);
+        }
+    }
+
+    fn worker_exited(&self, pid: Option<u32>, exit: &WorkerExit) {
+        if let (Some(ref f), Some(pid)) = (&self.on_worker_exit, pid) {
+            f(pid, exit);
+        }
+    }
+}
+
+/// The number of queue depths kept for [`PoolStats::queue_depths`].
+const QUEUE_DEPTH_HISTORY: usize = 100;
+
+/// Statistics of a pool.
+///
+/// This is returned by [`Pool::stats`](struct.Pool.html#method.stats) and
+/// reflects the state of the pool at the time of the call.
+#[derive(Debug, Clone)]
+pub struct PoolStats {
+    completed: u64,
+    panicked: u64,
+    failed: u64,
+    queued: usize,
+    active: usize,
+    queue_depths: Vec<usize>,
+    average_latency: Option<Duration>,
+    workers: Vec<WorkerInfo>,
+}
+
+impl PoolStats {
+    /// The number of calls that returned a value.
+    pub fn completed(&self) -> u64 {
+        self.completed
+    }
+
+    /// The number of calls that panicked.
+    pub fn panicked(&self) -> u64 {
+        self.panicked
+    }
+
+    /// The number of calls that failed otherwise.
+    ///
+    /// This includes calls that crashed their worker, timed out or were
+    /// cancelled.
+    pub fn failed(&self) -> u64 {
+        self.failed
+    }
+
+    /// The number of calls waiting for a worker.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// The number of calls running right now.
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// The number of waiting calls after each of the latest changes to the
+    /// queue, oldest first.
+    ///
+    /// Up to the last 100 changes are kept.
+    pub fn queue_depths(&self) -> &[usize] {
+        &self.queue_depths
+    }
+
+    /// The average time from spawning a call until its worker returned.
+    ///
+    /// This includes the time the call waited for a worker.  It's `None`
+    /// until the first call returned.
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.average_latency
+    }
+
+    /// Information about the workers of the pool.
+    ///
+    /// This is the same as [`Pool::workers`](struct.Pool.html#method.workers).
+    pub fn workers(&self) -> &[WorkerInfo] {
+        &self.workers
+    }
+}
+
+/// Statistics of the calls of a pool.
+#[derive(Default)]
+struct CallStats {
+    on_job_complete: Option<Arc<JobCompleteFunc>>,
+    completed: AtomicU64,
+    panicked: AtomicU64,
+    failed: AtomicU64,
+    returned: AtomicU64,
+    total_latency_nanos: AtomicU64,
+    queue_depths: Mutex<VecDeque<usize>>,
+}
+
+impl CallStats {
+    fn record_return<T>(&self, rv: &Result<T, SpawnError>, state: &PooledHandleState) {
+        let outcome = match *rv {
+            Ok(_) => JobOutcome::Completed,
+            Err(ref err) if err.is_panic() => JobOutcome::Panicked,
+            Err(_) => JobOutcome::Failed,
+        };
+        let latency = state.spawned.elapsed();
+        self.returned.fetch_add(1, Ordering::Relaxed);
+        self.total_latency_nanos
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+        self.record(outcome, latency, state);
+    }
+
+    /// Records a call that did not return, most likely because its worker
+    /// went away.
+    fn record_error(&self, err: &SpawnError, state: &PooledHandleState) {
+        let outcome = if err.is_panic() {
+            JobOutcome::Panicked
+        } else {
+            JobOutcome::Failed
+        };
+        self.record(outcome, state.spawned.elapsed(), state);
+    }
+
+    fn record(&self, outcome: JobOutcome, latency: Duration, state: &PooledHandleState) {
+        match outcome {
+            JobOutcome::Completed => &self.completed,
+            JobOutcome::Panicked => &self.panicked,
+            JobOutcome::Failed => &self.failed,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+        if let Some(ref f) = self.on_job_complete {
+            f(&JobStats {
+                call_id: state.call_id,
+                pid: state
+                    .process_handle_state
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|state| state.pid()),
+                outcome,
+                latency,
+                run_time: state
+                    .started
+                    .lock()
+                    .unwrap()
+                    .map(|started| started.elapsed()),
+            });
+        }
+    }
+
+    fn record_queue_depth(&self, depth: usize) {
+        let mut queue_depths = self.queue_depths.lock().unwrap();
+        if queue_depths.len() == QUEUE_DEPTH_HISTORY {
+            queue_depths.pop_front();
+        }
+        queue_depths.push_back(depth);
+    }
+
+    fn average_latency(&self) -> Option<Duration> {
+        match self.returned.load(Ordering::Relaxed) {
+            0 => None,
+            returned => Some(Duration::from_nanos(
+                self.total_latency_nanos.load(Ordering::Relaxed) / returned,
+            )),
+        }
+    }
+}
+
+/// Statistics of a worker that are updated by its monitor.
+struct WorkerStats {
+    started: Mutex<Instant>,
+    jobs_handled: AtomicU64,
+    restart_count: AtomicU64,
+    /// `0` if the worker is idle.
+    current_call_id: AtomicU64,
+}
+
+/// What a worker is busy with, shared by its monitors.
+struct WorkerLoad {
+    /// `false` while the process is stopped for being idle.
+    running: bool,
+    /// The number of calls handed to the process.
+    busy: usize,
+}
+
 struct WorkerMonitor {
-    join_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+    worker_handle: Arc<Mutex<Option<WorkerHandle>>>,
+    stats: Arc<WorkerStats>,
+}
+
+impl WorkerMonitor {
+    fn info(&self) -> WorkerInfo {
+        WorkerInfo {
+            pid: self
+                .worker_handle
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(WorkerHandle::process_handle_state)
+                .and_then(|state| state.pid()),
+            uptime: self.stats.started.lock().unwrap().elapsed(),
+            jobs_handled: self.stats.jobs_handled.load(Ordering::SeqCst),
+            restart_count: self.stats.restart_count.load(Ordering::SeqCst),
+            current_call_id: match self.stats.current_call_id.load(Ordering::SeqCst) {
+                0 => None,
+                call_id => Some(call_id),
+            },
+        }
+    }
+}
+
+enum WorkerHandle {
+    Spawned(Box<JoinHandle<()>>),
+    // adopted workers are not our children so all we have is the pid.
+    Adopted(Arc<ProcessHandleState>),
+}
+
+impl WorkerHandle {
+    fn pid(&self) -> Option<u32> {
+        self.process_handle_state()?.pid()
+    }
+
+    fn process_handle_state(&self) -> Option<Arc<ProcessHandleState>> {
+        match *self {
+            WorkerHandle::Spawned(ref handle) => handle.process_handle_state(),
+            WorkerHandle::Adopted(ref state) => Some(state.clone()),
+        }
+    }
+
+    fn kill(&mut self) -> Result<(), SpawnError> {
+        match *self {
+            WorkerHandle::Spawned(ref mut handle) => handle.kill(),
+            WorkerHandle::Adopted(ref state) => {
+                state.kill();
+                Ok(())
+            }
+        }
+    }
+
+    fn join(self) -> Result<(), SpawnError> {
+        match self {
+            WorkerHandle::Spawned(handle) => handle.join(),
+            WorkerHandle::Adopted(state) => {
+                state.kill();
+                Ok(())
+            }
+        }
+    }
+}
+
+type WorkerArgs = (
+    IpcReceiver<MarshalledCall>,
+    Option<Adoption>,
+    Option<Call>,
+    usize,
+);
+
+fn run_worker((mut call_rx, adoption, init, concurrency): WorkerArgs) {
+    if let Some(init) = init {
+        // the call was created by the parent which runs the same
+        // executable.
+        unsafe { init.execute() }.ok();
+    }
+    loop {
+        while let Ok(call) = call_rx.recv() {
+            if concurrency > 1 {
+                // the parent never sends more calls than the worker may
+                // run at once.  Panics are handled so they don't take the
+                // other calls down.  If no thread can be started the call
+                // runs right here instead of getting lost.
+                let call = Arc::new(Mutex::new(Some(call)));
+                let thread_call = call.clone();
+                let spawned =
+                    thread::Builder::new()
+                        .name("procspawn-call".into())
+                        .spawn(move || {
+                            if let Some(call) = thread_call.lock().unwrap().take() {
+                                call.call(true);
+                            }
+                        });
+                if spawned.is_err() {
+                    if let Some(call) = call.lock().unwrap().take() {
+                        call.call(true);
+                    }
+                }
+            } else {
+                // we never want panic handling here as we're going to
+                // defer this to the process'.
+                call.call(false);
+            }
+        }
+        match adoption.as_ref().and_then(|x| x.wait_for_parent()) {
+            Some(new_call_rx) => call_rx = new_call_rx,
+            None => break,
+        }
+    }
 }
 
 fn spawn_worker(
     shared: Arc<PoolShared>,
     builder: &PoolBuilder,
+    index: usize,
+    adopted: Option<AdoptedWorker>,
 ) -> Result<WorkerMonitor, SpawnError> {
-    let join_handle = Arc::new(Mutex::new(None::<JoinHandle<()>>));
+    let join_handle = Arc::new(Mutex::new(None::<WorkerHandle>));
     let current_call_tx = Arc::new(Mutex::new(None::<ipc::IpcSender<MarshalledCall>>));
+    let stats = Arc::new(WorkerStats {
+        started: Mutex::new(Instant::now()),
+        jobs_handled: AtomicU64::new(0),
+        restart_count: AtomicU64::new(0),
+        current_call_id: AtomicU64::new(0),
+    });
+    // bumped whenever the process is replaced so that calls that ran at
+    // the same time don't all restart the worker.
+    let generation = Arc::new(AtomicU64::new(0));
 
     let spawn = Arc::new(Mutex::new({
         let disable_stdin = builder.disable_stdin;
         let disable_stdout = builder.disable_stdout;
         let disable_stderr = builder.disable_stderr;
-        let common = builder.common.clone();
+        let adoption = builder.adoption();
+        let worker_init = builder.worker_init.clone();
+        let concurrency = builder.worker_concurrency;
+        let shared = shared.clone();
+        // workers only hold a permit while they run a call
+        let common = ProcCommon {
+            concurrency_limiter: None,
+            ..builder.common.clone()
+        };
         let join_handle = join_handle.clone();
         let current_call_tx = current_call_tx.clone();
+        let stats = stats.clone();
+        let generation = generation.clone();
         move || {
             let (call_tx, call_rx) = ipc::channel::<MarshalledCall>().unwrap();
             let mut builder = Builder::new();
             builder.common(common.clone());
+            builder.pool_worker();
             if disable_stdin {
                 builder.stdin(process::Stdio::null());
             }
@@ -395,14 +2394,31 @@ fn spawn_worker(
             if disable_stderr {
                 builder.stderr(process::Stdio::null());
             }
-            *join_handle.lock().unwrap() = Some(builder.spawn(call_rx, |rx| {
-                while let Ok(call) = rx.recv() {
-                    // we never want panic handling here as we're going to
-                    // defer this to the process'.
-                    call.call(false);
+            let (init_call, init_args) = match worker_init {
+                Some(ref worker_init) => {
+                    let (call, args) = (worker_init.0)();
+                    (Some(call), Some(args))
                 }
-            }));
-            *current_call_tx.lock().unwrap() = Some(call_tx);
+                None => (None, None),
+            };
+            let mut handle = builder.spawn(
+                (call_rx, adoption.clone(), init_call, concurrency),
+                run_worker,
+            );
+            let rv = init_args.map_or(Ok(()), |send_args| send_args());
+            shared.hooks.worker_started(handle.pid());
+            if rv.is_err() {
+                // calls to the worker fail and restart it.
+                handle.kill().ok();
+            }
+            *join_handle.lock().unwrap() = Some(WorkerHandle::Spawned(Box::new(handle)));
+            {
+                let mut current_call_tx = current_call_tx.lock().unwrap();
+                generation.fetch_add(1, Ordering::SeqCst);
+                *current_call_tx = Some(call_tx);
+            }
+            *stats.started.lock().unwrap() = Instant::now();
+            rv
         }
     }));
 
@@ -410,96 +2426,237 @@ fn spawn_worker(
         let spawn = spawn.clone();
         let join_handle = join_handle.clone();
         let shared = shared.clone();
-        move |f: &mut NotifyErrorFunc| {
+        let stats = stats.clone();
+        let generation = generation.clone();
+        let restart_lock = Mutex::new(());
+        move |f: &mut NotifyErrorFunc, backoff: Option<Duration>, call_generation: u64| {
+            let _restart = restart_lock.lock().unwrap();
+            if generation.load(Ordering::SeqCst) != call_generation {
+                // another call that ran on the same process already
+                // replaced it.
+                f(SpawnError::from(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "client process died",
+                )));
+                return;
+            }
+
             // something went wrong so we're expecting the join handle to
             // indicate an error.
-            if let Some(join_handle) = join_handle.lock().unwrap().take() {
-                match join_handle.join() {
-                    Ok(()) => f(SpawnError::from(io::Error::new(
+            // the lock is not held while joining so that the workers of
+            // the pool can be inspected in the meantime.
+            let worker_handle = join_handle.lock().unwrap().take();
+            if let Some(worker_handle) = worker_handle {
+                let pid = worker_handle.pid();
+                let err = match worker_handle.join() {
+                    Ok(()) => SpawnError::from(io::Error::new(
                         io::ErrorKind::BrokenPipe,
                         "client process died",
-                    ))),
-                    Err(err) => f(err),
+                    )),
+                    Err(err) => err,
+                };
+                let exit = WorkerExit::Crashed(err);
+                shared.hooks.worker_exited(pid, &exit);
+                if let WorkerExit::Crashed(err) = exit {
+                    f(err);
                 }
             }
 
+            if !shared.dead.load(Ordering::SeqCst) && !shared.record_restart() {
+                shared.fail();
+            }
+
+            if let Some(backoff) = backoff.filter(|_| !shared.dead.load(Ordering::SeqCst)) {
+                thread::sleep(backoff);
+            }
+
             // next step is respawning the client.
             if !shared.dead.load(Ordering::SeqCst) {
-                (*spawn.lock().unwrap())();
+                (*spawn.lock().unwrap())().ok();
+                stats.restart_count.fetch_add(1, Ordering::SeqCst);
             }
         }
     };
 
-    // for each worker we spawn a monitoring thread
+    match adopted {
+        Some(worker) => {
+            *join_handle.lock().unwrap() = Some(WorkerHandle::Adopted(
+                ProcessHandleState::new_live(Some(worker.pid), next_spawn_sequence(), None),
+            ));
+            shared.hooks.worker_started(Some(worker.pid));
+            *current_call_tx.lock().unwrap() = Some(worker.call_tx);
+        }
+        None => (*spawn.lock().unwrap())()?,
+    }
+
+    // for each call a worker can run at once we spawn a monitoring thread
     {
         let join_handle = join_handle.clone();
-        thread::Builder::new()
-            .name("procspawn-monitor".into())
-            .spawn(move || {
-                loop {
-                    if shared.dead.load(Ordering::SeqCst) {
-                        break;
-                    }
+        let stats = stats.clone();
+        let load = Mutex::new(WorkerLoad {
+            running: true,
+            busy: 0,
+        });
+        let limiter = builder.common.concurrency_limiter.clone();
+        let idle_timeout = builder.idle_timeout;
+        let restart_backoff = builder.restart_backoff;
+        let min_idle = builder.min_idle;
+        let monitor = Arc::new(move || {
+            // the delay before the last restart, reset by calls that
+            // did not need a restart.
+            let mut backoff = None::<Duration>;
+            loop {
+                if shared.dead.load(Ordering::SeqCst) {
+                    break;
+                }
 
-                    let (call, state, wait_func, mut err_func) = {
-                        // Only lock jobs for the time it takes
-                        // to get a job, not run it.
-                        let lock = shared
-                            .call_receiver
-                            .lock()
-                            .expect("Monitor thread unable to lock call receiver");
-                        match lock.recv() {
-                            Ok(rv) => rv,
-                            Err(_) => break,
+                let timeout = if load.lock().unwrap().running {
+                    idle_timeout
+                } else {
+                    None
+                };
+                let (call, state, wait_func, mut err_func) = match shared.queue.pop(index, timeout)
+                {
+                    Pop::Job(job) => *job,
+                    Pop::Idle => {
+                        let mut load = load.lock().unwrap();
+                        // the process is only stopped once none of
+                        // its calls run anymore.
+                        if load.running && load.busy == 0 && shared.claim_idle_shutdown(min_idle) {
+                            current_call_tx.lock().unwrap().take();
+                            let worker_handle = join_handle.lock().unwrap().take();
+                            if let Some(mut worker_handle) = worker_handle {
+                                worker_handle.kill().ok();
+                                shared
+                                    .hooks
+                                    .worker_exited(worker_handle.pid(), &WorkerExit::Idle);
+                            }
+                            load.running = false;
                         }
-                    };
+                        continue;
+                    }
+                    Pop::Closed => break,
+                };
 
-                    shared.active_count.fetch_add(1, Ordering::SeqCst);
-                    shared.queued_count.fetch_sub(1, Ordering::SeqCst);
+                {
+                    let mut load = load.lock().unwrap();
+                    if !load.running && !shared.dead.load(Ordering::SeqCst) {
+                        // a failed initialization kills the process
+                        // again so the call fails below.
+                        (*spawn.lock().unwrap())().ok();
+                        shared.running_count.fetch_add(1, Ordering::SeqCst);
+                        load.running = true;
+                    }
+                    load.busy += 1;
+                }
 
-                    // this task was already cancelled, no need to execute it
-                    if state.cancelled.load(Ordering::SeqCst) {
-                        err_func(SpawnError::new_cancelled());
-                    } else {
-                        if let Some(ref mut handle) = *join_handle.lock().unwrap() {
-                            *state.process_handle_state.lock().unwrap() =
-                                handle.process_handle_state();
-                        }
+                shared.active_count.fetch_add(1, Ordering::SeqCst);
+                let depth = shared.queued_count.fetch_sub(1, Ordering::SeqCst) - 1;
+                shared.call_stats.record_queue_depth(depth);
+
+                // calls only hold a permit while they run in a worker
+                let permit = match limiter {
+                    Some(ref limiter) if !state.cancelled.load(Ordering::SeqCst) => {
+                        Permit::acquire(limiter).map(Some)
+                    }
+                    _ => Ok(None),
+                };
+
+                // this task was already cancelled, no need to execute it
+                if state.cancelled.load(Ordering::SeqCst) {
+                    // the call is finished before its handle learns about it.
+                    drop(wait_func);
+                    err_func(SpawnError::new_cancelled());
+                } else if let Err(err) = permit {
+                    // refused calls never read their arguments, this tells
+                    // the spawning thread that it can stop sending them.
+                    state.cancelled.store(true, Ordering::SeqCst);
+                    drop(wait_func);
+                    err_func(err);
+                } else {
+                    let mut permit = permit.unwrap();
+                    if let Some(ref mut handle) = *join_handle.lock().unwrap() {
+                        *state.process_handle_state.lock().unwrap() = handle.process_handle_state();
+                        *state.worker.lock().unwrap() = handle.process_handle_state();
+                    }
 
-                        let mut restart = false;
-                        {
-                            let mut call_tx = current_call_tx.lock().unwrap();
-                            if let Some(ref mut call_tx) = *call_tx {
-                                match with_ipc_mode(|| call_tx.send(call)) {
-                                    Ok(()) => {}
-                                    Err(..) => {
-                                        restart = true;
-                                    }
+                    stats.current_call_id.store(state.call_id, Ordering::SeqCst);
+                    *state.started.lock().unwrap() = Some(Instant::now());
+                    let mut restart = false;
+                    let call_generation;
+                    {
+                        let mut call_tx = current_call_tx.lock().unwrap();
+                        call_generation = generation.load(Ordering::SeqCst);
+                        if let Some(ref mut call_tx) = *call_tx {
+                            match with_ipc_mode(|| call_tx.send(call)) {
+                                Ok(()) => {}
+                                Err(..) => {
+                                    restart = true;
                                 }
-                            } else {
-                                restart = true;
                             }
-                        }
-
-                        if !restart && !wait_func() {
+                        } else {
                             restart = true;
                         }
+                    }
 
-                        *state.process_handle_state.lock().unwrap() = None;
+                    // the watchdog stops once its sender is dropped.
+                    let watchdog = state.timeout.filter(|_| !restart).map(|timeout| {
+                        let (done_tx, done_rx) = mpsc::channel::<()>();
+                        let state = state.clone();
+                        thread::Builder::new()
+                            .name("procspawn-timeout".into())
+                            .spawn(move || {
+                                if let Err(mpsc::RecvTimeoutError::Timeout) =
+                                    done_rx.recv_timeout(timeout)
+                                {
+                                    state.time_out();
+                                }
+                            })
+                            .unwrap();
+                        done_tx
+                    });
 
-                        if restart {
-                            check_for_restart(&mut err_func);
-                        }
+                    if restart {
+                        drop(wait_func);
+                    } else if !wait_func(permit.take()) {
+                        restart = true;
                     }
+                    drop(watchdog);
+
+                    *state.process_handle_state.lock().unwrap() = None;
+                    // other calls might have started in the meantime.
+                    stats
+                        .current_call_id
+                        .compare_exchange(state.call_id, 0, Ordering::SeqCst, Ordering::SeqCst)
+                        .ok();
+                    stats.jobs_handled.fetch_add(1, Ordering::SeqCst);
 
-                    shared.active_count.fetch_sub(1, Ordering::SeqCst);
-                    shared.no_work_notify_all();
+                    if restart {
+                        backoff = restart_backoff.map(|(initial, max)| {
+                            backoff.map_or(initial, |backoff| (backoff * 2).min(max))
+                        });
+                        check_for_restart(&mut err_func, backoff, call_generation);
+                    } else {
+                        backoff = None;
+                    }
                 }
-            })
-            .unwrap();
-    }
 
-    (*spawn.lock().unwrap())();
+                load.lock().unwrap().busy -= 1;
+                shared.active_count.fetch_sub(1, Ordering::SeqCst);
+                shared.no_work_notify_all();
+            }
+        });
+        for _ in 0..builder.worker_concurrency {
+            let monitor = monitor.clone();
+            thread::Builder::new()
+                .name("procspawn-monitor".into())
+                .spawn(move || monitor())
+                .unwrap();
+        }
+    }
 
-    Ok(WorkerMonitor { join_handle })
+    Ok(WorkerMonitor {
+        worker_handle: join_handle,
+        stats,
+    })
 }
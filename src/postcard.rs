@@ -0,0 +1,53 @@
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::codec::BytesVisitor;
+
+/// Utility wrapper to force values through postcard serialization.
+///
+/// This works like [`Json`](struct.Json.html) but encodes the value with
+/// [`postcard`](https://github.com/jamesmunns/postcard) instead.  Postcard
+/// uses variable length integers which makes the encoding of integer heavy
+/// data a lot more compact than bincode's.  Unlike JSON it is not self
+/// describing, so it does not help with `#[serde(flatten)]`.
+///
+/// To use postcard for all arguments and return values instead of single
+/// values select the [`Postcard`](../codec/struct.Postcard.html) codec,
+/// either per spawn or as the default with
+/// [`ProcConfig::codec`](../struct.ProcConfig.html#method.codec).
+///
+/// Examples:
+///
+/// ```rust,no_run
+/// use procspawn::{spawn, serde::Postcard};
+///
+/// let handle = spawn((), |()| Postcard((0..1000u64).collect::<Vec<_>>()));
+/// let values = handle.join().unwrap().0;
+/// ```
+///
+/// This requires the `postcard` feature.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Postcard<T>(pub T);
+
+impl<T: Serialize> Serialize for Postcard<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let bytes =
+            ::postcard::to_allocvec(&self.0).map_err(|e| ser::Error::custom(e.to_string()))?;
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Postcard<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Postcard<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = deserializer.deserialize_byte_buf(BytesVisitor)?;
+        Ok(Postcard(
+            ::postcard::from_bytes(&bytes).map_err(|e| de::Error::custom(e.to_string()))?,
+        ))
+    }
+}
@@ -1,41 +1,128 @@
+use std::any::type_name;
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::fmt;
+use std::io::Read;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::path::PathBuf;
 use std::process::Stdio;
 use std::process::{ChildStderr, ChildStdin, ChildStdout};
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
-use std::{env, mem, process};
+use std::{env, fs, mem, process};
 use std::{io, thread};
 
-use ipc_channel::ipc::{self, IpcOneShotServer, IpcReceiver, IpcSender};
+use ipc_channel::ipc::{
+    self, IpcBytesReceiver, IpcOneShotServer, IpcReceiver, IpcSender, OpaqueIpcReceiver,
+};
 use serde::{de::DeserializeOwned, Serialize};
 
-use crate::core::{assert_spawn_okay, should_pass_args, MarshalledCall, ENV_NAME};
-use crate::error::{PanicInfo, SpawnError};
-use crate::pool::PooledHandle;
-use crate::serde::with_ipc_mode;
+use crate::audit::Audit;
+use crate::backend;
+use crate::bench;
+use crate::capture::CapturedOutput;
+use crate::channel::{parent_channel, typed_receiver, Receiver};
+use crate::chunked::{self, Frame};
+use crate::codec::{
+    create_codec, default_codec, with_codec, Codec, CodecFactory, Coded, CompressionConfig,
+};
+use crate::core::{
+    assert_spawn_okay, max_message_size, max_nesting_depth, nesting_depth, panic_handling,
+    run_in_process, should_pass_args, MarshalledCall, ResultStream, ReturnMessage, ENV_NAME,
+    MAX_NESTING_DEPTH_ENV_NAME, NESTING_DEPTH_ENV_NAME,
+};
+#[cfg(unix)]
+use crate::core::{prepare_forked_call, run_forked_call};
+#[cfg(unix)]
+use crate::coredump::CoreDumps;
+#[cfg(unix)]
+use crate::coverage::TERMINATE_GRACE_PERIOD;
+use crate::coverage::{
+    child_profile_file, coverage_enabled, COVERAGE_ENV_NAME, PROFILE_FILE_ENV_NAME,
+};
+use crate::deadline::{Deadline, DEADLINE_POLL_INTERVAL};
+#[cfg(unix)]
+use crate::detach::{Daemonize, DetachedChild};
+use crate::endpoints::EndpointGuard;
+use crate::env_policy::{inherited_vars, uninherited_vars, EnvPolicy};
+use crate::error::{ErrorContext, SpawnError};
+use crate::leak::LeakCheck;
+use crate::lifecycle::{function_name, ExitOutcome, Lifecycle};
+use crate::limiter::{ConcurrencyLimiter, Permit};
+use crate::messages::{report_message, MessageKind};
+use crate::pool::{HandleDropBehavior, PooledHandle};
+use crate::progress::{Progress, ProgressReceiver};
+#[cfg(unix)]
+use crate::reattach::{Reattach, ReattachToken};
+use crate::serde::{with_ipc_mode, without_ipc_mode};
+use crate::service::Service;
+#[cfg(feature = "metrics")]
+use crate::telemetry;
+#[cfg(unix)]
+use crate::user::{User, VERIFY_USER_ENV_NAME};
 
 #[cfg(unix)]
 type PreExecFunc = dyn FnMut() -> io::Result<()> + Send + Sync + 'static;
 
+pub(crate) type ProgressFunc = dyn Fn(u64, u64) + Send + Sync + 'static;
+
+static SPAWN_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) type BuilderDefaults = dyn Fn(&mut Builder) + Send + Sync;
+
+static BUILDER_DEFAULTS: Mutex<Option<Arc<BuilderDefaults>>> = Mutex::new(None);
+
+pub(crate) fn set_builder_defaults(defaults: Arc<BuilderDefaults>) {
+    *BUILDER_DEFAULTS.lock().unwrap() = Some(defaults);
+}
+
+/// Allocates the sequence number for the next spawned process.
+pub(crate) fn next_spawn_sequence() -> u64 {
+    SPAWN_SEQUENCE.fetch_add(1, Ordering::SeqCst) + 1
+}
+
 #[derive(Clone)]
 pub struct ProcCommon {
-    pub vars: HashMap<OsString, OsString>,
+    /// `None` as long as the spawned process inherits the environment.
+    pub vars: Option<HashMap<OsString, OsString>>,
+    pub bootstrap_timeout: Option<Duration>,
     #[cfg(unix)]
     pub uid: Option<u32>,
     #[cfg(unix)]
     pub gid: Option<u32>,
     #[cfg(unix)]
     pub pre_exec: Option<Arc<std::sync::Mutex<Box<PreExecFunc>>>>,
+    pub codec: Option<CodecFactory>,
+    pub compression: Option<CompressionConfig>,
+    pub stream_threshold: Option<u64>,
+    pub progress: bool,
+    pub parent_channel: bool,
+    #[cfg(unix)]
+    pub run_as: Option<String>,
+    #[cfg(unix)]
+    pub verify_run_as: bool,
+    #[cfg(unix)]
+    pub core_dumps: Option<CoreDumps>,
+    pub concurrency_limiter: Option<Arc<dyn ConcurrencyLimiter>>,
+}
+
+impl ProcCommon {
+    /// Returns the variables of the spawned process.
+    ///
+    /// The environment is only copied once it is modified, which is
+    /// measurable when spawning a lot of processes.
+    pub fn vars_mut(&mut self) -> &mut HashMap<OsString, OsString> {
+        self.vars.get_or_insert_with(|| inherited_vars().collect())
+    }
 }
 
 impl fmt::Debug for ProcCommon {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("ProcCommon")
             .field("vars", &self.vars)
+            .field("bootstrap_timeout", &self.bootstrap_timeout)
             .finish()
     }
 }
@@ -43,26 +130,93 @@ impl fmt::Debug for ProcCommon {
 impl Default for ProcCommon {
     fn default() -> ProcCommon {
         ProcCommon {
-            vars: std::env::vars_os().collect(),
+            vars: None,
+            bootstrap_timeout: None,
             #[cfg(unix)]
             uid: None,
             #[cfg(unix)]
             gid: None,
             #[cfg(unix)]
             pre_exec: None,
+            codec: None,
+            compression: None,
+            stream_threshold: None,
+            progress: false,
+            parent_channel: false,
+            #[cfg(unix)]
+            run_as: None,
+            #[cfg(unix)]
+            verify_run_as: true,
+            #[cfg(unix)]
+            core_dumps: None,
+            concurrency_limiter: None,
         }
     }
 }
 
+/// The parts of spawning a process that do not depend on the call.
+///
+/// Batches prepare them once for all of their processes.
+struct SpawnSetup {
+    exe: PathBuf,
+    /// `/proc/self/exe` is a link, errors report where it points to.
+    canonical_exe: Option<PathBuf>,
+    removed_vars: Vec<OsString>,
+    vars: HashMap<OsString, OsString>,
+}
+
+impl SpawnSetup {
+    fn new(vars: Option<HashMap<OsString, OsString>>) -> io::Result<SpawnSetup> {
+        let exe = if cfg!(target_os = "linux") {
+            // will work even if exe is moved
+            let path: PathBuf = "/proc/self/exe".into();
+            if path.is_file() {
+                path
+            } else {
+                // might not exist, e.g. on chroot
+                env::current_exe()?
+            }
+        } else {
+            env::current_exe()?
+        };
+        let canonical_exe = fs::canonicalize(&exe).ok();
+        let (removed_vars, vars) = match vars {
+            // variables removed from the builder must not be inherited
+            // either.
+            Some(vars) => (
+                env::vars_os()
+                    .map(|(key, _)| key)
+                    .filter(|key| !vars.contains_key(key))
+                    .collect(),
+                vars,
+            ),
+            None => (uninherited_vars(), HashMap::new()),
+        };
+        Ok(SpawnSetup {
+            exe,
+            canonical_exe,
+            removed_vars,
+            vars,
+        })
+    }
+}
+
 /// Process factory, which can be used in order to configure the properties
 /// of a process being created.
 ///
 /// Methods can be chained on it in order to configure it.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Builder {
     stdin: Option<Stdio>,
     stdout: Option<Stdio>,
     stderr: Option<Stdio>,
+    #[cfg(unix)]
+    reattach: Option<Duration>,
+    #[cfg(unix)]
+    wait_for_debugger: bool,
+    /// Pool workers always run in processes and outlive the test that
+    /// started them, so their output is never forwarded.
+    pool_worker: bool,
     common: ProcCommon,
 }
 
@@ -77,7 +231,7 @@ macro_rules! define_common_methods {
             V: AsRef<OsStr>,
         {
             self.common
-                .vars
+                .vars_mut()
                 .insert(key.as_ref().to_owned(), val.as_ref().to_owned());
             self
         }
@@ -91,7 +245,7 @@ macro_rules! define_common_methods {
             K: AsRef<OsStr>,
             V: AsRef<OsStr>,
         {
-            self.common.vars.extend(
+            self.common.vars_mut().extend(
                 vars.into_iter()
                     .map(|(k, v)| (k.as_ref().to_owned(), v.as_ref().to_owned())),
             );
@@ -102,7 +256,7 @@ macro_rules! define_common_methods {
         ///
         /// Equivalent to `Command::env_remove`
         pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
-            self.common.vars.remove(key.as_ref());
+            self.common.vars_mut().remove(key.as_ref());
             self
         }
 
@@ -110,7 +264,117 @@ macro_rules! define_common_methods {
         ///
         /// Equivalent to `Command::env_clear`
         pub fn env_clear(&mut self) -> &mut Self {
-            self.common.vars.clear();
+            self.common.vars = Some(Default::default());
+            self
+        }
+
+        /// Limits which environment variables the spawned process inherits.
+        ///
+        /// Like [`env_clear`](#method.env_clear) this applies to the
+        /// variables inherited or set so far, so variables set afterwards
+        /// are passed regardless of the policy.  The default policy is set
+        /// with [`ProcConfig::env_policy`](struct.ProcConfig.html#method.env_policy).
+        pub fn env_policy(&mut self, policy: EnvPolicy) -> &mut Self {
+            self.common.vars_mut().retain(|key, _| policy.inherits(key));
+            self
+        }
+
+        /// Sets the maximum time to wait for the child to connect back.
+        ///
+        /// Before a function can be invoked the spawned process needs to
+        /// start up, reach [`init`](fn.init.html) and connect back to the
+        /// parent.  If this does not happen within the given duration the
+        /// child is killed and the spawn fails with an error for which
+        /// [`SpawnError::is_bootstrap_failure`](struct.SpawnError.html#method.is_bootstrap_failure)
+        /// returns `true`.  If the child's stderr was captured with
+        /// `Stdio::piped()` the output is attached to the error.
+        ///
+        /// Independently of this setting a child that exits before it
+        /// connects back is always reported as a bootstrap failure.
+        pub fn bootstrap_timeout(&mut self, timeout: Duration) -> &mut Self {
+            self.common.bootstrap_timeout = Some(timeout);
+            self
+        }
+
+        /// Sets the codec for arguments and return values.
+        ///
+        /// This overrides the codec configured with
+        /// [`ProcConfig::codec`](struct.ProcConfig.html#method.codec).  See the
+        /// [`codec`](codec/index.html) module for more information.
+        pub fn codec<C: $crate::codec::Codec + Default>(&mut self) -> &mut Self {
+            self.common.codec = Some($crate::codec::codec_factory::<C>());
+            self
+        }
+
+        /// Compresses arguments and return values larger than `threshold` bytes.
+        ///
+        /// This pays off for large payloads where the time spent moving the
+        /// bytes between the processes outweighs the time spent compressing
+        /// them.  See [`Compression`](codec/enum.Compression.html) for more
+        /// information.
+        pub fn compress(
+            &mut self,
+            compression: $crate::codec::Compression,
+            threshold: usize,
+        ) -> &mut Self {
+            self.common.compression = Some($crate::codec::CompressionConfig {
+                compression,
+                threshold,
+            });
+            self
+        }
+
+        /// Streams return values larger than `threshold` bytes in chunks.
+        ///
+        /// Normally the return value is sent back in a single message which
+        /// has to be fully received before anything can be observed.  With
+        /// this enabled, return values that serialize to more than the given
+        /// number of bytes are sent in chunks instead and the progress can be
+        /// observed with
+        /// [`JoinHandle::on_result_progress`](struct.JoinHandle.html#method.on_result_progress).
+        ///
+        /// Streamed values are serialized outside of IPC mode (see
+        /// [`in_ipc_mode`](serde/fn.in_ipc_mode.html)) so they must not
+        /// contain IPC channels.
+        pub fn stream_results(&mut self, threshold: u64) -> &mut Self {
+            self.common.stream_threshold = Some(threshold);
+            self
+        }
+
+        /// Lets spawned functions report their progress.
+        ///
+        /// The functions report their progress through
+        /// [`ProgressSender::current`](struct.ProgressSender.html#method.current)
+        /// and the reports can be read with
+        /// [`JoinHandle::progress`](struct.JoinHandle.html#method.progress).
+        pub fn with_progress(&mut self) -> &mut Self {
+            self.common.progress = true;
+            self
+        }
+
+        /// Gives spawned functions a channel to send messages to the parent.
+        ///
+        /// The functions get the sending end with
+        /// [`procspawn::parent`](fn.parent.html) and the messages can be
+        /// received through
+        /// [`JoinHandle::child_receiver`](struct.JoinHandle.html#method.child_receiver).
+        pub fn with_parent_channel(&mut self) -> &mut Self {
+            self.common.parent_channel = true;
+            self
+        }
+
+        /// Sets the limiter that bounds how many processes run at once.
+        ///
+        /// A permit is acquired from the limiter before the process is
+        /// launched and released once it exited.  For pools the permit is
+        /// instead held for the duration of every call while idle workers
+        /// hold none.  See [`ConcurrencyLimiter`](trait.ConcurrencyLimiter.html)
+        /// for more information.
+        pub fn concurrency_limiter(
+            &mut self,
+            limiter: std::sync::Arc<dyn $crate::ConcurrencyLimiter>,
+        ) -> &mut Self {
+            self.common.concurrency_limiter = Some(limiter);
             self
         }
 
@@ -139,6 +403,76 @@ macro_rules! define_common_methods {
             self
         }
 
+        /// Runs the child process as another user.
+        ///
+        /// Unlike [`uid`](#method.uid) this looks up the user by name,
+        /// switches to its primary group and supplementary groups and sets
+        /// the `HOME`, `USER` and `LOGNAME` environment variables.  Before
+        /// anything is spawned procspawn checks that the current process is
+        /// allowed to switch to the user, so a lack of privileges results in
+        /// a clear error instead of a failed spawn.
+        ///
+        /// By default the child also verifies that it really runs with the
+        /// user and group IDs of the user and that it cannot regain root
+        /// privileges before it accepts a call.  This can be turned off with
+        /// [`verify_run_as`](#method.verify_run_as).
+        ///
+        /// Unix-specific extension only available on unix.
+        #[cfg(unix)]
+        pub fn run_as(&mut self, user: &str) -> &mut Self {
+            self.common.run_as = Some(user.to_string());
+            self
+        }
+
+        /// Controls if the child verifies that it dropped its privileges.
+        ///
+        /// This is enabled by default and only has an effect together with
+        /// [`run_as`](#method.run_as).
+        ///
+        /// Unix-specific extension only available on unix.
+        #[cfg(unix)]
+        pub fn verify_run_as(&mut self, enabled: bool) -> &mut Self {
+            self.common.verify_run_as = enabled;
+            self
+        }
+
+        /// Prevents the child process from writing core dumps.
+        ///
+        /// This sets the soft `RLIMIT_CORE` limit of the child to zero so
+        /// that crashing children do not fill the disk with core files.  If
+        /// the kernel pipes core dumps to a handler (see `core_pattern` in
+        /// `core(5)`) it is up to the handler to honor the limit, which
+        /// `systemd-coredump` does.
+        ///
+        /// Unix-specific extension only available on unix.
+        #[cfg(unix)]
+        pub fn disable_core_dumps(&mut self) -> &mut Self {
+            self.common.core_dumps = Some($crate::coredump::CoreDumps::Disabled);
+            self
+        }
+
+        /// Lets the child process write core dumps into a directory.
+        ///
+        /// This raises the soft `RLIMIT_CORE` limit of the child to the hard
+        /// limit and starts the child in the given directory.  Note that the
+        /// child keeps running in that directory, so relative paths resolve
+        /// against it.
+        ///
+        /// The kernel only writes core dumps to the working directory if
+        /// `core_pattern` (see `core(5)`) is a relative path such as the
+        /// default `core`.  Absolute patterns or handlers such as
+        /// `systemd-coredump` ignore the directory, in which case
+        /// `coredumpctl` finds the dumps instead.
+        ///
+        /// Unix-specific extension only available on unix.
+        #[cfg(unix)]
+        pub fn core_dump_dir<P: AsRef<std::path::Path>>(&mut self, path: P) -> &mut Self {
+            self.common.core_dumps = Some($crate::coredump::CoreDumps::Directory(
+                path.as_ref().to_path_buf(),
+            ));
+            self
+        }
+
         /// Schedules a closure to be run just before the `exec` function is
         /// invoked.
         ///
@@ -147,6 +481,9 @@ macro_rules! define_common_methods {
         /// This method is inherently unsafe.  See the notes of the unix command
         /// ext for more information.
         ///
+        /// Setting a closure means the process can no longer be created
+        /// with `posix_spawn`, the same is true for `uid` and `gid`.
+        ///
         /// Equivalent to `std::os::unix::process::CommandExt::pre_exec`
         #[cfg(unix)]
         pub unsafe fn pre_exec<F>(&mut self, f: F) -> &mut Self
@@ -159,16 +496,35 @@ macro_rules! define_common_methods {
     };
 }
 
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
 impl Builder {
     /// Generates the base configuration for spawning a thread, from which
     /// configuration methods can be chained.
+    ///
+    /// The builder starts out with the defaults registered with
+    /// [`ProcConfig::builder_defaults`](struct.ProcConfig.html#method.builder_defaults).
     pub fn new() -> Self {
-        Self {
+        let mut builder = Self {
             stdin: None,
             stdout: None,
             stderr: None,
+            #[cfg(unix)]
+            reattach: None,
+            #[cfg(unix)]
+            wait_for_debugger: false,
+            pool_worker: false,
             common: ProcCommon::default(),
+        };
+        let defaults = BUILDER_DEFAULTS.lock().unwrap().clone();
+        if let Some(defaults) = defaults {
+            defaults(&mut builder);
         }
+        builder
     }
 
     pub(crate) fn common(&mut self, common: ProcCommon) -> &mut Self {
@@ -176,8 +532,27 @@ impl Builder {
         self
     }
 
+    pub(crate) fn pool_worker(&mut self) -> &mut Self {
+        self.pool_worker = true;
+        self
+    }
+
+    pub(crate) fn into_common(self) -> ProcCommon {
+        self.common
+    }
+
     define_common_methods!();
 
+    /// Spawns a [`Service`](struct.Service.html) with this configuration.
+    #[track_caller]
+    pub fn service<I: Serialize + DeserializeOwned, S: 'static>(
+        &mut self,
+        args: I,
+        init: fn(I) -> S,
+    ) -> Service<S> {
+        Service::spawn(self, args, init)
+    }
+
     /// Captures the `stdin` of the spawned process, allowing you to manually
     /// send data via `JoinHandle::stdin`
     pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
@@ -199,51 +574,364 @@ impl Builder {
         self
     }
 
+    /// Lets another process receive the result if this process goes away.
+    ///
+    /// Spawned processes normally can't deliver their result once their
+    /// parent exited.  With this enabled the handle hands out a
+    /// [`ReattachToken`](struct.ReattachToken.html) that a supervisor can
+    /// persist.  After a restart the new process passes the token to
+    /// [`JoinHandle::reattach`](struct.JoinHandle.html#method.reattach) to
+    /// pick up the result.  A child whose parent went away waits up to
+    /// `timeout` after the function returned for somebody to reattach before
+    /// it discards the result.
+    ///
+    /// Results of reattachable processes are never streamed and progress
+    /// reports and messages to the parent are lost once the parent is gone.
+    ///
+    /// This is only available on unix platforms.
+    #[cfg(unix)]
+    pub fn reattachable(&mut self, timeout: Duration) -> &mut Self {
+        self.reattach = Some(timeout);
+        self
+    }
+
+    /// Makes the spawned process wait for a debugger.
+    ///
+    /// Right before the function is invoked the process prints its process
+    /// ID to stderr and stops itself with `SIGSTOP`.  This gives you time to
+    /// attach a debugger such as gdb or lldb to exactly the right process,
+    /// after which it is resumed from the debugger or with `kill -CONT`.
+    ///
+    /// This is only available on unix platforms.
+    #[cfg(unix)]
+    pub fn wait_for_debugger(&mut self, enabled: bool) -> &mut Self {
+        self.wait_for_debugger = enabled;
+        self
+    }
+
     /// Spawns the process.
+    #[track_caller]
     pub fn spawn<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
         &mut self,
         args: A,
         func: fn(A) -> R,
     ) -> JoinHandle<R> {
         assert_spawn_okay();
-        JoinHandle {
-            inner: mem::take(self)
-                .spawn_helper(args, func)
+        JoinHandle::new(
+            mem::take(self)
+                .spawn_helper(args, func, None)
                 .map(JoinHandleInner::Process),
+        )
+        .with_function(func as *const ())
+    }
+
+    /// Spawns the function once for every item of an iterator.
+    ///
+    /// This works like calling [`spawn`](#method.spawn) with every item but
+    /// the executable and the environment are only looked up once for the
+    /// whole batch, which adds up when spawning hundreds of processes.
+    /// Standard streams cannot be shared between processes, so if any are
+    /// configured on the builder all spawns fail.
+    ///
+    /// ```rust,no_run
+    /// let handles = procspawn::Builder::new().spawn_many(0..500, |x: u32| x * 2);
+    /// let results: Vec<u32> = handles.into_iter().map(|x| x.join().unwrap()).collect();
+    /// ```
+    #[track_caller]
+    pub fn spawn_many<I, A, R>(&mut self, args: I, func: fn(A) -> R) -> Vec<JoinHandle<R>>
+    where
+        I: IntoIterator<Item = A>,
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned,
+    {
+        assert_spawn_okay();
+        let mut template = mem::take(self);
+        let setup =
+            if template.stdin.is_some() || template.stdout.is_some() || template.stderr.is_some() {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "standard streams cannot be redirected for a batch of processes",
+                ))
+            } else {
+                SpawnSetup::new(template.common.vars.take())
+            };
+        // a plain loop so that the handles record the location of the caller.
+        let mut handles = Vec::new();
+        for args in args {
+            let rv = match setup {
+                Ok(ref setup) => template.batch_clone().spawn_helper(args, func, Some(setup)),
+                Err(ref err) => Err(io::Error::new(err.kind(), err.to_string()).into()),
+            };
+            handles.push(
+                JoinHandle::new(rv.map(JoinHandleInner::Process)).with_function(func as *const ()),
+            );
+        }
+        handles
+    }
+
+    /// Copies the builder for a process of a batch.
+    ///
+    /// The standard streams cannot be copied and are left unset.
+    fn batch_clone(&self) -> Builder {
+        Builder {
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            #[cfg(unix)]
+            reattach: self.reattach,
+            #[cfg(unix)]
+            wait_for_debugger: self.wait_for_debugger,
+            pool_worker: self.pool_worker,
+            common: self.common.clone(),
+        }
+    }
+
+    /// Spawns the function in a fork of the current process.
+    ///
+    /// Unlike [`spawn`](#method.spawn) this does not execute the current
+    /// executable again but forks the current process and directly invokes
+    /// the function in the child.  Because the arguments are already in the
+    /// child's memory they are not serialized and `main` does not run again,
+    /// which makes this a lot cheaper.  Only the return value is sent back.
+    ///
+    /// Apart from the [`concurrency_limiter`](#method.concurrency_limiter)
+    /// none of the settings of the builder apply to forked processes, they
+    /// inherit environment, credentials and stdio from the current process.
+    ///
+    /// This is only available on unix platforms.
+    ///
+    /// # Safety
+    ///
+    /// Only the calling thread exists in the forked child.  Locks held by
+    /// other threads at the time of the fork stay locked forever in the
+    /// child, so the function must not touch any state that other threads
+    /// could have been using.  You must guarantee that the function is safe
+    /// to run in a forked process.
+    #[cfg(unix)]
+    #[track_caller]
+    pub unsafe fn spawn_forked<A, R: Serialize + DeserializeOwned>(
+        &mut self,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        assert_spawn_okay();
+        let sequence = next_spawn_sequence();
+        let mut audit = Audit::start(true, sequence);
+        audit.set_limits(nesting_depth(), max_nesting_depth(), None);
+        let rv = match self.common.concurrency_limiter {
+            Some(ref limiter) => Permit::acquire(limiter).map(Some),
+            None => Ok(None),
         }
+        .and_then(|permit| spawn_forked_helper(args, func, sequence, permit));
+        audit.finish(&rv, |handle| handle.state.pid());
+        JoinHandle::new(rv.map(JoinHandleInner::Process)).with_function(func as *const ())
+    }
+
+    /// Spawns the function in a process that is detached from this one.
+    ///
+    /// This is for launching background processes such as daemons that are
+    /// meant to outlive the spawning process.  The child starts a new
+    /// session and is forked a second time so that it is not a child of this
+    /// process and does not need to be joined.  Once the arguments were sent
+    /// no channel to the child is kept open, the return value of the
+    /// function is discarded and only the process ID is returned.
+    ///
+    /// Standard streams that are not configured on the builder are connected
+    /// to `/dev/null`.  The concurrency limiter does not apply to detached
+    /// processes.  Spawn hooks are invoked but exit hooks are not.
+    ///
+    /// This is only available on unix platforms.
+    #[cfg(unix)]
+    pub fn spawn_detached<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
+        &mut self,
+        args: A,
+        func: fn(A) -> R,
+    ) -> Result<DetachedChild, SpawnError> {
+        assert_spawn_okay();
+        let sequence = next_spawn_sequence();
+        let mut audit = Audit::start(false, sequence);
+        let rv = mem::take(self).spawn_process(args, func, sequence, &mut audit, true, None);
+        audit.finish(&rv, |handle| handle.state.pid());
+        let handle = rv?;
+        Ok(DetachedChild::new(handle.state.pid().unwrap_or(0)))
+    }
+
+    /// Returns the codec for spawns from this builder.
+    pub(crate) fn codec_factory(&self) -> Option<CodecFactory> {
+        self.common.codec.or_else(default_codec)
+    }
+
+    /// Returns the compression for spawns from this builder.
+    pub(crate) fn compression(&self) -> Option<CompressionConfig> {
+        self.common.compression
     }
 
     fn spawn_helper<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
         self,
         args: A,
         func: fn(A) -> R,
+        setup: Option<&SpawnSetup>,
+    ) -> Result<ProcessHandle<R>, SpawnError> {
+        let sequence = next_spawn_sequence();
+        let mut audit = Audit::start(false, sequence);
+        let rv = if run_in_process() && !self.pool_worker {
+            self.spawn_in_process(args, func, sequence)
+        } else {
+            self.spawn_process(args, func, sequence, &mut audit, false, setup)
+        };
+        audit.finish(&rv, |handle| handle.state.pid());
+        rv
+    }
+
+    /// Runs the call on a thread instead of a process.
+    ///
+    /// The call is marshalled exactly like for a process so that the
+    /// function sees the same arguments.
+    fn spawn_in_process<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
+        self,
+        args: A,
+        func: fn(A) -> R,
+        sequence: u64,
+    ) -> Result<ProcessHandle<R>, SpawnError> {
+        let (args_tx, args_rx) = ipc::channel()?;
+        let (return_tx, return_rx) = ipc::channel()?;
+        let (result_stream, stream_rx) = result_stream(self.common.stream_threshold)?;
+        let (progress_tx, reports) = ProgressReceiver::channel(self.common.progress)?;
+        let (parent_tx, child_rx) = parent_channel(self.common.parent_channel)?;
+        let codec_factory = self.codec_factory();
+        let max_message_size = max_message_size();
+        let codec = create_codec(codec_factory, self.common.compression, max_message_size);
+        let call = MarshalledCall::marshal::<A, R>(
+            func,
+            args_rx,
+            return_tx,
+            codec_factory,
+            self.common.compression,
+            max_message_size,
+            result_stream,
+        )
+        .with_progress(progress_tx)
+        .with_parent(parent_tx);
+        thread::Builder::new()
+            .name("procspawn-in-process".into())
+            .spawn(move || call.call(panic_handling()))?;
+        with_codec(codec.as_ref(), || {
+            with_ipc_mode(|| -> Result<_, SpawnError> {
+                send_args(&args_tx, args)?;
+                Ok(())
+            })
+        })?;
+
+        Ok(ProcessHandle {
+            recv: return_rx,
+            codec,
+            stream: stream_rx,
+            progress: None,
+            reports,
+            child_rx,
+            state: Arc::new(ProcessHandleState::new(None, sequence)),
+            lifecycle: Lifecycle::spawned(func as *const (), process::id(), sequence, false),
+            exit_status: None,
+            forwarders: Vec::new(),
+            #[cfg(unix)]
+            reattach_token: None,
+            process: None,
+            permit: None,
+        })
+    }
+
+    fn spawn_process<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
+        mut self,
+        args: A,
+        func: fn(A) -> R,
+        sequence: u64,
+        audit: &mut Audit,
+        detached: bool,
+        setup: Option<&SpawnSetup>,
     ) -> Result<ProcessHandle<R>, SpawnError> {
+        let depth = nesting_depth();
+        let max_depth = max_nesting_depth();
+        audit.set_limits(depth + 1, max_depth, self.common.bootstrap_timeout);
+        if let Some(max_depth) = max_depth {
+            if depth >= max_depth {
+                return Err(SpawnError::new_nesting_limit_exceeded(max_depth));
+            }
+        }
+        // detached processes would hold on to their permit forever.
+        let permit = match self.common.concurrency_limiter {
+            Some(ref limiter) if !detached => Some(Permit::acquire(limiter)?),
+            _ => None,
+        };
+
+        // every spawn needs a server of its own.  ipc-channel only has one
+        // shot servers and cannot turn a connection accepted on a socket of
+        // our own into a channel, so a listener shared by all spawns is not
+        // possible.
         let (server, token) = IpcOneShotServer::<IpcSender<MarshalledCall>>::new()?;
-        let me = if cfg!(target_os = "linux") {
-            // will work even if exe is moved
-            let path: PathBuf = "/proc/self/exe".into();
-            if path.is_file() {
-                path
-            } else {
-                // might not exist, e.g. on chroot
-                env::current_exe()?
+        let _endpoint = EndpointGuard::register(&token);
+        let owned_setup;
+        let setup = match setup {
+            Some(setup) => setup,
+            None => {
+                owned_setup = SpawnSetup::new(self.common.vars.take())?;
+                &owned_setup
             }
-        } else {
-            env::current_exe()?
         };
-        let mut child = process::Command::new(me);
-        child.envs(self.common.vars);
-        child.env(ENV_NAME, token);
+        let executable = setup.canonical_exe.clone();
+        let codec_factory = self.codec_factory();
+        let mut child = process::Command::new(&setup.exe);
+        for key in &setup.removed_vars {
+            child.env_remove(key);
+        }
+        child.envs(&setup.vars);
+        child.env(ENV_NAME, &token);
+        child.env(NESTING_DEPTH_ENV_NAME, (depth + 1).to_string());
+        if let Some(max_depth) = max_depth {
+            child.env(MAX_NESTING_DEPTH_ENV_NAME, max_depth.to_string());
+        }
+        if coverage_enabled() {
+            child.env(COVERAGE_ENV_NAME, "1");
+            if let Some(profile_file) = child_profile_file() {
+                child.env(PROFILE_FILE_ENV_NAME, profile_file);
+            }
+        }
+        #[cfg(feature = "test-support")]
+        {
+            if let Some(faults) = crate::testing::encoded_faults() {
+                child.env(crate::testing::FAULTS_ENV_NAME, faults);
+            }
+        }
 
+        // the user the child runs as if it is not the current one
+        #[cfg(unix)]
+        let mut child_uid = None;
         #[cfg(unix)]
         {
             use std::os::unix::process::CommandExt;
+            child.env_remove(VERIFY_USER_ENV_NAME);
+            audit.set_credentials(
+                self.common.uid,
+                self.common.gid,
+                self.common.run_as.as_deref(),
+                self.common.pre_exec.is_some(),
+            );
+            if let Some(ref name) = self.common.run_as {
+                let user = User::lookup(name)?;
+                user.check_privileges()?;
+                user.grant_endpoint(&token)?;
+                user.apply(&mut child, self.common.verify_run_as);
+                child_uid = Some(user.uid);
+            }
             if let Some(id) = self.common.uid {
                 child.uid(id);
+                child_uid = Some(id);
             }
             if let Some(id) = self.common.gid {
                 child.gid(id);
             }
+            if let Some(ref core_dumps) = self.common.core_dumps {
+                core_dumps.apply(&mut child);
+            }
             if let Some(ref func) = self.common.pre_exec {
                 let func = func.clone();
                 unsafe {
@@ -252,21 +940,32 @@ impl Builder {
                 }
             }
         }
+        #[cfg(unix)]
+        let daemonize = if detached {
+            Some(Daemonize::apply(&mut child)?)
+        } else {
+            None
+        };
 
-        let (can_pass_args, should_silence_stdout) = {
+        let (can_pass_args, should_silence_stdout, should_forward_output) = {
             #[cfg(feature = "test-support")]
             {
                 match crate::testsupport::update_command_for_tests(&mut child) {
-                    None => (true, false),
+                    None => (true, false, false),
                     Some(crate::testsupport::TestMode {
                         can_pass_args,
                         should_silence_stdout,
-                    }) => (can_pass_args, should_silence_stdout),
+                        should_forward_output,
+                    }) => (
+                        can_pass_args,
+                        should_silence_stdout,
+                        should_forward_output && !self.pool_worker && !detached,
+                    ),
                 }
             }
             #[cfg(not(feature = "test-support"))]
             {
-                (true, false)
+                (true, false, false)
             }
         };
 
@@ -276,50 +975,439 @@ impl Builder {
 
         if let Some(stdin) = self.stdin {
             child.stdin(stdin);
+        } else if detached {
+            child.stdin(Stdio::null());
         }
+        // forwarded output is taken from the child right after it started.
+        let forward_stdout = should_forward_output && self.stdout.is_none();
+        let forward_stderr = should_forward_output && self.stderr.is_none();
         if let Some(stdout) = self.stdout {
             child.stdout(stdout);
-        } else if should_silence_stdout {
+        } else if forward_stdout {
+            child.stdout(Stdio::piped());
+        } else if should_silence_stdout || detached {
             child.stdout(Stdio::null());
         }
         if let Some(stderr) = self.stderr {
             child.stderr(stderr);
+        } else if forward_stderr {
+            child.stderr(Stdio::piped());
+        } else if detached {
+            child.stderr(Stdio::null());
         }
-        let process = child.spawn()?;
+        if audit.enabled() {
+            audit.record.describe_command(&child);
+        }
+        let launched = Instant::now();
+        let mut process = backend::launch(&mut child)?;
+        bench::record_exec(launched.elapsed());
+        let pid = process.id();
+        #[cfg(feature = "test-support")]
+        let forwarders =
+            crate::testsupport::forward_output(&mut process, forward_stdout, forward_stderr);
+        #[cfg(not(feature = "test-support"))]
+        let forwarders = Vec::new();
+        #[cfg(unix)]
+        let (mut process, pid) = match daemonize {
+            Some(daemonize) => (None, daemonize.finish(&mut process)?),
+            None => (Some(process), pid),
+        };
+        #[cfg(not(unix))]
+        let mut process = Some(process);
 
-        let (_rx, tx) = server.accept()?;
+        let connected = Instant::now();
+        let tx = accept_bootstrap(
+            server,
+            &token,
+            match process {
+                Some(ref mut process) => BootstrapProcess::Child(process),
+                None => BootstrapProcess::Pid(pid),
+            },
+            self.common.bootstrap_timeout,
+        )?;
+        bench::record_handshake(connected.elapsed());
 
         let (args_tx, args_rx) = ipc::channel()?;
         let (return_tx, return_rx) = ipc::channel()?;
+        #[cfg(unix)]
+        let reattach = match self.reattach {
+            Some(timeout) => Some(Reattach::new(timeout, detached, child_uid)?),
+            None => None,
+        };
+        #[cfg(unix)]
+        let stream_threshold = self.common.stream_threshold.filter(|_| reattach.is_none());
+        #[cfg(not(unix))]
+        let stream_threshold = self.common.stream_threshold;
+        let (result_stream, stream_rx) = result_stream(stream_threshold)?;
+        let (progress_tx, reports) = ProgressReceiver::channel(self.common.progress)?;
+        let (parent_tx, child_rx) = parent_channel(self.common.parent_channel)?;
 
-        tx.send(MarshalledCall::marshal::<A, R>(func, args_rx, return_tx))?;
-        with_ipc_mode(|| -> Result<_, SpawnError> {
-            args_tx.send(args)?;
-            Ok(())
+        let max_message_size = max_message_size();
+        let compression = self.common.compression;
+        let codec = create_codec(codec_factory, compression, max_message_size);
+        tx.send(
+            MarshalledCall::marshal::<A, R>(
+                func,
+                args_rx,
+                return_tx,
+                codec_factory,
+                self.common.compression,
+                max_message_size,
+                result_stream,
+            )
+            .with_progress(progress_tx)
+            .with_parent(parent_tx)
+            .with_reattach(reattach.clone())
+            .with_wait_for_debugger(self.wait_for_debugger),
+        )?;
+        with_codec(codec.as_ref(), || {
+            with_ipc_mode(|| -> Result<_, SpawnError> {
+                send_args(&args_tx, args)?;
+                Ok(())
+            })
         })?;
 
         Ok(ProcessHandle {
             recv: return_rx,
-            state: Arc::new(ProcessHandleState::new(Some(process.id()))),
+            codec,
+            stream: stream_rx,
+            progress: None,
+            reports,
+            child_rx,
+            state: if detached {
+                // detached processes outlive this process on purpose.
+                Arc::new(ProcessHandleState {
+                    executable,
+                    ..ProcessHandleState::new(Some(pid), sequence)
+                })
+            } else {
+                let state = ProcessHandleState::new_live(Some(pid), sequence, executable);
+                // reattachable processes may outlive it on purpose as well.
+                #[cfg(all(unix, feature = "test-support"))]
+                {
+                    if reattach.is_some() {
+                        crate::testsupport::untrack_child(&state);
+                    }
+                }
+                state
+            },
+            lifecycle: Lifecycle::spawned(func as *const (), pid, sequence, false),
+            exit_status: None,
+            forwarders,
+            #[cfg(unix)]
+            reattach_token: reattach.map(|reattach| ReattachToken::new(pid, reattach, compression)),
             process,
+            permit,
         })
     }
 }
 
-#[derive(Debug)]
-pub struct ProcessHandleState {
-    pub exited: AtomicBool,
-    pub pid: AtomicUsize,
+/// Creates the channel for streaming results if a threshold is set.
+pub(crate) fn result_stream(
+    threshold: Option<u64>,
+) -> Result<(Option<ResultStream>, Option<IpcBytesReceiver>), SpawnError> {
+    match threshold {
+        Some(threshold) => {
+            let (sender, receiver) = ipc::bytes_channel()?;
+            Ok((Some(ResultStream { threshold, sender }), Some(receiver)))
+        }
+        None => Ok((None, None)),
+    }
 }
 
-impl ProcessHandleState {
-    pub fn new(pid: Option<u32>) -> ProcessHandleState {
-        ProcessHandleState {
+/// Sends the arguments of a call.
+pub(crate) fn send_args<A: Serialize>(
+    args_tx: &IpcSender<Frame<Coded<A>>>,
+    args: A,
+) -> Result<(), ipc_channel::Error> {
+    let size = chunked::send(args_tx, Coded(args))?;
+    #[cfg(feature = "metrics")]
+    telemetry::args_sent(size);
+    report_message(MessageKind::Args, Some(size), type_name::<A>());
+    Ok(())
+}
+
+/// Unpacks the result sent back by the child.
+///
+/// If the result is streamed the chunks are read from `stream` and the
+/// progress is reported as they come in.
+pub(crate) fn unpack_result<T: Serialize + DeserializeOwned>(
+    msg: ReturnMessage<T>,
+    stream: Option<&IpcBytesReceiver>,
+    codec: Option<&Arc<dyn Codec>>,
+    progress: Option<&ProgressFunc>,
+) -> Result<T, SpawnError> {
+    let rv = match msg {
+        ReturnMessage::Value(rv) => {
+            report_message(
+                MessageKind::Return,
+                chunked::received_size(),
+                type_name::<T>(),
+            );
+            rv
+        }
+        ReturnMessage::Streamed(total) => {
+            report_message(MessageKind::Return, Some(total as usize), type_name::<T>());
+            let stream = stream.ok_or_else(SpawnError::new_remote_close)?;
+            let mut buf = Vec::with_capacity(total as usize);
+            while (buf.len() as u64) < total {
+                buf.extend_from_slice(&stream.recv()?);
+                if let Some(progress) = progress {
+                    progress(buf.len() as u64, total);
+                }
+            }
+            with_codec(codec, || without_ipc_mode(|| bincode::deserialize(&buf)))?
+        }
+        ReturnMessage::TooLarge(size, limit) => {
+            return Err(SpawnError::new_message_too_large(size, limit));
+        }
+    };
+    #[cfg(feature = "metrics")]
+    {
+        if rv.is_err() {
+            telemetry::call_panicked();
+        }
+    }
+    rv.map(|Coded(rv)| rv).map_err(Into::into)
+}
+
+#[cfg(unix)]
+unsafe fn spawn_forked_helper<A, R: Serialize + DeserializeOwned>(
+    args: A,
+    func: fn(A) -> R,
+    sequence: u64,
+    permit: Option<Permit>,
+) -> Result<ProcessHandle<R>, SpawnError> {
+    let (return_tx, return_rx) = ipc::channel()?;
+    let capture = prepare_forked_call();
+    match libc::fork() {
+        -1 => Err(io::Error::last_os_error().into()),
+        0 => {
+            drop(return_rx);
+            run_forked_call(func, args, return_tx, capture);
+        }
+        pid => Ok(ProcessHandle {
+            recv: return_rx,
+            codec: create_codec(None, None, max_message_size()),
+            stream: None,
+            progress: None,
+            reports: None,
+            child_rx: None,
+            state: ProcessHandleState::new_live(
+                Some(pid as u32),
+                sequence,
+                env::current_exe().ok(),
+            ),
+            lifecycle: Lifecycle::spawned(func as *const (), pid as u32, sequence, true),
+            exit_status: None,
+            forwarders: Vec::new(),
+            reattach_token: None,
+            process: None,
+            permit,
+        }),
+    }
+}
+
+/// True if all writers of the pipe went away.
+#[cfg(unix)]
+fn is_hung_up(fd: RawFd) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: 0,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut pollfd, 1, 0) > 0 && pollfd.revents & libc::POLLHUP != 0 }
+}
+
+/// How much of the stderr of a failed child is attached to the error.
+const CHILD_OUTPUT_TAIL: usize = 16 * 1024;
+
+/// Reads the end of what is buffered in the stderr pipe of a child.
+///
+/// Processes the child spawned might still hold the pipe open, so this
+/// never waits for more output.
+#[cfg(unix)]
+fn read_stderr_tail(stderr: &mut ChildStderr) -> Option<String> {
+    let fd = stderr.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return None;
+        }
+    }
+    let mut buf = Vec::new();
+    match stderr.read_to_end(&mut buf) {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+        Err(_) => return None,
+    }
+    if buf.is_empty() {
+        return None;
+    }
+    let start = buf.len().saturating_sub(CHILD_OUTPUT_TAIL);
+    Some(String::from_utf8_lossy(&buf[start..]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn read_stderr_tail(_stderr: &mut ChildStderr) -> Option<String> {
+    None
+}
+
+/// The process a bootstrap is waiting for.
+pub(crate) enum BootstrapProcess<'a> {
+    /// A direct child of this process.
+    Child(&'a mut process::Child),
+    /// A process we only know the pid of.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Pid(u32),
+}
+
+impl<'a> BootstrapProcess<'a> {
+    /// Returns `Some` with the exit status if known once the process exited.
+    fn try_wait(&mut self) -> io::Result<Option<Option<process::ExitStatus>>> {
+        match *self {
+            BootstrapProcess::Child(ref mut child) => Ok(child.try_wait()?.map(Some)),
+            #[cfg(unix)]
+            BootstrapProcess::Pid(pid) => Ok(if unsafe { libc::kill(pid as i32, 0) } != 0 {
+                Some(None)
+            } else {
+                None
+            }),
+            #[cfg(not(unix))]
+            BootstrapProcess::Pid(_) => Ok(None),
+        }
+    }
+
+    fn kill(&mut self) {
+        match *self {
+            BootstrapProcess::Child(ref mut child) => {
+                child.kill().ok();
+                child.wait().ok();
+            }
+            BootstrapProcess::Pid(pid) => ProcessHandleState::new(Some(pid), 0).kill(),
+        }
+    }
+
+    fn read_stderr(&mut self) -> Option<String> {
+        match *self {
+            // processes the child left behind might hold the pipe open.
+            BootstrapProcess::Child(ref mut child) => {
+                child.stderr.as_mut().and_then(read_stderr_tail)
+            }
+            BootstrapProcess::Pid(_) => None,
+        }
+    }
+}
+
+/// Waits for the child to connect to the bootstrap server.
+///
+/// The accept happens on a helper thread so that we can notice if the
+/// child goes away before connecting or if it exceeds the timeout.
+pub(crate) fn accept_bootstrap(
+    server: IpcOneShotServer<IpcSender<MarshalledCall>>,
+    token: &str,
+    mut process: BootstrapProcess<'_>,
+    timeout: Option<Duration>,
+) -> Result<IpcSender<MarshalledCall>, SpawnError> {
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let (accept_tx, accept_rx) = mpsc::channel();
+    thread::Builder::new()
+        .name("procspawn-bootstrap".into())
+        .spawn(move || {
+            accept_tx.send(server.accept()).ok();
+        })?;
+
+    let (status, timed_out) = loop {
+        match accept_rx.recv_timeout(Duration::from_millis(10)) {
+            Ok(rv) => return Ok(rv?.1),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err(SpawnError::new_remote_close()),
+        }
+        if let Some(status) = process.try_wait()? {
+            // the child might have connected right before it went away.
+            if let Ok(rv) = accept_rx.try_recv() {
+                return Ok(rv?.1);
+            }
+            break (status, false);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            process.kill();
+            break (None, true);
+        }
+    };
+
+    // nobody is going to connect any more.  Connect ourselves so that the
+    // helper thread returns and the bootstrap socket gets cleaned up.
+    if let Ok(sender) = IpcSender::<IpcSender<MarshalledCall>>::connect(token.to_string()) {
+        if let Ok((tx, _rx)) = ipc::channel() {
+            sender.send(tx).ok();
+        }
+    }
+
+    Err(SpawnError::new_bootstrap_failure(
+        status,
+        timed_out,
+        process.read_stderr(),
+    ))
+}
+
+/// The processes spawned by this process that might still be running.
+static LIVE_PROCESSES: Mutex<Vec<Weak<ProcessHandleState>>> = Mutex::new(Vec::new());
+
+/// Returns the processes spawned by this process that have not been
+/// joined or killed yet.
+#[cfg_attr(not(unix), allow(dead_code))]
+pub(crate) fn live_processes() -> Vec<Arc<ProcessHandleState>> {
+    LIVE_PROCESSES
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|state| !state.exited.load(Ordering::SeqCst))
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct ProcessHandleState {
+    pub exited: AtomicBool,
+    pub pid: AtomicUsize,
+    pub sequence: u64,
+    pub executable: Option<PathBuf>,
+}
+
+impl ProcessHandleState {
+    pub fn new(pid: Option<u32>, sequence: u64) -> ProcessHandleState {
+        ProcessHandleState {
             exited: AtomicBool::new(false),
             pid: AtomicUsize::new(pid.unwrap_or(0) as usize),
+            sequence,
+            executable: None,
         }
     }
 
+    /// Creates the state of a spawned process and keeps track of it until
+    /// the process is gone.
+    pub fn new_live(
+        pid: Option<u32>,
+        sequence: u64,
+        executable: Option<PathBuf>,
+    ) -> Arc<ProcessHandleState> {
+        let state = Arc::new(ProcessHandleState {
+            executable,
+            ..ProcessHandleState::new(pid, sequence)
+        });
+        let mut live = LIVE_PROCESSES.lock().unwrap();
+        live.retain(|state| {
+            state
+                .upgrade()
+                .is_some_and(|state| !state.exited.load(Ordering::SeqCst))
+        });
+        live.push(Arc::downgrade(&state));
+        #[cfg(all(unix, feature = "test-support"))]
+        crate::testsupport::track_child(&state);
+        state
+    }
+
     pub fn pid(&self) -> Option<u32> {
         match self.pid.load(Ordering::SeqCst) {
             0 => None,
@@ -350,9 +1438,29 @@ impl ProcessHandleState {
 }
 
 pub struct ProcessHandle<T> {
-    pub(crate) recv: IpcReceiver<Result<T, PanicInfo>>,
-    pub(crate) process: process::Child,
+    pub(crate) recv: IpcReceiver<Frame<ReturnMessage<T>>>,
+    pub(crate) codec: Option<Arc<dyn Codec>>,
+    pub(crate) stream: Option<IpcBytesReceiver>,
+    pub(crate) progress: Option<Arc<ProgressFunc>>,
+    /// The progress reports of the spawned function.
+    pub(crate) reports: Option<ProgressReceiver>,
+    /// The receiving end of the channel the spawned function sends
+    /// messages to its parent on.
+    pub(crate) child_rx: Option<OpaqueIpcReceiver>,
+    /// `None` if the process is not a direct child of this process.
+    pub(crate) process: Option<process::Child>,
     pub(crate) state: Arc<ProcessHandleState>,
+    /// Released once the process exited.
+    pub(crate) permit: Option<Permit>,
+    /// Reports the end of the process to the exit hook.
+    pub(crate) lifecycle: Option<Lifecycle>,
+    /// The exit status once the process was reaped.
+    pub(crate) exit_status: Option<process::ExitStatus>,
+    /// Threads printing the output of the process in tests.
+    pub(crate) forwarders: Vec<thread::JoinHandle<()>>,
+    /// Lets another process receive the result.
+    #[cfg(unix)]
+    pub(crate) reattach_token: Option<ReattachToken>,
 }
 
 fn is_ipc_timeout(err: &ipc_channel::ipc::TryRecvError) -> bool {
@@ -369,62 +1477,255 @@ impl<T> ProcessHandle<T> {
             return Ok(());
         }
 
-        let rv = self.process.kill().map_err(Into::into);
-        self.wait();
+        #[cfg(unix)]
+        {
+            if coverage_enabled() && self.terminate() {
+                self.wait(ExitOutcome::Killed);
+                return Ok(());
+            }
+        }
+
+        let rv = match self.process {
+            Some(ref mut process) => process.kill().map_err(Into::into),
+            None => {
+                self.state.kill();
+                Ok(())
+            }
+        };
+        self.wait(ExitOutcome::Killed);
         rv
     }
 
+    /// Asks the process to exit so that it can write its coverage profile.
+    ///
+    /// Returns `false` if it did not exit within the grace period.
+    #[cfg(unix)]
+    fn terminate(&mut self) -> bool {
+        let pid = match self.state.pid() {
+            Some(pid) => pid,
+            None => return false,
+        };
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+            return false;
+        }
+        let deadline = Instant::now() + TERMINATE_GRACE_PERIOD;
+        while Instant::now() < deadline {
+            if self.try_reap() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
     pub fn stdin(&mut self) -> Option<&mut ChildStdin> {
-        self.process.stdin.as_mut()
+        self.process.as_mut()?.stdin.as_mut()
     }
 
     pub fn stdout(&mut self) -> Option<&mut ChildStdout> {
-        self.process.stdout.as_mut()
+        self.process.as_mut()?.stdout.as_mut()
     }
 
     pub fn stderr(&mut self) -> Option<&mut ChildStderr> {
-        self.process.stderr.as_mut()
+        self.process.as_mut()?.stderr.as_mut()
+    }
+
+    #[cfg(unix)]
+    pub fn stdout_closed(&mut self) -> Option<bool> {
+        self.stdout().map(|stdout| is_hung_up(stdout.as_raw_fd()))
+    }
+
+    #[cfg(unix)]
+    pub fn stderr_closed(&mut self) -> Option<bool> {
+        self.stderr().map(|stderr| is_hung_up(stderr.as_raw_fd()))
+    }
+
+    fn wait(&mut self, outcome: ExitOutcome) -> Option<process::ExitStatus> {
+        let status = self.reap();
+        self.report_exit(outcome);
+        status
+    }
+
+    /// Waits for the process to exit and reaps it.
+    fn reap(&mut self) -> Option<process::ExitStatus> {
+        if self.exit_status.is_none() {
+            self.exit_status = match self.process {
+                Some(ref mut process) => process.wait().ok(),
+                #[cfg(unix)]
+                None => {
+                    // reap forked children.  For processes that are not our
+                    // children this fails right away.
+                    self.state.pid().and_then(|pid| {
+                        use std::os::unix::process::ExitStatusExt;
+                        let mut status = 0;
+                        match unsafe { libc::waitpid(pid as libc::pid_t, &mut status, 0) } {
+                            -1 => None,
+                            _ => Some(process::ExitStatus::from_raw(status)),
+                        }
+                    })
+                }
+                #[cfg(not(unix))]
+                None => None,
+            };
+        }
+        self.state.exited.store(true, Ordering::SeqCst);
+        self.permit.take();
+        // all output is printed before the result is handed out.
+        for forwarder in self.forwarders.drain(..) {
+            forwarder.join().ok();
+        }
+        self.exit_status
     }
 
-    fn wait(&mut self) {
-        self.process.wait().ok();
+    /// Reaps the process if it exited without blocking.
+    pub fn try_reap(&mut self) -> bool {
+        if self.exit_status.is_some() {
+            return true;
+        }
+        match self.process {
+            Some(ref mut process) => match process.try_wait() {
+                Ok(Some(status)) => self.exit_status = Some(status),
+                Ok(None) => return false,
+                Err(_) => {}
+            },
+            #[cfg(unix)]
+            None => {
+                if let Some(pid) = self.state.pid() {
+                    use std::os::unix::process::ExitStatusExt;
+                    let pid = pid as libc::pid_t;
+                    let mut status = 0;
+                    match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+                        0 => return false,
+                        // processes forked by a zygote are not our children,
+                        // all we can tell is whether they still exist.
+                        -1 => {
+                            if unsafe { libc::kill(pid, 0) } == 0 {
+                                return false;
+                            }
+                        }
+                        _ => self.exit_status = Some(process::ExitStatus::from_raw(status)),
+                    }
+                }
+            }
+            #[cfg(not(unix))]
+            None => {}
+        }
         self.state.exited.store(true, Ordering::SeqCst);
+        self.permit.take();
+        true
+    }
+
+    /// Waits for the process to exit without receiving its result.
+    pub fn wait_exit(&mut self) -> Result<process::ExitStatus, SpawnError> {
+        if let Some(status) = self.reap() {
+            return Ok(status);
+        }
+        while !self.try_reap() {
+            thread::sleep(DEADLINE_POLL_INTERVAL);
+        }
+        self.exit_status.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "exit status of a process that is not a child is unavailable",
+            )
+            .into()
+        })
+    }
+
+    /// Reports the end of the process to the exit hook once.
+    fn report_exit(&mut self, outcome: ExitOutcome) {
+        if let Some(lifecycle) = self.lifecycle.take() {
+            lifecycle.exited(outcome);
+        }
+    }
+}
+
+fn exit_outcome<T>(rv: &Result<T, SpawnError>) -> ExitOutcome {
+    match rv {
+        Ok(_) => ExitOutcome::Returned,
+        Err(err) if err.is_panic() => ExitOutcome::Panicked,
+        Err(_) => ExitOutcome::Failed,
     }
 }
 
 impl<T: Serialize + DeserializeOwned> ProcessHandle<T> {
     pub fn join(&mut self) -> Result<T, SpawnError> {
-        let rv = with_ipc_mode(|| self.recv.recv())?.map_err(Into::into);
-        self.wait();
+        let msg = match with_codec(self.codec.as_ref(), || {
+            with_ipc_mode(|| chunked::recv(&self.recv))
+        }) {
+            Ok(msg) => msg,
+            Err(err) => return Err(self.fail(err.into())),
+        };
+        let rv = self.unpack(msg);
+        self.wait(exit_outcome(&rv));
         rv
     }
 
-    pub fn join_timeout(&mut self, timeout: Duration) -> Result<T, SpawnError> {
-        let deadline = match Instant::now().checked_add(timeout) {
-            Some(deadline) => deadline,
-            None => {
-                return Err(io::Error::new(io::ErrorKind::Other, "timeout out of bounds").into())
-            }
-        };
+    pub fn join_deadline(&mut self, deadline: &Deadline) -> Result<T, SpawnError> {
         let mut to_sleep = Duration::from_millis(1);
         let rv = loop {
-            match with_ipc_mode(|| self.recv.try_recv()) {
-                Ok(rv) => break rv.map_err(Into::into),
+            match with_codec(self.codec.as_ref(), || {
+                with_ipc_mode(|| chunked::try_recv(&self.recv))
+            }) {
+                Ok(msg) => break self.unpack(msg),
                 Err(err) if is_ipc_timeout(&err) => {
-                    if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
-                        thread::sleep(remaining.min(to_sleep));
-                        to_sleep *= 2;
-                    } else {
-                        return Err(SpawnError::new_timeout());
+                    // the deadline can move while we wait so it is re-read
+                    // every time.
+                    match deadline.remaining() {
+                        Some(Duration::ZERO) => return Err(SpawnError::new_timeout()),
+                        Some(remaining) => thread::sleep(remaining.min(to_sleep)),
+                        None => thread::sleep(to_sleep),
                     }
+                    to_sleep = (to_sleep * 2).min(DEADLINE_POLL_INTERVAL);
                 }
-                Err(err) => return Err(err.into()),
+                Err(err) => return Err(self.fail(err.into())),
             }
         };
 
-        self.wait();
+        self.wait(exit_outcome(&rv));
         rv
     }
+
+    /// Returns the result if the process already sent it.
+    pub fn try_join(&mut self) -> Option<Result<T, SpawnError>> {
+        let rv = match with_codec(self.codec.as_ref(), || {
+            with_ipc_mode(|| chunked::try_recv(&self.recv))
+        }) {
+            Ok(msg) => self.unpack(msg),
+            Err(err) if is_ipc_timeout(&err) => return None,
+            Err(err) => Err(self.fail(err.into())),
+        };
+        self.wait(exit_outcome(&rv));
+        Some(rv)
+    }
+
+    /// Reports a failed join.
+    ///
+    /// If the child went away without sending a result, its exit status and
+    /// the end of what it wrote to a captured stderr are attached to the
+    /// error.
+    fn fail(&mut self, err: SpawnError) -> SpawnError {
+        if !err.is_remote_close() {
+            self.report_exit(ExitOutcome::Failed);
+            return err;
+        }
+        let status = self.wait(ExitOutcome::Failed);
+        let output = self
+            .process
+            .as_mut()
+            .and_then(|process| process.stderr.as_mut())
+            .and_then(read_stderr_tail);
+        err.with_child_output(output).with_exit_status(status)
+    }
+
+    fn unpack(&self, msg: ReturnMessage<T>) -> Result<T, SpawnError> {
+        unpack_result(
+            msg,
+            self.stream.as_ref(),
+            self.codec.as_ref(),
+            self.progress.as_deref(),
+        )
+    }
 }
 
 pub enum JoinHandleInner<T> {
@@ -438,6 +1739,10 @@ pub enum JoinHandleInner<T> {
 /// ability to kill it.
 pub struct JoinHandle<T> {
     pub(crate) inner: Result<JoinHandleInner<T>, SpawnError>,
+    leak_check: LeakCheck,
+    spawn_location: &'static std::panic::Location<'static>,
+    /// The address of the spawned function.
+    function: Option<usize>,
 }
 
 impl<T> fmt::Debug for JoinHandle<T> {
@@ -449,6 +1754,54 @@ impl<T> fmt::Debug for JoinHandle<T> {
 }
 
 impl<T> JoinHandle<T> {
+    #[track_caller]
+    pub(crate) fn new(inner: Result<JoinHandleInner<T>, SpawnError>) -> JoinHandle<T> {
+        let leak_check = match inner {
+            Ok(JoinHandleInner::Process(_)) => LeakCheck::new(),
+            // dropping pooled handles cancels or kills their call unless
+            // they are detached.
+            Ok(JoinHandleInner::Pooled(ref handle))
+                if handle.drop_behavior() == HandleDropBehavior::Detach =>
+            {
+                LeakCheck::new()
+            }
+            _ => LeakCheck::disarmed(),
+        };
+        JoinHandle {
+            inner,
+            leak_check,
+            spawn_location: std::panic::Location::caller(),
+            function: None,
+        }
+    }
+
+    /// Records the spawned function for the context of errors.
+    pub(crate) fn with_function(mut self, func: *const ()) -> JoinHandle<T> {
+        self.function = Some(func as usize);
+        self
+    }
+
+    /// Returns what is known about the spawn for errors of the join.
+    fn error_context(&self) -> ErrorContext {
+        let state = match self.inner {
+            Ok(JoinHandleInner::Process(ref handle)) => Some(handle.state()),
+            Ok(JoinHandleInner::Pooled(ref handle)) => handle.worker_state(),
+            Err(..) => None,
+        };
+        ErrorContext {
+            pid: state.as_ref().and_then(|x| x.pid()),
+            executable: state.and_then(|x| x.executable.clone()),
+            function: self.function.and_then(|x| function_name(x as *const ())),
+            spawn_location: Some(self.spawn_location),
+        }
+    }
+
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub(crate) fn into_inner(mut self) -> Result<JoinHandleInner<T>, SpawnError> {
+        self.leak_check.disarm();
+        self.inner
+    }
+
     pub(crate) fn process_handle_state(&self) -> Option<Arc<ProcessHandleState>> {
         match self.inner {
             Ok(JoinHandleInner::Process(ref handle)) => Some(handle.state()),
@@ -465,6 +1818,53 @@ impl<T> JoinHandle<T> {
         self.process_handle_state().and_then(|x| x.pid())
     }
 
+    /// Returns where the process or call was spawned.
+    ///
+    /// Errors returned by the join methods carry this location as well, see
+    /// [`SpawnError::spawn_location`](struct.SpawnError.html#method.spawn_location).
+    pub fn spawn_location(&self) -> &'static std::panic::Location<'static> {
+        self.spawn_location
+    }
+
+    /// Returns the token another process can receive the result with.
+    ///
+    /// This is only available for processes spawned with
+    /// [`Builder::reattachable`](struct.Builder.html#method.reattachable).
+    #[cfg(unix)]
+    pub fn reattach_token(&self) -> Option<&ReattachToken> {
+        match self.inner {
+            Ok(JoinHandleInner::Process(ref handle)) => handle.reattach_token.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the spawn sequence number of the process if available.
+    ///
+    /// Every process spawned by this process is numbered in the order the
+    /// spawns were started, beginning with `1`.  Unlike process IDs these
+    /// numbers are the same across runs of a deterministic program which
+    /// makes them useful for assertions in tests.  Processes spawned for
+    /// pools are numbered as well.  For pooled calls this is the sequence
+    /// number of the process the call was scheduled to, so like the
+    /// [`pid`](#method.pid) it is unavailable until then.
+    pub fn sequence(&self) -> Option<u64> {
+        self.process_handle_state().map(|x| x.sequence)
+    }
+
+    /// Returns the ID of a pooled call.
+    ///
+    /// Calls are numbered per pool in the order they were spawned, beginning
+    /// with `1`.  The ID shows up in
+    /// [`WorkerInfo::current_call_id`](struct.WorkerInfo.html#method.current_call_id)
+    /// while the call runs.  This is `None` for handles that were not
+    /// created from a pool.
+    pub fn call_id(&self) -> Option<u64> {
+        match self.inner {
+            Ok(JoinHandleInner::Pooled(ref handle)) => Some(handle.call_id()),
+            _ => None,
+        }
+    }
+
     /// Kill the child process.
     ///
     /// If the join handle was created from a pool this call will do one of
@@ -474,6 +1874,7 @@ impl<T> JoinHandle<T> {
     ///   be killed.
     /// * if the call was not yet scheduled to a process it will be cancelled.
     pub fn kill(&mut self) -> Result<(), SpawnError> {
+        self.leak_check.disarm();
         match self.inner {
             Ok(JoinHandleInner::Process(ref mut handle)) => handle.kill(),
             Ok(JoinHandleInner::Pooled(ref mut handle)) => handle.kill(),
@@ -481,6 +1882,133 @@ impl<T> JoinHandle<T> {
         }
     }
 
+    /// Waits for the process to exit and returns its exit status.
+    ///
+    /// Unlike the join methods this does not receive the result, which can
+    /// still be joined afterwards.  This lets supervisors notice that a
+    /// process went away right when it happens.  Note that a child can only
+    /// exit once its result was written to the channel to the parent, so
+    /// for results that exceed what the channel buffers this waits until
+    /// they are received.
+    ///
+    /// Pooled calls don't have a process of their own and return an error.
+    /// Neither does the exit status of a process forked from a
+    /// [`Zygote`](struct.Zygote.html) reach this process, in which case an
+    /// error is returned once the process is gone.
+    pub fn wait(&mut self) -> Result<process::ExitStatus, SpawnError> {
+        match self.inner {
+            Ok(JoinHandleInner::Process(ref mut handle)) => handle.wait_exit(),
+            Ok(JoinHandleInner::Pooled(_)) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "pooled calls have no process of their own",
+            )
+            .into()),
+            Err(_) => Err(io::Error::new(io::ErrorKind::Other, "no process to wait for").into()),
+        }
+    }
+
+    /// Detaches the handle from its process.
+    ///
+    /// The process keeps running but its result can no longer be received.
+    /// This is what happens when a handle is dropped, but detaching states
+    /// that this is intended and does not trigger the warning of
+    /// [`ProcConfig::leak_detection`](struct.ProcConfig.html#method.leak_detection).
+    /// Pooled calls keep running regardless of the
+    /// [`HandleDropBehavior`](enum.HandleDropBehavior.html) of their pool.
+    pub fn detach(mut self) {
+        self.leak_check.disarm();
+        if let Ok(JoinHandleInner::Pooled(ref mut handle)) = self.inner {
+            handle.detach();
+        }
+    }
+
+    /// Registers a callback for the progress of receiving the result.
+    ///
+    /// This only has an effect if results are streamed, see
+    /// [`Builder::stream_results`](struct.Builder.html#method.stream_results).
+    /// The callback is invoked with the number of bytes received so far and
+    /// the total size of the result after every chunk.  It runs on the thread
+    /// that waits for the result.
+    pub fn on_result_progress<F>(&mut self, f: F)
+    where
+        F: Fn(u64, u64) + Send + Sync + 'static,
+    {
+        match self.inner {
+            Ok(JoinHandleInner::Process(ref mut handle)) => handle.progress = Some(Arc::new(f)),
+            Ok(JoinHandleInner::Pooled(ref mut handle)) => handle.set_progress(Arc::new(f)),
+            Err(_) => {}
+        }
+    }
+
+    fn reports(&mut self) -> Option<&mut ProgressReceiver> {
+        match self.inner {
+            Ok(JoinHandleInner::Process(ref mut handle)) => handle.reports.as_mut(),
+            Ok(JoinHandleInner::Pooled(ref mut handle)) => handle.reports.as_mut(),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns the latest progress reported by the spawned function.
+    ///
+    /// This is `None` until the function reported progress for the first
+    /// time and if progress reporting was not enabled with
+    /// [`Builder::with_progress`](struct.Builder.html#method.with_progress).
+    /// This does not block.
+    pub fn progress(&mut self) -> Option<Progress> {
+        self.reports().and_then(ProgressReceiver::latest)
+    }
+
+    /// Returns the progress reports that arrived since the last call.
+    ///
+    /// Unlike [`progress`](#method.progress) this yields every report in
+    /// the order they were sent.  The iterator does not block but ends
+    /// once all reports that arrived so far were yielded.
+    pub fn progress_updates(&mut self) -> impl Iterator<Item = Progress> + '_ {
+        let mut reports = self.reports();
+        std::iter::from_fn(move || reports.as_mut()?.try_next())
+    }
+
+    /// Takes the receiver for the messages the spawned function sends with
+    /// [`procspawn::parent`](fn.parent.html).
+    ///
+    /// This is `None` if the channel was not enabled with
+    /// [`Builder::with_parent_channel`](struct.Builder.html#method.with_parent_channel)
+    /// and when the receiver was already taken.  The type of the messages
+    /// has to match the type the spawned function sends.
+    pub fn child_receiver<M: Serialize + DeserializeOwned>(&mut self) -> Option<Receiver<M>> {
+        let child_rx = match self.inner {
+            Ok(JoinHandleInner::Process(ref mut handle)) => handle.child_rx.take(),
+            Ok(JoinHandleInner::Pooled(ref mut handle)) => handle.child_rx.take(),
+            Err(_) => None,
+        };
+        child_rx.map(typed_receiver)
+    }
+
+    /// Returns the underlying child process.
+    ///
+    /// This is for capabilities procspawn does not wrap.  Processes are only
+    /// available for handles of executed processes, not for pooled calls or
+    /// for processes that were forked, as those are not represented by a
+    /// `std::process::Child`.  Killing or waiting for the process through
+    /// the child makes joining the handle fail unless the result was already
+    /// sent.
+    pub fn as_child(&self) -> Option<&process::Child> {
+        match self.inner {
+            Ok(JoinHandleInner::Process(ref handle)) => handle.process.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying child process mutably.
+    ///
+    /// See [`as_child`](#method.as_child).
+    pub fn as_child_mut(&mut self) -> Option<&mut process::Child> {
+        match self.inner {
+            Ok(JoinHandleInner::Process(ref mut handle)) => handle.process.as_mut(),
+            _ => None,
+        }
+    }
+
     /// Fetch the `stdin` handle if it has been captured
     pub fn stdin(&mut self) -> Option<&mut ChildStdin> {
         match self.inner {
@@ -507,33 +2035,150 @@ impl<T> JoinHandle<T> {
             Err(_) => None,
         }
     }
+
+    /// Checks if the child closed its captured `stdout`.
+    ///
+    /// This is independent of the process exiting: a child can close its
+    /// stdout and keep running, and the stream stays open after the exit if
+    /// the child passed it on to its own children.  Supervisors can use
+    /// this to treat the end of a control stream as completion.  Data that
+    /// was written before the stream was closed can still be read.
+    ///
+    /// Returns `None` if stdout was not captured.  This is only available
+    /// on unix.
+    #[cfg(unix)]
+    pub fn stdout_closed(&mut self) -> Option<bool> {
+        match self.inner {
+            Ok(JoinHandleInner::Process(ref mut process)) => process.stdout_closed(),
+            Ok(JoinHandleInner::Pooled(..)) => None,
+            Err(_) => None,
+        }
+    }
+
+    /// Checks if the child closed its captured `stderr`.
+    ///
+    /// See [`stdout_closed`](#method.stdout_closed).
+    #[cfg(unix)]
+    pub fn stderr_closed(&mut self) -> Option<bool> {
+        match self.inner {
+            Ok(JoinHandleInner::Process(ref mut process)) => process.stderr_closed(),
+            Ok(JoinHandleInner::Pooled(..)) => None,
+            Err(_) => None,
+        }
+    }
 }
 
 impl<T: Serialize + DeserializeOwned> JoinHandle<T> {
-    /// Wait for the child process to return a result.
+    /// Attaches to a process that was spawned by another process.
     ///
-    /// If the join handle was created from a pool the join is virtualized.
-    pub fn join(self) -> Result<T, SpawnError> {
+    /// This takes the [`ReattachToken`](struct.ReattachToken.html) of a
+    /// process spawned with
+    /// [`Builder::reattachable`](struct.Builder.html#method.reattachable)
+    /// and returns a handle that receives its result once the process that
+    /// spawned it is gone.  `T` must be the return type of the spawned
+    /// function and the codec configuration must match the one of the
+    /// process that spawned it.  Every process can only be reattached once.
+    ///
+    /// The process is not a child of this process, so it can't be waited for
+    /// and its output can't be accessed.
+    ///
+    /// This is only available on unix platforms.
+    #[cfg(unix)]
+    #[track_caller]
+    pub fn reattach(token: &ReattachToken) -> JoinHandle<T> {
+        JoinHandle::new(reattach_helper(token).map(JoinHandleInner::Process))
+    }
+
+    /// Returns `true` if the process exited, without blocking.
+    ///
+    /// Like [`wait`](#method.wait) this does not receive the result.  For
+    /// pooled calls it tells if the result of the call is available.  Handles
+    /// without a process, because the spawn failed or the result was
+    /// already received, are finished as well.
+    pub fn is_finished(&mut self) -> bool {
         match self.inner {
-            Ok(JoinHandleInner::Process(mut handle)) => handle.join(),
-            Ok(JoinHandleInner::Pooled(mut handle)) => handle.join(),
-            Err(err) => Err(err),
+            Ok(JoinHandleInner::Process(ref mut handle)) => handle.try_reap(),
+            Ok(JoinHandleInner::Pooled(ref mut handle)) => handle.is_finished(),
+            Err(_) => true,
         }
     }
 
+    /// Wait for the child process to return a result.
+    ///
+    /// If the join handle was created from a pool the join is virtualized.
+    pub fn join(mut self) -> Result<T, SpawnError> {
+        self.leak_check.disarm();
+        let rv = match self.inner {
+            Ok(JoinHandleInner::Process(ref mut handle)) => handle.join(),
+            Ok(JoinHandleInner::Pooled(ref mut handle)) => handle.join(),
+            Err(ref mut err) => Err(mem::replace(err, SpawnError::new_consumed())),
+        };
+        rv.map_err(|err| err.with_context(self.error_context()))
+    }
+
+    /// Like `join` but also returns the output the call wrote to stdout
+    /// and stderr.
+    ///
+    /// The output is only available for calls of pools built with
+    /// [`PoolBuilder::capture_output`](struct.PoolBuilder.html#method.capture_output).
+    /// It's also returned for calls that panicked if the worker got to send
+    /// it.
+    pub fn join_with_output(mut self) -> (Result<T, SpawnError>, Option<CapturedOutput>) {
+        self.leak_check.disarm();
+        let (rv, output) = match self.inner {
+            Ok(JoinHandleInner::Process(ref mut handle)) => (handle.join(), None),
+            Ok(JoinHandleInner::Pooled(ref mut handle)) => {
+                let rv = handle.join();
+                (rv, handle.take_output())
+            }
+            Err(ref mut err) => (Err(mem::replace(err, SpawnError::new_consumed())), None),
+        };
+        (
+            rv.map_err(|err| err.with_context(self.error_context())),
+            output,
+        )
+    }
+
     /// Like `join` but with a timeout.
     ///
     /// Can be called multiple times. If anything other than a timeout error is returned, the
     /// handle becomes unusuable, and subsequent calls to either `join` or `join_timeout` will
     /// return an error.
     pub fn join_timeout(&mut self, timeout: Duration) -> Result<T, SpawnError> {
+        if Instant::now().checked_add(timeout).is_none() {
+            return Err(io::Error::new(io::ErrorKind::Other, "timeout out of bounds").into());
+        }
+        self.join_deadline(&Deadline::after(timeout))
+    }
+
+    /// Like `join_timeout` but waits until a [`Deadline`](struct.Deadline.html).
+    ///
+    /// The deadline is re-read while waiting, so other threads holding a
+    /// clone of it can extend or shorten the time the join waits.  When the
+    /// deadline passes this behaves like a timeout of `join_timeout`.
+    pub fn join_deadline(&mut self, deadline: &Deadline) -> Result<T, SpawnError> {
+        self.join_deadline_inner(deadline)
+            .map_err(|err| err.with_context(self.error_context()))
+    }
+
+    fn join_deadline_inner(&mut self, deadline: &Deadline) -> Result<T, SpawnError> {
         match self.inner {
             Ok(ref mut handle_inner) => {
-                let result = match handle_inner {
-                    JoinHandleInner::Process(ref mut handle) => handle.join_timeout(timeout),
-                    JoinHandleInner::Pooled(ref mut handle) => handle.join_timeout(timeout),
+                let (result, pooled) = match handle_inner {
+                    JoinHandleInner::Process(ref mut handle) => {
+                        (handle.join_deadline(deadline), false)
+                    }
+                    JoinHandleInner::Pooled(ref mut handle) => {
+                        (handle.join_deadline(deadline), true)
+                    }
                 };
 
+                // processes keep running after a timeout, pooled calls are
+                // killed.
+                if pooled || !result.as_ref().is_err_and(SpawnError::is_timeout) {
+                    self.leak_check.disarm();
+                }
+
                 if result.is_ok() {
                     self.inner = Err(SpawnError::new_consumed());
                 }
@@ -549,6 +2194,111 @@ impl<T: Serialize + DeserializeOwned> JoinHandle<T> {
     }
 }
 
+impl<R, E> JoinHandle<Result<R, E>>
+where
+    R: Serialize + DeserializeOwned,
+    E: Serialize + DeserializeOwned + fmt::Debug + Send + Sync + 'static,
+{
+    /// Like `join` but reports an error returned by the function as a
+    /// [`SpawnError`](struct.SpawnError.html).
+    ///
+    /// This is for functions that return a `Result`.  The error they return
+    /// can be told apart from panics and failures of procspawn itself with
+    /// [`SpawnError::app_error`](struct.SpawnError.html#method.app_error):
+    ///
+    /// ```rust,no_run
+    /// # use serde::{Serialize, Deserialize};
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// enum ParseError {
+    ///     Empty,
+    /// }
+    ///
+    /// let handle = procspawn::spawn(String::new(), |input| {
+    ///     if input.is_empty() {
+    ///         Err(ParseError::Empty)
+    ///     } else {
+    ///         Ok(input.len())
+    ///     }
+    /// });
+    /// match handle.join_result() {
+    ///     Ok(len) => println!("parsed {} bytes", len),
+    ///     Err(err) => match err.app_error::<ParseError>() {
+    ///         Some(err) => println!("invalid input: {:?}", err),
+    ///         None => println!("spawn failed: {}", err),
+    ///     },
+    /// }
+    /// ```
+    pub fn join_result(self) -> Result<R, SpawnError> {
+        let context = self.error_context();
+        self.join()?
+            .map_err(|err| SpawnError::new_app_error(err).with_context(context))
+    }
+
+    /// Like `join_timeout` but reports an error returned by the function as
+    /// a [`SpawnError`](struct.SpawnError.html).
+    ///
+    /// See [`join_result`](#method.join_result).
+    pub fn join_result_timeout(&mut self, timeout: Duration) -> Result<R, SpawnError> {
+        self.join_timeout(timeout)?
+            .map_err(|err| SpawnError::new_app_error(err).with_context(self.error_context()))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> JoinHandle<T> {
+    /// Returns the result if the call is already done without blocking.
+    ///
+    /// Once a result was returned the handle is consumed like after `join`.
+    pub(crate) fn try_join(&mut self) -> Option<Result<T, SpawnError>> {
+        let result = match self.inner {
+            Ok(JoinHandleInner::Process(ref mut handle)) => handle.try_join()?,
+            Ok(JoinHandleInner::Pooled(ref mut handle)) => handle.try_join()?,
+            Err(ref mut err) => Err(mem::replace(err, SpawnError::new_consumed())),
+        };
+        let result = result.map_err(|err| err.with_context(self.error_context()));
+        self.leak_check.disarm();
+        self.inner = Err(SpawnError::new_consumed());
+        Some(result)
+    }
+}
+
+#[cfg(unix)]
+fn reattach_helper<T: Serialize + DeserializeOwned>(
+    token: &ReattachToken,
+) -> Result<ProcessHandle<T>, SpawnError> {
+    // the process announces itself once it picked up the call.  The pid
+    // might have been reused, so this does not wait longer than the process
+    // would wait for us.
+    let deadline = Instant::now() + token.timeout();
+    let name = loop {
+        match token.read_announcement() {
+            Ok(name) => break name,
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            Err(_) if unsafe { libc::kill(token.pid() as libc::pid_t, 0) } != 0 => {
+                return Err(SpawnError::new_remote_close());
+            }
+            Err(_) if Instant::now() >= deadline => return Err(SpawnError::new_timeout()),
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    let (return_tx, return_rx) = ipc::channel()?;
+    IpcSender::<ipc_channel::ipc::OpaqueIpcSender>::connect(name)?.send(return_tx.to_opaque())?;
+    Ok(ProcessHandle {
+        recv: return_rx,
+        codec: create_codec(default_codec(), token.compression(), max_message_size()),
+        stream: None,
+        progress: None,
+        reports: None,
+        child_rx: None,
+        state: ProcessHandleState::new_live(Some(token.pid()), 0, None),
+        lifecycle: None,
+        exit_status: None,
+        forwarders: Vec::new(),
+        reattach_token: None,
+        process: None,
+        permit: None,
+    })
+}
+
 /// Spawn a new process to run a function with some payload.
 ///
 /// ```rust,no_run
@@ -563,6 +2313,13 @@ impl<T: Serialize + DeserializeOwned> JoinHandle<T> {
 /// });
 /// let result = handle.join().unwrap();
 /// ```
+///
+/// With the `tracing` feature the span that is current when the function is
+/// spawned is carried over to the child.  The function runs in a span named
+/// `procspawn` whose `parent.name`, `parent.target`, `parent.id` and
+/// `parent.pid` fields identify the span and process it was spawned from.
+/// This also applies to pooled calls.
+#[track_caller]
 pub fn spawn<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
     args: A,
     f: fn(A) -> R,
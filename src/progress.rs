@@ -0,0 +1,163 @@
+use std::sync::Mutex;
+
+use ipc_channel::ipc::{self, IpcReceiver, IpcSender};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpawnError;
+
+/// The sender of the call that is running in this process.
+static CURRENT_SENDER: Mutex<Option<IpcSender<Progress>>> = Mutex::new(None);
+
+/// The progress of a spawned function.
+///
+/// Spawned functions report their progress with a
+/// [`ProgressSender`](struct.ProgressSender.html) and the parent reads it
+/// with [`JoinHandle::progress`](struct.JoinHandle.html#method.progress).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    done: u64,
+    total: Option<u64>,
+    message: Option<String>,
+}
+
+impl Progress {
+    /// Creates a progress report of `done` out of `total` steps.
+    ///
+    /// The total can be `None` if it's not known.
+    pub fn new(done: u64, total: Option<u64>) -> Progress {
+        Progress {
+            done,
+            total,
+            message: None,
+        }
+    }
+
+    /// Attaches a message, for instance the item that is being worked on.
+    pub fn with_message<S: Into<String>>(mut self, message: S) -> Progress {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// The number of steps that are done.
+    pub fn done(&self) -> u64 {
+        self.done
+    }
+
+    /// The total number of steps if known.
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// The attached message.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// The done fraction between `0.0` and `1.0` if the total is known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.done as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+}
+
+/// Reports the progress of a spawned function to the parent.
+///
+/// Progress reporting has to be enabled with
+/// [`Builder::with_progress`](struct.Builder.html#method.with_progress) or
+/// [`PoolBuilder::with_progress`](struct.PoolBuilder.html#method.with_progress).
+/// The spawned function then gets its sender from
+/// [`current`](#method.current):
+///
+/// ```rust,no_run
+/// use procspawn::{Builder, Progress, ProgressSender};
+///
+/// let mut handle = Builder::new().with_progress().spawn(100, |n: u64| {
+///     let progress = ProgressSender::current().unwrap();
+///     for i in 0..n {
+///         // do some work
+///         progress.report(Progress::new(i + 1, Some(n))).ok();
+///     }
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProgressSender {
+    sender: IpcSender<Progress>,
+}
+
+impl ProgressSender {
+    /// Returns the sender for the call that is running in this process.
+    ///
+    /// This is `None` outside of spawned functions and if progress
+    /// reporting was not enabled for the call.
+    pub fn current() -> Option<ProgressSender> {
+        CURRENT_SENDER
+            .lock()
+            .unwrap()
+            .clone()
+            .map(|sender| ProgressSender { sender })
+    }
+
+    /// Sends a progress report to the parent.
+    pub fn report(&self, progress: Progress) -> Result<(), SpawnError> {
+        self.sender.send(progress).map_err(Into::into)
+    }
+}
+
+/// Makes a sender the current one until the guard is dropped.
+pub struct CurrentSenderGuard(());
+
+impl CurrentSenderGuard {
+    pub fn install(sender: Option<IpcSender<Progress>>) -> CurrentSenderGuard {
+        *CURRENT_SENDER.lock().unwrap() = sender;
+        CurrentSenderGuard(())
+    }
+}
+
+impl Drop for CurrentSenderGuard {
+    fn drop(&mut self) {
+        if let Ok(mut sender) = CURRENT_SENDER.lock() {
+            *sender = None;
+        }
+    }
+}
+
+/// Receives the progress reports of a call.
+pub struct ProgressReceiver {
+    receiver: IpcReceiver<Progress>,
+    latest: Option<Progress>,
+}
+
+impl ProgressReceiver {
+    /// Creates the channel for progress reports if they are enabled.
+    pub fn channel(
+        enabled: bool,
+    ) -> Result<(Option<IpcSender<Progress>>, Option<ProgressReceiver>), SpawnError> {
+        if !enabled {
+            return Ok((None, None));
+        }
+        let (sender, receiver) = ipc::channel()?;
+        Ok((
+            Some(sender),
+            Some(ProgressReceiver {
+                receiver,
+                latest: None,
+            }),
+        ))
+    }
+
+    /// Returns the next report if one arrived.
+    pub fn try_next(&mut self) -> Option<Progress> {
+        let progress = self.receiver.try_recv().ok()?;
+        self.latest = Some(progress.clone());
+        Some(progress)
+    }
+
+    /// Returns the latest report.
+    pub fn latest(&mut self) -> Option<Progress> {
+        while self.try_next().is_some() {}
+        self.latest.clone()
+    }
+}
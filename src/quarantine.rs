@@ -0,0 +1,85 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::codec::{Codec, CodecError};
+
+/// Remembers the arguments of pooled calls that panicked or crashed their
+/// worker.
+///
+/// Arguments are identified by a fingerprint of their encoded bytes.
+pub struct Quarantine {
+    period: Duration,
+    entries: Mutex<HashMap<u64, Instant>>,
+}
+
+impl Quarantine {
+    pub fn new(period: Duration) -> Quarantine {
+        Quarantine {
+            period,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// True if the arguments with this fingerprint failed recently.
+    pub fn contains(&self, fingerprint: u64) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, since| since.elapsed() < self.period);
+        entries.contains_key(&fingerprint)
+    }
+
+    /// Quarantines the arguments with this fingerprint.
+    pub fn insert(&self, fingerprint: u64) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(fingerprint, Instant::now());
+    }
+}
+
+fn fingerprint(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Wraps a codec and passes the fingerprint of encoded arguments to a
+/// callback which decides if they can be sent.
+///
+/// The callback is invoked before anything is written to the channel.
+pub struct Admission<F> {
+    inner: Arc<dyn Codec>,
+    admit: F,
+}
+
+impl<F> Admission<F>
+where
+    F: Fn(u64) -> Result<(), CodecError> + Send + Sync + 'static,
+{
+    pub fn new(inner: Arc<dyn Codec>, admit: F) -> Admission<F> {
+        Admission { inner, admit }
+    }
+}
+
+impl<F> Codec for Admission<F>
+where
+    F: Fn(u64) -> Result<(), CodecError> + Send + Sync + 'static,
+{
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        let bytes = self.inner.encode(value)?;
+        (self.admit)(fingerprint(&bytes))?;
+        Ok(bytes)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(
+            &mut dyn erased_serde::Deserializer,
+        ) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError> {
+        self.inner.decode(bytes, visit)
+    }
+}
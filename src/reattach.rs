@@ -0,0 +1,281 @@
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ipc_channel::ipc::{IpcOneShotServer, OpaqueIpcSender};
+use serde::{Deserialize, Serialize};
+
+use crate::codec::CompressionConfig;
+use crate::endpoints::EndpointGuard;
+
+/// The listener of the reattachable call that is running in this process.
+static CURRENT_LISTENER: Mutex<Option<Arc<Listener>>> = Mutex::new(None);
+
+static DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Configuration for calls whose result can be picked up by a new parent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Reattach {
+    pub path: PathBuf,
+    pub owner: u32,
+    pub timeout: Duration,
+    pub parent: u32,
+    pub detached: bool,
+}
+
+impl Reattach {
+    /// Prepares a reattachable call of a process running as `uid`.
+    ///
+    /// The process announces itself in a directory that only its user can
+    /// access so that nobody else can read or forge the announcement.
+    pub fn new(timeout: Duration, detached: bool, uid: Option<u32>) -> io::Result<Reattach> {
+        let dir = loop {
+            let dir = env::temp_dir().join(format!(
+                ".tmpprocspawnreattach{}x{}",
+                process::id(),
+                DIR_COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            match DirBuilder::new().mode(0o700).create(&dir) {
+                Ok(()) => break dir,
+                // left behind by an earlier process with the same pid
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        let owner = match uid {
+            Some(uid) => {
+                let c_dir = CString::new(dir.as_os_str().as_bytes())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                if unsafe { libc::lchown(c_dir.as_ptr(), uid, !0) } != 0 {
+                    let err = io::Error::last_os_error();
+                    fs::remove_dir(&dir).ok();
+                    return Err(err);
+                }
+                uid
+            }
+            None => unsafe { libc::geteuid() },
+        };
+        Ok(Reattach {
+            path: dir.join("token"),
+            owner,
+            timeout,
+            parent: process::id(),
+            detached,
+        })
+    }
+}
+
+/// Identifies a spawned process so that another process can receive its
+/// result.
+///
+/// Returned by [`JoinHandle::reattach_token`](struct.JoinHandle.html#method.reattach_token)
+/// for processes spawned with
+/// [`Builder::reattachable`](struct.Builder.html#method.reattachable).  The
+/// token can be serialized and persisted so that a parent that restarts can
+/// pass it to [`JoinHandle::reattach`](struct.JoinHandle.html#method.reattach).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReattachToken {
+    pid: u32,
+    path: PathBuf,
+    owner: u32,
+    timeout: Duration,
+    compression: Option<CompressionConfig>,
+}
+
+impl ReattachToken {
+    pub(crate) fn new(
+        pid: u32,
+        reattach: Reattach,
+        compression: Option<CompressionConfig>,
+    ) -> ReattachToken {
+        ReattachToken {
+            pid,
+            path: reattach.path,
+            owner: reattach.owner,
+            timeout: reattach.timeout,
+            compression,
+        }
+    }
+
+    /// The process ID of the spawned process.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// The file the spawned process announces where it can be reached in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub(crate) fn compression(&self) -> Option<CompressionConfig> {
+        self.compression
+    }
+
+    /// Reads where the spawned process can be reached.
+    ///
+    /// The announcement is only trusted if its directory still belongs to
+    /// the user of the process and nobody else can access it.
+    pub(crate) fn read_announcement(&self) -> io::Result<String> {
+        let dir = self.path.parent().unwrap_or_else(|| Path::new("/"));
+        let metadata = fs::symlink_metadata(dir)?;
+        if !metadata.is_dir() || metadata.uid() != self.owner || metadata.mode() & 0o077 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "reattach directory is not private",
+            ));
+        }
+        let mut name = String::new();
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&self.path)?
+            .read_to_string(&mut name)?;
+        Ok(name)
+    }
+}
+
+/// Writes a file that must not exist yet and that only the user can read.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?
+        .write_all(contents)
+}
+
+/// Waits in the child for a new parent to connect.
+struct Listener {
+    parent: u32,
+    detached: bool,
+    timeout: Duration,
+    sender: Mutex<Option<OpaqueIpcSender>>,
+    connected: Condvar,
+}
+
+/// Lets a new parent connect to the running call until the guard is
+/// dropped.
+pub struct ReattachGuard {
+    path: Option<PathBuf>,
+    _endpoint: Option<EndpointGuard>,
+}
+
+impl ReattachGuard {
+    pub fn install(reattach: Option<Reattach>) -> ReattachGuard {
+        let reattach = match reattach {
+            Some(reattach) => reattach,
+            None => {
+                return ReattachGuard {
+                    path: None,
+                    _endpoint: None,
+                }
+            }
+        };
+        let (server, token) = match IpcOneShotServer::<OpaqueIpcSender>::new() {
+            Ok(rv) => rv,
+            Err(_) => {
+                return ReattachGuard {
+                    path: Some(reattach.path),
+                    _endpoint: None,
+                }
+            }
+        };
+        let endpoint = EndpointGuard::register(&token);
+
+        // written to the side first so that a new parent never reads half
+        // of the token.
+        let tmp = reattach.path.with_extension("tmp");
+        if write_private(&tmp, token.as_bytes()).is_ok() {
+            fs::rename(&tmp, &reattach.path).ok();
+        } else {
+            fs::remove_file(&tmp).ok();
+        }
+
+        let listener = Arc::new(Listener {
+            parent: reattach.parent,
+            detached: reattach.detached,
+            timeout: reattach.timeout,
+            sender: Mutex::new(None),
+            connected: Condvar::new(),
+        });
+        {
+            let listener = listener.clone();
+            thread::Builder::new()
+                .name("procspawn-reattach".into())
+                .spawn(move || {
+                    if let Ok((_, sender)) = server.accept() {
+                        *listener.sender.lock().unwrap() = Some(sender);
+                        listener.connected.notify_all();
+                    }
+                })
+                .ok();
+        }
+        *CURRENT_LISTENER.lock().unwrap() = Some(listener);
+        ReattachGuard {
+            path: Some(reattach.path),
+            _endpoint: Some(endpoint),
+        }
+    }
+}
+
+impl Drop for ReattachGuard {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.path {
+            fs::remove_file(path).ok();
+            if let Some(dir) = path.parent() {
+                fs::remove_dir(dir).ok();
+            }
+            if let Ok(mut listener) = CURRENT_LISTENER.lock() {
+                *listener = None;
+            }
+        }
+    }
+}
+
+/// Returns the sender of a new parent if the original parent went away.
+///
+/// If nobody reattached yet this waits for the configured timeout.
+pub fn reattached_sender() -> Option<OpaqueIpcSender> {
+    let listener = CURRENT_LISTENER.lock().ok()?.clone()?;
+    // orphans are handed over to another process.  The parent is the
+    // process that spawned us rather than our parent at the time of the
+    // call as it might have gone away before the call was picked up.
+    // Detached processes were never its children so for them it's only
+    // checked if the process still exists.
+    let orphaned = if listener.detached {
+        unsafe { libc::kill(listener.parent as libc::pid_t, 0) != 0 }
+    } else {
+        unsafe { libc::getppid() as u32 != listener.parent }
+    };
+    if !orphaned {
+        return None;
+    }
+    let deadline = Instant::now() + listener.timeout;
+    let mut sender = listener.sender.lock().unwrap();
+    while sender.is_none() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::ZERO {
+            break;
+        }
+        sender = listener
+            .connected
+            .wait_timeout(sender, remaining)
+            .unwrap()
+            .0;
+    }
+    sender.take()
+}
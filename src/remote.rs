@@ -0,0 +1,573 @@
+//! Spawning functions on other hosts.
+//!
+//! An [`Agent`](struct.Agent.html) accepts calls over a
+//! [`Transport`](../transport/trait.Transport.html) and executes every one
+//! of them in a process of its own.  Agents only run functions that were
+//! registered with them under a name, which is typically done by starting
+//! the application with a flag that turns it into an agent:
+//!
+//! ```rust,no_run
+//! use procspawn::remote::{Agent, Remote};
+//! use procspawn::transport::TcpTransport;
+//!
+//! fn add((a, b): (u32, u32)) -> u32 {
+//!     a + b
+//! }
+//!
+//! fn main() {
+//!     procspawn::init();
+//!     let secret = std::env::var("AGENT_SECRET").unwrap();
+//!
+//!     if std::env::args().any(|x| x == "--agent") {
+//!         let mut agent = Agent::listen(&TcpTransport, "127.0.0.1:7007", secret.as_bytes())
+//!             .unwrap();
+//!         agent.register("add", add);
+//!         agent.run().unwrap();
+//!         return;
+//!     }
+//!
+//!     let remote = Remote::new(TcpTransport, "127.0.0.1:7007", secret.as_bytes());
+//!     let handle = remote.spawn::<_, u32>("add", (1, 2));
+//!     assert_eq!(handle.join().unwrap(), 3);
+//! }
+//! ```
+//!
+//! [`Remote::spawn`](struct.Remote.html#method.spawn) returns a regular
+//! [`JoinHandle`](../struct.JoinHandle.html).  The call is relayed to the
+//! agent by a local process which returns the result or resumes the panic
+//! of the remote function.
+//!
+//! Remotes and agents share a secret.  Every connection starts with a
+//! handshake in which both sides prove that they know the secret and every
+//! message after it is authenticated with a key derived from it.  Messages
+//! are not encrypted though, so arguments and results can be read by
+//! anyone on the network.  Agents should listen on a trusted network or on
+//! the loopback interface behind a tunnel such as SSH.
+//!
+//! Arguments and return values are serialized with plain bincode, so they
+//! cannot carry channels, shared memory or file handles.
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::mem;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use hmac_sha256::HMAC;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::core::{assert_spawn_okay, init};
+use crate::proc::{spawn, JoinHandle};
+use crate::transport::{Connection, Listener, Transport};
+
+/// The version of the protocol spoken between remotes and agents.
+const PROTOCOL_VERSION: u32 = 2;
+
+/// The largest message exchanged during the handshake.
+const HANDSHAKE_LIMIT: usize = 128;
+
+/// How long either side waits for a message of the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type RemoteWrapper = unsafe fn(isize, &[u8]) -> Result<Vec<u8>, String>;
+
+/// The first message of an agent on a new connection.
+#[derive(Serialize, Deserialize, Debug)]
+struct Challenge {
+    version: u32,
+    nonce: [u8; 16],
+}
+
+/// The answer of a remote to the challenge of an agent.
+#[derive(Serialize, Deserialize, Debug)]
+struct Hello {
+    version: u32,
+    nonce: [u8; 16],
+    proof: [u8; 32],
+}
+
+/// A call sent to an agent.
+#[derive(Serialize, Deserialize, Debug)]
+struct RemoteCall {
+    function: String,
+    args: Vec<u8>,
+}
+
+/// A registered function passed to the process executing it.
+#[derive(Serialize, Deserialize, Debug)]
+struct LocalCall {
+    fn_offset: isize,
+    wrapper_offset: isize,
+    args: Vec<u8>,
+}
+
+/// The answer of an agent to a call.
+#[derive(Serialize, Deserialize, Debug)]
+enum Reply {
+    Value(Vec<u8>),
+    Panic(String),
+    Failed(String),
+}
+
+/// Dispatches calls to an agent.
+///
+/// See the [module documentation](index.html) for details.
+#[derive(Clone)]
+pub struct Remote<T> {
+    transport: T,
+    address: String,
+    secret: Vec<u8>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Remote<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Remote")
+            .field("transport", &self.transport)
+            .field("address", &self.address)
+            .finish()
+    }
+}
+
+impl<T> Remote<T>
+where
+    T: Transport + Clone + Serialize + DeserializeOwned,
+{
+    /// Creates a remote for the agent listening on an address.
+    ///
+    /// The secret has to be the one the agent was started with.  No
+    /// connection is made until a call is spawned.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the secret is empty.
+    pub fn new(transport: T, address: &str, secret: &[u8]) -> Remote<T> {
+        assert!(!secret.is_empty(), "the secret of a remote cannot be empty");
+        Remote {
+            transport,
+            address: address.to_string(),
+            secret: secret.to_vec(),
+        }
+    }
+
+    /// Returns the address of the agent.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Spawns a function that was registered with the agent under a name.
+    ///
+    /// The argument and return types have to match the ones of the
+    /// registered function.  Failures to reach the agent or calls the agent
+    /// refuses are reported as panics when joining the handle.
+    ///
+    /// # Panics
+    ///
+    /// This panics if procspawn was not initialized.
+    pub fn spawn<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
+        &self,
+        function: &str,
+        args: A,
+    ) -> JoinHandle<R> {
+        assert_spawn_okay();
+        spawn(
+            (
+                self.transport.clone(),
+                self.address.clone(),
+                self.secret.clone(),
+                function.to_string(),
+                args,
+            ),
+            relay::<T, A, R>,
+        )
+    }
+}
+
+/// A function that was registered with an agent.
+struct Registered {
+    fn_offset: isize,
+    wrapper_offset: isize,
+}
+
+/// Executes calls sent by a [`Remote`](struct.Remote.html).
+///
+/// See the [module documentation](index.html) for details.
+pub struct Agent {
+    listener: Box<dyn Listener>,
+    secret: Vec<u8>,
+    functions: HashMap<String, Registered>,
+}
+
+impl fmt::Debug for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Agent")
+            .field("address", &self.address())
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Agent {
+    /// Listens for calls on an address.
+    ///
+    /// Only remotes that were created with the same secret can connect.
+    /// An empty secret is refused.
+    pub fn listen<T: Transport>(transport: &T, address: &str, secret: &[u8]) -> io::Result<Agent> {
+        assert_spawn_okay();
+        if secret.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the secret of an agent cannot be empty",
+            ));
+        }
+        Ok(Agent {
+            listener: transport.listen(address)?,
+            secret: secret.to_vec(),
+            functions: HashMap::new(),
+        })
+    }
+
+    /// Returns the address remotes connect to.
+    pub fn address(&self) -> String {
+        self.listener.address()
+    }
+
+    /// Registers a function that remotes can call under a name.
+    ///
+    /// Calls of names that were not registered are refused.  Registering a
+    /// name again replaces the function.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the function is defined in a shared library rather
+    /// than the executable.
+    pub fn register<A, R>(&mut self, name: &str, f: fn(A) -> R) -> &mut Self
+    where
+        A: DeserializeOwned,
+        R: Serialize,
+    {
+        self.functions.insert(
+            name.to_string(),
+            Registered {
+                fn_offset: image_offset(f as *const ()),
+                wrapper_offset: image_offset(run_remote::<A, R> as *const ()),
+            },
+        );
+        self
+    }
+
+    /// Accepts connections and executes their calls.
+    ///
+    /// Every connection is served on a thread of its own and can send any
+    /// number of calls.  This only returns if accepting a connection fails.
+    pub fn run(self) -> io::Result<()> {
+        let secret = Arc::new(self.secret);
+        let functions = Arc::new(self.functions);
+        loop {
+            let conn = self.listener.accept()?;
+            let secret = secret.clone();
+            let functions = functions.clone();
+            thread::Builder::new()
+                .name("procspawn-agent".into())
+                .spawn(move || serve_connection(conn, &secret, &functions))?;
+        }
+    }
+}
+
+fn serve_connection(
+    conn: Box<dyn Connection>,
+    secret: &[u8],
+    functions: &HashMap<String, Registered>,
+) {
+    // connections that fail the handshake are dropped without a word.
+    let mut session = match Session::accept(conn, secret) {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+    while let Ok(msg) = session.recv() {
+        let reply = match bincode::deserialize::<RemoteCall>(&msg) {
+            Ok(call) => execute(functions, call),
+            Err(err) => Reply::Failed(format!("malformed call: {}", err)),
+        };
+        let reply = bincode::serialize(&reply).expect("could not serialize reply");
+        if session.send(&reply).is_err() {
+            break;
+        }
+    }
+}
+
+fn execute(functions: &HashMap<String, Registered>, call: RemoteCall) -> Reply {
+    // only the offsets of registered functions are ever passed on, the
+    // remote merely names them.
+    let registered = match functions.get(&call.function) {
+        Some(registered) => registered,
+        None => return Reply::Failed(format!("function {:?} is not registered", call.function)),
+    };
+    let call = LocalCall {
+        fn_offset: registered.fn_offset,
+        wrapper_offset: registered.wrapper_offset,
+        args: call.args,
+    };
+    match spawn(call, execute_in_process).join() {
+        Ok(Ok(rv)) => Reply::Value(rv),
+        Ok(Err(msg)) => Reply::Failed(msg),
+        Err(err) => match err.panic_info() {
+            Some(info) => Reply::Panic(info.message().to_string()),
+            None => Reply::Failed(err.to_string()),
+        },
+    }
+}
+
+fn execute_in_process(call: LocalCall) -> Result<Vec<u8>, String> {
+    let base = init as *const () as isize;
+    unsafe {
+        let wrapper: RemoteWrapper = mem::transmute(call.wrapper_offset + base);
+        wrapper(call.fn_offset + base, &call.args)
+    }
+}
+
+unsafe fn run_remote<A, R>(ptr: isize, args: &[u8]) -> Result<Vec<u8>, String>
+where
+    A: DeserializeOwned,
+    R: Serialize,
+{
+    let function: fn(A) -> R = mem::transmute(ptr);
+    let args = bincode::deserialize(args).map_err(|err| err.to_string())?;
+    bincode::serialize(&function(args)).map_err(|err| err.to_string())
+}
+
+fn relay<T, A, R>(
+    (transport, address, secret, function, args): (T, String, Vec<u8>, String, A),
+) -> R
+where
+    T: Transport,
+    A: Serialize,
+    R: DeserializeOwned,
+{
+    let call = RemoteCall {
+        function,
+        args: bincode::serialize(&args).expect("could not serialize arguments"),
+    };
+    let mut session = transport
+        .connect(&address)
+        .and_then(|conn| Session::connect(conn, &secret))
+        .unwrap_or_else(|err| panic!("could not connect to agent at {}: {}", address, err));
+    session
+        .send(&bincode::serialize(&call).unwrap())
+        .and_then(|_| session.recv())
+        .map_err(|err| err.to_string())
+        .and_then(|reply| bincode::deserialize(&reply).map_err(|err| err.to_string()))
+        .map(|reply| match reply {
+            Reply::Value(rv) => bincode::deserialize(&rv).expect("could not deserialize result"),
+            Reply::Panic(msg) => panic!("{}", msg),
+            Reply::Failed(msg) => panic!("agent at {} refused call: {}", address, msg),
+        })
+        .unwrap_or_else(|err| panic!("lost connection to agent at {}: {}", address, err))
+}
+
+/// A connection whose messages are authenticated with a shared secret.
+struct Session {
+    conn: Box<dyn Connection>,
+    key: [u8; 32],
+    /// Tells the directions apart so that messages cannot be reflected.
+    is_agent: bool,
+    sent: u64,
+    received: u64,
+}
+
+impl Session {
+    /// Performs the handshake on the side of the agent.
+    fn accept(mut conn: Box<dyn Connection>, secret: &[u8]) -> io::Result<Session> {
+        // the remote is not trusted until it answered the challenge, so it
+        // must neither tie up the connection nor send large messages.
+        conn.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let agent_nonce = nonce();
+        conn.send(&encode(&Challenge {
+            version: PROTOCOL_VERSION,
+            nonce: agent_nonce,
+        })?)?;
+        let hello: Hello = decode(&conn.recv(HANDSHAKE_LIMIT)?)?;
+        if hello.version != PROTOCOL_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "remote speaks another protocol version",
+            ));
+        }
+        if !verify(
+            &hello.proof,
+            &proof(secret, b"remote", &agent_nonce, &hello.nonce),
+        ) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "remote does not know the secret",
+            ));
+        }
+        conn.send(&proof(secret, b"agent", &agent_nonce, &hello.nonce))?;
+        conn.set_read_timeout(None)?;
+        Ok(Session::new(conn, secret, &agent_nonce, &hello.nonce, true))
+    }
+
+    /// Performs the handshake on the side of the remote.
+    fn connect(mut conn: Box<dyn Connection>, secret: &[u8]) -> io::Result<Session> {
+        conn.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let challenge: Challenge = decode(&conn.recv(HANDSHAKE_LIMIT)?)?;
+        if challenge.version != PROTOCOL_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "agent speaks protocol version {} but version {} is supported",
+                    challenge.version, PROTOCOL_VERSION
+                ),
+            ));
+        }
+        let remote_nonce = nonce();
+        conn.send(&encode(&Hello {
+            version: PROTOCOL_VERSION,
+            nonce: remote_nonce,
+            proof: proof(secret, b"remote", &challenge.nonce, &remote_nonce),
+        })?)?;
+        // agents hang up on remotes with the wrong secret.
+        let agent_proof = conn.recv(HANDSHAKE_LIMIT).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("agent refused the secret: {}", err),
+            )
+        })?;
+        if !verify(
+            &agent_proof,
+            &proof(secret, b"agent", &challenge.nonce, &remote_nonce),
+        ) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "agent does not know the secret",
+            ));
+        }
+        conn.set_read_timeout(None)?;
+        Ok(Session::new(
+            conn,
+            secret,
+            &challenge.nonce,
+            &remote_nonce,
+            false,
+        ))
+    }
+
+    fn new(
+        conn: Box<dyn Connection>,
+        secret: &[u8],
+        agent_nonce: &[u8; 16],
+        remote_nonce: &[u8; 16],
+        is_agent: bool,
+    ) -> Session {
+        Session {
+            conn,
+            key: proof(secret, b"session", agent_nonce, remote_nonce),
+            is_agent,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+        let mut data = msg.to_vec();
+        data.extend_from_slice(&self.tag(self.is_agent, self.sent, msg));
+        self.sent += 1;
+        self.conn.send(&data)
+    }
+
+    fn recv(&mut self) -> io::Result<Vec<u8>> {
+        // the handshake proved that the peer knows the secret, so calls and
+        // results can be as large as they like.
+        let mut data = self.conn.recv(usize::MAX)?;
+        if data.len() < 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message is missing its tag",
+            ));
+        }
+        let tag = data.split_off(data.len() - 32);
+        if !verify(&tag, &self.tag(!self.is_agent, self.received, &data)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message failed authentication",
+            ));
+        }
+        self.received += 1;
+        Ok(data)
+    }
+
+    /// Authenticates a message together with its sender and position.
+    fn tag(&self, from_agent: bool, sequence: u64, msg: &[u8]) -> [u8; 32] {
+        let mut mac = HMAC::new(self.key);
+        mac.update([from_agent as u8]);
+        mac.update(sequence.to_le_bytes());
+        mac.update(msg);
+        mac.finalize()
+    }
+}
+
+/// Proves the knowledge of the secret for the nonces of a connection.
+fn proof(
+    secret: &[u8],
+    purpose: &[u8],
+    agent_nonce: &[u8; 16],
+    remote_nonce: &[u8; 16],
+) -> [u8; 32] {
+    let mut mac = HMAC::new(secret);
+    mac.update(purpose);
+    mac.update(agent_nonce);
+    mac.update(remote_nonce);
+    mac.finalize()
+}
+
+/// Compares in constant time so that timing does not reveal a valid tag.
+fn verify(actual: &[u8], expected: &[u8; 32]) -> bool {
+    actual.len() == expected.len()
+        && actual
+            .iter()
+            .zip(expected.iter())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Creates a nonce for the handshake.
+///
+/// Nonces only have to be unique and unpredictable.  The keys of
+/// `RandomState` are seeded from the randomness of the operating system.
+fn nonce() -> [u8; 16] {
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|x| x.as_nanos())
+        .unwrap_or(0);
+    let mut rv = [0; 16];
+    for (idx, chunk) in rv.chunks_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(time);
+        hasher.write_usize(idx);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    rv
+}
+
+fn encode<T: Serialize>(msg: &T) -> io::Result<Vec<u8>> {
+    bincode::serialize(msg).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn decode<T: DeserializeOwned>(msg: &[u8]) -> io::Result<T> {
+    bincode::deserialize(msg).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Returns the offset of a function from the start of the executable.
+fn image_offset(f: *const ()) -> isize {
+    #[cfg(feature = "safe-shared-libraries")]
+    {
+        use crate::core::library_name_and_offset;
+        let image = library_name_and_offset(init as *const () as *const u8).map(|x| x.0);
+        if library_name_and_offset(f as *const u8).map(|x| x.0) != image {
+            panic!("remote calls only support functions defined in the executable");
+        }
+    }
+    f as isize - init as *const () as isize
+}
@@ -7,8 +7,13 @@ use ipc_channel::ipc::IpcSharedMemory;
 use serde::{de::Deserializer, de::Error, de::Visitor, ser::Serializer};
 use serde::{Deserialize, Serialize};
 use std::fmt;
+#[cfg(unix)]
+use std::io;
 use std::sync::atomic::{AtomicBool, Ordering};
 
+#[cfg(unix)]
+use crate::shm::SharedRegion;
+
 thread_local! {
     static IN_PROCSPAWN: AtomicBool = const { AtomicBool::new(false) };
 }
@@ -29,6 +34,14 @@ pub fn with_ipc_mode<F: FnOnce() -> R, R>(f: F) -> R {
     f()
 }
 
+/// Internal helper to leave IPC mode, for instance when serializing into
+/// a format that cannot carry IPC handles.
+pub fn without_ipc_mode<F: FnOnce() -> R, R>(f: F) -> R {
+    let old = IN_PROCSPAWN.with(|in_procspawn| in_procspawn.swap(false, Ordering::Relaxed));
+    let _dropper = ResetProcspawn(old);
+    f()
+}
+
 /// Checks if serde is in IPC mode.
 ///
 /// This can be used to customize the serialization behavior of custom
@@ -52,7 +65,7 @@ pub fn in_ipc_mode() -> bool {
 ///
 /// The buffer behind the scenes uses shared memory which is faster send
 /// between processes than to serialize the raw bytes directly.  It is however
-/// read-only.
+/// read-only, see [`ShmemMut`](struct.ShmemMut.html) for a writable buffer.
 pub struct Shmem {
     shmem: IpcSharedMemory,
 }
@@ -159,5 +172,169 @@ impl<'de> Deserialize<'de> for Shmem {
     }
 }
 
+/// A writable byte buffer that is shared between processes.
+///
+/// Unlike [`Shmem`](struct.Shmem.html) the memory behind this buffer is
+/// mapped writable into every process it is sent to, so a child can fill it
+/// in place and the parent sees the result without another copy.  The
+/// process that created the buffer owns the memory and releases it when its
+/// buffer is dropped, so the buffer should be created by the parent and a
+/// [`try_clone`](#method.try_clone) passed down to the child:
+///
+/// ```rust,no_run
+/// use procspawn::serde::ShmemMut;
+///
+/// let frame = ShmemMut::new(1024).unwrap();
+/// let handle = procspawn::spawn(frame.try_clone().unwrap(), |mut frame| {
+///     frame.fill(0xff);
+/// });
+/// handle.join().unwrap();
+/// assert!(frame.iter().all(|&x| x == 0xff));
+/// ```
+///
+/// Writes are not synchronized between processes, the parent should only
+/// look at the buffer once the child is done with it.  When the buffer is
+/// sent with a [codec](../codec/index.html) or serialized outside of
+/// procspawn only a copy of the bytes is sent.
+///
+/// This is only available on unix platforms.
+#[cfg(unix)]
+pub struct ShmemMut {
+    region: SharedRegion,
+}
+
+#[cfg(unix)]
+impl fmt::Debug for ShmemMut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShmemMut")
+            .field("name", &self.region.name())
+            .field("len", &self.region.len())
+            .finish()
+    }
+}
+
+#[cfg(unix)]
+impl ShmemMut {
+    /// Creates a zero-filled buffer of the given length.
+    pub fn new(len: usize) -> io::Result<ShmemMut> {
+        Ok(ShmemMut {
+            region: SharedRegion::create(len)?,
+        })
+    }
+
+    /// Creates a buffer from some bytes.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<ShmemMut> {
+        let mut rv = ShmemMut::new(bytes.len())?;
+        rv.as_bytes_mut().copy_from_slice(bytes);
+        Ok(rv)
+    }
+
+    /// Creates another handle to the same memory.
+    ///
+    /// Writes through either handle are visible through the other.
+    pub fn try_clone(&self) -> io::Result<ShmemMut> {
+        Ok(ShmemMut {
+            region: SharedRegion::open(self.region.name(), self.region.len())?,
+        })
+    }
+
+    /// Returns the bytes inside.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.region.as_slice()
+    }
+
+    /// Returns the bytes inside for writing.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.region.as_mut_slice()
+    }
+}
+
+#[cfg(unix)]
+impl std::ops::Deref for ShmemMut {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+#[cfg(unix)]
+impl std::ops::DerefMut for ShmemMut {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.as_bytes_mut()
+    }
+}
+
+#[cfg(unix)]
+impl Serialize for ShmemMut {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if in_ipc_mode() {
+            (self.region.name(), self.region.len()).serialize(serializer)
+        } else {
+            serializer.serialize_bytes(self.as_bytes())
+        }
+    }
+}
+
+#[cfg(unix)]
+struct ShmemMutVisitor;
+
+#[cfg(unix)]
+impl<'de> Visitor<'de> for ShmemMutVisitor {
+    type Value = ShmemMut;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte array")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        ShmemMut::from_bytes(v).map_err(E::custom)
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        self.visit_bytes(v.as_bytes())
+    }
+}
+
+#[cfg(unix)]
+impl<'de> Deserialize<'de> for ShmemMut {
+    fn deserialize<D>(deserializer: D) -> Result<ShmemMut, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if in_ipc_mode() {
+            let (name, len) = <(String, usize)>::deserialize(deserializer)?;
+            Ok(ShmemMut {
+                region: SharedRegion::open(&name, len).map_err(D::Error::custom)?,
+            })
+        } else {
+            deserializer.deserialize_bytes(ShmemMutVisitor)
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use crate::fdpass::{IpcFd, IpcFile, IpcTcpListener, IpcTcpStream, IpcUnixListener};
 #[cfg(feature = "json")]
 pub use crate::json::Json;
+#[cfg(unix)]
+pub use crate::mmap::MmapPayload;
+#[cfg(feature = "postcard")]
+pub use crate::postcard::Postcard;
+#[cfg(unix)]
+pub use crate::spill::Spill;
+#[cfg(feature = "cbor")]
+pub use crate::structural::Structural;
+#[cfg(feature = "zerocopy")]
+pub use crate::typed_shmem::TypedShmem;
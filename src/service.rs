@@ -0,0 +1,168 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use ipc_channel::ipc::{self, IpcReceiver, IpcSender};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::call::Call;
+use crate::core::{MarshalledFn, MarshalledMethod};
+use crate::error::SpawnError;
+use crate::proc::{Builder, JoinHandle};
+use crate::serde::with_ipc_mode;
+
+thread_local! {
+    /// The state of the service running in this process.
+    static SERVICE_STATE: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+}
+
+/// A long-lived process that keeps state between calls.
+///
+/// A service is spawned with an initialization function that creates the
+/// state in the new process.  Functions that receive a mutable reference to
+/// that state can then be invoked on the service over and over again:
+///
+/// ```rust,no_run
+/// use std::collections::HashMap;
+///
+/// let cache = procspawn::service((), |()| HashMap::<String, u64>::new());
+/// cache
+///     .call(|cache, (key, value)| { cache.insert(key, value); }, ("a".to_string(), 1))
+///     .unwrap();
+/// let value = cache.call(|cache, key: String| cache.get(&key).copied(), "a".into());
+/// assert_eq!(value.unwrap(), Some(1));
+/// ```
+///
+/// For services with many functions the [`interface!`](macro.interface.html)
+/// macro declares a client with one method per function.
+///
+/// Unlike with a [`Pool`](struct.Pool.html) all calls go to the same
+/// process and they are executed one after another in the order they were
+/// made.  Panics in a call are reported to the caller and the service keeps
+/// running.  If the process goes away, for instance because the
+/// initialization function panicked, all further calls fail.  The process is
+/// not restarted.
+///
+/// When the service is dropped its process is killed.
+pub struct Service<S> {
+    calls: Mutex<Option<IpcSender<Call>>>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+    _marker: PhantomData<fn() -> S>,
+}
+
+impl<S> fmt::Debug for Service<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Service").field("pid", &self.pid()).finish()
+    }
+}
+
+impl<S: 'static> Service<S> {
+    #[track_caller]
+    pub(crate) fn spawn<I: Serialize + DeserializeOwned>(
+        builder: &mut Builder,
+        args: I,
+        init: fn(I) -> S,
+    ) -> Service<S> {
+        let (calls_tx, calls_rx) = ipc::channel().unwrap();
+        let handle = builder.spawn((MarshalledFn::new(init), args, calls_rx), run_service);
+        Service {
+            calls: Mutex::new(Some(calls_tx)),
+            handle: Mutex::new(Some(handle)),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Invokes a function with the state of the service.
+    ///
+    /// This blocks until the service executed the call and returns its
+    /// return value.
+    pub fn call<A, R>(&self, method: fn(&mut S, A) -> R, args: A) -> Result<R, SpawnError>
+    where
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned,
+    {
+        let (call, args_tx) = Call::new(|(method, args): (MarshalledMethod<S, A, R>, A)| {
+            SERVICE_STATE.with(|state| {
+                let mut state = state.borrow_mut();
+                let state = state
+                    .as_mut()
+                    .and_then(|state| state.downcast_mut::<S>())
+                    .expect("call does not match the state of the service");
+                method.get()(state, args)
+            })
+        });
+        match *self.calls.lock().unwrap() {
+            Some(ref calls) => with_ipc_mode(|| calls.send(call))?,
+            None => return Err(SpawnError::new_remote_close()),
+        }
+        args_tx.send((MarshalledMethod::new(method), args))?.recv()
+    }
+}
+
+impl<S> Service<S> {
+    /// Returns the process ID of the service.
+    pub fn pid(&self) -> Option<u32> {
+        self.handle
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(JoinHandle::pid)
+    }
+
+    /// Kills the process of the service.
+    ///
+    /// All further calls fail.
+    pub fn kill(&mut self) -> Result<(), SpawnError> {
+        self.calls.lock().unwrap().take();
+        match self.handle.lock().unwrap().take() {
+            Some(mut handle) => handle.kill(),
+            None => Ok(()),
+        }
+    }
+
+    /// Waits for pending calls and shuts the service down.
+    ///
+    /// If the process of the service went away earlier, for instance
+    /// because the initialization function panicked, this returns the error.
+    pub fn shutdown(self) -> Result<(), SpawnError> {
+        self.calls.lock().unwrap().take();
+        let handle = self.handle.lock().unwrap().take();
+        match handle {
+            Some(handle) => handle.join(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S> Drop for Service<S> {
+    fn drop(&mut self) {
+        self.kill().ok();
+    }
+}
+
+fn run_service<I, S: 'static>((init, args, calls): (MarshalledFn<I, S>, I, IpcReceiver<Call>)) {
+    let state = init.get()(args);
+    SERVICE_STATE.with(|x| *x.borrow_mut() = Some(Box::new(state)));
+    while let Ok(call) = calls.recv() {
+        // calls are only created by the parent which runs the same
+        // executable.
+        unsafe { call.execute() }.ok();
+    }
+    SERVICE_STATE.with(|x| x.borrow_mut().take());
+}
+
+/// Spawns a [`Service`](struct.Service.html).
+///
+/// The initialization function is invoked with the given arguments in a new
+/// process and creates the state of the service.  To configure the process
+/// use [`Builder::service`](struct.Builder.html#method.service).
+#[track_caller]
+pub fn service<I, S>(args: I, init: fn(I) -> S) -> Service<S>
+where
+    I: Serialize + DeserializeOwned,
+    S: 'static,
+{
+    Builder::new().service(args, init)
+}
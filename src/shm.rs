@@ -0,0 +1,162 @@
+use std::ffi::CString;
+use std::io;
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static REGION_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A named POSIX shared memory region mapped writable into this process.
+///
+/// The region that created the name removes it again when it is dropped,
+/// regions opened by name only unmap their view.
+pub struct SharedRegion {
+    ptr: NonNull<u8>,
+    len: usize,
+    name: String,
+    owner: bool,
+}
+
+// the region is plain memory, access is governed by the borrow rules of
+// the wrapper.
+unsafe impl Send for SharedRegion {}
+unsafe impl Sync for SharedRegion {}
+
+fn cstring(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+impl SharedRegion {
+    /// Creates a new zero-filled region of the given size.
+    pub fn create(len: usize) -> io::Result<SharedRegion> {
+        loop {
+            // names are kept short because macOS limits them to 31 bytes.
+            let name = format!(
+                "/procspawn-{:x}-{:x}",
+                std::process::id(),
+                REGION_COUNTER.fetch_add(1, Ordering::SeqCst)
+            );
+            let c_name = cstring(&name)?;
+            let fd = unsafe {
+                libc::shm_open(
+                    c_name.as_ptr(),
+                    libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                    0o600 as libc::c_uint,
+                )
+            };
+            if fd < 0 {
+                let err = io::Error::last_os_error();
+                // left behind by an earlier process with the same pid
+                if err.kind() == io::ErrorKind::AlreadyExists {
+                    continue;
+                }
+                return Err(err);
+            }
+            let rv = unsafe {
+                if libc::ftruncate(fd, len as libc::off_t) != 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    map(fd, len)
+                }
+            };
+            unsafe {
+                libc::close(fd);
+            }
+            return match rv {
+                Ok(ptr) => Ok(SharedRegion {
+                    ptr,
+                    len,
+                    name,
+                    owner: true,
+                }),
+                Err(err) => {
+                    unsafe {
+                        libc::shm_unlink(c_name.as_ptr());
+                    }
+                    Err(err)
+                }
+            };
+        }
+    }
+
+    /// Maps an existing region by name.
+    pub fn open(name: &str, len: usize) -> io::Result<SharedRegion> {
+        let c_name = cstring(name)?;
+        let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDWR, 0 as libc::c_uint) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let rv = unsafe {
+            let mut stat: libc::stat = std::mem::zeroed();
+            if libc::fstat(fd, &mut stat) != 0 {
+                Err(io::Error::last_os_error())
+            } else if (stat.st_size as u64) < len as u64 {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "shared memory region is too small",
+                ))
+            } else {
+                map(fd, len)
+            }
+        };
+        unsafe {
+            libc::close(fd);
+        }
+        Ok(SharedRegion {
+            ptr: rv?,
+            len,
+            name: name.to_string(),
+            owner: false,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+unsafe fn map(fd: libc::c_int, len: usize) -> io::Result<NonNull<u8>> {
+    // empty mappings are not allowed
+    if len == 0 {
+        return Ok(NonNull::dangling());
+    }
+    let ptr = libc::mmap(
+        ptr::null_mut(),
+        len,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_SHARED,
+        fd,
+        0,
+    );
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(NonNull::new_unchecked(ptr as *mut u8))
+}
+
+impl Drop for SharedRegion {
+    fn drop(&mut self) {
+        unsafe {
+            if self.len > 0 {
+                libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len);
+            }
+            if self.owner {
+                if let Ok(name) = cstring(&self.name) {
+                    libc::shm_unlink(name.as_ptr());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::proc::live_processes;
+
+/// A signal that can be forwarded to spawned processes.
+///
+/// See [`ProcConfig::forward_signals`](struct.ProcConfig.html#method.forward_signals).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// `SIGINT`, sent by the terminal when the user hits Ctrl-C.
+    Interrupt,
+    /// `SIGTERM`, the regular request to shut down.
+    Terminate,
+    /// `SIGHUP`, sent when the controlling terminal goes away.
+    Hangup,
+    /// `SIGQUIT`, sent by the terminal when the user hits Ctrl-\.
+    Quit,
+}
+
+impl Signal {
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Hangup => libc::SIGHUP,
+            Signal::Quit => libc::SIGQUIT,
+        }
+    }
+}
+
+/// The write end of the pipe the signal handler reports signals to.
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+/// The process that installed the signal handlers.
+static SIGNAL_PID: AtomicI32 = AtomicI32::new(0);
+static ESCALATION: Mutex<Option<Duration>> = Mutex::new(None);
+static PREVIOUS_ACTIONS: Mutex<Option<HashMap<libc::c_int, libc::sigaction>>> = Mutex::new(None);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    // only async-signal-safe work here, the rest happens on the
+    // forwarding thread.
+    if unsafe { libc::getpid() } != SIGNAL_PID.load(Ordering::SeqCst) {
+        // a forked child inherited the handler but not the forwarding
+        // thread, so the signal gets its default behavior.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+        return;
+    }
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = signal as u8;
+        unsafe {
+            libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+        }
+    }
+}
+
+fn cvt(rv: libc::c_int) -> io::Result<libc::c_int> {
+    if rv == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(rv)
+    }
+}
+
+/// Opens the pipe the signal handler writes to and returns its read end.
+fn open_signal_pipe() -> io::Result<File> {
+    let mut fds = [0; 2];
+    unsafe {
+        cvt(libc::pipe(fds.as_mut_ptr()))?;
+        let read = File::from_raw_fd(fds[0]);
+        for &fd in &fds {
+            cvt(libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC))?;
+        }
+        // the signal handler must never block on a full pipe.
+        cvt(libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK))?;
+        SIGNAL_PIPE.store(fds[1], Ordering::SeqCst);
+        SIGNAL_PID.store(libc::getpid(), Ordering::SeqCst);
+        Ok(read)
+    }
+}
+
+fn set_action(signal: libc::c_int, action: &libc::sigaction) -> io::Result<libc::sigaction> {
+    unsafe {
+        let mut previous: libc::sigaction = mem::zeroed();
+        cvt(libc::sigaction(signal, action, &mut previous))?;
+        Ok(previous)
+    }
+}
+
+fn forwarding_action() -> libc::sigaction {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        action
+    }
+}
+
+/// Checks if a process went away without reaping it.
+fn has_exited(pid: u32) -> bool {
+    unsafe {
+        let mut info: libc::siginfo_t = mem::zeroed();
+        if libc::waitid(
+            libc::P_PID,
+            pid as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        ) == 0
+        {
+            return info.si_pid() != 0;
+        }
+        // not our child, so all we can check is if it is still around.
+        libc::kill(pid as libc::pid_t, 0) != 0
+    }
+}
+
+fn forward_signal(signal: libc::c_int) {
+    let processes = live_processes();
+    let pids: Vec<u32> = processes.iter().filter_map(|state| state.pid()).collect();
+    for &pid in &pids {
+        unsafe {
+            libc::kill(pid as libc::pid_t, signal);
+        }
+    }
+
+    let escalation = *ESCALATION.lock().unwrap();
+    if let Some(timeout) = escalation {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut running = pids;
+        loop {
+            running.retain(|&pid| !has_exited(pid));
+            if running.is_empty() || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        for pid in running {
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+
+    // now let the signal do to us whatever it did before we started to
+    // forward it.  For most signals this terminates the process here.
+    let previous = PREVIOUS_ACTIONS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|actions| actions.get(&signal).copied());
+    if let Some(previous) = previous {
+        if set_action(signal, &previous).is_ok() {
+            unsafe {
+                libc::raise(signal);
+            }
+            set_action(signal, &forwarding_action()).ok();
+        }
+    }
+}
+
+/// Installs signal handlers that forward the given signals to all live
+/// spawned processes.
+pub(crate) fn forward_signals(signals: &[Signal], escalation: Option<Duration>) -> io::Result<()> {
+    *ESCALATION.lock().unwrap() = escalation;
+    let mut previous_actions = PREVIOUS_ACTIONS.lock().unwrap();
+
+    if previous_actions.is_none() {
+        let mut read = open_signal_pipe()?;
+        thread::Builder::new()
+            .name("procspawn-signals".into())
+            .spawn(move || {
+                let mut buf = [0; 1];
+                while read.read_exact(&mut buf).is_ok() {
+                    forward_signal(buf[0] as libc::c_int);
+                }
+            })?;
+        *previous_actions = Some(HashMap::new());
+    }
+
+    let previous_actions = previous_actions.as_mut().unwrap();
+    for signal in signals {
+        let signal = signal.as_raw();
+        if previous_actions.contains_key(&signal) {
+            continue;
+        }
+        let previous = set_action(signal, &forwarding_action())?;
+        if previous.sa_sigaction == libc::SIG_IGN {
+            // signals somebody asked to ignore (for instance through
+            // `nohup`) stay ignored.
+            set_action(signal, &previous)?;
+            continue;
+        }
+        previous_actions.insert(signal, previous);
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::process;
+
+use serde::{Deserialize, Serialize};
+use tracing::span::EnteredSpan;
+use tracing::Span;
+
+/// The span that was current in the parent when a call was spawned.
+///
+/// Spans cannot cross process boundaries, so the child enters a span named
+/// `procspawn` that records the name, target and ID of the parent span
+/// together with the process ID of the parent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpanContext {
+    name: String,
+    target: String,
+    id: Option<u64>,
+    pid: u32,
+}
+
+impl SpanContext {
+    /// Captures the current span if there is one.
+    pub fn current() -> Option<SpanContext> {
+        let span = Span::current();
+        let metadata = span.metadata()?;
+        Some(SpanContext {
+            name: metadata.name().to_string(),
+            target: metadata.target().to_string(),
+            id: span.id().map(|id| id.into_u64()),
+            pid: process::id(),
+        })
+    }
+
+    /// Enters the equivalent span in the child.
+    pub fn enter(self) -> EnteredSpan {
+        tracing::info_span!(
+            target: "procspawn",
+            "procspawn",
+            parent.name = %self.name,
+            parent.target = %self.target,
+            parent.id = self.id,
+            parent.pid = self.pid,
+        )
+        .entered()
+    }
+}
@@ -0,0 +1,204 @@
+use std::env;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::os::unix::io::OwnedFd;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::ser::{self, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::fdpass::{deserialize_fd, serialize_fd};
+use crate::serde::{in_ipc_mode, without_ipc_mode};
+
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates a file that only exists for as long as it's open.
+fn anonymous_file() -> io::Result<File> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::FromRawFd;
+        let fd =
+            unsafe { libc::memfd_create(b"procspawn-spill\0".as_ptr() as _, libc::MFD_CLOEXEC) };
+        if fd >= 0 {
+            return Ok(unsafe { File::from_raw_fd(fd) });
+        }
+    }
+
+    loop {
+        let path = env::temp_dir().join(format!(
+            ".tmpprocspawnspill{}x{}",
+            process::id(),
+            SPILL_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => {
+                std::fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            // left behind by an earlier process with the same pid
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Reads a file from a position without moving the file offset, which is
+/// shared with all other processes holding the file.
+struct ReadAt<'a> {
+    file: &'a File,
+    offset: u64,
+}
+
+impl<'a> Read for ReadAt<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.file.read_at(buf, self.offset)?;
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+fn invalid_data(err: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+enum SpillState<T> {
+    Value(T),
+    Spilled(File),
+}
+
+/// A value that is sent through a temporary file instead of the IPC channel.
+///
+/// Sending a huge value through procspawn normally pushes all of its bytes
+/// through the unix socket between the processes.  A spill instead writes
+/// the serialized value to an anonymous file and only hands the file
+/// descriptor to the other process, which reads the value back from the file
+/// the first time it's accessed:
+///
+/// ```rust,no_run
+/// use procspawn::serde::Spill;
+///
+/// let samples = Spill::new(vec![0u64; 100_000_000]);
+/// let handle = procspawn::spawn(samples, |mut samples| {
+///     samples.get().unwrap().iter().sum::<u64>()
+/// });
+/// ```
+///
+/// On Linux the file is a memfd, elsewhere it is a file in the temporary
+/// directory that is deleted right after it was created.  When a spill is
+/// sent with a [codec](../codec/index.html) or serialized outside of
+/// procspawn the value is serialized inline.
+///
+/// This is only available on unix platforms.
+pub struct Spill<T> {
+    state: SpillState<T>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Spill<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.state {
+            SpillState::Value(ref value) => f.debug_tuple("Spill").field(value).finish(),
+            SpillState::Spilled(_) => f.debug_tuple("Spill").field(&"<spilled>").finish(),
+        }
+    }
+}
+
+impl<T> Spill<T> {
+    /// Wraps a value to be spilled when it's sent.
+    pub fn new(value: T) -> Spill<T> {
+        Spill {
+            state: SpillState::Value(value),
+        }
+    }
+
+    /// Returns `true` if the value still has to be read from its file.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.state, SpillState::Spilled(_))
+    }
+}
+
+impl<T: DeserializeOwned> Spill<T> {
+    fn load(&mut self) -> io::Result<()> {
+        if let SpillState::Spilled(ref file) = self.state {
+            let reader = BufReader::new(ReadAt { file, offset: 0 });
+            let value =
+                without_ipc_mode(|| bincode::deserialize_from(reader)).map_err(invalid_data)?;
+            self.state = SpillState::Value(value);
+        }
+        Ok(())
+    }
+
+    /// Returns the value, reading it from its file if needed.
+    pub fn get(&mut self) -> io::Result<&T> {
+        self.get_mut().map(|value| &*value)
+    }
+
+    /// Returns the value mutably, reading it from its file if needed.
+    pub fn get_mut(&mut self) -> io::Result<&mut T> {
+        self.load()?;
+        match self.state {
+            SpillState::Value(ref mut value) => Ok(value),
+            SpillState::Spilled(_) => unreachable!(),
+        }
+    }
+
+    /// Unwraps the value, reading it from its file if needed.
+    pub fn into_inner(mut self) -> io::Result<T> {
+        self.load()?;
+        match self.state {
+            SpillState::Value(value) => Ok(value),
+            SpillState::Spilled(_) => unreachable!(),
+        }
+    }
+}
+
+impl<T: Serialize> Spill<T> {
+    fn spill(value: &T) -> io::Result<OwnedFd> {
+        let file = anonymous_file()?;
+        let mut writer = BufWriter::new(&file);
+        without_ipc_mode(|| bincode::serialize_into(&mut writer, value)).map_err(invalid_data)?;
+        writer.flush()?;
+        drop(writer);
+        Ok(OwnedFd::from(file))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Serialize for Spill<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.state {
+            SpillState::Value(ref value) if in_ipc_mode() => {
+                serialize_fd(Spill::spill(value), serializer)
+            }
+            SpillState::Value(ref value) => value.serialize(serializer),
+            SpillState::Spilled(ref file) if in_ipc_mode() => {
+                serialize_fd(file.try_clone().map(OwnedFd::from), serializer)
+            }
+            SpillState::Spilled(ref file) => {
+                let reader = BufReader::new(ReadAt { file, offset: 0 });
+                let value: T = bincode::deserialize_from(reader).map_err(ser::Error::custom)?;
+                value.serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Spill<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Spill<T>, D::Error> {
+        if in_ipc_mode() {
+            Ok(Spill {
+                state: SpillState::Spilled(File::from(deserialize_fd(deserializer)?)),
+            })
+        } else {
+            T::deserialize(deserializer).map(Spill::new)
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::codec::BytesVisitor;
+
+/// Utility wrapper to force values through a self-describing format.
+///
+/// Bincode does not record the structure of the data it serializes, so
+/// types that need to inspect the data while deserializing cannot be sent
+/// across processes.  This affects `#[serde(flatten)]` as well as untagged
+/// and internally tagged enums.  This wrapper transcodes the value through
+/// [CBOR](https://cbor.io/) which records the structure and then embeds the
+/// result as bytes.
+///
+/// Unlike [`Json`](struct.Json.html) this stays binary, so byte buffers are
+/// not blown up and maps with non-string keys are supported.
+///
+/// Examples:
+///
+/// ```rust,no_run
+/// use procspawn::{spawn, serde::Structural};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Debug)]
+/// #[serde(untagged)]
+/// enum Value {
+///     Number(u64),
+///     Text(String),
+/// }
+///
+/// let handle = spawn(Structural(Value::Number(42)), |Structural(value)| {
+///     Structural(Value::Text(format!("{:?}", value)))
+/// });
+/// let value = handle.join().unwrap().0;
+/// ```
+///
+/// This requires the `cbor` feature.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Structural<T>(pub T);
+
+impl<T: Serialize> Serialize for Structural<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let bytes = serde_cbor::to_vec(&self.0).map_err(|e| ser::Error::custom(e.to_string()))?;
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Structural<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Structural<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = deserializer.deserialize_byte_buf(BytesVisitor)?;
+        Ok(Structural(
+            serde_cbor::from_slice(&bytes).map_err(|e| de::Error::custom(e.to_string()))?,
+        ))
+    }
+}
@@ -0,0 +1,41 @@
+//! Emits metrics through the [`metrics`](https://crates.io/crates/metrics)
+//! facade.
+use std::time::Duration;
+
+fn spawn_kind(forked: bool) -> &'static str {
+    if forked {
+        "fork"
+    } else {
+        "exec"
+    }
+}
+
+/// Records that a process is about to be spawned.
+pub fn spawn_started(forked: bool) {
+    metrics::counter!("procspawn_spawns_total", "kind" => spawn_kind(forked)).increment(1);
+}
+
+/// Records how long spawning a process took and whether it failed.
+pub fn spawn_finished(forked: bool, duration: Duration, failed: bool) {
+    let kind = spawn_kind(forked);
+    metrics::histogram!("procspawn_spawn_duration_seconds", "kind" => kind)
+        .record(duration.as_secs_f64());
+    if failed {
+        metrics::counter!("procspawn_spawn_failures_total", "kind" => kind).increment(1);
+    }
+}
+
+/// Records that a spawned function panicked.
+pub fn call_panicked() {
+    metrics::counter!("procspawn_panics_total").increment(1);
+}
+
+/// Records the size of the arguments sent to a call.
+pub fn args_sent(size: usize) {
+    metrics::histogram!("procspawn_args_bytes").record(size as f64);
+}
+
+/// Records calls entering or leaving the queues of pools.
+pub fn pool_queue_changed(delta: f64) {
+    metrics::gauge!("procspawn_pool_queue_depth").increment(delta);
+}
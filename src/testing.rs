@@ -0,0 +1,158 @@
+//! Fault injection and output forwarding for testing.
+//!
+//! Code that spawns processes usually needs to deal with children that
+//! crash, time out or fail to send their results back.  Instead of writing
+//! functions that kill themselves, faults can be injected into all spawns
+//! that follow:
+//!
+//! ```rust,no_run
+//! use procspawn::testing::{self, Fault};
+//!
+//! testing::inject(Fault::KillAfter(100));
+//! let handle = procspawn::spawn((), |()| std::thread::sleep(std::time::Duration::from_secs(5)));
+//! assert!(handle.join().unwrap_err().is_remote_close());
+//! testing::clear();
+//! ```
+//!
+//! Faults stay active until they are cleared.  They are carried to the
+//! spawned process when it is started, so processes that are already
+//! running (for instance the processes of a pool) are not affected.  Forked
+//! processes and zygotes do not support fault injection.
+//!
+//! The output of spawned processes bypasses the output capturing of the
+//! test harness.  stdout is silenced unless `--nocapture` or
+//! `--show-output` is passed and stderr always shows up.  With
+//! [`forward_output`](fn.forward_output.html) both are instead printed by
+//! the parent, so they only show up for tests that fail.
+//!
+//! This requires the `test-support` feature.
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+pub(crate) const FAULTS_ENV_NAME: &str = "__PROCSPAWN_INJECTED_FAULTS";
+
+static FAULTS: Mutex<Vec<Fault>> = Mutex::new(Vec::new());
+static FAIL_SERIALIZATION: AtomicBool = AtomicBool::new(false);
+static FORWARD_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// A fault that is injected into spawned processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Kills the process the given number of milliseconds after it started.
+    KillAfter(u64),
+    /// Fails the serialization of the return value in the child.
+    ///
+    /// The child goes away without sending a result, just like it does
+    /// when a real return value cannot be serialized.
+    SerializationFailure,
+    /// Delays connecting back to the parent by the given number of
+    /// milliseconds.  This can be combined with a bootstrap timeout.
+    DelayBootstrap(u64),
+}
+
+impl Fault {
+    fn encode(&self) -> String {
+        match *self {
+            Fault::KillAfter(ms) => format!("kill_after={}", ms),
+            Fault::SerializationFailure => "serialization_failure".into(),
+            Fault::DelayBootstrap(ms) => format!("delay_bootstrap={}", ms),
+        }
+    }
+
+    fn decode(s: &str) -> Option<Fault> {
+        let mut pieces = s.splitn(2, '=');
+        let name = pieces.next()?;
+        let ms = pieces.next().and_then(|x| x.parse().ok());
+        match name {
+            "kill_after" => ms.map(Fault::KillAfter),
+            "serialization_failure" => Some(Fault::SerializationFailure),
+            "delay_bootstrap" => ms.map(Fault::DelayBootstrap),
+            _ => None,
+        }
+    }
+}
+
+/// Injects a fault into all subsequent spawns.
+pub fn inject(fault: Fault) {
+    FAULTS.lock().unwrap().push(fault);
+}
+
+/// Removes all injected faults.
+pub fn clear() {
+    FAULTS.lock().unwrap().clear();
+}
+
+/// Returns the faults encoded for the environment of a spawned process.
+pub(crate) fn encoded_faults() -> Option<String> {
+    let faults = FAULTS.lock().unwrap();
+    if faults.is_empty() {
+        None
+    } else {
+        Some(
+            faults
+                .iter()
+                .map(Fault::encode)
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+}
+
+/// Applies the faults the parent injected into this process.
+///
+/// This is called in the child before it connects to the parent.
+pub(crate) fn apply_faults() {
+    let faults = match env::var(FAULTS_ENV_NAME) {
+        Ok(faults) => faults,
+        Err(_) => return,
+    };
+    env::remove_var(FAULTS_ENV_NAME);
+
+    let mut delay = None;
+    for fault in faults.split(',').filter_map(Fault::decode) {
+        match fault {
+            Fault::KillAfter(ms) => {
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(ms));
+                    #[cfg(unix)]
+                    unsafe {
+                        libc::kill(libc::getpid(), libc::SIGKILL);
+                    }
+                    process::abort();
+                });
+            }
+            Fault::SerializationFailure => FAIL_SERIALIZATION.store(true, Ordering::SeqCst),
+            Fault::DelayBootstrap(ms) => delay = Some(Duration::from_millis(ms)),
+        }
+    }
+    if let Some(delay) = delay {
+        thread::sleep(delay);
+    }
+}
+
+/// True if sending the return value should fail.
+pub(crate) fn should_fail_serialization() -> bool {
+    FAIL_SERIALIZATION.load(Ordering::SeqCst)
+}
+
+/// Prints the output of spawned processes from the test that spawned them.
+///
+/// The stdout and stderr of processes spawned afterwards are read by the
+/// parent and printed so that they participate in the output capturing of
+/// the test harness.  Output that was redirected with
+/// [`Builder::stdout`](../struct.Builder.html#method.stdout) or
+/// [`Builder::stderr`](../struct.Builder.html#method.stderr) and the output
+/// of pool workers are not affected.  This has no effect if `--nocapture`
+/// or `--show-output` is passed.
+pub fn forward_output(enabled: bool) {
+    FORWARD_OUTPUT.store(enabled, Ordering::SeqCst);
+}
+
+/// True if the output of spawned processes is forwarded.
+pub(crate) fn forwards_output() -> bool {
+    FORWARD_OUTPUT.load(Ordering::SeqCst)
+}
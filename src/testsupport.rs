@@ -1,12 +1,46 @@
 #![cfg(feature = "test-support")]
 use std::env;
-use std::process::Command;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command};
 use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::sync::{Arc, TryLockError, Weak};
+use std::thread;
 
-use crate::core::mark_initialized;
+use crate::core::{log_stderr, ProcConfig, ENV_NAME};
+#[cfg(unix)]
+use crate::proc::ProcessHandleState;
 
 static TEST_MODE: AtomicBool = AtomicBool::new(false);
 static TEST_MODULE: AtomicPtr<String> = AtomicPtr::new(std::ptr::null_mut());
+static TEST_CONFIG: Mutex<Option<fn(&mut ProcConfig)>> = Mutex::new(None);
+
+/// The processes spawned by the tests that might still be running.
+#[cfg(unix)]
+static CHILDREN: Mutex<Vec<TrackedChild>> = Mutex::new(Vec::new());
+
+/// A process spawned by the tests.
+///
+/// The pid is kept next to the state as the state goes away with the
+/// handle while a leaked process keeps running.
+#[cfg(unix)]
+struct TrackedChild {
+    pid: Option<u32>,
+    state: Weak<ProcessHandleState>,
+}
+
+#[cfg(unix)]
+impl TrackedChild {
+    /// Returns the pid if the process might still be running.
+    fn running_pid(&self) -> Option<u32> {
+        match self.state.upgrade() {
+            Some(state) if state.exited.load(Ordering::SeqCst) => None,
+            Some(state) => state.pid(),
+            None => self.pid,
+        }
+    }
+}
 
 // we need this.
 pub use small_ctor::ctor;
@@ -21,40 +55,134 @@ pub use small_ctor::ctor;
 /// procspawn::enable_test_support!();
 /// ```
 ///
+/// Tests that need a [`ProcConfig`](../struct.ProcConfig.html) must not
+/// call `init` themselves as the other tests of the binary run at the same
+/// time.  Instead the configuration is passed to the macro which applies
+/// it once before the tests start and in every spawned process:
+///
+/// ```rust,no_run
+/// procspawn::enable_test_support!(|config| {
+///     config.max_nesting_depth(3);
+/// });
+/// ```
+///
 /// Requires the `test-support` feature.
 #[macro_export]
 macro_rules! enable_test_support {
     () => {
+        $crate::enable_test_support!(|_| {});
+    };
+    ($config:expr) => {
         #[$crate::testsupport::ctor]
         unsafe fn __procspawn_test_support_init() {
             // strip the crate name from the module path
             let module_path = std::module_path!().splitn(2, "::").nth(1);
-            $crate::testsupport::enable(module_path);
+            $crate::testsupport::enable(module_path, $config);
         }
 
         #[test]
         fn procspawn_test_helper() {
-            $crate::init();
+            $crate::testsupport::init();
         }
     };
 }
 
-pub fn enable(module: Option<&str>) {
+pub fn enable(module: Option<&str>, config: fn(&mut ProcConfig)) {
     if TEST_MODE.swap(true, Ordering::SeqCst) {
         panic!("procspawn testmode can only be enabled once");
     }
+    *TEST_CONFIG.lock().unwrap() = Some(config);
 
     if let Some(module) = module {
         let ptr = Box::into_raw(Box::new(module.to_string()));
         TEST_MODULE.store(ptr, Ordering::SeqCst);
     }
 
-    mark_initialized();
+    // spawned processes are configured by the helper test before they
+    // bootstrap.
+    if env::var_os(ENV_NAME).is_none() {
+        init();
+    }
+
+    // a test that panics between spawning and joining would leave the
+    // process running after the tests finished.
+    #[cfg(unix)]
+    unsafe {
+        libc::atexit(kill_leaked_children);
+    }
+}
+
+/// Initializes procspawn with the configuration of the tests.
+pub fn init() {
+    let mut config = ProcConfig::new();
+    if let Some(configure) = *TEST_CONFIG.lock().unwrap() {
+        configure(&mut config);
+    }
+    config.init();
+}
+
+/// Remembers a spawned process so that it can be killed if it is leaked.
+#[cfg(unix)]
+pub fn track_child(state: &Arc<ProcessHandleState>) {
+    if !TEST_MODE.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut children = CHILDREN.lock().unwrap();
+    children.retain(|child| {
+        child
+            .running_pid()
+            .is_some_and(|pid| unsafe { libc::kill(pid as libc::pid_t, 0) } == 0)
+    });
+    children.push(TrackedChild {
+        pid: state.pid(),
+        state: Arc::downgrade(state),
+    });
+}
+
+/// Forgets a process that is meant to outlive the tests.
+#[cfg(unix)]
+pub fn untrack_child(state: &Arc<ProcessHandleState>) {
+    CHILDREN
+        .lock()
+        .unwrap()
+        .retain(|child| child.state.as_ptr() != Arc::as_ptr(state));
+}
+
+#[cfg(unix)]
+extern "C" fn kill_leaked_children() {
+    // a test thread might still hold the lock while the process exits, in
+    // which case the children are left alone rather than hanging here.
+    let children = match CHILDREN.try_lock() {
+        Ok(mut children) => std::mem::take(&mut *children),
+        Err(TryLockError::Poisoned(err)) => std::mem::take(&mut *err.into_inner()),
+        Err(TryLockError::WouldBlock) => return,
+    };
+    for child in children {
+        let pid = match child.running_pid() {
+            Some(pid) => pid as libc::pid_t,
+            None => continue,
+        };
+        // only processes that were not reaped yet are known to be ours,
+        // the pids of the others might have been reused.
+        let mut status = 0;
+        if unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } != 0 {
+            continue;
+        }
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+            libc::waitpid(pid, &mut status, 0);
+        }
+        log_stderr(format_args!(
+            "procspawn: killed process {} which was still running when the tests finished",
+            pid
+        ));
+    }
 }
 
 pub struct TestMode {
     pub can_pass_args: bool,
     pub should_silence_stdout: bool,
+    pub should_forward_output: bool,
 }
 
 fn test_helper_path() -> String {
@@ -70,11 +198,71 @@ pub fn update_command_for_tests(cmd: &mut Command) -> Option<TestMode> {
         cmd.arg("--exact");
         cmd.arg("--test-threads=1");
         cmd.arg("-q");
+        let capturing = !env::args().any(|x| x == "--show-output" || x == "--nocapture");
+        let should_forward_output = capturing && crate::testing::forwards_output();
+        if should_forward_output {
+            // the harness of the child would otherwise swallow the output.
+            cmd.arg("--nocapture");
+        }
         Some(TestMode {
             can_pass_args: false,
-            should_silence_stdout: !env::args().any(|x| x == "--show-output" || x == "--nocapture"),
+            should_silence_stdout: capturing,
+            should_forward_output,
         })
     } else {
         None
     }
 }
+
+/// Prints the output of a child from threads started on the test thread.
+///
+/// Threads inherit the output capture of the thread that started them so
+/// the output ends up with the test that spawned the child.
+pub fn forward_output(
+    child: &mut Child,
+    stdout: bool,
+    stderr: bool,
+) -> Vec<thread::JoinHandle<()>> {
+    let mut rv = Vec::new();
+    if stdout {
+        rv.extend(
+            child
+                .stdout
+                .take()
+                .and_then(|out| forward_lines(out, true, |line| print!("{}", line))),
+        );
+    }
+    if stderr {
+        rv.extend(
+            child
+                .stderr
+                .take()
+                .and_then(|err| forward_lines(err, false, |line| eprint!("{}", line))),
+        );
+    }
+    rv
+}
+
+fn forward_lines<R: Read + Send + 'static>(
+    reader: R,
+    skip_banner: bool,
+    print: fn(&str),
+) -> Option<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name("procspawn-test-output".into())
+        .spawn(move || {
+            let mut reader = BufReader::new(reader);
+            let mut line = Vec::new();
+            let mut in_banner = skip_banner;
+            while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+                let text = String::from_utf8_lossy(&line);
+                // the harness of the child announces the helper test first.
+                in_banner = in_banner && matches!(text.trim_end(), "" | "running 1 test");
+                if !in_banner {
+                    print(&text);
+                }
+                line.clear();
+            }
+        })
+        .ok()
+}
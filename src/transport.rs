@@ -0,0 +1,421 @@
+//! Pluggable transports for calls that leave the machine.
+//!
+//! Spawned processes and pools talk to their parent over
+//! [`ipc-channel`](https://crates.io/crates/ipc-channel) channels.  These
+//! can carry channels and file descriptors which the marshalled calls rely
+//! on, so they are bound to processes sharing a kernel.  Calls that are
+//! dispatched elsewhere, for instance to another host or into a virtual
+//! machine, go over a [`Transport`](trait.Transport.html) instead which
+//! only has to move framed messages of bytes between two endpoints.
+//!
+//! A transport listens on and connects to addresses whose format it
+//! defines.  The following transports are available:
+//!
+//! * [`TcpTransport`](struct.TcpTransport.html): `host:port`
+//! * [`UnixTransport`](struct.UnixTransport.html): the path of a socket,
+//!   only on unix platforms
+//! * [`VsockTransport`](struct.VsockTransport.html): `cid:port` of a
+//!   virtual machine socket, only on Linux
+//!
+//! Custom transports can wrap any byte stream in a
+//! [`StreamConnection`](struct.StreamConnection.html) which takes care of
+//! framing the messages:
+//!
+//! ```rust,no_run
+//! use procspawn::transport::{TcpTransport, Transport};
+//!
+//! let listener = TcpTransport.listen("127.0.0.1:0").unwrap();
+//! let mut conn = TcpTransport.connect(&listener.address()).unwrap();
+//! conn.send(b"hello").unwrap();
+//! assert_eq!(listener.accept().unwrap().recv(1024).unwrap(), b"hello");
+//! ```
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// A connection between two endpoints that carries framed messages.
+pub trait Connection: Send {
+    /// Sends a message.
+    fn send(&mut self, msg: &[u8]) -> io::Result<()>;
+
+    /// Receives the next message if it is not larger than `limit` bytes.
+    ///
+    /// Fails with an error of kind `UnexpectedEof` once the other side
+    /// closed the connection and with an error of kind `InvalidData` if the
+    /// message is larger than the limit.
+    fn recv(&mut self, limit: usize) -> io::Result<Vec<u8>>;
+
+    /// Sets how long receiving a message may block.
+    ///
+    /// `None` blocks forever.  Connections that cannot time out ignore
+    /// this, which is the default.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let _ = timeout;
+        Ok(())
+    }
+}
+
+/// Accepts the connections of a transport.
+pub trait Listener: Send {
+    /// Waits for the next connection.
+    fn accept(&self) -> io::Result<Box<dyn Connection>>;
+
+    /// Returns the address other endpoints connect to.
+    fn address(&self) -> String;
+}
+
+/// Establishes connections over which calls can be dispatched.
+///
+/// See the [module documentation](index.html) for details.
+pub trait Transport: Send + Sync + 'static {
+    /// Listens for connections on an address.
+    fn listen(&self, address: &str) -> io::Result<Box<dyn Listener>>;
+
+    /// Connects to an address another endpoint listens on.
+    fn connect(&self, address: &str) -> io::Result<Box<dyn Connection>>;
+}
+
+/// Sets the read timeout of a byte stream.
+type SetReadTimeout<S> = fn(&S, Option<Duration>) -> io::Result<()>;
+
+/// Frames messages on a byte stream.
+///
+/// Every message is prefixed with its length as a 64 bit little endian
+/// integer.
+pub struct StreamConnection<S> {
+    stream: S,
+    set_read_timeout: Option<SetReadTimeout<S>>,
+}
+
+impl<S> fmt::Debug for StreamConnection<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamConnection").finish()
+    }
+}
+
+impl<S: Read + Write + Send> StreamConnection<S> {
+    /// Wraps a byte stream.
+    pub fn new(stream: S) -> StreamConnection<S> {
+        StreamConnection {
+            stream,
+            set_read_timeout: None,
+        }
+    }
+
+    /// Wraps a byte stream whose reads can time out.
+    ///
+    /// `set_read_timeout` is invoked with the stream by
+    /// [`Connection::set_read_timeout`](trait.Connection.html#method.set_read_timeout).
+    pub fn with_read_timeout(
+        stream: S,
+        set_read_timeout: fn(&S, Option<Duration>) -> io::Result<()>,
+    ) -> StreamConnection<S> {
+        StreamConnection {
+            stream,
+            set_read_timeout: Some(set_read_timeout),
+        }
+    }
+
+    /// Returns the wrapped byte stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read + Write + Send> Connection for StreamConnection<S> {
+    fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+        self.stream.write_all(&(msg.len() as u64).to_le_bytes())?;
+        self.stream.write_all(msg)?;
+        self.stream.flush()
+    }
+
+    fn recv(&mut self, limit: usize) -> io::Result<Vec<u8>> {
+        let mut len = [0; 8];
+        self.stream.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+        if len > limit as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("message of {} bytes exceeds limit of {} bytes", len, limit),
+            ));
+        }
+        // the buffer grows as data arrives so that a bogus length does not
+        // allocate memory up front.
+        let mut msg = Vec::new();
+        (&mut self.stream).take(len).read_to_end(&mut msg)?;
+        if (msg.len() as u64) < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(msg)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        match self.set_read_timeout {
+            Some(set_read_timeout) => set_read_timeout(&self.stream, timeout),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A transport over TCP.
+///
+/// Messages are not encrypted or authenticated, so this should only be
+/// used on trusted networks or tunneled through something that is.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct TcpTransport;
+
+struct TcpTransportListener(TcpListener);
+
+impl Listener for TcpTransportListener {
+    fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        let (stream, _) = self.0.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(Box::new(StreamConnection::with_read_timeout(
+            stream,
+            TcpStream::set_read_timeout,
+        )))
+    }
+
+    fn address(&self) -> String {
+        self.0
+            .local_addr()
+            .map(|x| x.to_string())
+            .unwrap_or_default()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn listen(&self, address: &str) -> io::Result<Box<dyn Listener>> {
+        Ok(Box::new(TcpTransportListener(TcpListener::bind(address)?)))
+    }
+
+    fn connect(&self, address: &str) -> io::Result<Box<dyn Connection>> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(Box::new(StreamConnection::with_read_timeout(
+            stream,
+            TcpStream::set_read_timeout,
+        )))
+    }
+}
+
+/// A transport over unix domain sockets.
+///
+/// This is only available on unix platforms.
+#[cfg(unix)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct UnixTransport;
+
+#[cfg(unix)]
+struct UnixTransportListener {
+    listener: std::os::unix::net::UnixListener,
+    address: String,
+}
+
+#[cfg(unix)]
+impl Listener for UnixTransportListener {
+    fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        let (stream, _) = self.listener.accept()?;
+        Ok(Box::new(StreamConnection::with_read_timeout(
+            stream,
+            std::os::unix::net::UnixStream::set_read_timeout,
+        )))
+    }
+
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixTransport {
+    fn listen(&self, address: &str) -> io::Result<Box<dyn Listener>> {
+        Ok(Box::new(UnixTransportListener {
+            listener: std::os::unix::net::UnixListener::bind(address)?,
+            address: address.to_string(),
+        }))
+    }
+
+    fn connect(&self, address: &str) -> io::Result<Box<dyn Connection>> {
+        Ok(Box::new(StreamConnection::with_read_timeout(
+            std::os::unix::net::UnixStream::connect(address)?,
+            std::os::unix::net::UnixStream::set_read_timeout,
+        )))
+    }
+}
+
+/// The context ID of the host as seen from a virtual machine.
+#[cfg(target_os = "linux")]
+const VMADDR_CID_HOST: u32 = 2;
+
+/// The context ID for loopback connections.
+#[cfg(target_os = "linux")]
+const VMADDR_CID_LOCAL: u32 = 1;
+
+/// A transport over virtual machine sockets.
+///
+/// This lets a parent on the host dispatch calls into a virtual machine
+/// such as a Firecracker or QEMU guest running the same executable, or the
+/// other way round.  Addresses are written as `cid:port` where the context
+/// ID is a number or one of `any` (only for listening), `local` and
+/// `host`.  Listening on port `any` picks a free port.
+///
+/// This is only available on Linux.
+#[cfg(target_os = "linux")]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct VsockTransport;
+
+#[cfg(target_os = "linux")]
+struct VsockTransportListener(std::fs::File);
+
+#[cfg(target_os = "linux")]
+impl VsockTransport {
+    fn parse_address(address: &str) -> io::Result<libc::sockaddr_vm> {
+        fn parse(value: &str) -> Option<u32> {
+            match value {
+                "any" => Some(libc::VMADDR_CID_ANY),
+                "local" => Some(VMADDR_CID_LOCAL),
+                "host" => Some(VMADDR_CID_HOST),
+                _ => value.parse().ok(),
+            }
+        }
+        let (cid, port) = address
+            .split_once(':')
+            .and_then(|(cid, port)| Some((parse(cid)?, parse(port)?)))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid vsock address {:?}", address),
+                )
+            })?;
+        let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+        addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+        addr.svm_cid = cid;
+        addr.svm_port = port;
+        Ok(addr)
+    }
+
+    fn socket() -> io::Result<std::fs::File> {
+        use std::os::unix::io::FromRawFd;
+        let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+    }
+
+    fn set_read_timeout(socket: &std::fs::File, timeout: Option<Duration>) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        // a zero timeval blocks forever
+        let mut tv = libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        };
+        if let Some(timeout) = timeout {
+            if timeout.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cannot set a 0 duration timeout",
+                ));
+            }
+            tv.tv_sec = timeout.as_secs() as libc::time_t;
+            tv.tv_usec = timeout.subsec_micros() as libc::suseconds_t;
+            if tv.tv_sec == 0 && tv.tv_usec == 0 {
+                tv.tv_usec = 1;
+            }
+        }
+        check_os_result(unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const _ as *const libc::c_void,
+                std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        })
+        .map(|_| ())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_os_result(rv: libc::c_int) -> io::Result<libc::c_int> {
+    if rv < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(rv)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Listener for VsockTransportListener {
+    fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+        let fd = check_os_result(unsafe {
+            libc::accept4(
+                self.0.as_raw_fd(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                libc::SOCK_CLOEXEC,
+            )
+        })?;
+        Ok(Box::new(StreamConnection::with_read_timeout(
+            unsafe { std::fs::File::from_raw_fd(fd) },
+            VsockTransport::set_read_timeout,
+        )))
+    }
+
+    fn address(&self) -> String {
+        use std::os::unix::io::AsRawFd;
+        let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+        let rv = unsafe {
+            libc::getsockname(
+                self.0.as_raw_fd(),
+                &mut addr as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            )
+        };
+        if rv < 0 {
+            return String::new();
+        }
+        format!("{}:{}", addr.svm_cid, addr.svm_port)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Transport for VsockTransport {
+    fn listen(&self, address: &str) -> io::Result<Box<dyn Listener>> {
+        use std::os::unix::io::AsRawFd;
+        let addr = VsockTransport::parse_address(address)?;
+        let socket = VsockTransport::socket()?;
+        check_os_result(unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &addr as *const _ as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        })?;
+        check_os_result(unsafe { libc::listen(socket.as_raw_fd(), 128) })?;
+        Ok(Box::new(VsockTransportListener(socket)))
+    }
+
+    fn connect(&self, address: &str) -> io::Result<Box<dyn Connection>> {
+        use std::os::unix::io::AsRawFd;
+        let addr = VsockTransport::parse_address(address)?;
+        let socket = VsockTransport::socket()?;
+        check_os_result(unsafe {
+            libc::connect(
+                socket.as_raw_fd(),
+                &addr as *const _ as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        })?;
+        Ok(Box::new(StreamConnection::with_read_timeout(
+            socket,
+            VsockTransport::set_read_timeout,
+        )))
+    }
+}
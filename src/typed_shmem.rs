@@ -0,0 +1,120 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+use crate::serde::Shmem;
+
+/// Stands in for the bytes of an empty buffer which are not aligned.
+#[repr(C, align(4096))]
+struct AlignedEmpty([u8; 0]);
+
+static ALIGNED_EMPTY: AlignedEmpty = AlignedEmpty([]);
+
+fn aligned_bytes(shmem: &Shmem) -> &[u8] {
+    if shmem.is_empty() {
+        &ALIGNED_EMPTY.0
+    } else {
+        // shared memory is mapped at page boundaries
+        shmem.as_bytes()
+    }
+}
+
+/// A typed read-only value in shared memory.
+///
+/// This works like [`Shmem`](struct.Shmem.html) but holds a value of type
+/// `T` in its in-memory representation.  The receiving process accesses
+/// the value directly in the shared memory without deserializing it, which
+/// makes this a good fit for large structured data such as lookup tables
+/// or arrays of records.  The layout of the type is checked with
+/// [`zerocopy`](https://docs.rs/zerocopy) so `T` has to derive its
+/// `FromBytes`, `IntoBytes`, `Immutable` and `KnownLayout` traits.
+///
+/// Examples:
+///
+/// ```rust,no_run
+/// use procspawn::{spawn, serde::TypedShmem};
+///
+/// let table: Vec<u64> = (0..1_000_000).collect();
+/// let handle = spawn(TypedShmem::<[u64]>::new(&table[..]), |table| {
+///     table.iter().sum::<u64>()
+/// });
+/// let sum = handle.join().unwrap();
+/// ```
+///
+/// This requires the `zerocopy` feature.
+pub struct TypedShmem<T: ?Sized> {
+    shmem: Shmem,
+    _marker: PhantomData<fn() -> Box<T>>,
+}
+
+impl<T: ?Sized + IntoBytes + Immutable> TypedShmem<T> {
+    /// Copies a value into shared memory.
+    pub fn new(value: &T) -> TypedShmem<T> {
+        TypedShmem {
+            shmem: Shmem::from_bytes(value.as_bytes()),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> TypedShmem<T> {
+    /// Returns the raw bytes of the value.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.shmem.as_bytes()
+    }
+}
+
+impl<T: ?Sized + FromBytes + KnownLayout + Immutable> TypedShmem<T> {
+    /// Returns the value inside.
+    pub fn get(&self) -> &T {
+        // the layout was checked when the value was created or received
+        T::ref_from_bytes(aligned_bytes(&self.shmem)).expect("invalid typed shared memory")
+    }
+}
+
+impl<T: ?Sized + FromBytes + KnownLayout + Immutable> Deref for TypedShmem<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.get()
+    }
+}
+
+impl<T: ?Sized + FromBytes + KnownLayout + Immutable + fmt::Debug> fmt::Debug for TypedShmem<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypedShmem").field(&self.get()).finish()
+    }
+}
+
+impl<T: ?Sized> Serialize for TypedShmem<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.shmem.serialize(serializer)
+    }
+}
+
+impl<'de, T: ?Sized + FromBytes + KnownLayout + Immutable> Deserialize<'de> for TypedShmem<T> {
+    fn deserialize<D>(deserializer: D) -> Result<TypedShmem<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let shmem = Shmem::deserialize(deserializer)?;
+        if T::ref_from_bytes(aligned_bytes(&shmem)).is_err() {
+            return Err(de::Error::custom(format!(
+                "{} bytes of shared memory do not match the layout of the type",
+                shmem.len()
+            )));
+        }
+        Ok(TypedShmem {
+            shmem,
+            _marker: PhantomData,
+        })
+    }
+}
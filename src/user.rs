@@ -0,0 +1,187 @@
+use std::env;
+use std::ffi::{CStr, CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process;
+use std::ptr;
+
+use crate::core::log_stderr;
+
+pub const VERIFY_USER_ENV_NAME: &str = "__PROCSPAWN_VERIFY_USER";
+
+/// A user looked up in the user database.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    pub home: PathBuf,
+    pub groups: Vec<libc::gid_t>,
+}
+
+impl User {
+    /// Looks up a user by name.
+    pub fn lookup(name: &str) -> io::Result<User> {
+        let c_name = CString::new(name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid user name"))?;
+        let mut buf = vec![0 as libc::c_char; 4096];
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = ptr::null_mut();
+        loop {
+            let rv = unsafe {
+                libc::getpwnam_r(
+                    c_name.as_ptr(),
+                    &mut pwd,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            };
+            match rv {
+                libc::ERANGE => buf.resize(buf.len() * 2, 0),
+                0 => break,
+                err => return Err(io::Error::from_raw_os_error(err)),
+            }
+        }
+        if result.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown user '{}'", name),
+            ));
+        }
+
+        let home = unsafe { CStr::from_ptr(pwd.pw_dir) };
+        Ok(User {
+            name: name.to_string(),
+            uid: pwd.pw_uid,
+            gid: pwd.pw_gid,
+            home: PathBuf::from(OsStr::from_bytes(home.to_bytes())),
+            groups: supplementary_groups(&c_name, pwd.pw_gid)?,
+        })
+    }
+
+    /// Checks that the current process is allowed to switch to this user.
+    pub fn check_privileges(&self) -> io::Result<()> {
+        let euid = unsafe { libc::geteuid() };
+        if euid == 0 || (euid == self.uid && unsafe { libc::getegid() } == self.gid) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "cannot run as user '{}': switching users requires root privileges",
+                    self.name
+                ),
+            ))
+        }
+    }
+
+    /// Hands the bootstrap endpoint over to this user.
+    ///
+    /// The endpoint lives in a private directory of the current user which
+    /// the child could not connect to otherwise.
+    pub fn grant_endpoint(&self, token: &str) -> io::Result<()> {
+        if unsafe { libc::geteuid() } != 0 {
+            return Ok(());
+        }
+        let socket = PathBuf::from(token);
+        for path in socket.parent().into_iter().chain(Some(socket.as_path())) {
+            let c_path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid path"))?;
+            if unsafe { libc::chown(c_path.as_ptr(), self.uid, self.gid) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Configures the command to run as this user.
+    pub fn apply(&self, cmd: &mut process::Command, verify: bool) {
+        cmd.env("HOME", &self.home);
+        cmd.env("USER", &self.name);
+        cmd.env("LOGNAME", &self.name);
+        if verify {
+            cmd.env(VERIFY_USER_ENV_NAME, format!("{}:{}", self.uid, self.gid));
+        }
+
+        // if we are already that user there is nothing to switch and we
+        // would not be permitted to change the groups anyways.
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        // the groups are resolved up front because reading the group
+        // database is not safe between fork and exec.
+        let user = self.clone();
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::setgroups(user.groups.len() as _, user.groups.as_ptr()) != 0
+                    || libc::setgid(user.gid) != 0
+                    || libc::setuid(user.uid) != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+fn supplementary_groups(name: &CStr, gid: libc::gid_t) -> io::Result<Vec<libc::gid_t>> {
+    let mut groups: Vec<libc::gid_t> = vec![0; 64];
+    loop {
+        let mut count = groups.len() as libc::c_int;
+        let rv = unsafe {
+            libc::getgrouplist(
+                name.as_ptr(),
+                gid as _,
+                groups.as_mut_ptr() as *mut _,
+                &mut count,
+            )
+        };
+        if rv >= 0 {
+            groups.truncate(count as usize);
+            return Ok(groups);
+        }
+        if groups.len() >= 65536 {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "user is member of too many groups",
+            ));
+        }
+        let len = groups.len() * 2;
+        groups.resize((count as usize).max(len), 0);
+    }
+}
+
+/// Aborts the process if it does not run as the user the parent requested.
+///
+/// This is called in the child before it connects to the parent.
+pub fn verify_user() {
+    let expected = match env::var(VERIFY_USER_ENV_NAME) {
+        Ok(expected) => expected,
+        Err(_) => return,
+    };
+    env::remove_var(VERIFY_USER_ENV_NAME);
+
+    let mut pieces = expected.splitn(2, ':');
+    let uid = pieces.next().and_then(|x| x.parse::<libc::uid_t>().ok());
+    let gid = pieces.next().and_then(|x| x.parse::<libc::gid_t>().ok());
+    let okay = unsafe {
+        Some(libc::getuid()) == uid
+            && Some(libc::geteuid()) == uid
+            && Some(libc::getgid()) == gid
+            && Some(libc::getegid()) == gid
+            // if root privileges can be regained they were never dropped
+            && (uid == Some(0) || libc::setuid(0) != 0)
+    };
+    if !okay {
+        log_stderr(format_args!(
+            "procspawn: refusing to run, process did not switch to uid:gid {}",
+            expected
+        ));
+        process::exit(1);
+    }
+}
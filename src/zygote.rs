@@ -0,0 +1,200 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+
+use ipc_channel::ipc::{self, IpcOneShotServer, IpcReceiver, IpcSender};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::audit::Audit;
+use crate::codec::{create_codec, with_codec, CodecFactory, CompressionConfig};
+use crate::core::{
+    assert_spawn_okay, max_message_size, max_nesting_depth, nesting_depth, run_bootstrapped_call,
+    MarshalledCall,
+};
+use crate::endpoints::EndpointGuard;
+use crate::error::SpawnError;
+use crate::lifecycle::Lifecycle;
+use crate::proc::{
+    accept_bootstrap, next_spawn_sequence, send_args, BootstrapProcess, Builder, JoinHandle,
+    JoinHandleInner, ProcessHandle, ProcessHandleState,
+};
+use crate::serde::with_ipc_mode;
+
+/// A pre-initialized template process that new processes are forked from.
+///
+/// Spawning a process with [`spawn`](fn.spawn.html) executes the current
+/// executable again which means that dynamic linking and everything that
+/// happens before [`init`](fn.init.html) is repeated for every process.  A
+/// zygote is spawned once and then forks a new process for every call
+/// which is a lot cheaper.
+///
+/// The forked processes start out as a copy of the zygote right after it
+/// was initialized.  Only the thread that forks survives in the child, so
+/// threads that were started before `init` are not available in them.
+/// Because the forked processes are not direct children, capturing stdio
+/// is not supported and they inherit the zygote's.
+///
+/// When the zygote is dropped the template process is killed, processes
+/// forked from it keep running.
+///
+/// This is only available on unix platforms.
+///
+/// ```rust,no_run
+/// let zygote = procspawn::Zygote::new().unwrap();
+/// let handle = zygote.spawn((1, 2), |(a, b)| a + b);
+/// assert_eq!(handle.join().unwrap(), 3);
+/// ```
+pub struct Zygote {
+    channel: Mutex<(IpcSender<String>, IpcReceiver<u32>)>,
+    handle: Mutex<JoinHandle<()>>,
+    codec: Option<CodecFactory>,
+    compression: Option<CompressionConfig>,
+}
+
+impl fmt::Debug for Zygote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Zygote").field("pid", &self.pid()).finish()
+    }
+}
+
+impl Zygote {
+    /// Spawns a new zygote with the default settings.
+    pub fn new() -> Result<Zygote, SpawnError> {
+        Zygote::with_builder(&mut Builder::new())
+    }
+
+    /// Spawns a new zygote from a configured builder.
+    ///
+    /// All processes forked from the zygote share the environment and
+    /// stdio configured on the builder and use its codec and compression.
+    pub fn with_builder(builder: &mut Builder) -> Result<Zygote, SpawnError> {
+        let (token_tx, token_rx) = ipc::channel()?;
+        let (pid_tx, pid_rx) = ipc::channel()?;
+        let inner = builder.spawn((token_rx, pid_tx), run_zygote).into_inner()?;
+        Ok(Zygote {
+            channel: Mutex::new((token_tx, pid_rx)),
+            handle: Mutex::new(JoinHandle::new(Ok(inner))),
+            codec: builder.codec_factory(),
+            compression: builder.compression(),
+        })
+    }
+
+    /// Returns the process ID of the zygote.
+    pub fn pid(&self) -> Option<u32> {
+        self.handle.lock().unwrap().pid()
+    }
+
+    /// Forks a new process from the zygote to run a function.
+    ///
+    /// This works exactly like [`procspawn::spawn`](fn.spawn.html).
+    #[track_caller]
+    pub fn spawn<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
+        &self,
+        args: A,
+        func: fn(A) -> R,
+    ) -> JoinHandle<R> {
+        assert_spawn_okay();
+        let sequence = next_spawn_sequence();
+        let mut audit = Audit::start(true, sequence);
+        audit.set_limits(nesting_depth() + 1, max_nesting_depth(), None);
+        let rv = self.spawn_helper(args, func, sequence);
+        audit.finish(&rv, |handle| handle.state.pid());
+        JoinHandle::new(rv.map(JoinHandleInner::Process)).with_function(func as *const ())
+    }
+
+    fn spawn_helper<A: Serialize + DeserializeOwned, R: Serialize + DeserializeOwned>(
+        &self,
+        args: A,
+        func: fn(A) -> R,
+        sequence: u64,
+    ) -> Result<ProcessHandle<R>, SpawnError> {
+        let (server, token) = IpcOneShotServer::<IpcSender<MarshalledCall>>::new()?;
+        let _endpoint = EndpointGuard::register(&token);
+
+        let pid = {
+            let channel = self.channel.lock().unwrap();
+            channel.0.send(token.clone())?;
+            channel.1.recv()?
+        };
+        if pid == 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "zygote failed to fork").into());
+        }
+
+        let tx = accept_bootstrap(server, &token, BootstrapProcess::Pid(pid), None)?;
+
+        let (args_tx, args_rx) = ipc::channel()?;
+        let (return_tx, return_rx) = ipc::channel()?;
+
+        let max_message_size = max_message_size();
+        let codec = create_codec(self.codec, self.compression, max_message_size);
+        tx.send(MarshalledCall::marshal::<A, R>(
+            func,
+            args_rx,
+            return_tx,
+            self.codec,
+            self.compression,
+            max_message_size,
+            None,
+        ))?;
+        with_codec(codec.as_ref(), || {
+            with_ipc_mode(|| -> Result<_, SpawnError> {
+                send_args(&args_tx, args)?;
+                Ok(())
+            })
+        })?;
+
+        Ok(ProcessHandle {
+            recv: return_rx,
+            codec,
+            stream: None,
+            progress: None,
+            reports: None,
+            child_rx: None,
+            state: ProcessHandleState::new_live(Some(pid), sequence, env::current_exe().ok()),
+            lifecycle: Lifecycle::spawned(func as *const (), pid, sequence, true),
+            exit_status: None,
+            forwarders: Vec::new(),
+            reattach_token: None,
+            process: None,
+            permit: None,
+        })
+    }
+}
+
+impl Drop for Zygote {
+    fn drop(&mut self) {
+        self.handle.lock().unwrap().kill().ok();
+    }
+}
+
+fn run_zygote((token_rx, pid_tx): (IpcReceiver<String>, IpcSender<u32>)) {
+    // the forked processes are not our business once they are running, let
+    // the kernel reap them.
+    unsafe {
+        libc::signal(libc::SIGCHLD, libc::SIG_IGN);
+    }
+
+    while let Ok(token) = token_rx.recv() {
+        match unsafe { libc::fork() } {
+            0 => {
+                drop(token_rx);
+                drop(pid_tx);
+                unsafe {
+                    libc::signal(libc::SIGCHLD, libc::SIG_DFL);
+                }
+                run_bootstrapped_call(token);
+            }
+            -1 => {
+                if pid_tx.send(0).is_err() {
+                    break;
+                }
+            }
+            pid => {
+                if pid_tx.send(pid as u32).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Mutex;
+
+use procspawn::{self, AuditRecord, Builder};
+
+procspawn::enable_test_support!(|config| {
+    config.audit_log(|record| RECORDS.lock().unwrap().push(record.clone()));
+});
+
+static RECORDS: Mutex<Vec<AuditRecord>> = Mutex::new(Vec::new());
+
+fn last_record() -> AuditRecord {
+    RECORDS.lock().unwrap().last().cloned().unwrap()
+}
+
+#[test]
+fn test_audit_log() {
+    let handle = Builder::new()
+        .env("PROCSPAWN_AUDIT_TEST", "1")
+        .spawn((), |()| 42);
+    let pid = handle.pid();
+    assert_eq!(handle.sequence(), Some(1));
+    assert_eq!(handle.join().unwrap(), 42);
+
+    let record = last_record();
+    assert!(!record.forked());
+    assert!(record.executable().is_some());
+    assert_eq!(record.pid(), pid);
+    assert_eq!(record.sequence(), 1);
+    assert_eq!(record.error(), None);
+    assert_eq!(record.nesting_depth(), 1);
+    assert!(record
+        .env_set()
+        .iter()
+        .any(|key| key == "PROCSPAWN_AUDIT_TEST"));
+    assert!(record
+        .env_set()
+        .iter()
+        .all(|key| !key.starts_with("__PROCSPAWN_")));
+    assert!(record
+        .to_string()
+        .contains(&format!("pid={}", pid.unwrap())));
+
+    #[cfg(unix)]
+    {
+        let handle = Builder::new()
+            .run_as("procspawn-no-such-user")
+            .spawn((), |()| ());
+        assert!(handle.join().is_err());
+        let record = last_record();
+        assert_eq!(record.run_as(), Some("procspawn-no-such-user"));
+        assert_eq!(record.pid(), None);
+        assert_eq!(record.sequence(), 2);
+        assert!(record.error().is_some());
+
+        let handle = unsafe { Builder::new().spawn_forked((), |()| 23) };
+        let pid = handle.pid();
+        assert_eq!(handle.sequence(), Some(3));
+        assert_eq!(handle.join().unwrap(), 23);
+        let record = last_record();
+        assert!(record.forked());
+        assert!(record.to_string().starts_with("spawn seq=3 forked"));
+        assert_eq!(record.pid(), pid);
+    }
+}
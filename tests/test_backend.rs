@@ -0,0 +1,47 @@
+use std::io;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use procspawn::{self, spawn, ExecBackend, SpawnBackend};
+
+procspawn::enable_test_support!(|config| {
+    config.spawn_backend(CountingBackend);
+});
+
+static LAUNCHES: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingBackend;
+
+impl SpawnBackend for CountingBackend {
+    fn launch(&self, cmd: &mut process::Command) -> io::Result<process::Child> {
+        if cmd
+            .get_envs()
+            .any(|(key, _)| key == "PROCSPAWN_TEST_BACKEND_FAIL")
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "launch refused",
+            ));
+        }
+        LAUNCHES.fetch_add(1, Ordering::SeqCst);
+        cmd.env("PROCSPAWN_TEST_BACKEND", "counting");
+        ExecBackend.launch(cmd)
+    }
+}
+
+#[test]
+fn test_custom_backend() {
+    let handle = spawn((), |()| std::env::var("PROCSPAWN_TEST_BACKEND").ok());
+    assert_eq!(handle.join().unwrap().as_deref(), Some("counting"));
+    assert_eq!(LAUNCHES.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_custom_backend_error() {
+    let handle = procspawn::Builder::new()
+        .env("PROCSPAWN_TEST_BACKEND_FAIL", "1")
+        .spawn((), |()| ());
+    let err = handle.join().unwrap_err();
+    assert!(!err.is_exec_failure());
+    assert_eq!(err.exec_errno(), None);
+}
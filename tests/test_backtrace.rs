@@ -0,0 +1,25 @@
+use procspawn::{self, ProcConfig};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_resolve_backtrace() {
+    ProcConfig::new().resolve_backtraces(false).init();
+
+    let handle =
+        unsafe { procspawn::Builder::new().spawn_forked::<_, ()>((), |()| panic!("unresolved")) };
+    let err = handle.join().unwrap_err();
+    let panic = err.panic_info().unwrap();
+    let backtrace = panic.backtrace().unwrap();
+    assert!(backtrace.frames().iter().all(|x| x.symbols().is_empty()));
+
+    let resolved = panic.resolve_backtrace().unwrap();
+    assert_eq!(resolved.frames().len(), backtrace.frames().len());
+    let names: Vec<String> = resolved
+        .frames()
+        .iter()
+        .flat_map(|frame| frame.symbols())
+        .filter_map(|symbol| symbol.name().map(|name| name.to_string()))
+        .collect();
+    assert!(names.iter().any(|x| x.contains("test_resolve_backtrace")));
+}
@@ -0,0 +1,26 @@
+use procspawn::{self, ProcConfig};
+
+procspawn::enable_test_support!();
+
+fn frame_names(backtrace: &backtrace::Backtrace) -> Vec<String> {
+    backtrace
+        .frames()
+        .iter()
+        .filter_map(|frame| frame.symbols().first()?.name().map(|x| format!("{:#}", x)))
+        .collect()
+}
+
+fn fail((): ()) {
+    panic!("trimmed");
+}
+
+#[test]
+fn test_trim_backtraces() {
+    ProcConfig::new().trim_backtraces(true).init();
+
+    let handle = unsafe { procspawn::Builder::new().spawn_forked::<_, ()>((), fail) };
+    let err = handle.join().unwrap_err();
+    let names = frame_names(err.panic_info().unwrap().backtrace().unwrap());
+    assert!(names[0].ends_with("::fail"), "{:?}", names);
+    assert_eq!(names.len(), 1, "{:?}", names);
+}
@@ -16,7 +16,7 @@ fn test_basic() {
 
 #[test]
 fn test_panic() {
-    let handle = spawn((), |()| panic!("something went wrong"));
+    let handle = spawn::<_, ()>((), |()| panic!("something went wrong"));
     let err = handle.join().unwrap_err();
 
     let panic_info = err.panic_info().unwrap();
@@ -25,7 +25,7 @@ fn test_panic() {
 
     let loc = panic_info.location().unwrap();
     assert_eq!(loc.line(), 19);
-    assert_eq!(loc.column(), 33);
+    assert_eq!(loc.column(), 42);
     assert!(loc.file().contains("test_basic.rs"));
 }
 
@@ -88,3 +88,536 @@ fn test_timeout() {
     let val = handle.join_timeout(Duration::from_secs(2)).unwrap();
     assert_eq!(val, 42);
 }
+
+#[procspawn::testsupport::ctor]
+unsafe fn misbehave_before_bootstrap() {
+    match env::var("PROCSPAWN_TEST_MISBEHAVE").as_deref() {
+        Ok("exit") => {
+            eprintln!("exiting early");
+            std::process::exit(3);
+        }
+        Ok("hang") => {
+            eprintln!("hanging forever");
+            thread::sleep(Duration::from_secs(30));
+        }
+        Ok("orphan") => {
+            // the grandchild keeps stderr open after we exited.
+            std::process::Command::new("sleep").arg("5").spawn().ok();
+            eprintln!("leaving a process behind");
+            std::process::exit(3);
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn test_exit_before_bootstrap() {
+    let err = procspawn::Builder::new()
+        .env("PROCSPAWN_TEST_MISBEHAVE", "exit")
+        .stderr(std::process::Stdio::piped())
+        .spawn((), |()| ())
+        .join()
+        .unwrap_err();
+    assert!(err.is_bootstrap_failure());
+    assert!(err.is_spawn_failure());
+    assert!(err.is_infrastructure());
+    assert_eq!(err.child_output(), Some("exiting early\n"));
+}
+
+#[test]
+fn test_debug_ipc() {
+    let mut handle = procspawn::Builder::new()
+        .env("PROCSPAWN_DEBUG_IPC", "1")
+        .stderr(std::process::Stdio::piped())
+        .spawn(vec![1u8; 100], |data| data.len());
+    let mut stderr = String::new();
+    std::io::Read::read_to_string(handle.stderr().unwrap(), &mut stderr).unwrap();
+    let pid = handle.pid().unwrap();
+    assert_eq!(handle.join().unwrap(), 100);
+    assert!(stderr.contains(&format!(
+        "procspawn ipc: pid={} received procspawn::codec::Coded<alloc::vec::Vec<u8>>",
+        pid
+    )));
+    assert!(stderr.contains("sent procspawn::core::ReturnMessage<usize>"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_exit_before_bootstrap_with_grandchild() {
+    let start = std::time::Instant::now();
+    let err = procspawn::Builder::new()
+        .env("PROCSPAWN_TEST_MISBEHAVE", "orphan")
+        .stderr(std::process::Stdio::piped())
+        .spawn((), |()| ())
+        .join()
+        .unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(4));
+    assert!(err.is_bootstrap_failure());
+    assert_eq!(err.child_output(), Some("leaving a process behind\n"));
+}
+
+#[test]
+fn test_bootstrap_timeout() {
+    let err = procspawn::Builder::new()
+        .env("PROCSPAWN_TEST_MISBEHAVE", "hang")
+        .stderr(std::process::Stdio::piped())
+        .bootstrap_timeout(Duration::from_millis(500))
+        .spawn((), |()| ())
+        .join()
+        .unwrap_err();
+    assert!(err.is_bootstrap_failure());
+    assert_eq!(err.child_output(), Some("hanging forever\n"));
+}
+
+#[test]
+fn test_exit_without_result() {
+    let err = procspawn::Builder::new()
+        .stderr(std::process::Stdio::piped())
+        .spawn::<_, ()>((), |()| {
+            // the test harness captures eprintln!
+            std::io::Write::write_all(&mut std::io::stderr(), b"giving up\n").unwrap();
+            std::process::exit(1);
+        })
+        .join()
+        .unwrap_err();
+    assert!(err.is_remote_close());
+    assert_eq!(err.child_output(), Some("giving up\n"));
+    assert!(err.to_string().ends_with("child output:\ngiving up"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_signal() {
+    let handle = spawn::<_, ()>((), |()| std::process::abort());
+    let location = handle.spawn_location();
+    let pid = handle.pid().unwrap();
+    let err = handle.join().unwrap_err();
+    assert!(err.is_remote_close());
+    assert!(err.is_infrastructure());
+    assert!(!err.is_spawn_failure());
+    assert_eq!(err.signal(), Some(libc::SIGABRT));
+    let msg = err.to_string();
+    assert!(msg.starts_with(&format!(
+        "process spawn error: child was terminated by signal {} (pid {}, ",
+        libc::SIGABRT,
+        pid
+    )));
+    assert!(msg.ends_with(&format!("spawned at {})", location)));
+
+    let err = spawn::<_, ()>((), |()| std::process::exit(1))
+        .join()
+        .unwrap_err();
+    assert_eq!(err.signal(), None);
+}
+
+static FORKED_STATE: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[test]
+fn test_spawn_forked() {
+    // state that only exists in this process shows up in forked children
+    FORKED_STATE.store(23, std::sync::atomic::Ordering::SeqCst);
+    let handle = unsafe {
+        procspawn::Builder::new().spawn_forked(vec![1, 2, 3], |data: Vec<usize>| {
+            data.into_iter().sum::<usize>() + FORKED_STATE.load(std::sync::atomic::Ordering::SeqCst)
+        })
+    };
+    assert!(handle.pid().is_some());
+    assert_eq!(handle.join().unwrap(), 29);
+
+    let handle =
+        unsafe { procspawn::Builder::new().spawn_forked::<_, ()>((), |()| panic!("forked panic")) };
+    let err = handle.join().unwrap_err();
+    assert_eq!(err.panic_info().unwrap().message(), "forked panic");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_as() {
+    let err = procspawn::Builder::new()
+        .run_as("procspawn-no-such-user")
+        .spawn((), |()| ())
+        .join()
+        .unwrap_err();
+    let source = std::error::Error::source(&err).unwrap();
+    assert!(source.to_string().contains("unknown user"));
+
+    // switching to root only works if we are root already
+    let handle = procspawn::Builder::new()
+        .run_as("root")
+        .spawn((), |()| (unsafe { libc::geteuid() }, env::var("USER").ok()));
+    if unsafe { libc::geteuid() } == 0 {
+        assert_eq!(handle.join().unwrap(), (0, Some("root".into())));
+    } else {
+        let err = handle.join().unwrap_err();
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.to_string().contains("requires root privileges"));
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_exec_failure() {
+    let handle = unsafe {
+        procspawn::Builder::new()
+            .pre_exec(|| Err(std::io::Error::from_raw_os_error(libc::EACCES)))
+            .spawn((), |()| ())
+    };
+    let err = handle.join().unwrap_err();
+    assert!(err.is_exec_failure());
+    assert!(err.is_spawn_failure());
+    assert_eq!(err.exec_errno(), Some(libc::EACCES));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stdout_closed() {
+    let mut handle = procspawn::Builder::new()
+        .stdout(std::process::Stdio::piped())
+        .spawn((), |()| {
+            unsafe { libc::close(1) };
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            42
+        });
+    let started = std::time::Instant::now();
+    while !handle.stdout_closed().unwrap() {
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(handle.stderr_closed(), None);
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+#[cfg(unix)]
+fn core_limit() -> (libc::rlim_t, libc::rlim_t) {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) };
+    (limit.rlim_cur, limit.rlim_max)
+}
+
+#[test]
+#[cfg(unix)]
+fn test_core_dumps() {
+    let handle = procspawn::Builder::new()
+        .disable_core_dumps()
+        .spawn((), |()| core_limit());
+    assert_eq!(handle.join().unwrap().0, 0);
+
+    let dir = std::env::temp_dir().canonicalize().unwrap();
+    let handle = procspawn::Builder::new()
+        .core_dump_dir(&dir)
+        .spawn((), |()| (core_limit(), std::env::current_dir().unwrap()));
+    let ((soft, hard), cwd) = handle.join().unwrap();
+    assert_eq!(soft, hard);
+    assert_eq!(cwd, dir);
+}
+
+#[test]
+fn test_panic_thread_name() {
+    let err = spawn::<_, ()>((), |()| {
+        let worker = std::thread::Builder::new()
+            .name("worker".into())
+            .spawn(|| panic!("worker failed"))
+            .unwrap();
+        if let Err(panic) = worker.join() {
+            std::panic::resume_unwind(panic);
+        }
+    })
+    .join()
+    .unwrap_err();
+    let panic = err.panic_info().unwrap();
+    assert_eq!(panic.message(), "worker failed");
+    assert_eq!(panic.thread_name(), Some("worker"));
+    assert!(panic.location().unwrap().file().ends_with("test_basic.rs"));
+}
+
+#[test]
+fn test_spawn_location() {
+    let line = line!() + 1;
+    let mut handle = spawn((), |()| std::thread::sleep(Duration::from_secs(10)));
+    assert_eq!(handle.spawn_location().file(), file!());
+    assert_eq!(handle.spawn_location().line(), line);
+
+    let err = handle.join_timeout(Duration::from_millis(100)).unwrap_err();
+    assert!(err.is_timeout());
+    assert_eq!(err.spawn_location(), Some(handle.spawn_location()));
+    assert!(err
+        .to_string()
+        .ends_with(&format!("spawned at {})", handle.spawn_location())));
+    handle.kill().unwrap();
+
+    let pool = procspawn::Pool::new(1).unwrap();
+    let line = line!() + 1;
+    let handle = pool.spawn::<_, ()>((), |()| panic!("pooled"));
+    let err = handle.join().unwrap_err();
+    assert_eq!(err.spawn_location().unwrap().line(), line);
+}
+
+#[test]
+fn test_error_context() {
+    fn fail((): ()) {
+        panic!("context");
+    }
+
+    let handle = spawn((), fail);
+    let pid = handle.pid();
+    let err = handle.join().unwrap_err();
+    assert!(!err.is_infrastructure());
+    assert_eq!(err.pid(), pid);
+    assert_eq!(
+        err.executable(),
+        Some(&*std::fs::canonicalize(std::env::current_exe().unwrap()).unwrap())
+    );
+    if let Some(function) = err.function() {
+        assert!(function.ends_with("test_error_context::fail"));
+        assert!(err.to_string().contains(&format!("function {}", function)));
+    }
+    assert!(err
+        .to_string()
+        .contains(&format!("(pid {}, ", pid.unwrap())));
+
+    let pool = procspawn::Pool::new(1).unwrap();
+    let handle = pool.spawn::<_, ()>((), |()| panic!("pooled"));
+    let err = handle.join().unwrap_err();
+    let pid = err.pid().unwrap();
+    assert!(err.to_string().contains(&format!("(pid {}, ", pid)));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_spawn_detached() {
+    let path = std::env::temp_dir().join(format!("procspawn-detached-{}", std::process::id()));
+    let child = procspawn::Builder::new()
+        .spawn_detached(path.clone(), |path: std::path::PathBuf| {
+            let info =
+                unsafe { format!("{} {} {}", libc::getpid(), libc::getsid(0), libc::getppid()) };
+            std::fs::write(path.with_extension("tmp"), info).unwrap();
+            std::fs::rename(path.with_extension("tmp"), path).unwrap();
+        })
+        .unwrap();
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while !path.exists() && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let info = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let info: Vec<u32> = info.split(' ').map(|x| x.parse().unwrap()).collect();
+    assert_eq!(info[0], child.pid());
+    // the child runs in a session of its own and was handed over to
+    // another parent.
+    assert_ne!(info[1], unsafe { libc::getsid(0) } as u32);
+    assert_ne!(info[2], std::process::id());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_reattach() {
+    // the process that spawns the job goes away without joining it.
+    let token = spawn((), |()| {
+        let handle = procspawn::Builder::new()
+            .reattachable(Duration::from_secs(10))
+            .spawn((), |()| {
+                std::thread::sleep(Duration::from_millis(500));
+                42
+            });
+        let token = handle.reattach_token().unwrap().clone();
+        handle.detach();
+        token
+    })
+    .join()
+    .unwrap();
+
+    let handle = procspawn::JoinHandle::<i32>::reattach(&token);
+    assert_eq!(handle.pid(), Some(token.pid()));
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_reattach_private() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut handle = procspawn::Builder::new()
+        .reattachable(Duration::from_secs(1))
+        .spawn((), |()| std::thread::sleep(Duration::from_secs(10)));
+    let token = handle.reattach_token().unwrap().clone();
+    let dir = token.path().parent().unwrap();
+    let mode = fs::symlink_metadata(dir).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+    while !token.path().exists() {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // a vanished announcement is only waited for as long as the timeout
+    fs::remove_file(token.path()).unwrap();
+    let start = std::time::Instant::now();
+    let err = procspawn::JoinHandle::<()>::reattach(&token)
+        .join()
+        .unwrap_err();
+    assert!(err.is_timeout());
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // nor are announcements trusted that others could have written
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+    let err = procspawn::JoinHandle::<()>::reattach(&token)
+        .join()
+        .unwrap_err();
+    let source = std::error::Error::source(&err).unwrap();
+    let source = source.downcast_ref::<std::io::Error>().unwrap();
+    assert_eq!(source.kind(), std::io::ErrorKind::PermissionDenied);
+
+    handle.kill().unwrap();
+    fs::remove_dir(dir).ok();
+}
+
+#[test]
+fn test_as_child() {
+    let mut handle = procspawn::Builder::new()
+        .stdin(std::process::Stdio::piped())
+        .spawn((), |()| 42);
+    let pid = handle.pid().unwrap();
+    assert_eq!(handle.as_child().unwrap().id(), pid);
+    assert!(handle.as_child_mut().unwrap().stdin.is_some());
+    assert_eq!(handle.join().unwrap(), 42);
+
+    let pool = procspawn::Pool::new(1).unwrap();
+    let handle = pool.spawn((), |()| 42);
+    assert!(handle.as_child().is_none());
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+#[test]
+#[ignore]
+fn test_forward_output_helper() {
+    procspawn::testing::forward_output(true);
+    spawn((), |()| {
+        println!("stdout of the child");
+        eprintln!("stderr of the child");
+    })
+    .join()
+    .unwrap();
+    panic!("failing on purpose");
+}
+
+#[test]
+fn test_forward_output() {
+    let output = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["test_forward_output_helper", "--exact", "--ignored"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("stdout of the child"));
+    assert!(stdout.contains("stderr of the child"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_leaked_children_are_killed() {
+    let pid = spawn((), |()| {
+        let handle = spawn((), |()| thread::sleep(Duration::from_secs(30)));
+        let pid = handle.pid().unwrap();
+        std::mem::forget(handle);
+        pid
+    })
+    .join()
+    .unwrap();
+    // the intermediate process killed its child when it exited.
+    assert_ne!(unsafe { libc::kill(pid as libc::pid_t, 0) }, 0);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_wait_for_debugger() {
+    let handle = procspawn::Builder::new()
+        .wait_for_debugger(true)
+        .spawn(21, |x: u32| x * 2);
+    let pid = handle.pid().unwrap() as libc::pid_t;
+    let mut status = 0;
+    assert_eq!(
+        unsafe { libc::waitpid(pid, &mut status, libc::WUNTRACED) },
+        pid
+    );
+    assert!(libc::WIFSTOPPED(status));
+    unsafe {
+        libc::kill(pid, libc::SIGCONT);
+    }
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+#[test]
+fn test_env_policy() {
+    use procspawn::EnvPolicy;
+
+    let vars = procspawn::Builder::new()
+        .envs([("SECRET_TOKEN", "1"), ("SECRET_KEY", "2"), ("PUBLIC", "3")])
+        .env_policy(EnvPolicy::Denylist(vec!["SECRET_*".into()]))
+        .env("SECRET_AFTERWARDS", "4")
+        .spawn((), |()| {
+            ["SECRET_TOKEN", "SECRET_KEY", "PUBLIC", "SECRET_AFTERWARDS"]
+                .iter()
+                .map(|x| env::var(x).ok())
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+    assert_eq!(vars, vec![None, None, Some("3".into()), Some("4".into())]);
+
+    let policy = EnvPolicy::Allowlist(vec!["PATH".into(), "LC_*".into()]);
+    assert!(policy.inherits("PATH"));
+    assert!(policy.inherits("LC_ALL"));
+    assert!(!policy.inherits("PATHS"));
+    assert!(!EnvPolicy::InheritNone.inherits("PATH"));
+}
+
+#[test]
+fn test_env_inherited_at_spawn() {
+    let mut builder = procspawn::Builder::new();
+    env::set_var("PROCSPAWN_LATE_VARIABLE", "late");
+    let val = builder
+        .spawn((), |()| env::var("PROCSPAWN_LATE_VARIABLE").ok())
+        .join()
+        .unwrap();
+    assert_eq!(val.as_deref(), Some("late"));
+}
+
+#[test]
+fn test_spawn_many() {
+    let handles = procspawn::Builder::new()
+        .env("PROCSPAWN_BATCH", "42")
+        .spawn_many(0..5, |x: u32| {
+            x + env::var("PROCSPAWN_BATCH").unwrap().parse::<u32>().unwrap()
+        });
+    let results: Vec<u32> = handles.into_iter().map(|x| x.join().unwrap()).collect();
+    assert_eq!(results, vec![42, 43, 44, 45, 46]);
+
+    let line = line!() + 1;
+    let handles = procspawn::Builder::new().spawn_many(0..2, |x: u32| x);
+    for handle in handles {
+        assert_eq!(handle.spawn_location().file(), file!());
+        assert_eq!(handle.spawn_location().line(), line);
+        handle.join().unwrap();
+    }
+
+    // standard streams cannot be shared by the processes of a batch
+    let handles = procspawn::Builder::new()
+        .stdout(std::process::Stdio::piped())
+        .spawn_many(0..2, |x: u32| x);
+    assert_eq!(handles.len(), 2);
+    for handle in handles {
+        let err = handle.join().unwrap_err();
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.to_string().contains("standard streams"));
+    }
+}
+
+#[test]
+fn test_refresh_shared_libraries() {
+    let handle = spawn((), |()| 1);
+    procspawn::refresh_shared_libraries();
+    let other = spawn((), |()| 2);
+    assert_eq!(handle.join().unwrap() + other.join().unwrap(), 3);
+}
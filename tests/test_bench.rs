@@ -0,0 +1,33 @@
+use procspawn::bench::{measure_spawn_overhead, BenchConfig};
+use procspawn::{self, codec};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_measure_spawn_overhead() {
+    let overhead = measure_spawn_overhead(
+        BenchConfig::new()
+            .iterations(3)
+            .builder(|builder| {
+                builder.codec::<codec::Bincode>();
+            })
+            .pool_builder(|builder| {
+                builder.disable_stdout();
+            }),
+    )
+    .unwrap();
+
+    for stats in &[
+        overhead.exec(),
+        overhead.handshake(),
+        overhead.round_trip(),
+        overhead.pool_dispatch(),
+    ] {
+        assert_eq!(stats.samples(), 3);
+        assert!(stats.min() <= stats.median());
+        assert!(stats.median() <= stats.max());
+        assert!(stats.min() <= stats.mean() && stats.mean() <= stats.max());
+    }
+    assert!(overhead.round_trip().min() > overhead.handshake().min());
+    assert!(overhead.to_string().contains("pool dispatch:"));
+}
@@ -0,0 +1,59 @@
+use std::thread;
+
+use procspawn::call::{Call, VERSION};
+use procspawn::{self, Pool};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_execute_in_spawned_process() {
+    let (call, args) = Call::new(|(a, b): (u32, u32)| a + b);
+    assert_eq!(call.version(), VERSION);
+    let handle = procspawn::spawn(call, |call| unsafe { call.execute() }.unwrap());
+    let rx = args.send((1, 2)).unwrap();
+    assert_eq!(rx.recv().unwrap(), 3);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_execute_in_pool() {
+    let pool = Pool::new(2).unwrap();
+    let calls: Vec<_> = (0..4u64)
+        .map(|x| {
+            let (call, args) = Call::new(|x: u64| x * x);
+            let handle = pool.spawn(call, |call| unsafe { call.execute() }.unwrap());
+            (handle, args.send(x).unwrap())
+        })
+        .collect();
+    let results: Vec<_> = calls
+        .into_iter()
+        .map(|(handle, rx)| {
+            let rv = rx.recv().unwrap();
+            handle.join().unwrap();
+            rv
+        })
+        .collect();
+    assert_eq!(results, vec![0, 1, 4, 9]);
+}
+
+#[test]
+fn test_panic() {
+    let (call, args) = Call::new::<(), ()>(|()| panic!("call panic"));
+    procspawn::spawn(call, |call| unsafe { call.execute() }.unwrap());
+    let err = args.send(()).unwrap().recv().unwrap_err();
+    assert_eq!(err.panic_info().unwrap().message(), "call panic");
+}
+
+#[test]
+fn test_dropped_call() {
+    let (call, args) = Call::new(|x: u32| x);
+    let rx = thread::spawn(move || {
+        drop(call);
+        args.send(1)
+    })
+    .join()
+    .unwrap();
+    if let Ok(rx) = rx {
+        assert!(rx.recv().is_err());
+    }
+}
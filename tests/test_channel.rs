@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use procspawn::{self, spawn, Builder, Pool};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_channel_both_directions() {
+    let (requests_tx, requests_rx) = procspawn::channel::<u32>().unwrap();
+    let (responses_tx, responses_rx) = procspawn::channel::<u32>().unwrap();
+    let handle = spawn((requests_rx, responses_tx), |(rx, tx)| {
+        let mut count = 0;
+        while let Ok(x) = rx.recv() {
+            tx.send(x * 2).unwrap();
+            count += 1;
+        }
+        count
+    });
+
+    for x in 0..3 {
+        requests_tx.send(x).unwrap();
+        assert_eq!(responses_rx.recv().unwrap(), x * 2);
+    }
+    drop(requests_tx);
+    assert_eq!(handle.join().unwrap(), 3);
+    assert!(responses_rx.recv().unwrap_err().is_remote_close());
+}
+
+#[test]
+fn test_channel_try_recv() {
+    let (tx, rx) = procspawn::channel::<String>().unwrap();
+    assert!(rx.try_recv().unwrap().is_none());
+    assert!(rx
+        .recv_timeout(Duration::from_millis(10))
+        .unwrap_err()
+        .is_timeout());
+
+    let handle = spawn(tx, |tx| tx.send("hello".into()).unwrap());
+    handle.join().unwrap();
+    assert_eq!(rx.try_recv().unwrap().as_deref(), Some("hello"));
+}
+
+#[test]
+fn test_parent() {
+    let mut handle = Builder::new().with_parent_channel().spawn(3, |n: u32| {
+        let parent = procspawn::parent::<String>().unwrap();
+        for x in 0..n {
+            parent.send(format!("event {}", x)).unwrap();
+        }
+        n
+    });
+    let messages = handle.child_receiver::<String>().unwrap();
+    assert!(handle.child_receiver::<String>().is_none());
+    assert_eq!(handle.join().unwrap(), 3);
+
+    let mut received = Vec::new();
+    while let Ok(message) = messages.recv() {
+        received.push(message);
+    }
+    assert_eq!(received, vec!["event 0", "event 1", "event 2"]);
+}
+
+#[test]
+fn test_parent_disabled() {
+    let mut handle = spawn((), |()| procspawn::parent::<u32>().is_none());
+    assert!(handle.child_receiver::<u32>().is_none());
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn test_parent_pool() {
+    let pool = Pool::builder(1).with_parent_channel().build().unwrap();
+    for x in 0..2u32 {
+        let mut handle = pool.spawn(x, |x| {
+            procspawn::parent::<u32>().unwrap().send(x * 10).unwrap();
+        });
+        let messages = handle.child_receiver::<u32>().unwrap();
+        assert_eq!(messages.recv().unwrap(), x * 10);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+use procspawn::{self, Pool, ProcConfig};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_chunked_transfer() {
+    ProcConfig::new().chunk_threshold(1024).init();
+
+    let data = (0..1_000_000u32).map(|x| x as u8).collect::<Vec<u8>>();
+    let handle = procspawn::spawn(data.clone(), |data: Vec<u8>| {
+        data.iter().map(|&x| x as u64).sum::<u64>()
+    });
+    assert_eq!(
+        handle.join().unwrap(),
+        data.iter().map(|&x| x as u64).sum::<u64>()
+    );
+
+    // the child uses the default threshold
+    let handle = procspawn::spawn(20_000_000, |len: usize| vec![42u8; len]);
+    let rv = handle.join().unwrap();
+    assert_eq!(rv.len(), 20_000_000);
+    assert!(rv.iter().all(|&x| x == 42));
+
+    let pool = Pool::new(1).unwrap();
+    let handle = pool.spawn(data.clone(), |data: Vec<u8>| data.len());
+    assert_eq!(handle.join().unwrap(), data.len());
+
+    // small values can still carry IPC channels
+    let (tx, rx) = ipc_channel::ipc::channel::<u32>().unwrap();
+    let handle = procspawn::spawn(tx, |tx| tx.send(42).unwrap());
+    handle.join().unwrap();
+    assert_eq!(rx.recv().unwrap(), 42);
+}
@@ -0,0 +1,145 @@
+use procspawn::codec::{self, Codec};
+use procspawn::serde::Shmem;
+use procspawn::{self, Builder, Pool};
+use serde::{Deserialize, Serialize};
+
+procspawn::enable_test_support!();
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Inner {
+    value: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Flattened {
+    name: String,
+    #[serde(flatten)]
+    inner: Inner,
+}
+
+fn roundtrip<C: Codec + Default>() {
+    let handle = Builder::new().codec::<C>().spawn(
+        (vec![1u32, 2, 3], Some("hello".to_string())),
+        |(values, greeting)| (values.into_iter().sum::<u32>(), greeting),
+    );
+    assert_eq!(handle.join().unwrap(), (6, Some("hello".into())));
+}
+
+#[test]
+fn test_bincode() {
+    roundtrip::<codec::Bincode>();
+}
+
+#[test]
+fn test_json() {
+    roundtrip::<codec::Json>();
+
+    // bincode cannot deal with flattening
+    let handle = Builder::new().codec::<codec::Json>().spawn(
+        Flattened {
+            name: "answer".into(),
+            inner: Inner { value: 41 },
+        },
+        |mut value| {
+            value.inner.value += 1;
+            value
+        },
+    );
+    assert_eq!(
+        handle.join().unwrap(),
+        Flattened {
+            name: "answer".into(),
+            inner: Inner { value: 42 },
+        }
+    );
+}
+
+#[test]
+fn test_msgpack() {
+    roundtrip::<codec::MessagePack>();
+}
+
+#[test]
+fn test_cbor() {
+    roundtrip::<codec::Cbor>();
+}
+
+#[test]
+fn test_postcard() {
+    roundtrip::<codec::Postcard>();
+}
+
+#[test]
+fn test_postcard_wrapper() {
+    use procspawn::serde::Postcard;
+
+    let handle = procspawn::spawn(Postcard(vec![1u64, 2, 3]), |Postcard(values)| {
+        Postcard(values.into_iter().map(|x| x * 2).collect::<Vec<_>>())
+    });
+    assert_eq!(handle.join().unwrap().0, vec![2, 4, 6]);
+}
+
+#[test]
+fn test_structural_wrapper() {
+    use procspawn::serde::Structural;
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Untagged {
+        Number(u64),
+        Text(String),
+    }
+
+    let handle = procspawn::spawn(
+        Structural(Flattened {
+            name: "answer".into(),
+            inner: Inner { value: 42 },
+        }),
+        |Structural(value)| {
+            let mut map = HashMap::new();
+            map.insert(1u32, Untagged::Number(value.inner.value));
+            map.insert(2u32, Untagged::Text(value.name));
+            Structural(map)
+        },
+    );
+    let map = handle.join().unwrap().0;
+    assert_eq!(map[&1], Untagged::Number(42));
+    assert_eq!(map[&2], Untagged::Text("answer".into()));
+}
+
+#[test]
+fn test_panic() {
+    let handle = Builder::new()
+        .codec::<codec::Json>()
+        .spawn::<_, ()>((), |()| panic!("codec panic"));
+    let err = handle.join().unwrap_err();
+    assert_eq!(err.panic_info().unwrap().message(), "codec panic");
+}
+
+#[test]
+fn test_shmem() {
+    // codecs cannot carry shared memory so the bytes are sent instead
+    let handle = Builder::new()
+        .codec::<codec::Bincode>()
+        .spawn(Shmem::from_bytes(b"abc"), |shmem| shmem.to_vec());
+    assert_eq!(handle.join().unwrap(), b"abc");
+}
+
+#[test]
+fn test_pool() {
+    let pool = Pool::builder(2).codec::<codec::Json>().build().unwrap();
+    let handles: Vec<_> = (0..4u64)
+        .map(|value| {
+            pool.spawn(
+                Flattened {
+                    name: "x".into(),
+                    inner: Inner { value },
+                },
+                |value| value.inner.value * 2,
+            )
+        })
+        .collect();
+    let results: Vec<_> = handles.into_iter().map(|x| x.join().unwrap()).collect();
+    assert_eq!(results, vec![0, 2, 4, 6]);
+}
@@ -0,0 +1,59 @@
+use procspawn::codec::{Compression, Json};
+use procspawn::{self, Builder, Pool};
+
+procspawn::enable_test_support!();
+
+fn roundtrip(compression: Compression) {
+    let data = "hello world ".repeat(100_000);
+    let handle = Builder::new()
+        .compress(compression, 1024)
+        .spawn(data.clone(), |data: String| data.to_uppercase());
+    assert_eq!(handle.join().unwrap(), data.to_uppercase());
+}
+
+#[test]
+fn test_lz4() {
+    roundtrip(Compression::Lz4);
+}
+
+#[test]
+fn test_zstd() {
+    roundtrip(Compression::Zstd(3));
+}
+
+#[test]
+fn test_small_payload() {
+    let handle = Builder::new()
+        .compress(Compression::Lz4, 1024)
+        .spawn((1, 2), |(a, b): (i32, i32)| a + b);
+    assert_eq!(handle.join().unwrap(), 3);
+}
+
+#[test]
+fn test_with_codec() {
+    let handle = Builder::new()
+        .codec::<Json>()
+        .compress(Compression::Zstd(1), 0)
+        .spawn(vec![42u64; 10_000], |values: Vec<u64>| values.len());
+    assert_eq!(handle.join().unwrap(), 10_000);
+}
+
+#[test]
+fn test_ipc_channel() {
+    let (tx, rx) = ipc_channel::ipc::channel::<u32>().unwrap();
+    let handle = Builder::new()
+        .compress(Compression::Lz4, 1024)
+        .spawn(rx, |rx| rx.recv().unwrap());
+    tx.send(42).unwrap();
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+#[test]
+fn test_pool() {
+    let pool = Pool::builder(1)
+        .compress(Compression::Lz4, 1024)
+        .build()
+        .unwrap();
+    let handle = pool.spawn(vec![7u8; 100_000], |values: Vec<u8>| values);
+    assert_eq!(handle.join().unwrap(), vec![7u8; 100_000]);
+}
@@ -0,0 +1,41 @@
+use std::env;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use procspawn::spawn;
+
+procspawn::enable_test_support!();
+
+fn enable() {
+    env::set_var("PROCSPAWN_COVERAGE", "1");
+    env::set_var("LLVM_PROFILE_FILE", "/tmp/procspawn-coverage.profraw");
+}
+
+#[test]
+fn test_profile_file() {
+    enable();
+    let handle = spawn((), |()| env::var("LLVM_PROFILE_FILE").unwrap());
+    assert_eq!(handle.join().unwrap(), "/tmp/procspawn-coverage-%p.profraw");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_kill_runs_exit_handlers() {
+    use std::os::unix::process::ExitStatusExt;
+
+    enable();
+    let marker = env::temp_dir().join(format!("procspawn-coverage-{}", std::process::id()));
+    let mut handle = spawn(marker.clone(), |marker: PathBuf| {
+        std::fs::write(marker, "").unwrap();
+        thread::sleep(Duration::from_secs(10));
+    });
+    while !marker.exists() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    std::fs::remove_file(&marker).ok();
+    handle.kill().unwrap();
+    let status = handle.wait().unwrap();
+    assert_eq!(status.signal(), None);
+    assert_eq!(status.code(), Some(128 + libc::SIGTERM));
+}
@@ -0,0 +1,76 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use procspawn::{self, Deadline, Pool};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_extend_from_other_thread() {
+    let deadline = Deadline::after(Duration::from_millis(200));
+    let mut handle = procspawn::spawn((), |()| {
+        thread::sleep(Duration::from_millis(600));
+        42
+    });
+    let extender = thread::spawn({
+        let deadline = deadline.clone();
+        move || deadline.extend(Duration::from_secs(10))
+    });
+    extender.join().unwrap();
+    assert_eq!(handle.join_deadline(&deadline).unwrap(), 42);
+}
+
+#[test]
+fn test_shorten_while_joining() {
+    let deadline = Deadline::after(Duration::from_secs(30));
+    let mut handle = procspawn::spawn((), |()| {
+        thread::sleep(Duration::from_secs(30));
+    });
+    let shortener = thread::spawn({
+        let deadline = deadline.clone();
+        move || {
+            thread::sleep(Duration::from_millis(100));
+            deadline.reset(Duration::ZERO);
+        }
+    });
+    let started = Instant::now();
+    let err = handle.join_deadline(&deadline).unwrap_err();
+    assert!(err.is_timeout());
+    assert!(started.elapsed() < Duration::from_secs(10));
+    shortener.join().unwrap();
+    handle.kill().unwrap();
+}
+
+#[test]
+fn test_pooled_deadline() {
+    let pool = Pool::new(2).unwrap();
+    let deadline = Deadline::after(Duration::from_secs(30));
+    let mut handle = pool.spawn((), |()| {
+        thread::sleep(Duration::from_secs(30));
+    });
+    while handle.pid().is_none() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    deadline.shorten(Duration::from_secs(30));
+    assert!(deadline.has_passed());
+    let err = handle.join_deadline(&deadline).unwrap_err();
+    assert!(err.is_timeout());
+
+    let mut handle = pool.spawn(21, |x: u32| x * 2);
+    assert_eq!(handle.join_timeout(Duration::from_secs(2)).unwrap(), 42);
+}
+
+#[test]
+fn test_deadline_arithmetic() {
+    let deadline = Deadline::never();
+    assert_eq!(deadline.remaining(), None);
+    deadline.shorten(Duration::from_secs(1));
+    assert!(!deadline.has_passed());
+
+    let instant = Instant::now() + Duration::from_secs(60);
+    let deadline = Deadline::at(instant);
+    deadline.extend(Duration::from_secs(1));
+    assert_eq!(deadline.instant(), Some(instant + Duration::from_secs(1)));
+    deadline.set(instant);
+    assert_eq!(deadline.clone().instant(), Some(instant));
+}
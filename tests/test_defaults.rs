@@ -0,0 +1,40 @@
+use std::env;
+
+use procspawn::{self, Builder, Pool, ProcConfig};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_builder_defaults() {
+    env::set_var("PROCSPAWN_SECRET", "hunter2");
+    ProcConfig::new()
+        .builder_defaults(|builder| {
+            builder
+                .env("PROCSPAWN_DEFAULT", "default")
+                .env_remove("PROCSPAWN_SECRET");
+        })
+        .init();
+
+    let read_env = |()| {
+        (
+            env::var("PROCSPAWN_DEFAULT").ok(),
+            env::var("PROCSPAWN_SECRET").ok(),
+        )
+    };
+
+    let handle = procspawn::spawn((), read_env);
+    assert_eq!(handle.join().unwrap(), (Some("default".into()), None));
+
+    // options of the builder override the defaults.
+    let handle = Builder::new()
+        .env("PROCSPAWN_DEFAULT", "overridden")
+        .spawn((), read_env);
+    assert_eq!(handle.join().unwrap(), (Some("overridden".into()), None));
+
+    let handle = Builder::default().spawn((), read_env);
+    assert_eq!(handle.join().unwrap(), (Some("default".into()), None));
+
+    let pool = Pool::new(1).unwrap();
+    let handle = pool.spawn((), read_env);
+    assert_eq!(handle.join().unwrap(), (Some("default".into()), None));
+}
@@ -0,0 +1,98 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use procspawn::{self, Builder};
+
+procspawn::enable_test_support!();
+
+#[procspawn::testsupport::ctor]
+unsafe fn hang_before_bootstrap() {
+    if env::var("PROCSPAWN_TEST_HANG").is_ok() {
+        thread::sleep(Duration::from_secs(30));
+    }
+}
+
+fn make_endpoint_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("procspawn-{}-{}", name, process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn count_entries(dir: &Path) -> usize {
+    fs::read_dir(dir).unwrap().count()
+}
+
+/// Spawns a child that starts bootstrapping a grandchild (which never
+/// connects) and then terminates while the endpoint is still live.
+fn leave_endpoint_behind(dir: &Path, crash: bool) {
+    let handle = Builder::new()
+        .env("TMPDIR", dir)
+        .spawn::<_, ()>(crash, |crash| {
+            thread::spawn(|| {
+                Builder::new()
+                    .env("PROCSPAWN_TEST_HANG", "1")
+                    .bootstrap_timeout(Duration::from_secs(5))
+                    .spawn((), |()| ())
+                    .join()
+                    .ok();
+            });
+            thread::sleep(Duration::from_millis(500));
+            if crash {
+                process::abort();
+            } else {
+                process::exit(1);
+            }
+        });
+    assert!(handle.join().is_err());
+}
+
+#[test]
+fn test_crash_leaves_stale_endpoint() {
+    let dir = make_endpoint_dir("crash");
+    leave_endpoint_behind(&dir, true);
+    assert_eq!(count_entries(&dir), 1);
+
+    // too young to be considered stale
+    let removed = procspawn::cleanup_stale_endpoints(&dir, Duration::from_secs(3600)).unwrap();
+    assert_eq!(removed, 0);
+    assert_eq!(count_entries(&dir), 1);
+
+    // the crashed child might still be tearing down its listener
+    let mut removed = 0;
+    for _ in 0..50 {
+        removed = procspawn::cleanup_stale_endpoints(&dir, Duration::ZERO).unwrap();
+        if removed > 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(removed, 1);
+    assert_eq!(count_entries(&dir), 0);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_exit_removes_endpoint() {
+    let dir = make_endpoint_dir("exit");
+    leave_endpoint_behind(&dir, false);
+    assert_eq!(count_entries(&dir), 0);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_cleanup_ignores_unrelated_files() {
+    let dir = make_endpoint_dir("unrelated");
+    fs::create_dir(dir.join(".tmpunrelated")).unwrap();
+    fs::write(dir.join(".tmpunrelated").join("socket"), b"not a socket").unwrap();
+    fs::write(dir.join("socket"), b"not a socket").unwrap();
+
+    let removed = procspawn::cleanup_stale_endpoints(&dir, Duration::ZERO).unwrap();
+    assert_eq!(removed, 0);
+    assert_eq!(count_entries(&dir), 2);
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,33 @@
+use std::thread;
+use std::time::Duration;
+
+use procspawn::testing::{self, Fault};
+use procspawn::{self, Builder};
+
+procspawn::enable_test_support!();
+
+// faults apply to all spawns so they are tested in sequence.
+#[test]
+fn test_faults() {
+    testing::inject(Fault::KillAfter(100));
+    let handle = procspawn::spawn((), |()| {
+        thread::sleep(Duration::from_secs(10));
+    });
+    assert!(handle.join().unwrap_err().is_remote_close());
+    testing::clear();
+
+    testing::inject(Fault::SerializationFailure);
+    let handle = procspawn::spawn((), |()| 42);
+    assert!(handle.join().unwrap_err().is_remote_close());
+    testing::clear();
+
+    testing::inject(Fault::DelayBootstrap(2000));
+    let handle = Builder::new()
+        .bootstrap_timeout(Duration::from_millis(200))
+        .spawn((), |()| 42);
+    assert!(handle.join().unwrap_err().is_bootstrap_failure());
+    testing::clear();
+
+    let handle = procspawn::spawn((), |()| 42);
+    assert_eq!(handle.join().unwrap(), 42);
+}
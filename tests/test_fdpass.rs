@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::OwnedFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use procspawn::serde::{IpcFd, IpcFile, IpcTcpListener, IpcTcpStream, IpcUnixListener};
+use procspawn::{self, codec, Builder, Pool};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_pass_socket() {
+    let (mut read, write) = UnixStream::pair().unwrap();
+    let handle = procspawn::spawn(IpcFd(OwnedFd::from(write)), |IpcFd(fd)| {
+        let mut stream = UnixStream::from(fd);
+        stream.write_all(b"hello from the child").unwrap();
+    });
+    handle.join().unwrap();
+
+    let mut buf = [0; 20];
+    read.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello from the child");
+}
+
+#[test]
+fn test_pass_file() {
+    let path = std::env::temp_dir().join(format!("procspawn-fdpass-{}", std::process::id()));
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let handle = procspawn::spawn(IpcFile(file.try_clone().unwrap()), |IpcFile(mut file)| {
+        file.write_all(b"written by the child").unwrap();
+    });
+    handle.join().unwrap();
+
+    let mut file = file;
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "written by the child");
+}
+
+#[test]
+fn test_return_fd() {
+    let handle = procspawn::spawn((), |()| {
+        let (read, mut write) = UnixStream::pair().unwrap();
+        write.write_all(b"buffered").unwrap();
+        IpcFd(OwnedFd::from(read))
+    });
+    let IpcFd(fd) = handle.join().unwrap();
+    let mut stream = UnixStream::from(fd);
+    let mut buf = [0; 8];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"buffered");
+}
+
+#[test]
+fn test_pass_tcp_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = procspawn::spawn(IpcTcpListener(listener), |IpcTcpListener(listener)| {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"accepted").unwrap();
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut buf = [0; 8];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"accepted");
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_delegate_tcp_stream() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+
+    let handle = procspawn::spawn(IpcTcpStream(stream), |IpcTcpStream(mut stream)| {
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).unwrap();
+        stream.write_all(&buf).unwrap();
+    });
+    client.write_all(b"ping").unwrap();
+    handle.join().unwrap();
+
+    let mut buf = [0; 4];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[test]
+fn test_pass_unix_listener() {
+    let dir = std::env::temp_dir().join(format!("procspawn-fdpass-unix-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("socket");
+    std::fs::remove_file(&path).ok();
+    let listener = UnixListener::bind(&path).unwrap();
+
+    let handle = procspawn::spawn(IpcUnixListener(listener), |IpcUnixListener(listener)| {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"accepted").unwrap();
+    });
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    let mut buf = [0; 8];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"accepted");
+    handle.join().unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_pool() {
+    let pool = Pool::new(2).unwrap();
+    let handles: Vec<_> = (0..4u8)
+        .map(|idx| {
+            let (read, write) = UnixStream::pair().unwrap();
+            let handle = pool.spawn((IpcFd(OwnedFd::from(write)), idx), |(IpcFd(fd), idx)| {
+                UnixStream::from(fd).write_all(&[idx]).unwrap();
+            });
+            (handle, read)
+        })
+        .collect();
+    for (idx, (handle, mut read)) in handles.into_iter().enumerate() {
+        handle.join().unwrap();
+        let mut buf = [0];
+        read.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[0], idx as u8);
+    }
+}
+
+#[test]
+fn test_codec_refuses() {
+    let (_read, write) = UnixStream::pair().unwrap();
+    let err = Builder::new()
+        .codec::<codec::Bincode>()
+        .spawn(IpcFd(OwnedFd::from(write)), |_| ())
+        .join()
+        .unwrap_err();
+    let source = std::error::Error::source(&err).unwrap().to_string();
+    assert!(source.contains("file descriptors can only be sent"));
+}
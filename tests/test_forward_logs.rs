@@ -0,0 +1,85 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use procspawn::{self, ProcConfig};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+procspawn::enable_test_support!();
+
+static EVENTS: Mutex<Vec<(Level, String, Vec<String>)>> = Mutex::new(Vec::new());
+
+struct FieldVisitor(Vec<String>);
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push(format!("{}={:?}", field.name(), value));
+    }
+}
+
+/// Records all events of this process.
+struct Recorder;
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut visitor = FieldVisitor(Vec::new());
+        event.record(&mut visitor);
+        EVENTS.lock().unwrap().push((
+            *event.metadata().level(),
+            event.metadata().target().to_string(),
+            visitor.0,
+        ));
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn test_forward_logs() {
+    tracing::subscriber::set_global_default(Recorder).unwrap();
+    ProcConfig::new().forward_logs().init();
+
+    let handle = procspawn::spawn((), |()| {
+        tracing::warn!(answer = 42, "hello from the child");
+        std::process::id()
+    });
+    let pid = handle.join().unwrap();
+
+    // the records are emitted by a background thread.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let event = loop {
+        let event = EVENTS
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, target, _)| target == "procspawn::child")
+            .cloned();
+        match event {
+            Some(event) => break event,
+            None if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            None => panic!("no event was forwarded"),
+        }
+    };
+    let (level, _, fields) = event;
+    assert_eq!(level, Level::WARN);
+    assert!(fields.contains(&"message=hello from the child answer=42".to_string()));
+    assert!(fields.contains(&format!("pid={}", pid)));
+    assert!(fields.contains(&"child.target=test_forward_logs".to_string()));
+}
@@ -0,0 +1,34 @@
+use std::env;
+use std::process;
+
+use procspawn::spawn;
+
+procspawn::enable_test_support!();
+
+fn enable() {
+    env::set_var("PROCSPAWN_IN_PROCESS", "1");
+}
+
+#[test]
+fn test_in_process() {
+    enable();
+    let handle = spawn((1, 2), |(a, b): (u32, u32)| (a + b, process::id()));
+    assert_eq!(handle.pid(), None);
+    assert_eq!(handle.join().unwrap(), (3, process::id()));
+}
+
+#[test]
+fn test_in_process_panic() {
+    enable();
+    let handle = spawn::<_, ()>((), |()| panic!("failed in process"));
+    let err = handle.join().unwrap_err();
+    assert_eq!(err.panic_info().unwrap().message(), "failed in process");
+}
+
+#[test]
+fn test_in_process_pool() {
+    enable();
+    let pool = procspawn::Pool::new(1).unwrap();
+    let pid = pool.spawn((), |()| process::id()).join().unwrap();
+    assert_ne!(pid, process::id());
+}
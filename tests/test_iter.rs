@@ -0,0 +1,35 @@
+use procspawn::iter::spawn_iter;
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_spawn_iter() {
+    let items = spawn_iter(5u32, |n| (0..n).map(|x| x * 2))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(items, vec![0, 2, 4, 6, 8]);
+}
+
+#[test]
+fn test_spawn_iter_panic() {
+    let mut iter = spawn_iter((), |()| {
+        (0..3u32).inspect(|&x| {
+            if x == 2 {
+                panic!("oh no");
+            }
+        })
+    });
+    assert_eq!(iter.next().unwrap().unwrap(), 0);
+    assert_eq!(iter.next().unwrap().unwrap(), 1);
+    let err = iter.next().unwrap().unwrap_err();
+    assert!(err.panic_info().is_some());
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_spawn_iter_drop() {
+    let mut iter = spawn_iter((), |()| 0u64..);
+    assert_eq!(iter.next().unwrap().unwrap(), 0);
+    assert!(iter.pid().is_some());
+    drop(iter);
+}
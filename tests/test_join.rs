@@ -0,0 +1,252 @@
+use std::thread;
+use std::time::Duration;
+
+use procspawn::{self, JoinSet, Pool};
+use serde::{Deserialize, Serialize};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_next_in_completion_order() {
+    let mut set = JoinSet::new();
+    for delay in [300u64, 0, 150].iter().copied() {
+        set.insert(procspawn::spawn(delay, |delay| {
+            thread::sleep(Duration::from_millis(delay));
+            delay
+        }));
+    }
+    assert_eq!(set.len(), 3);
+
+    let mut results = Vec::new();
+    while let Some(result) = set.next() {
+        results.push(result.unwrap());
+    }
+    assert_eq!(results, vec![0, 150, 300]);
+    assert!(set.is_empty());
+}
+
+#[test]
+fn test_mixed_handles() {
+    let pool = Pool::new(2).unwrap();
+    let mut set = JoinSet::new();
+    set.insert(procspawn::spawn(1, |x: u32| x));
+    set.insert(pool.spawn(2, |x: u32| x));
+    set.insert(pool.spawn((), |()| -> u32 { panic!("failed") }));
+
+    let mut values = Vec::new();
+    let mut panics = 0;
+    while let Some(result) = set.next() {
+        match result {
+            Ok(value) => values.push(value),
+            Err(err) => {
+                assert!(err.panic_info().is_some());
+                panics += 1;
+            }
+        }
+    }
+    values.sort_unstable();
+    assert_eq!(values, vec![1, 2]);
+    assert_eq!(panics, 1);
+}
+
+#[test]
+fn test_next_timeout() {
+    let mut set = JoinSet::new();
+    set.insert(procspawn::spawn((), |()| {
+        thread::sleep(Duration::from_millis(500));
+        42
+    }));
+
+    assert!(set.next_timeout(Duration::from_millis(50)).is_none());
+    assert_eq!(set.len(), 1);
+    assert_eq!(
+        set.next_timeout(Duration::from_secs(10)).unwrap().unwrap(),
+        42
+    );
+    assert!(set.next_timeout(Duration::from_secs(10)).is_none());
+}
+
+#[test]
+fn test_drop_kills_pooled_calls() {
+    let pool = Pool::new(1).unwrap();
+    let mut set = JoinSet::new();
+    set.insert(pool.spawn((), |()| {
+        thread::sleep(Duration::from_secs(30));
+    }));
+    while pool.active_count() == 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(set);
+
+    // the worker is replaced and picks up new calls right away.
+    let mut handle = pool.spawn(42, |x: u32| x);
+    assert_eq!(handle.join_timeout(Duration::from_secs(10)).unwrap(), 42);
+}
+
+#[test]
+fn test_join_all_keeps_order() {
+    let handles = [200u64, 0, 100]
+        .iter()
+        .copied()
+        .map(|delay| {
+            procspawn::spawn(delay, |delay| {
+                thread::sleep(Duration::from_millis(delay));
+                delay
+            })
+        })
+        .collect::<Vec<_>>();
+    let results = procspawn::join_all(handles)
+        .into_iter()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+    assert_eq!(results, vec![200, 0, 100]);
+}
+
+#[test]
+fn test_join_all_pooled_out_of_order() {
+    // a single worker runs the calls in order, joining them in reverse
+    // must not block on the first one.
+    let pool = Pool::new(1).unwrap();
+    let handles = (0..4u32)
+        .map(|x| pool.spawn(x, |x| x * 2))
+        .rev()
+        .collect::<Vec<_>>();
+    let results = procspawn::join_all(handles)
+        .into_iter()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+    assert_eq!(results, vec![6, 4, 2, 0]);
+}
+
+#[test]
+fn test_join_all_timeout() {
+    let handles = vec![
+        procspawn::spawn(0, |delay: u64| {
+            thread::sleep(Duration::from_millis(delay));
+            delay
+        }),
+        procspawn::spawn(30_000, |delay: u64| {
+            thread::sleep(Duration::from_millis(delay));
+            delay
+        }),
+    ];
+    let results = procspawn::join_all_timeout(handles, Duration::from_millis(500));
+    assert_eq!(*results[0].as_ref().unwrap(), 0);
+    assert!(results[1].as_ref().unwrap_err().is_timeout());
+}
+
+#[test]
+fn test_wait_any() {
+    let mut handles = [300u64, 0, 600]
+        .iter()
+        .copied()
+        .map(|delay| {
+            procspawn::spawn(delay, |delay| {
+                thread::sleep(Duration::from_millis(delay));
+                delay
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let (index, result) = procspawn::wait_any(&mut handles);
+    assert_eq!((index, result.unwrap()), (1, 0));
+    assert!(procspawn::wait_any_timeout(&mut handles, Duration::from_millis(10)).is_none());
+    assert_eq!(handles.len(), 2);
+
+    let (index, result) = procspawn::wait_any(&mut handles);
+    assert_eq!((index, result.unwrap()), (0, 300));
+    let (index, result) = procspawn::wait_any(&mut handles);
+    assert_eq!((index, result.unwrap()), (0, 600));
+    assert!(procspawn::wait_any_timeout(&mut handles, Duration::from_secs(1)).is_none());
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum ParseError {
+    Empty,
+    Invalid(String),
+}
+
+fn parse(input: String) -> Result<u32, ParseError> {
+    if input.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    input.parse().map_err(|_| ParseError::Invalid(input))
+}
+
+#[test]
+fn test_join_result() {
+    assert_eq!(
+        procspawn::spawn("42".to_string(), parse)
+            .join_result()
+            .unwrap(),
+        42
+    );
+
+    let handle = procspawn::spawn("x".to_string(), parse);
+    let location = handle.spawn_location();
+    let pid = handle.pid().unwrap();
+    let err = handle.join_result().unwrap_err();
+    assert!(err.is_app_error());
+    assert!(!err.is_panic());
+    assert_eq!(
+        err.app_error::<ParseError>(),
+        Some(&ParseError::Invalid("x".into()))
+    );
+    assert_eq!(err.app_error::<String>(), None);
+    assert_eq!(err.pid(), Some(pid));
+    let msg = err.to_string();
+    assert!(msg.starts_with(&format!(
+        "process spawn error: function failed: Invalid(\"x\") (pid {}, ",
+        pid
+    )));
+    assert!(msg.ends_with(&format!("spawned at {})", location)));
+
+    let pool = Pool::new(1).unwrap();
+    let mut handle = pool.spawn(String::new(), parse);
+    let err = handle
+        .join_result_timeout(Duration::from_secs(5))
+        .unwrap_err();
+    assert_eq!(
+        err.into_app_error::<ParseError>().unwrap(),
+        ParseError::Empty
+    );
+
+    let err = procspawn::spawn::<_, Result<(), ParseError>>((), |()| panic!("boom"))
+        .join_result()
+        .unwrap_err();
+    assert!(err.is_panic());
+    assert!(err.into_app_error::<ParseError>().unwrap_err().is_panic());
+}
+
+#[test]
+fn test_wait_and_is_finished() {
+    let mut handle = procspawn::spawn(200u64, |delay| {
+        thread::sleep(Duration::from_millis(delay));
+        42
+    });
+    assert!(!handle.is_finished());
+    assert!(handle.wait().unwrap().success());
+    assert!(handle.is_finished());
+    assert_eq!(handle.join().unwrap(), 42);
+
+    let mut handle = procspawn::spawn::<_, ()>((), |()| std::process::exit(3));
+    assert_eq!(handle.wait().unwrap().code(), Some(3));
+    let err = handle.join().unwrap_err();
+    assert!(err.is_remote_close());
+
+    let pool = Pool::new(1).unwrap();
+    let mut handle = pool.spawn(21, |x: i32| x * 2);
+    assert!(handle.wait().is_err());
+    while !handle.is_finished() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_wait_forked() {
+    let mut handle = unsafe { procspawn::Builder::new().spawn_forked(5, |x: i32| x + 1) };
+    assert!(handle.wait().unwrap().success());
+    assert_eq!(handle.join().unwrap(), 6);
+}
@@ -0,0 +1,71 @@
+use std::io::Read;
+use std::process::Stdio;
+
+use procspawn::{self, Builder, HandleDropBehavior, Pool, ProcConfig};
+
+procspawn::enable_test_support!();
+
+/// Runs `f` in a process with leak detection and returns its stderr.
+fn leak_warnings(f: fn(()) -> Vec<u32>) -> (String, Vec<u32>) {
+    let mut handle = Builder::new().stderr(Stdio::piped()).spawn((), f);
+    let mut stderr = String::new();
+    handle
+        .stderr()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+    (stderr, handle.join().unwrap())
+}
+
+#[test]
+fn test_dropped_handle() {
+    let (stderr, lines) = leak_warnings(|()| {
+        ProcConfig::new().leak_detection(true).init();
+        procspawn::spawn((), |()| ()).join().unwrap();
+        procspawn::spawn((), |()| ()).kill().unwrap();
+        procspawn::spawn((), |()| ()).detach();
+        let line = line!() + 1;
+        let _leaked = procspawn::spawn((), |()| ());
+        vec![line]
+    });
+    assert_eq!(
+        stderr.matches("was dropped without being joined").count(),
+        1
+    );
+    assert!(stderr.contains(&format!("{}:{}:", file!(), lines[0])));
+}
+
+#[test]
+fn test_pooled_handle() {
+    let (stderr, lines) = leak_warnings(|()| {
+        ProcConfig::new().leak_detection(true).init();
+        let pool = Pool::new(1).unwrap();
+        pool.spawn((), |()| ()).join().unwrap();
+        let line = line!() + 1;
+        let leaked = pool.spawn((), |()| ());
+        drop(leaked);
+
+        // dropping cancels these calls
+        let pool = Pool::builder(1)
+            .drop_behavior(HandleDropBehavior::Cancel)
+            .build()
+            .unwrap();
+        let cancelled = pool.spawn((), |()| ());
+        drop(cancelled);
+        vec![line]
+    });
+    assert_eq!(
+        stderr.matches("was dropped without being joined").count(),
+        1
+    );
+    assert!(stderr.contains(&format!("{}:{}:", file!(), lines[0])));
+}
+
+#[test]
+fn test_disabled() {
+    let (stderr, _) = leak_warnings(|()| {
+        let _leaked = procspawn::spawn((), |()| ());
+        vec![]
+    });
+    assert!(!stderr.contains("was dropped without being joined"));
+}
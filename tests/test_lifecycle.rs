@@ -0,0 +1,56 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use procspawn::{self, ExitInfo, ExitOutcome, ProcConfig, SpawnInfo};
+
+procspawn::enable_test_support!();
+
+static SPAWNED: Mutex<Vec<SpawnInfo>> = Mutex::new(Vec::new());
+static EXITED: Mutex<Vec<ExitInfo>> = Mutex::new(Vec::new());
+
+fn double(x: u32) -> u32 {
+    x * 2
+}
+
+fn last_exit() -> ExitInfo {
+    EXITED.lock().unwrap().last().cloned().unwrap()
+}
+
+#[test]
+fn test_lifecycle_hooks() {
+    ProcConfig::new()
+        .on_spawn(|info| SPAWNED.lock().unwrap().push(info.clone()))
+        .on_exit(|info| EXITED.lock().unwrap().push(info.clone()))
+        .init();
+
+    let handle = procspawn::spawn(21, double);
+    let pid = handle.pid().unwrap();
+    assert_eq!(handle.join().unwrap(), 42);
+
+    let spawned = SPAWNED.lock().unwrap().last().cloned().unwrap();
+    assert_eq!(spawned.pid(), pid);
+    assert_eq!(spawned.sequence(), 1);
+    assert!(!spawned.forked());
+    #[cfg(feature = "backtrace")]
+    {
+        assert!(spawned.function().unwrap().ends_with("double"));
+    }
+    let exited = last_exit();
+    assert_eq!(exited.pid(), pid);
+    assert_eq!(exited.sequence(), 1);
+    assert_eq!(exited.outcome(), ExitOutcome::Returned);
+    assert_eq!(exited.function(), spawned.function());
+
+    let handle = procspawn::spawn::<_, ()>((), |()| panic!("boom"));
+    assert!(handle.join().is_err());
+    assert_eq!(last_exit().outcome(), ExitOutcome::Panicked);
+
+    let mut handle = procspawn::spawn((), |()| thread::sleep(Duration::from_secs(10)));
+    handle.kill().unwrap();
+    assert_eq!(last_exit().outcome(), ExitOutcome::Killed);
+    assert!(last_exit().duration() < Duration::from_secs(10));
+
+    assert_eq!(SPAWNED.lock().unwrap().len(), 3);
+    assert_eq!(EXITED.lock().unwrap().len(), 3);
+}
@@ -0,0 +1,125 @@
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use procspawn::{self, Builder, ConcurrencyLimiter, Pool};
+
+procspawn::enable_test_support!();
+
+#[derive(Default)]
+struct Counts {
+    current: usize,
+    peak: usize,
+    acquired: usize,
+}
+
+struct Semaphore {
+    limit: usize,
+    counts: Mutex<Counts>,
+    condvar: Condvar,
+}
+
+impl Semaphore {
+    fn new(limit: usize) -> Arc<Semaphore> {
+        Arc::new(Semaphore {
+            limit,
+            counts: Mutex::new(Counts::default()),
+            condvar: Condvar::new(),
+        })
+    }
+}
+
+impl ConcurrencyLimiter for Semaphore {
+    fn acquire(&self) -> io::Result<()> {
+        if self.limit == 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "no capacity"));
+        }
+        let mut counts = self.counts.lock().unwrap();
+        while counts.current >= self.limit {
+            counts = self.condvar.wait(counts).unwrap();
+        }
+        counts.current += 1;
+        counts.acquired += 1;
+        counts.peak = counts.peak.max(counts.current);
+        Ok(())
+    }
+
+    fn release(&self) {
+        self.counts.lock().unwrap().current -= 1;
+        self.condvar.notify_one();
+    }
+}
+
+#[test]
+fn test_builder_limiter() {
+    let limiter = Semaphore::new(1);
+    let threads: Vec<_> = (0..3)
+        .map(|_| {
+            let limiter = limiter.clone();
+            thread::spawn(move || {
+                Builder::new()
+                    .concurrency_limiter(limiter)
+                    .spawn((), |()| thread::sleep(Duration::from_millis(100)))
+                    .join()
+                    .unwrap();
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let counts = limiter.counts.lock().unwrap();
+    assert_eq!(counts.acquired, 3);
+    assert_eq!(counts.peak, 1);
+    assert_eq!(counts.current, 0);
+}
+
+#[test]
+fn test_pool_limiter() {
+    let limiter = Semaphore::new(1);
+    let pool = Pool::builder(3)
+        .concurrency_limiter(limiter.clone())
+        .build()
+        .unwrap();
+    assert_eq!(limiter.counts.lock().unwrap().acquired, 0);
+
+    let handles: Vec<_> = (0..4)
+        .map(|x| {
+            pool.spawn(x, |x: u32| {
+                thread::sleep(Duration::from_millis(50));
+                x * 2
+            })
+        })
+        .collect();
+    let results: Vec<_> = handles.into_iter().map(|x| x.join().unwrap()).collect();
+    assert_eq!(results, vec![0, 2, 4, 6]);
+
+    pool.join();
+    let counts = limiter.counts.lock().unwrap();
+    assert_eq!(counts.acquired, 4);
+    assert_eq!(counts.peak, 1);
+    assert_eq!(counts.current, 0);
+}
+
+#[test]
+fn test_refused_permit() {
+    let limiter = Semaphore::new(0);
+    let err = Builder::new()
+        .concurrency_limiter(limiter.clone())
+        .spawn((), |()| ())
+        .join()
+        .unwrap_err();
+    assert!(err.is_concurrency_limited());
+
+    let pool = Pool::builder(1)
+        .concurrency_limiter(limiter)
+        .build()
+        .unwrap();
+    let err = pool.spawn((), |()| ()).join().unwrap_err();
+    assert!(err.is_concurrency_limited());
+
+    // the worker was not affected
+    assert_eq!(pool.size(), 1);
+}
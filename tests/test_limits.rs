@@ -0,0 +1,40 @@
+use procspawn::{self, Builder, Pool, ProcConfig};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_max_message_size() {
+    ProcConfig::new().max_message_size(1024).init();
+
+    let handle = Builder::new().spawn(vec![1u8; 100], |data: Vec<u8>| data.len());
+    assert_eq!(handle.join().unwrap(), 100);
+
+    let err = Builder::new()
+        .spawn(vec![1u8; 4096], |data: Vec<u8>| data.len())
+        .join()
+        .unwrap_err();
+    assert!(err.is_message_too_large());
+    assert!(err.is_serialization());
+    assert!(!err.is_infrastructure());
+    assert!(err.message_size().unwrap() > 4096);
+
+    let err = Builder::new()
+        .spawn(4096, |len: usize| vec![1u8; len])
+        .join()
+        .unwrap_err();
+    assert!(err.is_message_too_large());
+    assert!(err.message_size().unwrap() > 4096);
+
+    let pool = Pool::new(1).unwrap();
+    let err = pool
+        .spawn(vec![1u8; 4096], |data: Vec<u8>| data.len())
+        .join()
+        .unwrap_err();
+    assert!(err.is_message_too_large());
+    let err = pool
+        .spawn(4096, |len: usize| vec![1u8; len])
+        .join()
+        .unwrap_err();
+    assert!(err.is_message_too_large());
+    assert_eq!(pool.spawn(2, |x: i32| x * 2).join().unwrap(), 4);
+}
@@ -0,0 +1,44 @@
+use std::sync::Mutex;
+
+use procspawn::{self, MessageInfo, MessageKind, Pool, ProcConfig};
+
+procspawn::enable_test_support!();
+
+static MESSAGES: Mutex<Vec<MessageInfo>> = Mutex::new(Vec::new());
+
+fn take_messages() -> Vec<MessageInfo> {
+    std::mem::take(&mut *MESSAGES.lock().unwrap())
+}
+
+#[test]
+fn test_message_hook() {
+    ProcConfig::new()
+        .on_message(|info| MESSAGES.lock().unwrap().push(info.clone()))
+        .init();
+
+    let handle = procspawn::spawn(vec![0u8; 100_000], |data| data.len());
+    assert_eq!(handle.join().unwrap(), 100_000);
+
+    let messages = take_messages();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].kind(), MessageKind::Args);
+    assert!(messages[0].size() >= 100_000);
+    assert!(messages[0].type_name().contains("Vec<u8>"));
+    assert_eq!(messages[1].kind(), MessageKind::Return);
+    assert!(messages[1].size() < 100);
+
+    let pool = Pool::new(1).unwrap();
+    let handle = pool.spawn(1000, |n| vec![1u8; n]);
+    assert_eq!(handle.join().unwrap().len(), 1000);
+
+    let messages = take_messages();
+    let returned = messages
+        .iter()
+        .find(|x| x.kind() == MessageKind::Return)
+        .unwrap();
+    assert!(returned.size() >= 1000);
+    assert!(returned.type_name().contains("Vec<u8>"));
+    assert!(messages
+        .iter()
+        .any(|x| x.kind() == MessageKind::Args && x.type_name() == "usize"));
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit,
+};
+use procspawn::{self, Pool};
+
+procspawn::enable_test_support!();
+
+/// Sums up everything that is recorded for a metric.
+static VALUES: Mutex<Vec<(String, f64)>> = Mutex::new(Vec::new());
+
+struct Handle(String);
+
+impl Handle {
+    fn add(&self, value: f64) {
+        VALUES.lock().unwrap().push((self.0.clone(), value));
+    }
+}
+
+impl CounterFn for Handle {
+    fn increment(&self, value: u64) {
+        self.add(value as f64);
+    }
+
+    fn absolute(&self, _value: u64) {}
+}
+
+impl GaugeFn for Handle {
+    fn increment(&self, value: f64) {
+        self.add(value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.add(-value);
+    }
+
+    fn set(&self, _value: f64) {}
+}
+
+impl HistogramFn for Handle {
+    fn record(&self, value: f64) {
+        self.add(value);
+    }
+}
+
+struct TestRecorder;
+
+fn handle(key: &Key) -> Arc<Handle> {
+    let mut name = key.name().to_string();
+    for label in key.labels() {
+        name.push_str(&format!(",{}={}", label.key(), label.value()));
+    }
+    Arc::new(Handle(name))
+}
+
+impl Recorder for TestRecorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::from_arc(handle(key))
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(handle(key))
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(handle(key))
+    }
+}
+
+/// Returns the number of records and the sum of the values per metric.
+fn recorded() -> HashMap<String, (usize, f64)> {
+    let mut rv = HashMap::new();
+    for (name, value) in VALUES.lock().unwrap().iter() {
+        let entry = rv.entry(name.clone()).or_insert((0, 0.0));
+        entry.0 += 1;
+        entry.1 += value;
+    }
+    rv
+}
+
+#[test]
+fn test_metrics() {
+    metrics::set_global_recorder(TestRecorder).unwrap();
+
+    let handle = procspawn::spawn(vec![0u8; 1000], |data| data.len());
+    assert_eq!(handle.join().unwrap(), 1000);
+    let handle = procspawn::spawn::<_, ()>((), |()| panic!("boom"));
+    assert!(handle.join().is_err());
+
+    let pool = Pool::new(1).unwrap();
+    let handles: Vec<_> = (0..4).map(|i| pool.spawn(i, |i: u32| i)).collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    pool.shutdown();
+
+    let recorded = recorded();
+    // two spawns plus the worker of the pool
+    assert_eq!(recorded["procspawn_spawns_total,kind=exec"], (3, 3.0));
+    assert_eq!(recorded["procspawn_spawn_duration_seconds,kind=exec"].0, 3);
+    assert!(!recorded.contains_key("procspawn_spawn_failures_total,kind=exec"));
+    assert_eq!(recorded["procspawn_panics_total"], (1, 1.0));
+    // the arguments of both spawns, the worker and the four calls
+    let (count, bytes) = recorded["procspawn_args_bytes"];
+    assert_eq!(count, 7);
+    assert!(bytes >= 1000.0);
+    let (count, depth) = recorded["procspawn_pool_queue_depth"];
+    assert_eq!(count, 8);
+    assert_eq!(depth, 0.0);
+}
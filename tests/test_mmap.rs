@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::PathBuf;
+
+use procspawn::serde::MmapPayload;
+use procspawn::{self, codec, Builder};
+
+procspawn::enable_test_support!();
+
+fn payload_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("procspawn-mmap-{}-{}", name, std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_send_payload() {
+    let path = payload_file("send", b"hello world");
+    let payload = MmapPayload::open(&path).unwrap();
+    let handle = procspawn::spawn(payload, |payload| payload.to_vec());
+    assert_eq!(handle.join().unwrap(), b"hello world");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_range() {
+    let contents: Vec<u8> = (0..10000u32).map(|x| x as u8).collect();
+    let path = payload_file("range", &contents);
+    let payload = MmapPayload::open_range(&path, 5000, 100).unwrap();
+    assert_eq!(&payload[..], &contents[5000..5100]);
+    let handle = procspawn::spawn(payload, |payload| (payload.offset(), payload.to_vec()));
+    assert_eq!(
+        handle.join().unwrap(),
+        (5000, contents[5000..5100].to_vec())
+    );
+
+    let err = MmapPayload::open_range(&path, 9990, 100).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_replaced_file() {
+    let path = payload_file("replaced", b"original");
+    let payload = MmapPayload::open(&path).unwrap();
+    let handle = procspawn::spawn(payload, |payload| {
+        // the file was replaced by now, the child still sees the original
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        payload.to_vec()
+    });
+    fs::remove_file(&path).unwrap();
+    fs::write(&path, b"replaced").unwrap();
+    assert_eq!(handle.join().unwrap(), b"original");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_codec_reopens_path() {
+    let path = payload_file("codec", b"by path");
+    let payload = MmapPayload::open(&path).unwrap();
+    let handle = Builder::new()
+        .codec::<codec::Bincode>()
+        .spawn(payload, |payload| {
+            (payload.path().to_path_buf(), payload.to_vec())
+        });
+    assert_eq!(handle.join().unwrap(), (path.clone(), b"by path".to_vec()));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_empty() {
+    let path = payload_file("empty", b"");
+    let payload = MmapPayload::open(&path).unwrap();
+    let handle = procspawn::spawn(payload, |payload| payload.len());
+    assert_eq!(handle.join().unwrap(), 0);
+    fs::remove_file(&path).unwrap();
+}
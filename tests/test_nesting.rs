@@ -0,0 +1,19 @@
+use procspawn::{self, spawn};
+
+procspawn::enable_test_support!(|config| {
+    config.max_nesting_depth(3);
+});
+
+fn spawn_nested(level: u32) -> Result<u32, ()> {
+    match spawn(level + 1, spawn_nested).join() {
+        Ok(rv) => rv,
+        Err(err) if err.is_nesting_limit_exceeded() => Ok(level),
+        Err(_) => Err(()),
+    }
+}
+
+#[test]
+fn test_nesting_limit() {
+    let handle = spawn(1, spawn_nested);
+    assert_eq!(handle.join().unwrap(), Ok(3));
+}
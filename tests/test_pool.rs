@@ -1,7 +1,17 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use procspawn::{self, Pool};
+use procspawn::iter::ParallelBridge;
+use procspawn::{
+    self, spawn, DropBehavior, HandleDropBehavior, JobOutcome, Pool, PoolHealth, Priority,
+    WorkerExit,
+};
 
 procspawn::enable_test_support!();
 
@@ -77,3 +87,696 @@ fn test_timeout() {
     let val = handle.join_timeout(Duration::from_secs(2)).unwrap();
     assert_eq!(val, 42);
 }
+
+#[test]
+fn test_dropped_handle() {
+    let pool = Pool::new(1).unwrap();
+    drop(pool.spawn((), |()| thread::sleep(Duration::from_millis(100))));
+    pool.join();
+
+    let mut handle = pool.spawn((), |()| 42);
+    assert_eq!(handle.join_timeout(Duration::from_secs(2)).unwrap(), 42);
+}
+
+#[test]
+fn test_workers() {
+    let pool = Pool::new(2).unwrap();
+    let workers = pool.workers();
+    assert_eq!(workers.len(), 2);
+    assert!(workers.iter().all(|worker| worker.pid().is_some()
+        && worker.jobs_handled() == 0
+        && worker.current_call_id().is_none()));
+
+    let mut handle = pool.spawn((), |()| thread::sleep(Duration::from_secs(10)));
+    let call_id = handle.call_id().unwrap();
+    let running = loop {
+        let running: Vec<_> = pool
+            .workers()
+            .iter()
+            .filter_map(|worker| worker.current_call_id())
+            .collect();
+        if !running.is_empty() {
+            break running;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(running, vec![call_id]);
+
+    // killing the call replaces the worker process
+    handle.kill().unwrap();
+    assert!(handle.join().is_err());
+    while pool
+        .workers()
+        .iter()
+        .all(|worker| worker.restart_count() == 0)
+    {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let handle = pool.spawn((), |()| 42);
+    assert!(handle.call_id().unwrap() > call_id);
+    assert_eq!(handle.join().unwrap(), 42);
+    pool.join();
+    let workers = pool.workers();
+    assert_eq!(
+        workers
+            .iter()
+            .map(|worker| worker.jobs_handled())
+            .sum::<u64>(),
+        2
+    );
+    assert_eq!(
+        workers
+            .iter()
+            .map(|worker| worker.restart_count())
+            .sum::<u64>(),
+        1
+    );
+    assert!(workers.iter().all(|worker| worker.pid().is_some()));
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_call(_: ()) -> usize {
+    CALLS.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+#[test]
+fn test_adoption() {
+    let state_file = env::temp_dir().join(format!("procspawn-adoption-{}", process::id()));
+    fs::remove_file(&state_file).ok();
+
+    // a parent that warms up its worker and then crashes
+    let handle = spawn::<_, ()>(state_file.clone(), |state_file| {
+        let pool = Pool::builder(1).adoptable(state_file).build().unwrap();
+        assert_eq!(pool.spawn((), count_call).join().unwrap(), 1);
+        process::abort();
+    });
+    assert!(handle.join().is_err());
+
+    for _ in 0..50 {
+        if fs::read_to_string(&state_file).is_ok_and(|x| x.ends_with('\n')) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let pool = Pool::builder(1).adoptable(&state_file).build().unwrap();
+    assert_eq!(pool.spawn((), count_call).join().unwrap(), 2);
+    assert!(!state_file.exists());
+}
+
+#[test]
+fn test_drop_cancel() {
+    let pool = Pool::builder(1)
+        .drop_behavior(HandleDropBehavior::Cancel)
+        .build()
+        .unwrap();
+
+    let busy = pool.spawn((), |()| thread::sleep(Duration::from_millis(300)));
+    drop(pool.spawn((), count_call));
+    busy.join().unwrap();
+
+    // the dropped call never ran in the worker
+    assert_eq!(pool.spawn((), count_call).join().unwrap(), 1);
+}
+
+#[test]
+fn test_drop_kill() {
+    let pool = Pool::builder(1)
+        .drop_behavior(HandleDropBehavior::Kill)
+        .build()
+        .unwrap();
+
+    let handle = pool.spawn((), |()| thread::sleep(Duration::from_secs(10)));
+    let mut pid = None;
+    for _ in 0..50 {
+        pid = handle.pid();
+        if pid.is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(handle);
+
+    let mut handle = pool.spawn((), |()| process::id());
+    let new_pid = handle.join_timeout(Duration::from_secs(5)).unwrap();
+    assert_ne!(Some(new_pid), pid);
+}
+
+#[test]
+fn test_par_bridge() {
+    let pool = Pool::new(2).unwrap();
+    let rv = (0..20u32)
+        .par_bridge(&pool)
+        .chunk_size(3)
+        .max_in_flight(2)
+        .map(|x| x * 2)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(rv, (0..20).map(|x| x * 2).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_par_bridge_panic() {
+    let pool = Pool::new(2).unwrap();
+    let rv = (0..4u32)
+        .par_bridge(&pool)
+        .chunk_size(2)
+        .map(|x| {
+            if x == 3 {
+                panic!("bad item");
+            }
+            x
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rv.len(), 3);
+    assert_eq!(*rv[0].as_ref().unwrap(), 0);
+    assert_eq!(*rv[1].as_ref().unwrap(), 1);
+    assert!(rv[2].as_ref().unwrap_err().is_panic());
+}
+
+#[test]
+fn test_par_bridge_adaptive() {
+    let pool = Pool::new(2).unwrap();
+    let mut iter = (0..2000u32)
+        .par_bridge(&pool)
+        .adaptive_chunk_size(0.1)
+        .map(|x| x + 1);
+    assert_eq!(iter.chunk_size(), 1);
+    let rv = iter.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(rv, (1..2001).collect::<Vec<_>>());
+    assert!(iter.chunk_size() > 1);
+}
+
+#[test]
+fn test_quarantine() {
+    let pool = Pool::builder(1)
+        .quarantine(Duration::from_secs(60))
+        .build()
+        .unwrap();
+
+    let check = |x: u32| {
+        if x == 13 {
+            panic!("unlucky");
+        }
+        if x == 42 {
+            process::abort();
+        }
+        x
+    };
+
+    assert!(pool.spawn(13, check).join().unwrap_err().is_panic());
+    assert!(pool.spawn(13, check).join().unwrap_err().is_quarantined());
+    assert!(!pool.spawn(42, check).join().unwrap_err().is_quarantined());
+    assert!(pool.spawn(42, check).join().unwrap_err().is_quarantined());
+    assert_eq!(pool.spawn(1, check).join().unwrap(), 1);
+}
+
+#[test]
+fn test_spawn_keyed() {
+    let pool = Pool::new(4).unwrap();
+
+    for key in &["a", "b", "c"] {
+        let handles = (0..8)
+            .map(|_| pool.spawn_keyed(*key, (), |()| process::id()))
+            .collect::<Vec<_>>();
+        let pids = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert!(pids.iter().all(|&pid| pid == pids[0]));
+    }
+}
+
+#[test]
+fn test_spawn_keyed_without_workers() {
+    let pool = Pool::new(0).unwrap();
+    let handle = pool.spawn_keyed("a", (), |()| process::id());
+    assert_eq!(pool.queued_count(), 1);
+    drop(handle);
+}
+
+#[test]
+fn test_worker_init() {
+    let pool = Pool::builder(1)
+        .worker_init(10u32, |start| vec![start])
+        .build()
+        .unwrap();
+
+    let push = |x: u32| {
+        procspawn::with_worker_state(|state: &mut Vec<u32>| {
+            state.push(x);
+            state.clone()
+        })
+        .unwrap()
+    };
+    assert_eq!(pool.spawn(1, push).join().unwrap(), vec![10, 1]);
+    assert_eq!(pool.spawn(2, push).join().unwrap(), vec![10, 1, 2]);
+
+    // the state is initialized again after a restart.
+    assert!(pool
+        .spawn((), |()| -> u32 { process::abort() })
+        .join()
+        .is_err());
+    assert_eq!(pool.spawn(3, push).join().unwrap(), vec![10, 3]);
+
+    assert!(Pool::builder(1)
+        .worker_init((), |()| -> u32 { panic!("no state") })
+        .build()
+        .unwrap_err()
+        .is_panic());
+}
+
+#[test]
+fn test_idle_timeout() {
+    let pool = Pool::builder(2)
+        .idle_timeout(Duration::from_millis(100))
+        .min_idle(1)
+        .build()
+        .unwrap();
+
+    let running = |pool: &Pool| {
+        pool.workers()
+            .iter()
+            .filter(|worker| worker.pid().is_some())
+            .count()
+    };
+    assert_eq!(running(&pool), 2);
+    for _ in 0..100 {
+        if running(&pool) == 1 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(running(&pool), 1);
+
+    // stopped workers are spawned again for new calls.
+    let handles = (0..4)
+        .map(|x| pool.spawn(x, |x: u32| x + 1))
+        .collect::<Vec<_>>();
+    let rv = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(rv, vec![1, 2, 3, 4]);
+    assert_eq!(pool.spawn_keyed("a", 1, |x: u32| x).join().unwrap(), 1);
+    assert_eq!(pool.spawn_keyed("b", 2, |x: u32| x).join().unwrap(), 2);
+}
+
+#[test]
+fn test_priority() {
+    let pool = Pool::new(1).unwrap();
+
+    // keeps the worker busy until everything is queued.
+    let (release_tx, release_rx) = procspawn::channel::<()>().unwrap();
+    let blocker = pool.spawn(release_rx, |rx| rx.recv().unwrap());
+    while pool.active_count() == 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let now = |()| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+    };
+    let low = (0..3)
+        .map(|_| pool.spawn_with_priority(Priority::Low, (), now))
+        .collect::<Vec<_>>();
+    let high = (0..3)
+        .map(|_| pool.spawn_with_priority(Priority::High, (), now))
+        .collect::<Vec<_>>();
+    release_tx.send(()).unwrap();
+    blocker.join().unwrap();
+
+    // results are handed over one by one so they are joined in the
+    // expected order.
+    let high = high
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+    let low = low
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+    assert!(high.iter().max() < low.iter().min());
+}
+
+#[test]
+fn test_queue_limit() {
+    let pool = Pool::builder(1).queue_limit(2).build().unwrap();
+
+    let (release_tx, release_rx) = procspawn::channel::<()>().unwrap();
+    let blocker = pool.spawn(release_rx, |rx| rx.recv().unwrap());
+    while pool.active_count() == 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let first = pool.try_spawn(1, |x: u32| x + 1).unwrap();
+    let second = pool.try_spawn(2, |x: u32| x + 1).unwrap();
+    let err = pool.try_spawn(3, |x: u32| x + 1).unwrap_err();
+    assert_eq!(err.into_inner(), 3);
+
+    // the worker only takes the next call once the blocker was joined.
+    let waiting = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        release_tx.send(()).unwrap();
+        blocker.join().unwrap();
+    });
+    let third = pool.spawn_wait(3, |x: u32| x + 1);
+    waiting.join().unwrap();
+    assert_eq!(first.join().unwrap(), 2);
+    assert_eq!(second.join().unwrap(), 3);
+    assert_eq!(third.join().unwrap(), 4);
+}
+
+#[test]
+fn test_spawn_after() {
+    let pool = Pool::new(1).unwrap();
+
+    let start = Instant::now();
+    let later = pool.spawn_after(Duration::from_millis(300), 1, |x: u32| x);
+    let sooner = pool.spawn_at(start + Duration::from_millis(100), 2, |x: u32| x);
+    let now = pool.spawn(3, |x: u32| x);
+
+    assert_eq!(now.join().unwrap(), 3);
+    assert_eq!(sooner.join().unwrap(), 2);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(later.join().unwrap(), 1);
+    assert!(start.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+fn test_spawn_with_timeout() {
+    let pool = Pool::new(1).unwrap();
+
+    let handle = pool.spawn_with_timeout(Duration::from_millis(100), (), |()| {
+        thread::sleep(Duration::from_secs(10));
+    });
+    assert!(handle.join().unwrap_err().is_timeout());
+
+    // the worker was replaced and calls that finish in time are unaffected.
+    let handle = pool.spawn_with_timeout(Duration::from_secs(10), 1, |x: u32| x + 1);
+    assert_eq!(handle.join().unwrap(), 2);
+}
+
+#[test]
+fn test_stats() {
+    let pool = Pool::new(2).unwrap();
+    assert!(pool.stats().average_latency().is_none());
+
+    assert_eq!(pool.spawn(1, |x: u32| x).join().unwrap(), 1);
+    assert_eq!(pool.spawn(2, |x: u32| x).join().unwrap(), 2);
+    assert!(pool
+        .spawn((), |()| -> u32 { panic!("oops") })
+        .join()
+        .unwrap_err()
+        .is_panic());
+    assert!(pool
+        .spawn((), |()| -> u32 { process::abort() })
+        .join()
+        .is_err());
+
+    let stats = pool.stats();
+    assert_eq!(stats.completed(), 2);
+    assert_eq!(stats.panicked(), 1);
+    assert_eq!(stats.failed(), 1);
+    assert_eq!(stats.queued(), 0);
+    assert!(stats.average_latency().is_some());
+    assert_eq!(stats.queue_depths().len(), 8);
+    assert_eq!(stats.workers().len(), 2);
+}
+
+#[test]
+fn test_hooks() {
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let exited = Arc::new(Mutex::new(Vec::new()));
+    let jobs = Arc::new(Mutex::new(Vec::new()));
+    let pool = Pool::builder(1)
+        .on_worker_start({
+            let started = started.clone();
+            move |pid| started.lock().unwrap().push(pid)
+        })
+        .on_worker_exit({
+            let exited = exited.clone();
+            move |pid, exit| {
+                let shutdown = matches!(exit, WorkerExit::Shutdown);
+                exited.lock().unwrap().push((pid, shutdown));
+            }
+        })
+        .on_job_complete({
+            let jobs = jobs.clone();
+            move |stats| {
+                jobs.lock()
+                    .unwrap()
+                    .push((stats.call_id(), stats.outcome()))
+            }
+        })
+        .build()
+        .unwrap();
+
+    let first_pid = started.lock().unwrap()[0];
+    let handle = pool.spawn(1, |x: u32| x);
+    let call_id = handle.call_id().unwrap();
+    assert_eq!(handle.join().unwrap(), 1);
+    assert_eq!(
+        *jobs.lock().unwrap(),
+        vec![(call_id, JobOutcome::Completed)]
+    );
+
+    assert!(pool
+        .spawn((), |()| -> u32 { process::abort() })
+        .join()
+        .is_err());
+    assert_eq!(jobs.lock().unwrap()[1].1, JobOutcome::Failed);
+    assert_eq!(*exited.lock().unwrap(), vec![(first_pid, false)]);
+
+    // the worker is restarted after the call failed.
+    while started.lock().unwrap().len() < 2 {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let second_pid = started.lock().unwrap()[1];
+    drop(pool);
+    assert_eq!(exited.lock().unwrap()[1], (second_pid, true));
+}
+
+#[test]
+fn test_max_restarts() {
+    let pool = Pool::builder(1)
+        .max_restarts(2, Duration::from_secs(60))
+        .build()
+        .unwrap();
+    let crash = |()| -> u32 { process::abort() };
+
+    assert!(pool.spawn((), crash).join().is_err());
+    assert!(pool.spawn((), crash).join().is_err());
+    assert_eq!(pool.health(), PoolHealth::Healthy);
+
+    let last = pool.spawn((), crash);
+    let queued = pool.spawn(1, |x: u32| x);
+    assert!(last.join().is_err());
+    assert!(queued.join().unwrap_err().is_pool_failed());
+    assert_eq!(pool.health(), PoolHealth::Failed);
+    assert!(pool
+        .spawn(1, |x: u32| x)
+        .join()
+        .unwrap_err()
+        .is_pool_failed());
+}
+
+#[test]
+fn test_restart_backoff() {
+    let pool = Pool::builder(1)
+        .restart_backoff(Duration::from_millis(100), Duration::from_millis(300))
+        .build()
+        .unwrap();
+    let crash = |()| -> u32 { process::abort() };
+
+    // the worker is only replaced after the delay so the next call waits
+    // for it.
+    let start = Instant::now();
+    assert!(pool.spawn((), crash).join().is_err());
+    assert!(pool.spawn((), crash).join().is_err());
+    assert_eq!(pool.spawn(1, |x: u32| x).join().unwrap(), 1);
+    assert!(start.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+fn test_map() {
+    let pool = Pool::new(2).unwrap();
+
+    let rv = pool
+        .map(0..20u32, |x| x * 2)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(rv, (0..20).map(|x| x * 2).collect::<Vec<_>>());
+
+    // the slow first item does not hold back the others.
+    let rv = pool
+        .map_unordered(vec![500u64, 0, 0, 0], |delay| {
+            thread::sleep(Duration::from_millis(delay));
+            delay
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(rv, vec![0, 0, 0, 500]);
+}
+
+#[test]
+fn test_capture_output() {
+    let pool = Pool::builder(1).capture_output().build().unwrap();
+
+    let handles = (0..3u32)
+        .map(|x| {
+            pool.spawn(x, |x| {
+                // not `println!` which the test harness captures.
+                writeln!(io::stdout(), "stdout of {}", x).unwrap();
+                writeln!(io::stderr(), "stderr of {}", x).unwrap();
+                x
+            })
+        })
+        .collect::<Vec<_>>();
+    for (x, handle) in handles.into_iter().enumerate() {
+        let (rv, output) = handle.join_with_output();
+        assert_eq!(rv.unwrap(), x as u32);
+        let output = output.unwrap();
+        assert_eq!(output.stdout(), format!("stdout of {}\n", x).as_bytes());
+        assert_eq!(output.stderr(), format!("stderr of {}\n", x).as_bytes());
+    }
+
+    // output is not captured by default.
+    let pool = Pool::new(1).unwrap();
+    let (rv, output) = pool.spawn(42, |x: u32| x).join_with_output();
+    assert_eq!(rv.unwrap(), 42);
+    assert!(output.is_none());
+}
+
+#[test]
+fn test_spawn_with_env() {
+    let pool = Pool::new(1).unwrap();
+
+    let handle = pool.spawn_with_env(vec![("PROCSPAWN_TEST_ENV", "overridden")], (), |()| {
+        env::var("PROCSPAWN_TEST_ENV").ok()
+    });
+    assert_eq!(handle.join().unwrap().as_deref(), Some("overridden"));
+
+    // the variable is gone again for later calls.
+    let handle = pool.spawn((), |()| env::var("PROCSPAWN_TEST_ENV").ok());
+    assert_eq!(handle.join().unwrap(), None);
+}
+
+#[test]
+fn test_worker_concurrency() {
+    let pool = Pool::builder(1).worker_concurrency(4).build().unwrap();
+
+    let start = Instant::now();
+    let handles = (0..4)
+        .map(|_| {
+            pool.spawn((), |()| {
+                thread::sleep(Duration::from_millis(500));
+                process::id()
+            })
+        })
+        .collect::<Vec<_>>();
+    let pids = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+    assert!(start.elapsed() < Duration::from_millis(1500));
+    assert!(pids.iter().all(|&pid| pid == pids[0]));
+
+    // a panic only fails its own call.
+    let sleeper = pool.spawn((), |()| {
+        thread::sleep(Duration::from_millis(300));
+        process::id()
+    });
+    let err = pool
+        .spawn((), |()| -> u32 { panic!("boom") })
+        .join()
+        .unwrap_err();
+    assert!(err.is_panic());
+    assert_eq!(sleeper.join().unwrap(), pids[0]);
+}
+
+#[test]
+fn test_worker_concurrency_shared_state() {
+    assert!(Pool::builder(1)
+        .worker_concurrency(2)
+        .with_progress()
+        .build()
+        .is_err());
+    assert!(Pool::builder(1)
+        .worker_concurrency(2)
+        .capture_output()
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_worker_concurrency_refuses_env() {
+    let pool = Pool::builder(1).worker_concurrency(2).build().unwrap();
+    let err = pool
+        .spawn_with_env(vec![("PROCSPAWN_TEST_ENV", "1")], (), |()| ())
+        .join()
+        .unwrap_err();
+    let source = std::error::Error::source(&err).unwrap();
+    assert!(source
+        .to_string()
+        .contains("per-call environment variables"));
+    assert_eq!(pool.queued_count(), 0);
+}
+
+#[test]
+fn test_on_drop_wait() {
+    let pool = Pool::builder(1)
+        .on_drop(DropBehavior::Wait)
+        .build()
+        .unwrap();
+    let handles = (0..3u32)
+        .map(|x| {
+            pool.spawn(x, |x| {
+                thread::sleep(Duration::from_millis(100));
+                x
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(pool);
+
+    let results = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(results, vec![0, 1, 2]);
+}
+
+#[test]
+fn test_on_drop_wait_timeout() {
+    let pool = Pool::builder(1)
+        .on_drop(DropBehavior::WaitTimeout(Duration::from_millis(200)))
+        .build()
+        .unwrap();
+    let handle = pool.spawn((), |()| {
+        thread::sleep(Duration::from_secs(30));
+    });
+    let start = Instant::now();
+    drop(pool);
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(handle.join().is_err());
+}
+
+#[test]
+fn test_spawn_many() {
+    let pool = Pool::new(2).unwrap();
+    let results = pool
+        .spawn_many(0..6, |x: u32| x * 2)
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(results, vec![0, 2, 4, 6, 8, 10]);
+
+    let line = line!() + 1;
+    for handle in pool.spawn_many(0..2, |x: u32| x) {
+        assert_eq!(handle.spawn_location().file(), file!());
+        assert_eq!(handle.spawn_location().line(), line);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+use std::thread;
+use std::time::Duration;
+
+use procspawn::{self, spawn, Builder, JoinHandle, Pool, Progress, ProgressSender, Receiver};
+
+procspawn::enable_test_support!();
+
+/// Reports `n` steps and then waits for the parent to let it finish.
+fn report_items((n, finish): (u64, Receiver<()>)) -> u64 {
+    let progress = ProgressSender::current().unwrap();
+    for i in 0..n {
+        progress
+            .report(Progress::new(i + 1, Some(n)).with_message(format!("item {}", i)))
+            .unwrap();
+    }
+    finish.recv().unwrap();
+    n
+}
+
+fn wait_for_progress<T>(handle: &mut JoinHandle<T>, done: u64) -> Vec<Progress> {
+    let mut updates = Vec::new();
+    while updates.last().map_or(true, |x: &Progress| x.done() < done) {
+        updates.extend(handle.progress_updates());
+        thread::sleep(Duration::from_millis(10));
+    }
+    updates
+}
+
+#[test]
+fn test_progress() {
+    let (finish_tx, finish_rx) = procspawn::channel().unwrap();
+    let mut handle = Builder::new()
+        .with_progress()
+        .spawn((3, finish_rx), report_items);
+
+    let updates = wait_for_progress(&mut handle, 3);
+    assert_eq!(
+        updates.iter().map(Progress::done).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(updates[0].message(), Some("item 0"));
+    assert_eq!(updates[0].total(), Some(3));
+
+    let latest = handle.progress().unwrap();
+    assert_eq!(latest.done(), 3);
+    assert_eq!(latest.fraction(), Some(1.0));
+    assert!(handle.progress_updates().next().is_none());
+
+    finish_tx.send(()).unwrap();
+    assert_eq!(handle.join().unwrap(), 3);
+}
+
+#[test]
+fn test_progress_disabled() {
+    let mut handle = spawn((), |()| ProgressSender::current().is_none());
+    assert!(handle.progress().is_none());
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn test_progress_pool() {
+    let pool = Pool::builder(1).with_progress().build().unwrap();
+    let (finish_tx, finish_rx) = procspawn::channel().unwrap();
+    let mut handle = pool.spawn((2, finish_rx), report_items);
+    wait_for_progress(&mut handle, 2);
+    assert_eq!(handle.progress().unwrap().done(), 2);
+    finish_tx.send(()).unwrap();
+    assert_eq!(handle.join().unwrap(), 2);
+
+    // every call gets its own sender
+    let handle = pool.spawn((), |()| ProgressSender::current().is_some());
+    assert!(handle.join().unwrap());
+}
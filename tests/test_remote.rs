@@ -0,0 +1,110 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use procspawn::remote::{Agent, Remote};
+use procspawn::transport::{TcpTransport, Transport};
+
+procspawn::enable_test_support!();
+
+const SECRET: &[u8] = b"procspawn-test-secret";
+
+fn double(x: u32) -> (u32, u32) {
+    (x * 2, std::process::id())
+}
+
+fn fail(_: ()) {
+    panic!("remote failure");
+}
+
+fn start_agent() -> String {
+    let mut agent = Agent::listen(&TcpTransport, "127.0.0.1:0", SECRET).unwrap();
+    agent.register("double", double).register("fail", fail);
+    let address = agent.address();
+    thread::spawn(move || agent.run());
+    address
+}
+
+#[test]
+fn test_remote_spawn() {
+    let remote = Remote::new(TcpTransport, &start_agent(), SECRET);
+    let handles = (0..4u32)
+        .map(|x| remote.spawn::<_, (u32, u32)>("double", x))
+        .collect::<Vec<_>>();
+    for (x, handle) in handles.into_iter().enumerate() {
+        let (rv, pid) = handle.join().unwrap();
+        assert_eq!(rv, x as u32 * 2);
+        assert_ne!(pid, std::process::id());
+    }
+}
+
+#[test]
+fn test_remote_panic() {
+    let remote = Remote::new(TcpTransport, &start_agent(), SECRET);
+    let handle = remote.spawn::<_, ()>("fail", ());
+    let err = handle.join().unwrap_err();
+    assert_eq!(err.panic_info().unwrap().message(), "remote failure");
+}
+
+#[test]
+fn test_unregistered_function() {
+    let remote = Remote::new(TcpTransport, &start_agent(), SECRET);
+    let err = remote.spawn::<_, u32>("triple", 1u32).join().unwrap_err();
+    let message = err.panic_info().unwrap().message().to_string();
+    assert!(message.contains("refused call"));
+    assert!(message.contains("not registered"));
+}
+
+#[test]
+fn test_wrong_secret() {
+    let remote = Remote::new(TcpTransport, &start_agent(), b"wrong");
+    let err = remote
+        .spawn::<_, (u32, u32)>("double", 1u32)
+        .join()
+        .unwrap_err();
+    assert!(err
+        .panic_info()
+        .unwrap()
+        .message()
+        .contains("agent refused the secret"));
+}
+
+#[test]
+fn test_unauthenticated_call() {
+    let address = start_agent();
+    let mut conn = TcpTransport.connect(&address).unwrap();
+    // the challenge of the agent
+    assert!(!conn.recv(1024).unwrap().is_empty());
+    conn.send(b"garbage").unwrap();
+    assert!(conn.recv(1024).is_err());
+}
+
+#[test]
+fn test_oversized_hello() {
+    let address = start_agent();
+    let mut conn = TcpStream::connect(&address).unwrap();
+    let mut challenge = [0; 8];
+    conn.read_exact(&mut challenge).unwrap();
+    let len = u64::from_le_bytes(challenge);
+    io::copy(&mut (&mut conn).take(len), &mut io::sink()).unwrap();
+    // the agent hangs up instead of waiting for the announced message.
+    conn.write_all(&u64::MAX.to_le_bytes()).unwrap();
+    conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(conn.read(&mut [0; 1]).unwrap(), 0);
+}
+
+#[test]
+fn test_unreachable_agent() {
+    let address = TcpTransport.listen("127.0.0.1:0").unwrap().address();
+    let remote = Remote::new(TcpTransport, &address, SECRET);
+    let err = remote
+        .spawn::<_, (u32, u32)>("double", 1u32)
+        .join()
+        .unwrap_err();
+    assert!(err
+        .panic_info()
+        .unwrap()
+        .message()
+        .starts_with("could not connect to agent"));
+}
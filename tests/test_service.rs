@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::process;
+use std::thread;
+
+use procspawn::{self, Builder};
+
+procspawn::enable_test_support!();
+
+struct Counter {
+    count: u32,
+}
+
+fn add(counter: &mut Counter, x: u32) -> u32 {
+    counter.count += x;
+    counter.count
+}
+
+#[test]
+fn test_service_keeps_state() {
+    let service = procspawn::service(10, |start| Counter { count: start });
+    assert_eq!(service.call(add, 1).unwrap(), 11);
+    assert_eq!(service.call(add, 2).unwrap(), 13);
+    let pid = service.call(|_, ()| process::id(), ()).unwrap();
+    assert_eq!(Some(pid), service.pid());
+    assert_ne!(pid, process::id());
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn test_service_concurrent_calls() {
+    let service = procspawn::service((), |()| Counter { count: 0 });
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..10 {
+                    service.call(add, 1).unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(service.call(add, 0).unwrap(), 40);
+}
+
+#[test]
+fn test_service_panic() {
+    let service = Builder::new().service((), |()| Counter { count: 0 });
+    let err = service
+        .call(|_, ()| -> u32 { panic!("oh no") }, ())
+        .unwrap_err();
+    assert!(err.panic_info().is_some());
+    // the service survives panicking calls
+    assert_eq!(service.call(add, 1).unwrap(), 1);
+}
+
+#[test]
+fn test_service_init_panic() {
+    let service = procspawn::service((), |()| -> Counter { panic!("broken") });
+    assert!(service.call(add, 1).is_err());
+    let err = service.shutdown().unwrap_err();
+    assert!(err.panic_info().is_some());
+}
+
+#[test]
+fn test_service_kill() {
+    let mut service = procspawn::service((), |()| Counter { count: 0 });
+    assert_eq!(service.call(add, 1).unwrap(), 1);
+    service.kill().unwrap();
+    assert!(service.call(add, 1).is_err());
+}
+
+procspawn::interface! {
+    trait Store {
+        fn insert(&mut self, key: String, value: u32) -> Option<u32>;
+        fn get(&mut self, key: String) -> Option<u32>;
+        fn clear(&mut self);
+    }
+    client StoreClient;
+}
+
+impl Store for HashMap<String, u32> {
+    fn insert(&mut self, key: String, value: u32) -> Option<u32> {
+        HashMap::insert(self, key, value)
+    }
+
+    fn get(&mut self, key: String) -> Option<u32> {
+        HashMap::get(self, &key).copied()
+    }
+
+    fn clear(&mut self) {
+        HashMap::clear(self)
+    }
+}
+
+#[test]
+fn test_interface() {
+    let store = StoreClient::new(procspawn::service((), |()| HashMap::new()));
+    assert_eq!(store.insert("a".into(), 1).unwrap(), None);
+    assert_eq!(store.insert("a".into(), 2).unwrap(), Some(1));
+    assert_eq!(store.get("a".into()).unwrap(), Some(2));
+    store.clear().unwrap();
+    assert_eq!(store.get("a".into()).unwrap(), None);
+    store.into_service().shutdown().unwrap();
+}
@@ -0,0 +1,54 @@
+use procspawn::serde::ShmemMut;
+use procspawn::{self, codec, Builder, Pool};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_fill_in_child() {
+    let buf = ShmemMut::new(4096).unwrap();
+    assert!(buf.iter().all(|&x| x == 0));
+
+    let handle = procspawn::spawn(buf.try_clone().unwrap(), |mut buf| {
+        for (idx, byte) in buf.iter_mut().enumerate() {
+            *byte = (idx % 256) as u8;
+        }
+        buf.len()
+    });
+    assert_eq!(handle.join().unwrap(), 4096);
+    assert!(buf
+        .iter()
+        .enumerate()
+        .all(|(idx, &x)| x == (idx % 256) as u8));
+}
+
+#[test]
+fn test_pool() {
+    let buf = ShmemMut::from_bytes(&[0; 4]).unwrap();
+    let pool = Pool::new(2).unwrap();
+    let handles: Vec<_> = (0..4u8)
+        .map(|idx| {
+            pool.spawn((buf.try_clone().unwrap(), idx), |(mut buf, idx)| {
+                buf[idx as usize] = idx + 1;
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(buf.as_bytes(), &[1, 2, 3, 4]);
+}
+
+#[test]
+fn test_codec_copies() {
+    // codecs cannot carry shared memory so the child only gets a copy
+    let buf = ShmemMut::from_bytes(b"abc").unwrap();
+    let handle =
+        Builder::new()
+            .codec::<codec::Bincode>()
+            .spawn(buf.try_clone().unwrap(), |mut buf| {
+                buf[0] = b'x';
+                buf.to_vec()
+            });
+    assert_eq!(handle.join().unwrap(), b"xbc");
+    assert_eq!(buf.as_bytes(), b"abc");
+}
@@ -0,0 +1,72 @@
+use std::io::{Read, Write};
+use std::os::unix::io::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+use procspawn::serde::IpcFd;
+use procspawn::{self, ProcConfig, Signal};
+
+procspawn::enable_test_support!();
+
+/// Starts a worker holding on to `fd` and then sends ourselves `SIGTERM`.
+fn terminate_with_worker(fd: OwnedFd, ignore_terminate: bool) {
+    let (mut ready_read, ready_write) = UnixStream::pair().unwrap();
+    let args = (
+        IpcFd(fd),
+        IpcFd(OwnedFd::from(ready_write)),
+        ignore_terminate,
+    );
+    let _handle = procspawn::spawn(args, |(_fd, IpcFd(ready), ignore_terminate)| {
+        if ignore_terminate {
+            unsafe {
+                libc::signal(libc::SIGTERM, libc::SIG_IGN);
+            }
+        }
+        UnixStream::from(ready).write_all(b"!").unwrap();
+        thread::sleep(Duration::from_secs(60));
+    });
+    let mut buf = [0];
+    ready_read.read_exact(&mut buf).unwrap();
+
+    unsafe {
+        libc::raise(libc::SIGTERM);
+    }
+    thread::sleep(Duration::from_secs(10));
+}
+
+/// Waits for all writers of the stream to go away.
+fn wait_for_close(mut stream: UnixStream) -> bool {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).is_ok()
+}
+
+#[test]
+fn test_forward_signals() {
+    let (read, write) = UnixStream::pair().unwrap();
+    let handle = procspawn::spawn(IpcFd(OwnedFd::from(write)), |IpcFd(fd)| {
+        ProcConfig::new()
+            .forward_signals(&[Signal::Terminate])
+            .init();
+        terminate_with_worker(fd, false);
+    });
+    assert!(handle.join().is_err());
+    assert!(wait_for_close(read));
+}
+
+#[test]
+fn test_signal_escalation() {
+    let (read, write) = UnixStream::pair().unwrap();
+    let handle = procspawn::spawn(IpcFd(OwnedFd::from(write)), |IpcFd(fd)| {
+        ProcConfig::new()
+            .forward_signals(&[Signal::Terminate])
+            .signal_escalation(Duration::from_millis(100))
+            .init();
+        terminate_with_worker(fd, true);
+    });
+    assert!(handle.join().is_err());
+    assert!(wait_for_close(read));
+}
@@ -0,0 +1,61 @@
+use procspawn::serde::Spill;
+use procspawn::{self, codec, Builder, Pool};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_spill_argument() {
+    let values: Vec<u64> = (0..100_000).collect();
+    let handle = procspawn::spawn(Spill::new(values), |mut values: Spill<Vec<u64>>| {
+        let spilled = values.is_spilled();
+        (spilled, values.get().unwrap().iter().sum::<u64>())
+    });
+    assert_eq!(handle.join().unwrap(), (true, 4_999_950_000));
+}
+
+#[test]
+fn test_spill_return_value() {
+    let handle = procspawn::spawn(1000usize, |len| Spill::new(vec![42u8; len]));
+    let spill = handle.join().unwrap();
+    assert!(spill.is_spilled());
+    assert_eq!(spill.into_inner().unwrap(), vec![42u8; 1000]);
+}
+
+#[test]
+fn test_spill_forwarded() {
+    // a spill that was never read can be passed on as is
+    let handle = procspawn::spawn(Spill::new("hello".to_string()), |spill: Spill<String>| {
+        let handle = procspawn::spawn(spill, |spill: Spill<String>| {
+            spill.into_inner().unwrap().len()
+        });
+        handle.join().unwrap()
+    });
+    assert_eq!(handle.join().unwrap(), 5);
+}
+
+#[test]
+fn test_pool() {
+    let pool = Pool::new(2).unwrap();
+    let handles: Vec<_> = (0..4u64)
+        .map(|idx| {
+            pool.spawn(Spill::new(vec![idx; 1000]), |spill: Spill<Vec<u64>>| {
+                spill.into_inner().unwrap().iter().sum::<u64>()
+            })
+        })
+        .collect();
+    for (idx, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join().unwrap(), idx as u64 * 1000);
+    }
+}
+
+#[test]
+fn test_codec_inline() {
+    let handle = Builder::new().codec::<codec::Bincode>().spawn(
+        Spill::new(vec![1u32, 2, 3]),
+        |spill: Spill<Vec<u32>>| {
+            let spilled = spill.is_spilled();
+            (spilled, spill.into_inner().unwrap())
+        },
+    );
+    assert_eq!(handle.join().unwrap(), (false, vec![1, 2, 3]));
+}
@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+
+use procspawn::{self, Builder, Pool};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_stream_large_result() {
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let mut handle = Builder::new()
+        .stream_results(1024)
+        .spawn(3_000_000, |size: usize| vec![42u8; size]);
+    handle.on_result_progress({
+        let progress = progress.clone();
+        move |received, total| progress.lock().unwrap().push((received, total))
+    });
+    let rv = handle.join().unwrap();
+    assert_eq!(rv.len(), 3_000_000);
+    assert!(rv.iter().all(|&x| x == 42));
+
+    let progress = progress.lock().unwrap();
+    assert!(progress.len() > 1);
+    let (received, total) = *progress.last().unwrap();
+    assert_eq!(received, total);
+    assert!(progress.windows(2).all(|x| x[0].0 < x[1].0));
+}
+
+#[test]
+fn test_stream_small_result() {
+    let progress = Arc::new(Mutex::new(0));
+    let mut handle = Builder::new()
+        .stream_results(1024)
+        .spawn((1, 2), |(a, b): (i32, i32)| a + b);
+    handle.on_result_progress({
+        let progress = progress.clone();
+        move |_, _| *progress.lock().unwrap() += 1
+    });
+    assert_eq!(handle.join().unwrap(), 3);
+    assert_eq!(*progress.lock().unwrap(), 0);
+}
+
+#[test]
+fn test_stream_panic() {
+    let handle = Builder::new()
+        .stream_results(0)
+        .spawn((), |()| -> Vec<u8> { panic!("oh no") });
+    let err = handle.join().unwrap_err();
+    assert!(err.panic_info().is_some());
+}
+
+#[test]
+fn test_stream_pool() {
+    let pool = Pool::builder(1).stream_results(1024).build().unwrap();
+    let received = Arc::new(Mutex::new(0));
+    let mut handle = pool.spawn(2_000_000, |size: usize| vec![1u8; size]);
+    handle.on_result_progress({
+        let received = received.clone();
+        move |x, _| *received.lock().unwrap() = x
+    });
+    assert_eq!(handle.join().unwrap().len(), 2_000_000);
+    assert!(*received.lock().unwrap() > 2_000_000);
+}
@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use procspawn::{self, Pool};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_core::span::Current;
+
+procspawn::enable_test_support!();
+
+/// Keeps track of the current span and records the fields of new spans.
+struct Recorder {
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, (&'static Metadata<'static>, Vec<String>)>>,
+}
+
+thread_local! {
+    static STACK: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+struct FieldVisitor<'a>(&'a mut Vec<String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push(format!("{}={:?}", field.name(), value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut fields = Vec::new();
+        span.record(&mut FieldVisitor(&mut fields));
+        self.spans
+            .lock()
+            .unwrap()
+            .insert(id, (span.metadata(), fields));
+        Id::from_u64(id)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        STACK.with(|stack| stack.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, _span: &Id) {
+        STACK.with(|stack| stack.borrow_mut().pop());
+    }
+
+    fn current_span(&self) -> Current {
+        match STACK.with(|stack| stack.borrow().last().copied()) {
+            Some(id) => Current::new(Id::from_u64(id), self.spans.lock().unwrap()[&id].0),
+            None => Current::none(),
+        }
+    }
+}
+
+#[procspawn::testsupport::ctor]
+unsafe fn install_recorder() {
+    tracing::subscriber::set_global_default(Recorder {
+        next_id: AtomicU64::new(1),
+        spans: Mutex::new(HashMap::new()),
+    })
+    .unwrap();
+}
+
+/// Returns the name and fields of the current span.
+fn current_span(_: ()) -> Option<(String, Vec<String>)> {
+    tracing::dispatcher::get_default(|dispatch| {
+        let recorder = dispatch.downcast_ref::<Recorder>()?;
+        let id = STACK.with(|stack| stack.borrow().last().copied())?;
+        let spans = recorder.spans.lock().unwrap();
+        let (metadata, fields) = &spans[&id];
+        Some((metadata.name().to_string(), fields.clone()))
+    })
+}
+
+#[test]
+fn test_span_propagation() {
+    let handle = procspawn::spawn((), current_span);
+    assert_eq!(handle.join().unwrap(), None);
+
+    let span = tracing::info_span!("request");
+    let _entered = span.enter();
+    let parent_id = span.id().unwrap().into_u64();
+
+    let handle = procspawn::spawn((), current_span);
+    let (name, fields) = handle.join().unwrap().unwrap();
+    assert_eq!(name, "procspawn");
+    assert!(fields.contains(&"parent.name=request".to_string()));
+    assert!(fields.contains(&"parent.target=test_tracing".to_string()));
+    assert!(fields.contains(&format!("parent.id={}", parent_id)));
+    assert!(fields.contains(&format!("parent.pid={}", std::process::id())));
+
+    let pool = Pool::new(1).unwrap();
+    let (name, _) = pool.spawn((), current_span).join().unwrap().unwrap();
+    assert_eq!(name, "procspawn");
+}
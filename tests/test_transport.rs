@@ -0,0 +1,94 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use procspawn::transport::{Connection, StreamConnection, TcpTransport, Transport};
+
+procspawn::enable_test_support!();
+
+fn echo(transport: &dyn Transport, address: &str) -> String {
+    let listener = transport.listen(address).unwrap();
+    let address = listener.address();
+    let server = thread::spawn(move || {
+        let mut conn = listener.accept().unwrap();
+        while let Ok(msg) = conn.recv(usize::MAX) {
+            conn.send(&msg).unwrap();
+        }
+    });
+
+    let mut conn = transport.connect(&address).unwrap();
+    for msg in [&b""[..], b"hello", &vec![42u8; 1024 * 1024]].iter() {
+        conn.send(msg).unwrap();
+        assert_eq!(conn.recv(usize::MAX).unwrap(), *msg);
+    }
+    drop(conn);
+    server.join().unwrap();
+    address
+}
+
+#[test]
+fn test_tcp() {
+    let address = echo(&TcpTransport, "127.0.0.1:0");
+    assert!(address.starts_with("127.0.0.1:"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_unix() {
+    let path = std::env::temp_dir().join(format!("procspawn-transport-{}", std::process::id()));
+    let address = echo(&procspawn::transport::UnixTransport, path.to_str().unwrap());
+    assert_eq!(address, path.to_str().unwrap());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_truncated_message() {
+    let mut data = 10u64.to_le_bytes().to_vec();
+    data.extend_from_slice(b"short");
+    let mut conn = StreamConnection::new(io::Cursor::new(data));
+    let err = conn.recv(usize::MAX).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_message_limit() {
+    let mut data = u64::MAX.to_le_bytes().to_vec();
+    data.extend_from_slice(b"short");
+    let mut conn = StreamConnection::new(io::Cursor::new(data));
+    let err = conn.recv(1024).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_read_timeout() {
+    let listener = TcpTransport.listen("127.0.0.1:0").unwrap();
+    let mut conn = TcpTransport.connect(&listener.address()).unwrap();
+    let _server = listener.accept().unwrap();
+    conn.set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let start = Instant::now();
+    assert!(conn.recv(1024).is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_vsock() {
+    use procspawn::transport::VsockTransport;
+
+    // loopback needs the vsock_loopback module which is often missing.
+    if VsockTransport.listen("local:any").is_err() {
+        return;
+    }
+    let address = echo(&VsockTransport, "local:any");
+    assert!(address.starts_with("1:"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_vsock_invalid_address() {
+    use procspawn::transport::VsockTransport;
+
+    let err = VsockTransport.connect("nowhere").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,72 @@
+use procspawn::serde::TypedShmem;
+use serde::{Deserialize, Serialize};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+procspawn::enable_test_support!();
+
+#[derive(
+    FromBytes,
+    IntoBytes,
+    Immutable,
+    KnownLayout,
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+)]
+#[repr(C)]
+struct Record {
+    id: u32,
+    weight: f32,
+    offset: u64,
+}
+
+#[test]
+fn test_slice() {
+    let records: Vec<_> = (0..10_000u32)
+        .map(|id| Record {
+            id,
+            weight: id as f32 / 2.0,
+            offset: id as u64 * 8,
+        })
+        .collect();
+    let handle = procspawn::spawn(TypedShmem::<[Record]>::new(&records[..]), |records| {
+        assert_eq!(records.len(), 10_000);
+        records.iter().map(|x| x.offset).sum::<u64>()
+    });
+    assert_eq!(
+        handle.join().unwrap(),
+        (0..10_000u64).map(|x| x * 8).sum::<u64>()
+    );
+}
+
+#[test]
+fn test_value() {
+    let record = Record {
+        id: 42,
+        weight: 1.5,
+        offset: 23,
+    };
+    let handle = procspawn::spawn(TypedShmem::new(&record), |record| *record);
+    assert_eq!(handle.join().unwrap(), record);
+}
+
+#[test]
+fn test_empty() {
+    let handle = procspawn::spawn(TypedShmem::<[u64]>::new(&[]), |values| values.len());
+    assert_eq!(handle.join().unwrap(), 0);
+}
+
+#[test]
+fn test_layout_mismatch() {
+    let bytes = bincode::serialize(&TypedShmem::<[u8]>::new(b"abc")).unwrap();
+    assert!(bincode::deserialize::<TypedShmem<u32>>(&bytes).is_err());
+    assert_eq!(
+        bincode::deserialize::<TypedShmem<[u8]>>(&bytes)
+            .unwrap()
+            .get(),
+        b"abc"
+    );
+}
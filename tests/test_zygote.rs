@@ -0,0 +1,47 @@
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use procspawn::{self, Zygote};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn test_basic() {
+    let zygote = Zygote::new().unwrap();
+    let zygote_pid = zygote.pid().unwrap();
+
+    let handles: Vec<_> = (0..4)
+        .map(|x| zygote.spawn(x, |x| (x * 2, process::id())))
+        .collect();
+    let mut pids = vec![];
+    for (x, handle) in handles.into_iter().enumerate() {
+        let (value, pid) = handle.join().unwrap();
+        assert_eq!(value, x * 2);
+        assert_ne!(pid, zygote_pid);
+        pids.push(pid);
+    }
+    pids.dedup();
+    assert_eq!(pids.len(), 4);
+}
+
+#[test]
+fn test_panic() {
+    let zygote = Zygote::new().unwrap();
+    let handle = zygote.spawn::<_, ()>((), |()| panic!("forked panic"));
+    let err = handle.join().unwrap_err();
+    assert_eq!(err.panic_info().unwrap().message(), "forked panic");
+}
+
+#[test]
+fn test_kill() {
+    let zygote = Zygote::new().unwrap();
+    let mut handle = zygote.spawn((), |()| {
+        thread::sleep(Duration::from_secs(10));
+    });
+    handle.kill().unwrap();
+    assert!(handle.join().is_err());
+
+    // the zygote is still usable afterwards
+    assert_eq!(zygote.spawn(21, |x| x * 2).join().unwrap(), 42);
+}